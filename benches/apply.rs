@@ -0,0 +1,129 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use ffizer::{ApplyOpts, AskConfirmation, Ctx, SourceLoc, SourceUri, UpdateMode};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use tempfile::{tempdir, TempDir};
+
+const NB_FILES: usize = 10_000;
+const NB_FILES_PER_DIR: usize = 100;
+
+/// a synthetic template of `NB_FILES` files:
+/// raw files, rendered files (`.ffizer.hbs`) and files with a rendered path
+fn make_template() -> TempDir {
+    let dir = tempdir().expect("create template dir");
+    fs::write(
+        dir.path().join(".ffizer.yaml"),
+        "variables:\n  - name: project\n    default_value: bench\n",
+    )
+    .expect("create .ffizer.yaml");
+    for i in 0..NB_FILES {
+        let folder = dir.path().join(format!("dir_{}", i / NB_FILES_PER_DIR));
+        if i % NB_FILES_PER_DIR == 0 {
+            fs::create_dir_all(&folder).expect("create template sub dir");
+        }
+        let (name, content) = match i % 3 {
+            0 => (format!("file_{}.txt", i), format!("raw content {}\n", i)),
+            1 => (
+                format!("file_{}.txt.ffizer.hbs", i),
+                format!("rendered content {} of {{{{ project }}}}\n", i),
+            ),
+            _ => (
+                format!("file_{}_{{{{ project }}}}.txt", i),
+                format!("raw content {} with rendered path\n", i),
+            ),
+        };
+        fs::write(folder.join(name), content).expect("create template file");
+    }
+    dir
+}
+
+fn new_ctx(template: &Path, dst: &Path) -> Ctx {
+    Ctx {
+        cmd_opt: ApplyOpts {
+            confirm: AskConfirmation::Never,
+            update_mode: UpdateMode::Override,
+            no_interaction: true,
+            offline: true,
+            src: SourceLoc {
+                uri: SourceUri::from_str(template.to_str().expect("template path as str"))
+                    .expect("parse template path"),
+                rev: "master".to_owned(),
+                subfolder: None,
+                full_history: false,
+            },
+            dst_folder: dst.to_path_buf(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn apply_benchmark(c: &mut Criterion) {
+    let template = make_template();
+    let mut group = c.benchmark_group("apply");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(NB_FILES as u64));
+
+    // walk + plan (nothing exists), the plan is emitted instead of executed
+    let plan_dir = tempdir().expect("create plan dir");
+    group.bench_function("plan_into_empty_folder", |b| {
+        b.iter_batched(
+            || tempdir().expect("create dst dir"),
+            |dst| {
+                let mut ctx = new_ctx(template.path(), dst.path());
+                ctx.cmd_opt.emit_plan = Some(plan_dir.path().join("plan.json"));
+                ffizer::process(&ctx).expect("plan template");
+                dst
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    // walk + plan + execute (add every files)
+    group.bench_function("into_empty_folder", |b| {
+        b.iter_batched(
+            || tempdir().expect("create dst dir"),
+            |dst| {
+                ffizer::process(&new_ctx(template.path(), dst.path())).expect("apply template");
+                dst
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    // walk + plan + execute, with the render limits of the cli (default values)
+    group.bench_function("into_empty_folder_with_render_limits", |b| {
+        b.iter_batched(
+            || tempdir().expect("create dst dir"),
+            |dst| {
+                let mut ctx = new_ctx(template.path(), dst.path());
+                ctx.cmd_opt.render_max_size = 104_857_600;
+                ctx.cmd_opt.render_timeout = 60;
+                ffizer::process(&ctx).expect("apply template");
+                dst
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    // walk + plan (compare content with existing files), nothing to execute
+    let dst = tempdir().expect("create dst dir");
+    ffizer::process(&new_ctx(template.path(), dst.path())).expect("apply template");
+    group.bench_function("onto_identical_folder", |b| {
+        b.iter(|| ffizer::process(&new_ctx(template.path(), dst.path())).expect("apply template"))
+    });
+
+    // walk + plan (lookup of the existing files), the plan is emitted instead of executed
+    group.bench_function("plan_onto_identical_folder", |b| {
+        b.iter(|| {
+            let mut ctx = new_ctx(template.path(), dst.path());
+            ctx.cmd_opt.emit_plan = Some(plan_dir.path().join("plan.json"));
+            ffizer::process(&ctx).expect("plan template")
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, apply_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,145 @@
+//! record, into the destination folder, the report of the last apply (to audit what was applied):
+//! the templates with their commits, the answers (the values of the secrets are masked), and the
+//! files and folders created, overwritten or kept.
+
+use crate::error::*;
+use crate::fingerprint::{Fingerprint, FingerprintTemplate};
+use crate::variables::{is_secret_name, Variables, MASKED_VALUE};
+use crate::{ExecuteSummary, Outcome};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const APPLY_REPORT_FILENAME: &str = ".ffizer/last_apply.yaml";
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ApplyReport {
+    pub ffizer_version: String,
+    /// the date of the apply (rfc 3339)
+    pub applied_at: String,
+    /// the hash of the fingerprint of the apply
+    pub fingerprint: String,
+    /// the template applied, then its imports
+    pub templates: Vec<FingerprintTemplate>,
+    /// the answers, with the values of the secrets masked
+    pub variables: Variables,
+    /// paths (relative to the destination) of the files and folders created
+    pub created: Vec<PathBuf>,
+    /// paths of the existing files updated
+    pub overwritten: Vec<PathBuf>,
+    /// paths of the existing files and folders kept unchanged
+    pub kept: Vec<PathBuf>,
+}
+
+impl ApplyReport {
+    pub(crate) fn new(fingerprint: &Fingerprint, summary: &ExecuteSummary) -> ApplyReport {
+        let paths_of = |wanted: Outcome| {
+            let mut paths = summary
+                .outcomes
+                .iter()
+                .filter(|(_, outcome)| *outcome == wanted)
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+        ApplyReport {
+            ffizer_version: fingerprint.ffizer_version.clone(),
+            applied_at: chrono::Utc::now().to_rfc3339(),
+            fingerprint: fingerprint.hash.clone(),
+            templates: fingerprint.templates.clone(),
+            variables: mask_secrets(&fingerprint.answers),
+            created: paths_of(Outcome::Created),
+            overwritten: paths_of(Outcome::Updated),
+            kept: paths_of(Outcome::Skipped),
+        }
+    }
+
+    pub fn path_in<P>(dst_folder: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        dst_folder.as_ref().join(APPLY_REPORT_FILENAME)
+    }
+
+    pub fn save<P>(&self, dst_folder: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Self::path_in(dst_folder);
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder).map_err(|source| Error::CreateFolder {
+                path: folder.to_path_buf(),
+                source,
+            })?;
+        }
+        let content = serde_yaml::to_string(self)?;
+        fs::write(&path, content).map_err(|source| Error::WriteFile { path, source })
+    }
+}
+
+/// the variables with the values of the secrets (flagged or named like a secret) replaced by
+/// `MASKED_VALUE`
+pub(crate) fn mask_secrets(variables: &Variables) -> Variables {
+    let mut masked = variables.clone();
+    for name in variables.names() {
+        if variables.is_secret(&name) || is_secret_name(&name) {
+            // a string is always serializable
+            let _ = masked.insert(name, MASKED_VALUE);
+        }
+    }
+    masked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_mask_secrets() -> Result<(), Box<dyn std::error::Error>> {
+        let mut variables = Variables::default();
+        variables.insert("project", "foo")?;
+        variables.insert("db.password", "p4ss")?;
+        variables.insert("GITHUB_TOKEN", "ghp_xxx")?;
+        variables.insert("api-key", "xxx")?;
+        let masked = mask_secrets(&variables);
+        assert_that!(masked.get("project")).is_equal_to(variables.get("project"));
+        for name in &["db.password", "GITHUB_TOKEN", "api-key"] {
+            assert_that!(masked.get(*name))
+                .is_equal_to(Some(&serde_yaml::Value::String(MASKED_VALUE.to_owned())));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<(), Box<dyn std::error::Error>> {
+        let mut variables = Variables::default();
+        variables.insert("project", "foo")?;
+        variables.insert("password", "p4ss")?;
+        let fingerprint = Fingerprint::new("2.1.0", vec![], &variables)?;
+        let summary = ExecuteSummary {
+            outcomes: vec![
+                (PathBuf::from("src/main.rs"), Outcome::Created),
+                (PathBuf::from("README.md"), Outcome::Updated),
+                (PathBuf::from("src"), Outcome::Created),
+                (PathBuf::from("LICENSE"), Outcome::Skipped),
+            ],
+            ..Default::default()
+        };
+        let report = ApplyReport::new(&fingerprint, &summary);
+        assert_that!(report.created)
+            .is_equal_to(vec![PathBuf::from("src"), PathBuf::from("src/main.rs")]);
+        assert_that!(report.overwritten).is_equal_to(vec![PathBuf::from("README.md")]);
+        assert_that!(report.kept).is_equal_to(vec![PathBuf::from("LICENSE")]);
+
+        let tmp_dir = tempdir()?;
+        report.save(tmp_dir.path())?;
+        let content = fs::read_to_string(ApplyReport::path_in(tmp_dir.path()))?;
+        assert_that!(content.contains("p4ss")).is_false();
+        assert_that!(serde_yaml::from_str::<ApplyReport>(&content)?).is_equal_to(report);
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+//! retrieval of templates published as archive (`.zip`, `.tar.gz`, `.tgz`) via http(s) or s3
+//! (see [`download`](crate::download) for the backends).
+
+use crate::auth::HostAuth;
+use crate::download;
+use crate::error::*;
+use crate::files;
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use slog::{info, Logger};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// the kind of archive, from the extension of the path (or url)
+    pub fn from_path<P>(path: P) -> Option<ArchiveKind>
+    where
+        P: AsRef<Path>,
+    {
+        let name = path.as_ref().file_name()?.to_str()?;
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// download the archive (with the credentials of its host, if defined), verify its sha256 (if defined),
+/// then extract it into the directory `dst`
+/// (extracted into `<dst>.part` then swapped, so `dst` is never partially extracted)
+pub fn retrieve<P, U>(
+    logger: &Logger,
+    dst: P,
+    url: U,
+    checksum: Option<&str>,
+    auth: Option<&HostAuth>,
+) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    U: AsRef<str>,
+{
+    let dst = dst.as_ref();
+    let url = url.as_ref();
+    let kind = ArchiveKind::from_path(url)
+        .ok_or_else(|| Error::Unknown(format!("unsupported kind of archive: {}", url)))?;
+    info!(logger, "download archive"; "url" => url);
+    let content = download::backend_for(url)?.download(url, auth)?;
+    if let Some(expected) = checksum {
+        verify_checksum(url, &content, expected)?;
+    }
+    let tmp = files::part_path(dst);
+    if tmp.exists() {
+        fs::remove_dir_all(&tmp).map_err(|source| Error::RemoveFolder {
+            path: tmp.clone(),
+            source,
+        })?;
+    }
+    info!(logger, "extract archive into cached template"; "folder" => ?&dst);
+    extract(kind, &content, &tmp).map_err(|source| Error::ExtractArchive {
+        url: url.to_owned(),
+        dst: tmp.clone(),
+        source,
+    })?;
+    if dst.exists() {
+        fs::remove_dir_all(&dst).map_err(|source| Error::RemoveFolder {
+            path: dst.to_path_buf(),
+            source,
+        })?;
+    }
+    fs::rename(&tmp, &dst).map_err(|source| Error::RenameFile {
+        src: tmp.clone(),
+        dst: dst.to_path_buf(),
+        source,
+    })?;
+    Ok(())
+}
+
+fn verify_checksum(url: &str, content: &[u8], expected: &str) -> Result<()> {
+    let actual = format!("{:x}", Sha256::digest(content));
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(Error::ChecksumMismatch {
+            url: url.to_owned(),
+            expected: expected.to_owned(),
+            actual,
+        })
+    }
+}
+
+fn extract(kind: ArchiveKind, content: &[u8], dst: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dst)?;
+    match kind {
+        ArchiveKind::TarGz => tar::Archive::new(GzDecoder::new(content)).unpack(dst),
+        ArchiveKind::Zip => extract_zip(content, dst),
+    }
+}
+
+fn extract_zip(content: &[u8], dst: &Path) -> std::io::Result<()> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(content))?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let path = dst.join(enclosed_name(entry.name())?);
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            std::io::copy(&mut entry, &mut fs::File::create(&path)?)?;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Some(mode) = entry.unix_mode() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// reject absolute path and `..` (no write outside of the destination)
+fn enclosed_name(name: &str) -> std::io::Result<PathBuf> {
+    let path = PathBuf::from(name);
+    if path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path)
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("entry outside of the archive: {}", name),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use spectral::prelude::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    const CONTENT: &str = "Hello {{ name }}";
+
+    fn make_tar_gz() -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(CONTENT.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(
+                &mut header,
+                "tmpl-1.0/foo.txt.ffizer.hbs",
+                CONTENT.as_bytes(),
+            )
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn make_zip() -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        writer
+            .start_file(
+                "tmpl-1.0/foo.txt.ffizer.hbs",
+                zip::write::FileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(CONTENT.as_bytes()).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_archive_kind_from_path() {
+        assert_that!(ArchiveKind::from_path("https://h/a/t.zip"))
+            .is_equal_to(Some(ArchiveKind::Zip));
+        assert_that!(ArchiveKind::from_path("https://h/a/t.tar.gz"))
+            .is_equal_to(Some(ArchiveKind::TarGz));
+        assert_that!(ArchiveKind::from_path("t.tgz")).is_equal_to(Some(ArchiveKind::TarGz));
+        assert_that!(ArchiveKind::from_path("https://h/a/t.git")).is_equal_to(None);
+    }
+
+    #[test]
+    fn test_extract() {
+        for (kind, content) in &[
+            (ArchiveKind::TarGz, make_tar_gz()),
+            (ArchiveKind::Zip, make_zip()),
+        ] {
+            let tmp_dir = tempdir().unwrap();
+            extract(*kind, content, tmp_dir.path()).unwrap();
+            let extracted = tmp_dir.path().join("tmpl-1.0").join("foo.txt.ffizer.hbs");
+            assert_that!(fs::read_to_string(&extracted).unwrap()).is_equal_to(CONTENT.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_enclosed_name() {
+        assert_that!(enclosed_name("a/./b.txt").unwrap()).is_equal_to(PathBuf::from("a/./b.txt"));
+        assert!(enclosed_name("../b.txt").is_err());
+        assert!(enclosed_name("a/../../b.txt").is_err());
+        assert!(enclosed_name("/etc/b.txt").is_err());
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let expected = "185f8db32271fe25f561a6fc938b2e264306ec304eda518007d1764826381969";
+        assert!(verify_checksum("url", b"Hello", expected).is_ok());
+        assert!(verify_checksum("url", b"Hello", &expected.to_uppercase()).is_ok());
+        assert!(verify_checksum("url", b"Hello!", expected).is_err());
+    }
+}
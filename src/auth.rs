@@ -0,0 +1,244 @@
+//! credentials of the hosts that require authentication (to download archives or to clone git
+//! repositories of private templates), defined by the user into `auth.yaml` of the ffizer's config folder
+//! (eg `~/.config/ffizer/auth.yaml` on linux), or into the file set by `FFIZER_AUTH_FILE`.
+//! The credentials asked when a git authentication fails are saved into the same file, if the user
+//! confirms.
+//!
+//! ```yaml
+//! hosts:
+//!   - host: templates.example.com
+//!     token: xxxxxx # sent as "Authorization: Bearer xxxxxx" (or as password for git)
+//!   - host: other.example.com
+//!     username: bob
+//!     password: secret
+//!   - host: git.example.com
+//!     username: git
+//!     ssh_key: /home/bob/.ssh/id_ed25519 # for git over ssh, `password` is the passphrase
+//! ```
+
+use crate::error::*;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const AUTH_FILE_ENV: &str = "FFIZER_AUTH_FILE";
+const AUTH_FILENAME: &str = "auth.yaml";
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct AuthCfg {
+    pub hosts: Vec<HostAuth>,
+}
+
+/// the credentials of a host, the token is used if defined, else the basic auth
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct HostAuth {
+    pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// path of the private key (for git over ssh)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_key: Option<PathBuf>,
+}
+
+impl AuthCfg {
+    /// path of the file of the user, `FFIZER_AUTH_FILE` if defined
+    pub fn find_file() -> Result<PathBuf> {
+        if let Ok(path) = std::env::var(AUTH_FILE_ENV) {
+            return Ok(PathBuf::from(path));
+        }
+        let project_dirs = directories::ProjectDirs::from("", "", env!("CARGO_PKG_NAME"))
+            .ok_or(Error::ApplicationPathNotFound {})?;
+        Ok(project_dirs.config_dir().join(AUTH_FILENAME))
+    }
+
+    /// load the credentials of the user (none if the file doesn't exist)
+    pub fn load() -> Result<AuthCfg> {
+        Self::from_file(Self::find_file()?)
+    }
+
+    pub fn from_file<P>(path: P) -> Result<AuthCfg>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(AuthCfg::default());
+        }
+        let content = fs::read_to_string(path).map_err(|source| Error::ReadFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|source| Error::CreateFolder {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+        }
+        let content = serde_yaml::to_string(self)?;
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .and_then(|mut f| {
+                // the file contains secrets, readable only by the user (even if the file existed),
+                // restricted before writing the secrets
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    f.set_permissions(fs::Permissions::from_mode(0o600))?;
+                }
+                f.write_all(content.as_bytes())
+            })
+            .map_err(|source| Error::WriteFile {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    /// add the credentials, or replace the credentials of the same host
+    pub fn upsert(&mut self, auth: HostAuth) {
+        self.hosts
+            .retain(|h| !h.host.eq_ignore_ascii_case(&auth.host));
+        self.hosts.push(auth);
+    }
+
+    /// the credentials for the host (case insensitive)
+    pub fn find(&self, host: &str) -> Option<&HostAuth> {
+        self.hosts
+            .iter()
+            .find(|h| h.host.eq_ignore_ascii_case(host))
+    }
+}
+
+impl HostAuth {
+    /// add the authentication to the request
+    pub fn apply(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::blocking::RequestBuilder {
+        match (&self.token, &self.username) {
+            (Some(token), _) => request.bearer_auth(token),
+            (None, Some(username)) => request.basic_auth(username, self.password.as_ref()),
+            (None, None) => request,
+        }
+    }
+
+    /// the credentials for git (`None` if not compatible with the `allowed` types)
+    pub fn git_credential(
+        &self,
+        username_from_url: Option<&str>,
+        allowed: git2::CredentialType,
+    ) -> Option<Result<git2::Cred, git2::Error>> {
+        let username = self
+            .username
+            .as_deref()
+            .or(username_from_url)
+            .unwrap_or("git");
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            if let Some(ssh_key) = &self.ssh_key {
+                return Some(git2::Cred::ssh_key(
+                    username,
+                    None,
+                    ssh_key,
+                    self.password.as_deref(),
+                ));
+            }
+        }
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            match (&self.token, &self.password) {
+                (Some(token), _) => return Some(git2::Cred::userpass_plaintext(username, token)),
+                (None, Some(password)) if self.ssh_key.is_none() => {
+                    return Some(git2::Cred::userpass_plaintext(username, password))
+                }
+                _ => (),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_from_file_and_find() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let path = tmp_dir.path().join(AUTH_FILENAME);
+        assert_that!(AuthCfg::from_file(&path)?).is_equal_to(AuthCfg::default());
+
+        fs::write(
+            &path,
+            r#"
+            hosts:
+              - host: templates.example.com
+                token: xxxxxx
+              - host: other.example.com
+                username: bob
+                password: secret
+            "#,
+        )?;
+        let cfg = AuthCfg::from_file(&path)?;
+        assert_that!(cfg
+            .find("Templates.Example.com")
+            .and_then(|h| h.token.clone()))
+        .is_equal_to(Some("xxxxxx".to_owned()));
+        assert_that!(cfg
+            .find("other.example.com")
+            .and_then(|h| h.username.clone()))
+        .is_equal_to(Some("bob".to_owned()));
+        assert_that!(cfg.find("github.com")).is_none();
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_and_save() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let path = tmp_dir.path().join("config").join(AUTH_FILENAME);
+        let mut cfg = AuthCfg::default();
+        cfg.upsert(HostAuth {
+            host: "git.example.com".to_owned(),
+            token: Some("t1".to_owned()),
+            ..Default::default()
+        });
+        cfg.upsert(HostAuth {
+            host: "Git.Example.com".to_owned(),
+            token: Some("t2".to_owned()),
+            ..Default::default()
+        });
+        assert_that!(cfg.hosts.len()).is_equal_to(1);
+        // an existing file readable by everyone
+        fs::create_dir_all(tmp_dir.path().join("config"))?;
+        fs::write(&path, "hosts: []\n")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+        }
+        cfg.save(&path)?;
+        assert_that!(AuthCfg::from_file(&path)?).is_equal_to(cfg);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_that!(fs::metadata(&path)?.permissions().mode() & 0o777).is_equal_to(0o600);
+        }
+        Ok(())
+    }
+}
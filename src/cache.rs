@@ -0,0 +1,378 @@
+//! management of the local cache of remote templates (see `SourceLoc::find_remote_cache_folder`).
+//!
+//! The cache contains one folder per retrieved template:
+//! - `<host>/<path>/<rev>` for a git repository
+//! - `<host>/<path>` for an archive (`.zip`, `.tar.gz`, `.tgz`)
+//! - `<host>/<path>` for a raw file (the folder contains the file, its `ETag` is into `<host>/<path>.etag`)
+
+use crate::archive::ArchiveKind;
+use crate::auth::AuthCfg;
+use crate::error::*;
+use crate::git;
+use crate::raw_file;
+use crate::source_loc::SourceLoc;
+use crate::source_uri::SourceUri;
+use slog::{info, warn, Logger};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Git,
+    Archive,
+    RawFile,
+}
+
+/// a template in the cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheEntry {
+    /// the folder of the template in the cache
+    pub path: PathBuf,
+    /// the path relative to the cache folder (`<host>/<path>[/<rev>]`)
+    pub name: PathBuf,
+    pub kind: CacheKind,
+    /// size of the files (in bytes)
+    pub disk_usage: u64,
+    /// last time the template was downloaded or updated
+    pub last_refresh: Option<SystemTime>,
+    /// the source used to download the template (only for git repository)
+    pub source: Option<SourceLoc>,
+    /// the resolved rev (only for git repository)
+    pub rev_info: Option<git::RevInfo>,
+}
+
+impl CacheEntry {
+    /// the entry is selected by the filters (no filters means every entries): the components of a
+    /// filter are whole components of the name, in the same order (eg `github.com/ffizer` or
+    /// `template_sample` select `github.com/ffizer/template_sample/master`, `ffi` doesn't)
+    pub fn is_selected_by(&self, filters: &[String]) -> bool {
+        let components = self.name.components().collect::<Vec<_>>();
+        filters.is_empty()
+            || filters.iter().any(|f| {
+                let filter = Path::new(f).components().collect::<Vec<_>>();
+                !filter.is_empty()
+                    && components
+                        .windows(filter.len())
+                        .any(|w| w == filter.as_slice())
+            })
+    }
+}
+
+/// list the templates in the cache `folder` (sorted by name)
+pub fn list<P>(folder: P) -> Result<Vec<CacheEntry>>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let mut entries = vec![];
+    if folder.exists() {
+        collect_entries(folder, folder, &mut entries)?;
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+fn collect_entries(root: &Path, dir: &Path, entries: &mut Vec<CacheEntry>) -> Result<()> {
+    let children = fs::read_dir(dir).map_err(|source| Error::ListFolder {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    for child in children {
+        let path = child
+            .map_err(|source| Error::ListFolder {
+                path: dir.to_path_buf(),
+                source,
+            })?
+            .path();
+        // ignore files and leftovers of interrupted download (`.part`)
+        if !path.is_dir() || path.extension() == Some(std::ffi::OsStr::new("part")) {
+            continue;
+        }
+        if path.join(".git").exists() {
+            entries.push(make_git_entry(root, path)?);
+        } else if ArchiveKind::from_path(&path).is_some() {
+            entries.push(make_downloaded_entry(root, path, CacheKind::Archive)?);
+        } else if raw_file::cached_file(&path).map(|f| f.is_file()) == Some(true) {
+            entries.push(make_downloaded_entry(root, path, CacheKind::RawFile)?);
+        } else {
+            collect_entries(root, &path, entries)?;
+        }
+    }
+    Ok(())
+}
+
+fn make_git_entry(root: &Path, path: PathBuf) -> Result<CacheEntry> {
+    let name = relative_name(root, &path);
+    let git_dir = path.join(".git");
+    let last_refresh = modified(&git_dir.join("FETCH_HEAD")).or_else(|| modified(&git_dir));
+    // the rev is the remaining of the name after `<host>/<path>` of the remote url
+    let source = git::find_remote_url(&path).ok().map(|url| {
+        let uri = SourceUri::from_str(&url).unwrap_or_else(|_| SourceUri {
+            raw: url.clone(),
+            path: PathBuf::from(&url),
+            host: None,
+            subfolder: None,
+        });
+        let rev = uri
+            .host
+            .as_ref()
+            .and_then(|host| name.strip_prefix(Path::new(host).join(&uri.path)).ok())
+            .or_else(|| path.file_name().map(Path::new))
+            .map(|rev| rev.to_string_lossy().to_string())
+            .unwrap_or_default();
+        SourceLoc {
+            uri,
+            rev,
+            subfolder: None,
+            full_history: false,
+        }
+    });
+    let rev_info = source
+        .as_ref()
+        .and_then(|s| git::find_rev_info(&path, &s.rev).ok());
+    Ok(CacheEntry {
+        disk_usage: disk_usage(&path),
+        path,
+        name,
+        kind: CacheKind::Git,
+        last_refresh,
+        source,
+        rev_info,
+    })
+}
+
+fn make_downloaded_entry(root: &Path, path: PathBuf, kind: CacheKind) -> Result<CacheEntry> {
+    Ok(CacheEntry {
+        name: relative_name(root, &path),
+        kind,
+        disk_usage: disk_usage(&path),
+        last_refresh: modified(&path),
+        source: None,
+        rev_info: None,
+        path,
+    })
+}
+
+fn relative_name(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn disk_usage(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// remove from the cache `folder` the templates selected by the filters,
+/// without filters the whole cache is removed (including leftovers of interrupted downloads)
+pub fn clean<P>(logger: &Logger, folder: P, filters: &[String]) -> Result<Vec<CacheEntry>>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let removed = list(folder)?
+        .into_iter()
+        .filter(|e| e.is_selected_by(filters))
+        .collect::<Vec<_>>();
+    if filters.is_empty() {
+        if folder.exists() {
+            info!(logger, "remove cache"; "folder" => ?&folder);
+            fs::remove_dir_all(folder).map_err(|source| Error::RemoveFolder {
+                path: folder.to_path_buf(),
+                source,
+            })?;
+        }
+    } else {
+        for entry in &removed {
+            info!(logger, "remove cached template"; "folder" => ?&entry.path);
+            fs::remove_dir_all(&entry.path).map_err(|source| Error::RemoveFolder {
+                path: entry.path.clone(),
+                source,
+            })?;
+            let etag_path = raw_file::etag_path(&entry.path);
+            if entry.kind == CacheKind::RawFile && etag_path.exists() {
+                fs::remove_file(&etag_path).map_err(|source| Error::RemoveFile {
+                    path: etag_path,
+                    source,
+                })?;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// remove the leftovers of the interrupted downloads (`.part` files and folders) in the cache `folder`,
+/// return the removed paths
+pub fn remove_partials<P>(logger: &Logger, folder: P) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let mut removed = vec![];
+    if !folder.is_dir() {
+        return Ok(removed);
+    }
+    let mut it = WalkDir::new(folder).into_iter();
+    while let Some(entry) = it.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!(logger, "failed to list the cache"; "err" => ?err);
+                continue;
+            }
+        };
+        let path = entry.path();
+        if entry.file_type().is_dir() && path.join(".git").exists() {
+            // a cached template (its content is not a leftover)
+            it.skip_current_dir();
+            continue;
+        }
+        if path.extension() != Some(std::ffi::OsStr::new("part")) {
+            continue;
+        }
+        info!(logger, "remove leftover of interrupted download"; "path" => ?path);
+        let res = if entry.file_type().is_dir() {
+            it.skip_current_dir();
+            fs::remove_dir_all(path).map_err(|source| Error::RemoveFolder {
+                path: path.to_path_buf(),
+                source,
+            })
+        } else {
+            fs::remove_file(path).map_err(|source| Error::RemoveFile {
+                path: path.to_path_buf(),
+                source,
+            })
+        };
+        res?;
+        removed.push(path.to_path_buf());
+    }
+    Ok(removed)
+}
+
+/// update (fetch and merge) the git templates in the cache `folder` selected by the filters,
+/// return the updated entries (archives and raw files are not updated, they are downloaded again on apply)
+pub fn update<P>(logger: &Logger, folder: P, filters: &[String]) -> Result<Vec<CacheEntry>>
+where
+    P: AsRef<Path>,
+{
+    let folder = folder.as_ref();
+    let auth_cfg = AuthCfg::load()?;
+    let mut updated = vec![];
+    for entry in list(folder)?
+        .into_iter()
+        .filter(|e| e.is_selected_by(filters))
+    {
+        match &entry.source {
+            Some(source) => {
+                info!(logger, "update cached template"; "folder" => ?&entry.path, "src" => ?&source);
+                let auth = source.uri.host.as_deref().and_then(|h| auth_cfg.find(h));
+                git::retrieve_with_auth(
+                    logger,
+                    &entry.path,
+                    &source.uri.raw,
+                    &source.rev,
+                    auth,
+                    false,
+                )?;
+                updated.push(make_git_entry(folder, entry.path)?);
+            }
+            None => {
+                warn!(logger, "skip update of cached template (not a git repository)"; "folder" => ?&entry.path);
+            }
+        }
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_list_and_clean_archives() -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let tmp_dir = tempdir()?;
+        let cache = tmp_dir.path().join("cache");
+        for name in &["h1/a/t1.zip", "h1/b/t2.tar.gz", "h2/t3.tgz"] {
+            fs::create_dir_all(cache.join(name))?;
+            fs::write(cache.join(name).join("foo.txt"), "12345")?;
+        }
+        // leftover of an interrupted download
+        fs::create_dir_all(cache.join("h2/t4.zip.part"))?;
+
+        let entries = list(&cache)?;
+        let names = entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+        assert_that!(&names).is_equal_to(vec![
+            PathBuf::from("h1/a/t1.zip"),
+            PathBuf::from("h1/b/t2.tar.gz"),
+            PathBuf::from("h2/t3.tgz"),
+        ]);
+        assert_that!(entries[0].disk_usage).is_equal_to(5);
+        assert_that!(entries[0].kind).is_equal_to(CacheKind::Archive);
+        assert_that!(entries[0].last_refresh).is_some();
+
+        let removed = clean(&logger, &cache, &["h".to_owned()])?;
+        assert_that!(removed).is_empty();
+        let removed = clean(&logger, &cache, &["h1/".to_owned()])?;
+        assert_that!(removed.len()).is_equal_to(2);
+        assert_that!(list(&cache)?.len()).is_equal_to(1);
+        assert_that!(cache.join("h2/t4.zip.part").exists()).is_true();
+
+        fs::write(cache.join("h2/t5.tgz.part"), "123")?;
+        fs::create_dir_all(cache.join("h2/t6/.git"))?;
+        fs::create_dir_all(cache.join("h2/t6/keep.part"))?;
+        let mut partials = remove_partials(&logger, &cache)?;
+        partials.sort();
+        assert_that!(partials).is_equal_to(vec![
+            cache.join("h2/t4.zip.part"),
+            cache.join("h2/t5.tgz.part"),
+        ]);
+        assert_that!(cache.join("h2/t3.tgz").exists()).is_true();
+        assert_that!(cache.join("h2/t6/keep.part").exists()).is_true();
+
+        clean(&logger, &cache, &[])?;
+        assert_that!(cache.exists()).is_false();
+        assert_that!(list(&cache)?).is_empty();
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_and_clean_raw_files() -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let tmp_dir = tempdir()?;
+        let cache = tmp_dir.path().join("cache");
+        fs::create_dir_all(cache.join("h1/templates/README.md"))?;
+        fs::write(cache.join("h1/templates/README.md/README.md"), "hello")?;
+        fs::write(cache.join("h1/templates/README.md.etag"), "\"abc\"")?;
+        // not a raw file: the folder doesn't contain a file with its name
+        fs::create_dir_all(cache.join("h1/templates/other"))?;
+        fs::write(cache.join("h1/templates/other/foo.txt"), "hello")?;
+
+        let entries = list(&cache)?;
+        assert_that!(entries.len()).is_equal_to(1);
+        assert_that!(entries[0].name).is_equal_to(PathBuf::from("h1/templates/README.md"));
+        assert_that!(entries[0].kind).is_equal_to(CacheKind::RawFile);
+
+        // only whole components
+        clean(&logger, &cache, &["README".to_owned()])?;
+        assert_that!(cache.join("h1/templates/README.md").exists()).is_true();
+        clean(&logger, &cache, &["templates/README.md".to_owned()])?;
+        assert_that!(cache.join("h1/templates/README.md").exists()).is_false();
+        assert_that!(cache.join("h1/templates/README.md.etag").exists()).is_false();
+        assert_that!(cache.join("h1/templates/other").exists()).is_true();
+        Ok(())
+    }
+}
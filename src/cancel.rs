@@ -0,0 +1,61 @@
+use crate::error::*;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// token to cancel cooperatively a running generation (from an other thread),
+/// the clones of a token share the same state.
+///
+/// The cancellation is checked between steps (retrieve of each template, plan of each source file,
+/// execution of each action), so a running step is completed before to stop.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// return `Error::Cancelled` if cancelled
+    pub fn check<S>(&self, when: S) -> Result<()>
+    where
+        S: Into<String>,
+    {
+        self.check_with_done(when, vec![])
+    }
+
+    /// return `Error::Cancelled` (with the list of files and folders already done) if cancelled
+    pub fn check_with_done<S>(&self, when: S, done: Vec<PathBuf>) -> Result<()>
+    where
+        S: Into<String>,
+    {
+        if self.is_cancelled() {
+            Err(Error::Cancelled {
+                when: when.into(),
+                done,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_cancel_is_shared_by_clones() {
+        let token = CancelToken::default();
+        let clone = token.clone();
+        assert_that!(clone.check("test")).is_ok();
+        token.cancel();
+        assert_that!(clone.is_cancelled()).is_true();
+        assert!(clone.check("test").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use super::transform_values::TransformsValues;
+use crate::Result;
+use schemars::JsonSchema;
+use std::collections::BTreeMap;
+
+/// the language used when no message is defined for the locale of the user
+const FALLBACK_LANGUAGE: &str = "en";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(untagged)]
+/// sentence to ask the value: a message, or a message per locale (eg `en`, `fr`, `pt_BR`) selected by
+/// the locale of the user
+pub(crate) enum AskCfg {
+    Message(String),
+    Localized(BTreeMap<String, String>),
+}
+
+impl AskCfg {
+    /// the message for the `locale` (eg `fr_FR.UTF-8`): the message of the locale, else of its language
+    /// (`fr`), else of english, else the first one
+    pub fn for_locale(&self, locale: &str) -> Option<String> {
+        match self {
+            AskCfg::Message(message) => Some(message.clone()),
+            AskCfg::Localized(messages) => {
+                let find = |wanted: &str| {
+                    messages
+                        .iter()
+                        .find(|(k, _)| normalize_locale(k) == wanted)
+                        .map(|(_, v)| v.clone())
+                };
+                let locale = normalize_locale(locale);
+                let language = locale.split('_').next().unwrap_or_default();
+                find(&locale)
+                    .or_else(|| find(language))
+                    .or_else(|| find(FALLBACK_LANGUAGE))
+                    .or_else(|| messages.values().next().cloned())
+            }
+        }
+    }
+
+    /// all the messages (of every locale)
+    pub fn messages(&self) -> Vec<&String> {
+        match self {
+            AskCfg::Message(message) => vec![message],
+            AskCfg::Localized(messages) => messages.values().collect(),
+        }
+    }
+}
+
+impl TransformsValues for AskCfg {
+    fn transforms_values<F>(&self, render: &F) -> Result<Self>
+    where
+        F: Fn(&str) -> String,
+    {
+        match self {
+            AskCfg::Message(message) => Ok(AskCfg::Message(message.transforms_values(render)?)),
+            AskCfg::Localized(messages) => Ok(AskCfg::Localized(
+                messages
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), v.transforms_values(render)?)))
+                    .collect::<Result<_>>()?,
+            )),
+        }
+    }
+}
+
+/// the locale of the user, from `FFIZER_LANG`, else `LC_ALL`, `LC_MESSAGES`, `LANG` (eg `fr_FR.UTF-8`),
+/// else english
+pub(crate) fn user_locale() -> String {
+    ["FFIZER_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|k| std::env::var(k).ok())
+        .find(|v| !v.is_empty() && v != "C" && v != "POSIX")
+        .unwrap_or_else(|| FALLBACK_LANGUAGE.to_owned())
+}
+
+/// `fr-FR`, `fr_fr.UTF-8` and `fr_FR@euro` are `fr_fr`
+fn normalize_locale(locale: &str) -> String {
+    locale
+        .split(&['.', '@'][..])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .replace('-', "_")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_for_locale() -> Result<(), Box<dyn std::error::Error>> {
+        let ask: AskCfg = serde_yaml::from_str(
+            r#"
+            en: "Name of the project?"
+            fr: "Nom du projet ?"
+            pt-BR: "Nome do projeto?"
+            "#,
+        )?;
+        assert_that!(ask.for_locale("fr_FR.UTF-8")).is_equal_to(Some("Nom du projet ?".to_owned()));
+        assert_that!(ask.for_locale("fr")).is_equal_to(Some("Nom du projet ?".to_owned()));
+        assert_that!(ask.for_locale("pt_BR")).is_equal_to(Some("Nome do projeto?".to_owned()));
+        assert_that!(ask.for_locale("de_DE")).is_equal_to(Some("Name of the project?".to_owned()));
+
+        let ask: AskCfg = serde_yaml::from_str(r#"fr: "Nom du projet ?""#)?;
+        assert_that!(ask.for_locale("de_DE")).is_equal_to(Some("Nom du projet ?".to_owned()));
+
+        let ask: AskCfg = serde_yaml::from_str(r#""Name of {{ org }}'s project?""#)?;
+        assert_that!(ask.for_locale("fr_FR"))
+            .is_equal_to(Some("Name of {{ org }}'s project?".to_owned()));
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use super::ignore_cfg::IgnoreCfg;
+use super::transform_values::TransformsValues;
+use crate::Result;
+use schemars::JsonSchema;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DotfilesMode {
+    /// include every hidden files and folders (except `excludes`)
+    All,
+    /// exclude every hidden files and folders (except `includes`)
+    None,
+}
+
+impl Default for DotfilesMode {
+    fn default() -> Self {
+        DotfilesMode::All
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+/// selection of the hidden files and folders (name starting with `.`) of the template,
+/// the content of a hidden folder follows the selection of the folder
+pub(crate) struct DotfilesCfg {
+    /// `all` (default) or `none`
+    pub(crate) mode: DotfilesMode,
+    /// list of path of hidden files or folders to include (eg `.editorconfig`)
+    pub(crate) includes: Vec<IgnoreCfg>,
+    /// list of path of hidden files or folders to exclude (eg `**/.DS_Store`), win over `includes`
+    pub(crate) excludes: Vec<IgnoreCfg>,
+}
+
+impl TransformsValues for DotfilesCfg {
+    fn transforms_values<F>(&self, render: &F) -> Result<Self>
+    where
+        F: Fn(&str) -> String,
+    {
+        Ok(DotfilesCfg {
+            mode: self.mode,
+            includes: self.includes.transforms_values(render)?,
+            excludes: self.excludes.transforms_values(render)?,
+        })
+    }
+}
@@ -0,0 +1,28 @@
+use super::ignore_cfg::IgnoreCfg;
+use super::transform_values::TransformsValues;
+use crate::Result;
+use schemars::JsonSchema;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+/// define the encoding of text templates not encoded in UTF-8 (decoded before the rendering,
+/// the rendered content is encoded back)
+pub(crate) struct EncodingCfg {
+    /// path (from the template) of the files
+    pub files: IgnoreCfg,
+    /// label of the encoding (eg `windows-1252`, `shift_jis`, `utf-16le`),
+    /// see https://encoding.spec.whatwg.org/#names-and-labels
+    pub encoding: String,
+}
+
+impl TransformsValues for EncodingCfg {
+    /// transforms files
+    fn transforms_values<F>(&self, render: &F) -> Result<Self>
+    where
+        F: Fn(&str) -> String,
+    {
+        Ok(EncodingCfg {
+            files: self.files.transforms_values(render)?,
+            ..self.clone()
+        })
+    }
+}
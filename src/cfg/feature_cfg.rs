@@ -0,0 +1,40 @@
+use super::ask_cfg::AskCfg;
+use super::ignore_cfg::IgnoreCfg;
+use super::transform_values::TransformsValues;
+use super::variable_cfg::VariableCfg;
+use crate::Result;
+use schemars::JsonSchema;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+/// define an optional part of the template, selected by the user (multi-select)
+/// with the other features (the selection is the variable `features`)
+pub(crate) struct FeatureCfg {
+    /// name of the feature, usable into the template as `features.<name>` (`true` if selected)
+    pub name: String,
+    /// sentence displayed into the selection (default to the name of the feature),
+    /// or a sentence per locale (eg `en: ...`, `fr: ...`)
+    pub ask: Option<AskCfg>,
+    /// is the feature selected by default
+    #[serde(default)]
+    pub default: bool,
+    /// list of path (from the template) of the files and folders of the feature,
+    /// ignored if the feature is not selected
+    #[serde(default)]
+    pub files: Vec<IgnoreCfg>,
+    /// list of variables asked only if the feature is selected
+    #[serde(default)]
+    pub variables: Vec<VariableCfg>,
+}
+
+impl TransformsValues for FeatureCfg {
+    /// transforms files
+    fn transforms_values<F>(&self, render: &F) -> Result<Self>
+    where
+        F: Fn(&str) -> String,
+    {
+        Ok(FeatureCfg {
+            files: self.files.transforms_values(render)?,
+            ..self.clone()
+        })
+    }
+}
@@ -0,0 +1,10 @@
+use schemars::JsonSchema;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+/// define a boolean flag computed from the variables (after the questions)
+pub(crate) struct FlagCfg {
+    /// name of the flag, usable like a variable
+    pub name: String,
+    /// handlebars expression rendered as `true` or `false` (empty is `false`)
+    pub value: String,
+}
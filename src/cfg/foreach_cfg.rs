@@ -0,0 +1,12 @@
+use schemars::JsonSchema;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+/// define a variable to iterate over the values of a list,
+/// a file or a folder with this variable into its path is generated for each value of the list
+pub(crate) struct ForeachCfg {
+    /// name of the variable set with the current value (and `<item>__idx` with its index)
+    pub item: String,
+    /// name of the list variable to iterate over
+    #[serde(rename = "in")]
+    pub in_list: String,
+}
@@ -1,6 +1,11 @@
 use super::transform_values::TransformsValues;
+use crate::source_loc::SourceLoc;
+use crate::source_uri::SourceUri;
 use crate::Result;
 use schemars::JsonSchema;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
 /// define a template layer to import
@@ -8,6 +13,51 @@ pub(crate) struct ImportCfg {
     pub uri: String,
     pub rev: Option<String>,
     pub subfolder: Option<String>,
+    /// prefix of the variables of the imported template (`<namespace>.<name>`), to not collide
+    /// with the variables of the other templates
+    pub namespace: Option<String>,
+    /// values of the variables of the imported template (not asked), rendered with the variables
+    /// of the importing template
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+}
+
+/// the namespace and the bindings of the variables of an imported template
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ImportScope {
+    pub namespace: Option<String>,
+    /// (name of the variable, handlebars template of its value)
+    pub bindings: Vec<(String, String)>,
+}
+
+impl ImportCfg {
+    pub(crate) fn to_scope(&self) -> ImportScope {
+        ImportScope {
+            namespace: self
+                .namespace
+                .as_deref()
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_owned()),
+            bindings: self
+                .variables
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn to_sourceloc(&self) -> Result<SourceLoc> {
+        let uri = SourceUri::from_str(self.uri.trim())?;
+        let subfolder = self.subfolder.as_ref().map(|x| PathBuf::from(x.as_str()));
+        let rev = self.rev.as_deref().unwrap_or("master").to_owned();
+        Ok(SourceLoc {
+            uri,
+            rev,
+            subfolder,
+            full_history: false,
+        })
+    }
 }
 
 impl TransformsValues for ImportCfg {
@@ -19,10 +69,13 @@ impl TransformsValues for ImportCfg {
         let uri = self.uri.transforms_values(render)?;
         let rev = self.rev.transforms_values(render)?;
         let subfolder = self.subfolder.transforms_values(render)?;
+        // the bindings are rendered with the answers (into the plan)
         Ok(ImportCfg {
             uri,
             rev,
             subfolder,
+            namespace: self.namespace.clone(),
+            variables: self.variables.clone(),
         })
     }
 }
@@ -0,0 +1,15 @@
+use schemars::JsonSchema;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+/// define how to migrate an answer given for an older version of the template
+/// (eg a variable renamed, or values of a `select_in_values` changed)
+pub(crate) struct MigrationCfg {
+    /// name of the variable (in the older version of the template)
+    pub variable: String,
+    /// new name of the variable (the value is moved to it, if it is not already defined)
+    pub rename_to: Option<String>,
+    /// replacements of older values by new values (as yaml, like the values from cli)
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+}
@@ -1,24 +1,59 @@
+mod ask_cfg;
+mod dotfiles_cfg;
+mod encoding_cfg;
+mod feature_cfg;
+mod flag_cfg;
+mod foreach_cfg;
 mod ignore_cfg;
 mod import_cfg;
+mod migration_cfg;
+mod rule_cfg;
 mod script_cfg;
+mod section_cfg;
 mod template_cfg;
 mod template_composite;
 mod transform_values;
+mod validation;
 mod variable_cfg;
 
+pub(crate) use import_cfg::ImportScope;
+pub(crate) use migration_cfg::MigrationCfg;
+pub(crate) use template_cfg::TemplateCfg;
 pub(crate) use template_composite::*;
+pub use validation::CfgIssue;
 
+use crate::error::Error;
+use crate::files::DotfilesFilter;
+use crate::manifest::Ownership;
 use crate::path_pattern::PathPattern;
+use crate::rules::Rule;
 use crate::scripts::Script;
 use crate::source_loc::SourceLoc;
-use crate::source_uri::SourceUri;
-use crate::variable_def::VariableDef;
+use crate::value_provider::ValueProvider;
+use crate::variable_def::{SectionDef, VariableDef};
 use crate::Result;
-use std::path::PathBuf;
+use ask_cfg::user_locale;
+use encoding_rs::Encoding;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-const TEMPLATE_CFG_FILENAME: &str = ".ffizer.yaml";
+/// names of the configuration file of a template, by precedence
+pub(crate) const TEMPLATE_CFG_FILENAMES: &[&str] = &[".ffizer.yaml", "ffizer.yaml", "ffizer.yml"];
+/// name of the variable with the selection of the features (a map of `<name>: <selected>`)
+pub const FEATURES_VARIABLE: &str = "features";
 pub const TEMPLATE_SAMPLES_DIRNAME: &str = ".ffizer.samples.d";
+/// marker file of the folders to create even if empty (git doesn't store empty folders), never copied
+pub const KEEPDIR_FILENAME: &str = ".ffizer.keepdir";
+/// ignored by default (except if `keep_default_ignores`)
+const DEFAULT_IGNORES: &[&str] = &["**/.git", "**/.hg", "**/.svn"];
+
+/// the configuration file of the template into `template_base` (`None` if not defined)
+pub(crate) fn find_cfg_file(template_base: &Path) -> Option<PathBuf> {
+    TEMPLATE_CFG_FILENAMES
+        .iter()
+        .map(|name| template_base.join(name))
+        .find(|path| path.is_file())
+}
 
 impl template_cfg::TemplateCfg {
     pub(crate) fn find_ignores(&self) -> Result<Vec<PathPattern>> {
@@ -28,15 +63,108 @@ impl template_cfg::TemplateCfg {
             .filter(|v| !v.is_empty())
             .map(|v| PathPattern::from_str(v.as_str()))
             .collect::<Result<Vec<PathPattern>>>()?;
-        let cfg_pattern = PathPattern::from_str(TEMPLATE_CFG_FILENAME)?;
+        let cfg_filename = self
+            .cfg_filename
+            .as_deref()
+            .unwrap_or(TEMPLATE_CFG_FILENAMES[0]);
+        let cfg_pattern = PathPattern::from_str(cfg_filename)?;
         ignores.push(cfg_pattern);
         let samples_pattern = PathPattern::from_str(TEMPLATE_SAMPLES_DIRNAME)?;
         ignores.push(samples_pattern);
+        let keepdir_pattern = PathPattern::from_str(&format!("**/{}", KEEPDIR_FILENAME))?;
+        ignores.push(keepdir_pattern);
+        if !self.keep_default_ignores {
+            for pattern in DEFAULT_IGNORES {
+                ignores.push(PathPattern::from_str(pattern)?);
+            }
+        }
         Ok(ignores)
     }
 
+    pub(crate) fn find_dotfiles_filter(&self) -> Result<DotfilesFilter> {
+        let to_patterns = |values: &[ignore_cfg::IgnoreCfg]| {
+            values
+                .iter()
+                .filter(|v| !v.is_empty())
+                .map(|v| PathPattern::from_str(v.as_str()))
+                .collect::<Result<Vec<PathPattern>>>()
+        };
+        Ok(DotfilesFilter {
+            include_all: self.dotfiles.mode == dotfiles_cfg::DotfilesMode::All,
+            includes: to_patterns(&self.dotfiles.includes)?,
+            excludes: to_patterns(&self.dotfiles.excludes)?,
+        })
+    }
+
+    pub(crate) fn has_disabled_imports(&self) -> bool {
+        self.imports.iter().any(|v| v.uri.trim().is_empty())
+    }
+
+    pub(crate) fn find_copy_raws(&self) -> Result<Vec<PathPattern>> {
+        self.copy_raw
+            .iter()
+            .filter(|v| !v.is_empty())
+            .map(|v| PathPattern::from_str(v.as_str()))
+            .collect::<Result<Vec<PathPattern>>>()
+    }
+
+    /// the encodings of the files matching the patterns
+    pub(crate) fn find_encodings(&self) -> Result<Vec<(PathPattern, &'static Encoding)>> {
+        self.encodings
+            .iter()
+            .filter(|v| !v.files.is_empty())
+            .map(|v| {
+                let encoding =
+                    Encoding::for_label(v.encoding.trim().as_bytes()).ok_or_else(|| {
+                        Error::UnknownEncoding {
+                            label: v.encoding.clone(),
+                        }
+                    })?;
+                Ok((PathPattern::from_str(v.files.as_str())?, encoding))
+            })
+            .collect()
+    }
+
     pub(crate) fn find_variabledefs(&self) -> Result<Vec<VariableDef>> {
-        self.variables.iter().map(|v| to_variabledef(v)).collect()
+        self.variables
+            .iter()
+            .map(|v| self.to_variabledef(v))
+            .collect()
+    }
+
+    /// the definition of the variable, with its section (the name is the title of an undeclared section)
+    fn to_variabledef(&self, v: &variable_cfg::VariableCfg) -> Result<VariableDef> {
+        let mut variable = to_variabledef(v)?;
+        variable.section = v
+            .section
+            .as_ref()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let cfg = self.sections.iter().find(|s| s.name.trim() == name);
+                SectionDef {
+                    name: name.to_owned(),
+                    title: cfg
+                        .and_then(|s| s.title.clone())
+                        .filter(|x| !x.is_empty())
+                        .unwrap_or_else(|| name.to_owned()),
+                    intro: cfg.and_then(|s| s.intro.clone()).filter(|x| !x.is_empty()),
+                }
+            });
+        Ok(variable)
+    }
+
+    pub(crate) fn find_rules(&self) -> Vec<Rule> {
+        self.rules
+            .iter()
+            .map(|v| Rule {
+                message: v.message.clone().filter(|x| !x.trim().is_empty()),
+                dst_empty: v.dst_empty,
+                dst_in_git: v.dst_in_git,
+                condition: v.condition.clone().filter(|x| !x.trim().is_empty()),
+                cmd: v.cmd.clone().filter(|x| !x.trim().is_empty()),
+            })
+            .collect()
     }
 
     pub(crate) fn find_scripts(&self) -> Result<Vec<Script>> {
@@ -50,27 +178,126 @@ impl template_cfg::TemplateCfg {
             .collect())
     }
 
-    pub(crate) fn find_sourcelocs(&self) -> Result<Vec<SourceLoc>> {
-        self.imports
+    pub(crate) fn find_featuredefs(&self) -> Result<Vec<FeatureDef>> {
+        self.features
             .iter()
+            .filter(|v| !v.name.trim().is_empty())
             .map(|v| {
-                let uri = SourceUri::from_str(v.uri.as_str())?;
-                let subfolder = v.subfolder.as_ref().map(|x| PathBuf::from(x.as_str()));
-                let rev = v.rev.as_deref().unwrap_or("master").to_owned();
-                Ok(SourceLoc {
-                    uri,
-                    rev,
-                    subfolder,
+                Ok(FeatureDef {
+                    name: v.name.trim().to_owned(),
+                    ask: v
+                        .ask
+                        .as_ref()
+                        .and_then(|a| a.for_locale(&user_locale()))
+                        .filter(|x| !x.is_empty()),
+                    default: v.default,
+                    files: v
+                        .files
+                        .iter()
+                        .filter(|f| !f.is_empty())
+                        .map(|f| PathPattern::from_str(f.as_str()))
+                        .collect::<Result<Vec<PathPattern>>>()?,
+                    variables: v
+                        .variables
+                        .iter()
+                        .map(|v| self.to_variabledef(v))
+                        .collect::<Result<Vec<VariableDef>>>()?,
                 })
             })
             .collect()
     }
+
+    pub(crate) fn find_flagdefs(&self) -> Vec<(String, String)> {
+        self.flags
+            .iter()
+            .map(|v| (v.name.clone(), v.value.clone()))
+            .collect()
+    }
+
+    pub(crate) fn find_foreachs(&self) -> Vec<(String, String)> {
+        self.foreach
+            .iter()
+            .map(|v| (v.item.clone(), v.in_list.clone()))
+            .collect()
+    }
+
+    /// patterns of the destination paths with an ownership (managed first)
+    pub(crate) fn find_ownerships(&self) -> Result<Vec<(PathPattern, Ownership)>> {
+        let managed = self.managed.iter().map(|v| (v, Ownership::Managed));
+        let user_owned = self.user_owned.iter().map(|v| (v, Ownership::UserOwned));
+        managed
+            .chain(user_owned)
+            .filter(|(v, _)| !v.is_empty())
+            .map(|(v, ownership)| Ok((PathPattern::from_str(v.as_str())?, ownership)))
+            .collect()
+    }
+
+    pub(crate) fn find_migrations(&self) -> Vec<MigrationCfg> {
+        self.migrations
+            .iter()
+            .filter(|v| !v.variable.trim().is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// imports with an empty uri (eg disabled by a condition) are ignored
+    pub(crate) fn find_sourcelocs(&self) -> Result<Vec<SourceLoc>> {
+        self.imports
+            .iter()
+            .filter(|v| !v.uri.trim().is_empty())
+            .map(|v| v.to_sourceloc())
+            .collect()
+    }
+
+    /// the scope (namespace, bindings) of the variables of the imports
+    pub(crate) fn find_import_scopes(&self) -> Result<Vec<(SourceLoc, ImportScope)>> {
+        self.imports
+            .iter()
+            .filter(|v| !v.uri.trim().is_empty())
+            .map(|v| Ok((v.to_sourceloc()?, v.to_scope())))
+            .collect()
+    }
+}
+
+/// the templates listed into the stack file (yaml), from the base to the top: a list of
+/// `uri`, `rev` (default `master`), `subfolder` (like the imports of a template)
+pub(crate) fn load_stack(path: &Path) -> Result<Vec<SourceLoc>> {
+    let content = std::fs::read_to_string(path).map_err(|source| Error::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let entries: Vec<import_cfg::ImportCfg> = serde_yaml::from_str(&content)?;
+    entries.iter().map(|v| v.to_sourceloc()).collect()
+}
+
+/// an optional part of the template (see `features` of the template configuration)
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FeatureDef {
+    pub name: String,
+    pub ask: Option<String>,
+    pub default: bool,
+    /// patterns of the paths (from the template) of the files and folders of the feature
+    pub files: Vec<PathPattern>,
+    /// the variables to ask if the feature is selected
+    pub variables: Vec<VariableDef>,
+}
+
+impl FeatureDef {
+    /// true if the path (relative to the template), or one of its folders, belongs to the feature
+    pub(crate) fn contains(&self, relative: &Path) -> bool {
+        relative.ancestors().any(|p| {
+            p.to_str()
+                .filter(|s| !s.is_empty())
+                .map(|s| self.files.iter().any(|f| f.is_match(s)))
+                .unwrap_or(false)
+        })
+    }
 }
 
 fn to_variabledef(v: &variable_cfg::VariableCfg) -> Result<VariableDef> {
     let hidden: bool = match v.hidden {
-        None => false,
-        Some(ref v) => serde_yaml::from_str(&v)?,
+        Some(ref v) if !v.trim().is_empty() => serde_yaml::from_str(&v)?,
+        _ => false,
     };
     let select_in_values: Vec<serde_yaml::Value> = match &v.select_in_values {
         None => vec![],
@@ -93,12 +320,28 @@ fn to_variabledef(v: &variable_cfg::VariableCfg) -> Result<VariableDef> {
     //     //dbg!(&s_values);
     //     s_values
 
+    let default_providers = v
+        .default_from_cmd
+        .iter()
+        .filter(|cmd| !cmd.trim().is_empty())
+        .map(|cmd| ValueProvider::Cmd(cmd.clone()))
+        .chain(v.default_from_dotenv.iter().map(|d| ValueProvider::Dotenv {
+            path: PathBuf::from(&d.path),
+            key: d.key.clone().unwrap_or_else(|| v.name.clone()),
+        }))
+        .collect();
+
     Ok(VariableDef {
         name: v.name.clone(),
         default_value: v.default_value.as_ref().map(|v| v.0.clone()),
-        ask: v.ask.clone(),
+        default_providers,
+        ask: v.ask.as_ref().and_then(|a| a.for_locale(&user_locale())),
         hidden,
+        secret: v.secret,
         select_in_values,
+        value_type: v.value_type.unwrap_or_default(),
+        namespace: None,
+        section: None,
     })
 }
 
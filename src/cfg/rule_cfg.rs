@@ -0,0 +1,37 @@
+use super::transform_values::TransformsValues;
+use crate::Result;
+use schemars::JsonSchema;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+/// define a rule checked after the questions (before any change of the destination), the apply is
+/// aborted with the message when the rule is violated
+pub(crate) struct RuleCfg {
+    /// message displayed when the rule is violated (handlebars template rendered with the variables)
+    pub message: Option<String>,
+    /// the destination folder should be empty (or not exist)
+    pub dst_empty: bool,
+    /// the destination folder should be into a git repository
+    pub dst_in_git: bool,
+    /// handlebars expression rendered as `true` or `false` (an empty result is `false`), should be `true`
+    pub condition: Option<String>,
+    /// command that should succeed (exit code 0), run into the destination folder (or the current folder
+    /// if the destination doesn't exist)
+    pub cmd: Option<String>,
+}
+
+impl TransformsValues for RuleCfg {
+    /// transforms message, condition & cmd
+    fn transforms_values<F>(&self, render: &F) -> Result<Self>
+    where
+        F: Fn(&str) -> String,
+    {
+        Ok(RuleCfg {
+            message: self.message.transforms_values(render)?,
+            dst_empty: self.dst_empty,
+            dst_in_git: self.dst_in_git,
+            condition: self.condition.transforms_values(render)?,
+            cmd: self.cmd.transforms_values(render)?,
+        })
+    }
+}
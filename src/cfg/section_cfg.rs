@@ -0,0 +1,12 @@
+use schemars::JsonSchema;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+/// define a group of variables, asked together after the title and the intro of the section
+pub(crate) struct SectionCfg {
+    /// name of the section, referenced by the `section` of the variables
+    pub name: String,
+    /// title displayed before the questions of the section (default to the name of the section)
+    pub title: Option<String>,
+    /// text displayed after the title (handlebars template rendered with the variables already defined)
+    pub intro: Option<String>,
+}
@@ -1,27 +1,71 @@
 use super::transform_values::TransformsValues;
+use super::validation;
 use crate::error::*;
 use schemars::JsonSchema;
 use std::fs;
 use std::path::Path;
 
+use super::dotfiles_cfg::DotfilesCfg;
+use super::encoding_cfg::EncodingCfg;
+use super::feature_cfg::FeatureCfg;
+use super::flag_cfg::FlagCfg;
+use super::foreach_cfg::ForeachCfg;
 use super::ignore_cfg::IgnoreCfg;
 use super::import_cfg::ImportCfg;
+use super::migration_cfg::MigrationCfg;
+use super::rule_cfg::RuleCfg;
 use super::script_cfg::ScriptCfg;
+use super::section_cfg::SectionCfg;
 use super::variable_cfg::VariableCfg;
 
 #[derive(Deserialize, Debug, Default, Clone, PartialEq, JsonSchema)]
 #[serde(deny_unknown_fields, default)]
 pub struct TemplateCfg {
+    /// requirement on the version of ffizer (semver, eg `">=2.2"`), checked before the rest of the configuration
+    pub(crate) ffizer_version: Option<String>,
+    /// short description of the template (displayed when the template is selected into the registry, and into the catalog)
+    pub(crate) description: Option<String>,
     /// list of variables/parameters of the template
     pub(crate) variables: Vec<VariableCfg>,
+    /// list of the sections of the variables (title and intro displayed before their questions)
+    pub(crate) sections: Vec<SectionCfg>,
+    /// list of boolean flags computed from variables (to share conditions between ignores, scripts, imports,...)
+    pub(crate) flags: Vec<FlagCfg>,
+    /// list of optional parts of the template (groups of files and variables), selected by the user
+    pub(crate) features: Vec<FeatureCfg>,
+    /// list of iterations over list variables (to generate a file or a folder for each value)
+    pub(crate) foreach: Vec<ForeachCfg>,
     /// list of path from the current template to ignore
     pub(crate) ignores: Vec<IgnoreCfg>,
+    /// set to true to not ignore the files excluded by the `.gitignore` files of the template
+    pub(crate) keep_gitignored: bool,
+    /// set to true to not ignore the folders of version control (`.git`, `.hg`, `.svn`) of the template
+    pub(crate) keep_default_ignores: bool,
+    /// selection of the hidden files and folders (name starting with `.`) of the template
+    pub(crate) dotfiles: DotfilesCfg,
+    /// list of path from the current template to copy as is (without rendering of the content)
+    pub(crate) copy_raw: Vec<IgnoreCfg>,
+    /// list of the encodings of the text files not encoded in UTF-8
+    pub(crate) encodings: Vec<EncodingCfg>,
+    /// list of path (in the destination) of the files regenerated (overridden) on every apply of the template
+    pub(crate) managed: Vec<IgnoreCfg>,
+    /// list of path (in the destination) of the files created once, then never updated by the template
+    pub(crate) user_owned: Vec<IgnoreCfg>,
+    /// list of migrations of answers (given for older versions of the template)
+    pub(crate) migrations: Vec<MigrationCfg>,
     /// list of template to import and to apply as part of this template
     pub(crate) imports: Vec<ImportCfg>,
+    /// list of the rules checked after the questions (before any change of the destination), the apply
+    /// is aborted with the message of the first rule violated
+    pub(crate) rules: Vec<RuleCfg>,
     /// list of the scripts to apply at end of generation
     pub(crate) scripts: Vec<ScriptCfg>,
     /// set to true if the template content is under a `template` folder (not mixed with metadata)
     pub(crate) use_template_dir: bool,
+    /// name of the file of the configuration (into the folder of the template)
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub(crate) cfg_filename: Option<String>,
 }
 
 impl TemplateCfg {
@@ -34,14 +78,42 @@ impl TemplateCfg {
     }
 
     pub fn from_template_folder(template_base: &Path) -> Result<TemplateCfg> {
-        let cfg_path = template_base.join(super::TEMPLATE_CFG_FILENAME);
-        if cfg_path.exists() {
-            let cfg_str = fs::read_to_string(cfg_path)?;
-            Self::from_str(cfg_str)
+        if let Some(cfg_path) = super::find_cfg_file(template_base) {
+            let cfg_str = fs::read_to_string(&cfg_path)?;
+            validation::check_ffizer_version(&cfg_path, &cfg_str)?;
+            let issues = validation::validate(&cfg_path, &cfg_str);
+            if !issues.is_empty() {
+                return Err(Error::InvalidTemplateCfg {
+                    path: cfg_path,
+                    issues: issues
+                        .iter()
+                        .map(|i| i.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                });
+            }
+            let mut cfg = Self::from_str(cfg_str)?;
+            cfg.cfg_filename = cfg_path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned());
+            Ok(cfg)
         } else {
             Ok(TemplateCfg::default())
         }
     }
+
+    /// the issues of the configuration of the template (empty if valid or not defined)
+    pub fn lint(template_base: &Path) -> Result<Vec<validation::CfgIssue>> {
+        if let Some(cfg_path) = super::find_cfg_file(template_base) {
+            let cfg_str = fs::read_to_string(&cfg_path).map_err(|source| Error::ReadFile {
+                path: cfg_path.clone(),
+                source,
+            })?;
+            Ok(validation::validate(&cfg_path, &cfg_str))
+        } else {
+            Ok(vec![])
+        }
+    }
 }
 
 impl TransformsValues for TemplateCfg {
@@ -50,22 +122,61 @@ impl TransformsValues for TemplateCfg {
     where
         F: Fn(&str) -> String,
     {
-        let variables = self.variables.clone();
+        // only `hidden` is rendered before the questions (eg to hide a variable depending on
+        // `ffizer.target_os`), the default values are rendered when asked (with the previous answers)
+        let variables = self
+            .variables
+            .iter()
+            .map(|v| {
+                Ok(VariableCfg {
+                    hidden: v.hidden.transforms_values(render)?,
+                    ..v.clone()
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let sections = self.sections.clone();
+        let flags = self.flags.clone();
+        let features = self.features.transforms_values(render)?;
+        let foreach = self.foreach.clone();
+        let migrations = self.migrations.clone();
         let ignores = self.ignores.transforms_values(render)?;
+        let dotfiles = self.dotfiles.transforms_values(render)?;
+        let copy_raw = self.copy_raw.transforms_values(render)?;
+        let encodings = self.encodings.transforms_values(render)?;
+        let managed = self.managed.transforms_values(render)?;
+        let user_owned = self.user_owned.transforms_values(render)?;
         let imports = self.imports.transforms_values(render)?;
+        let rules = self.rules.transforms_values(render)?;
         let scripts = self.scripts.transforms_values(render)?;
         Ok(TemplateCfg {
+            ffizer_version: self.ffizer_version.clone(),
+            description: self.description.clone(),
             variables,
+            sections,
+            flags,
+            features,
+            foreach,
             ignores,
+            keep_gitignored: self.keep_gitignored,
+            keep_default_ignores: self.keep_default_ignores,
+            dotfiles,
+            copy_raw,
+            encodings,
+            managed,
+            user_owned,
+            migrations,
             imports,
+            rules,
             scripts,
             use_template_dir: self.use_template_dir,
+            cfg_filename: self.cfg_filename.clone(),
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::ask_cfg::AskCfg;
     use super::super::variable_cfg::VariableValueCfg;
     use super::*;
     use pretty_assertions::assert_eq;
@@ -184,6 +295,7 @@ mod tests {
             - name: k1
               default_value: to_transform
             - name: k3
+              hidden: to_transform
         scripts:
             - cmd: hello to_transform
         "#;
@@ -197,6 +309,7 @@ mod tests {
             - name: k1
               default_value: to_transform
             - name: k3
+              hidden: transformed
         scripts:
             - cmd: hello transformed
         "#;
@@ -225,6 +338,149 @@ mod tests {
         assert_that!(&actual.use_template_dir).is_true();
     }
 
+    #[test]
+    fn test_deserialize_cfg_yaml_flags() {
+        let cfg_str = r#"
+        flags:
+            - name: is_lib
+              value: '{{ eq kind "library" }}'
+        "#;
+        let actual = serde_yaml::from_str::<TemplateCfg>(&cfg_str).unwrap();
+        assert_that!(&actual.flags).is_equal_to(&vec![FlagCfg {
+            name: "is_lib".to_owned(),
+            value: "{{ eq kind \"library\" }}".to_owned(),
+        }]);
+    }
+
+    #[test]
+    fn test_deserialize_cfg_yaml_features() {
+        let cfg_str = r#"
+        features:
+            - name: docker
+              ask: Dockerfile and docker-compose
+              files:
+                - Dockerfile
+                - docker-compose.yml
+              variables:
+                - name: docker_image
+            - name: ci
+              default: true
+        "#;
+        let actual = serde_yaml::from_str::<TemplateCfg>(&cfg_str).unwrap();
+        assert_that!(&actual.features).is_equal_to(&vec![
+            FeatureCfg {
+                name: "docker".to_owned(),
+                ask: Some(AskCfg::Message("Dockerfile and docker-compose".to_owned())),
+                default: false,
+                files: vec!["Dockerfile".to_owned(), "docker-compose.yml".to_owned()],
+                variables: vec![VariableCfg {
+                    name: "docker_image".to_owned(),
+                    ..Default::default()
+                }],
+            },
+            FeatureCfg {
+                name: "ci".to_owned(),
+                default: true,
+                ..Default::default()
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_deserialize_cfg_yaml_rules() -> Result<(), Box<dyn std::error::Error>> {
+        let cfg_str = r#"
+        rules:
+            - dst_empty: true
+              message: "use an empty folder"
+            - condition: '{{ ne project "test" }}'
+            - cmd: "cargo search {{ project }}"
+        "#;
+        let actual = serde_yaml::from_str::<TemplateCfg>(&cfg_str)?;
+        assert_that!(&actual.rules).is_equal_to(&vec![
+            RuleCfg {
+                dst_empty: true,
+                message: Some("use an empty folder".to_owned()),
+                ..Default::default()
+            },
+            RuleCfg {
+                condition: Some("{{ ne project \"test\" }}".to_owned()),
+                ..Default::default()
+            },
+            RuleCfg {
+                cmd: Some("cargo search {{ project }}".to_owned()),
+                ..Default::default()
+            },
+        ]);
+        let render = |v: &str| v.replace("{{ project }}", "foo");
+        let rendered = actual.transforms_values(&render)?;
+        assert_that!(&rendered.rules[2].cmd).is_equal_to(Some("cargo search foo".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_cfg_yaml_sections() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::variable_def::SectionDef;
+
+        let cfg_str = r#"
+        sections:
+            - name: db
+              title: Database
+              intro: "the connection of {{ project }} to its database"
+        variables:
+            - name: project
+            - name: db_host
+              section: db
+            - name: db_port
+              section: db
+            - name: ci_provider
+              section: CI
+        "#;
+        let actual = serde_yaml::from_str::<TemplateCfg>(&cfg_str)?;
+        assert_that!(&actual.sections).is_equal_to(&vec![SectionCfg {
+            name: "db".to_owned(),
+            title: Some("Database".to_owned()),
+            intro: Some("the connection of {{ project }} to its database".to_owned()),
+        }]);
+        let variables = actual.find_variabledefs()?;
+        let found = variables
+            .iter()
+            .map(|v| (v.name.as_str(), v.section.clone()))
+            .collect::<Vec<_>>();
+        let db = SectionDef {
+            name: "db".to_owned(),
+            title: "Database".to_owned(),
+            intro: Some("the connection of {{ project }} to its database".to_owned()),
+        };
+        assert_that!(found).is_equal_to(vec![
+            ("project", None),
+            ("db_host", Some(db.clone())),
+            ("db_port", Some(db)),
+            (
+                "ci_provider",
+                Some(SectionDef {
+                    name: "CI".to_owned(),
+                    title: "CI".to_owned(),
+                    intro: None,
+                }),
+            ),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_cfg_yaml_foreach() {
+        let cfg_str = r#"
+        foreach:
+            - item: module
+              in: modules
+        "#;
+        let actual = serde_yaml::from_str::<TemplateCfg>(&cfg_str).unwrap();
+        assert_that!(&actual.foreach).is_equal_to(&vec![ForeachCfg {
+            item: "module".to_owned(),
+            in_list: "modules".to_owned(),
+        }]);
+    }
+
     #[test]
     fn test_accept_ignores_with_values() {
         let cfg_in_str = r#"
@@ -236,4 +492,39 @@ mod tests {
         "#;
         TemplateCfg::from_str(&cfg_in_str).unwrap();
     }
+
+    #[test]
+    fn test_from_template_folder_with_other_filename() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        fs::write(
+            tmp_dir.path().join("ffizer.yml"),
+            "variables:\n  - name: k1\n",
+        )?;
+        let cfg = TemplateCfg::from_template_folder(tmp_dir.path())?;
+        assert_that!(cfg.variables).has_length(1);
+        assert_that!(cfg.cfg_filename).is_equal_to(Some("ffizer.yml".to_owned()));
+        let ignores = cfg.find_ignores()?;
+        assert_that!(ignores.iter().any(|p| p.is_match("ffizer.yml"))).is_true();
+        assert_that!(ignores.iter().any(|p| p.is_match(".ffizer.yaml"))).is_false();
+        assert_that!(ignores.iter().any(|p| p.is_match(".ffizer.keepdir"))).is_true();
+        assert_that!(ignores.iter().any(|p| p.is_match("logs/.ffizer.keepdir"))).is_true();
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_template_folder_requires_ffizer_version() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let tmp_dir = tempfile::tempdir()?;
+        let cfg_path = tmp_dir.path().join(".ffizer.yaml");
+        fs::write(&cfg_path, "ffizer_version: \">=0.1\"\n")?;
+        assert_that!(TemplateCfg::from_template_folder(tmp_dir.path())?.ffizer_version)
+            .is_equal_to(Some(">=0.1".to_owned()));
+        // fields of a newer version are not reported as unknown
+        fs::write(&cfg_path, "ffizer_version: \">=999\"\nnew_field: true\n")?;
+        assert!(matches!(
+            TemplateCfg::from_template_folder(tmp_dir.path()),
+            Err(Error::IncompatibleFfizerVersion { .. })
+        ));
+        Ok(())
+    }
 }
@@ -1,15 +1,23 @@
+use super::migration_cfg::MigrationCfg;
 use super::template_cfg::TemplateCfg;
 use super::transform_values::TransformsValues;
-use crate::files;
+use super::FeatureDef;
+use super::ImportScope;
+use crate::error::Error;
+use crate::files::{self, ChildPath};
 use crate::graph::Graph;
+use crate::hbs_helpers::new_hbs;
+use crate::lockfile::Lockfile;
+use crate::manifest::Ownership;
+use crate::path_pattern::PathPattern;
+use crate::rules::Rule;
 use crate::scripts::Script;
-use crate::source_file::SourceFile;
+use crate::source_file::{SourceFile, SourceFileMetadata};
 use crate::source_loc::SourceLoc;
 use crate::variable_def::VariableDef;
 use crate::Ctx;
 use crate::Result;
 use crate::Variables;
-use handlebars_misc_helpers::new_hbs;
 use slog::{debug, warn};
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -19,6 +27,12 @@ pub struct TemplateLayer {
     order: usize,
     loc: SourceLoc,
     cfg: TemplateCfg,
+    /// prefix of the variables of the template (the namespaces of the imports are nested)
+    namespace: Option<String>,
+    /// values of the variables of the template, defined by the importing template
+    bindings: Vec<(String, String)>,
+    /// order of the importing layer
+    parent: Option<usize>,
 }
 
 impl TransformsValues for TemplateLayer {
@@ -31,6 +45,9 @@ impl TransformsValues for TemplateLayer {
             order: self.order,
             loc: self.loc.clone(),
             cfg,
+            namespace: self.namespace.clone(),
+            bindings: self.bindings.clone(),
+            parent: self.parent,
         })
     }
 }
@@ -40,6 +57,12 @@ pub struct TemplateComposite {
     layers: Vec<TemplateLayer>,
 }
 
+impl PartialEq for TemplateLayer {
+    fn eq(&self, other: &Self) -> bool {
+        self.order == other.order && self.loc == other.loc
+    }
+}
+
 impl TemplateComposite {
     pub fn from_src(
         ctx: &Ctx,
@@ -47,35 +70,211 @@ impl TemplateComposite {
         offline: bool,
         src: &SourceLoc,
     ) -> Result<TemplateComposite> {
+        Self::from_srcs(ctx, variables, offline, std::slice::from_ref(src))
+    }
+
+    /// the composite of the templates `srcs` stacked (with their imports), the later templates
+    /// override the earlier ones
+    pub fn from_srcs(
+        ctx: &Ctx,
+        variables: &Variables,
+        offline: bool,
+        srcs: &[SourceLoc],
+    ) -> Result<TemplateComposite> {
+        let lockfile = if ctx.cmd_opt.locked {
+            Some(Lockfile::load(&ctx.cmd_opt.dst_folder)?.unwrap_or_default())
+        } else {
+            None
+        };
         let mut templates = HashMap::new();
-        deep_download(ctx, variables, offline, src, &mut templates)?;
-        let layers = templates
-            .find_edges_ordered_by_depth(src)
-            .into_iter()
-            .enumerate()
-            .map(|(i, k)| {
-                let v = templates.get(&k).expect("should exist").clone();
-                TemplateLayer {
-                    order: i,
-                    loc: k,
-                    cfg: v,
+        let mut scopes = HashMap::new();
+        let mut locs: Vec<SourceLoc> = vec![];
+        for src in srcs.iter().rev() {
+            let src = &lock(lockfile.as_ref(), src)?;
+            deep_download(
+                ctx,
+                variables,
+                offline,
+                src,
+                lockfile.as_ref(),
+                &mut templates,
+                &mut scopes,
+            )?;
+            for loc in templates.find_edges_ordered_by_depth(src) {
+                if !locs.contains(&loc) {
+                    locs.push(loc);
                 }
-            })
-            .collect::<Vec<_>>();
+            }
+        }
+        let mut layers: Vec<TemplateLayer> = Vec::with_capacity(locs.len());
+        for (order, loc) in locs.iter().enumerate() {
+            let cfg = templates.get(loc).expect("should exist").clone();
+            let (parent, scope) = match scopes.get(loc) {
+                // the importing layer is before the imported one
+                Some((parent_loc, scope)) => (
+                    locs[..order].iter().position(|l| l == parent_loc),
+                    scope.clone(),
+                ),
+                None => (None, ImportScope::default()),
+            };
+            let parent_namespace = parent.and_then(|p| layers[p].namespace.clone());
+            let namespace = match (parent_namespace, scope.namespace) {
+                (Some(p), Some(n)) => Some(format!("{}.{}", p, n)),
+                (p, n) => n.or(p),
+            };
+            layers.push(TemplateLayer {
+                order,
+                loc: loc.clone(),
+                cfg,
+                namespace,
+                bindings: scope.bindings,
+                parent,
+            });
+        }
         debug!(ctx.logger, "templates"; "layers" => ?layers);
         Ok(TemplateComposite { layers })
     }
 
+    /// true if some imports are disabled (empty uri), they could be enabled by variables not yet defined
+    pub fn has_disabled_imports(&self) -> bool {
+        self.layers.iter().any(|l| l.cfg.has_disabled_imports())
+    }
+
+    /// true if both composites are made of the same templates (in the same order)
+    pub fn has_same_layers(&self, other: &TemplateComposite) -> bool {
+        self.layers == other.layers
+    }
+
+    /// the templates of the composite (the template itself and its imports)
+    pub fn find_locs(&self) -> Vec<&SourceLoc> {
+        self.layers.iter().map(|l| &l.loc).collect()
+    }
+
+    /// the features of the templates (the first feature of a name is kept)
+    pub fn find_featuredefs(&self) -> Result<Vec<FeatureDef>> {
+        let mut back = vec![];
+        let mut names = HashSet::new();
+        for layer in &self.layers {
+            for feature in layer.cfg.find_featuredefs()? {
+                if !names.contains(&feature.name) {
+                    names.insert(feature.name.clone());
+                    back.push(feature);
+                }
+            }
+        }
+        Ok(back)
+    }
+
+    pub fn find_flagdefs(&self) -> Vec<(String, String)> {
+        let mut back = vec![];
+        let mut names = HashSet::new();
+        for layer in &self.layers {
+            for (name, value) in layer.cfg.find_flagdefs() {
+                if !names.contains(&name) {
+                    names.insert(name.clone());
+                    back.push((name, value));
+                }
+            }
+        }
+        back
+    }
+
+    /// patterns of the destination paths with an ownership (the first matching pattern wins)
+    pub fn find_ownerships(&self) -> Result<Vec<(PathPattern, Ownership)>> {
+        let mut back = vec![];
+        for layer in &self.layers {
+            back.extend(layer.cfg.find_ownerships()?);
+        }
+        Ok(back)
+    }
+
+    /// migrations of answers (the first migration of a variable is kept)
+    pub fn find_migrations(&self) -> Vec<MigrationCfg> {
+        let mut back = vec![];
+        let mut names = HashSet::new();
+        for layer in &self.layers {
+            for migration in layer.cfg.find_migrations() {
+                if !names.contains(&migration.variable) {
+                    names.insert(migration.variable.clone());
+                    back.push(migration);
+                }
+            }
+        }
+        back
+    }
+
+    /// list of (item, list) to iterate over
+    pub fn find_foreachs(&self) -> Vec<(String, String)> {
+        let mut back = vec![];
+        let mut names = HashSet::new();
+        for layer in &self.layers {
+            for (item, list) in layer.cfg.find_foreachs() {
+                if !names.contains(&item) {
+                    names.insert(item.clone());
+                    back.push((item, list));
+                }
+            }
+        }
+        back
+    }
+
+    /// the variables to ask (the first variable of a name is kept), the variables of a namespaced
+    /// template are prefixed by its namespace, the variables bound by the importing template are not asked
     pub fn find_variabledefs(&self) -> Result<Vec<VariableDef>> {
         let mut back = vec![];
         let mut names = HashSet::new();
         for layer in &self.layers {
-            for variable in layer.cfg.find_variabledefs()? {
+            for mut variable in layer.cfg.find_variabledefs()? {
+                if layer
+                    .bindings
+                    .iter()
+                    .any(|(name, _)| name == &variable.name)
+                {
+                    continue;
+                }
+                if let Some(namespace) = &layer.namespace {
+                    variable.name = format!("{}.{}", namespace, variable.name);
+                    variable.namespace = Some(namespace.clone());
+                    if let Some(section) = variable.section.as_mut() {
+                        section.name = format!("{}.{}", namespace, section.name);
+                    }
+                }
                 if !names.contains(&variable.name) {
                     names.insert(variable.name.clone());
-                    back.push(variable.clone());
+                    back.push(variable);
+                }
+            }
+        }
+        Ok(back)
+    }
+
+    /// the variables specific to each layer (by order): the variables of its namespace (without prefix)
+    /// and its bound variables (rendered with the variables of the importing layer)
+    pub fn find_layer_variables(&self, variables: &Variables) -> Result<Vec<Variables>> {
+        let handlebars = new_hbs();
+        let mut back: Vec<Variables> = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            let mut layer_variables = match &layer.namespace {
+                Some(namespace) => variables.find_namespaced(namespace),
+                None => Variables::default(),
+            };
+            if !layer.bindings.is_empty() {
+                let mut importer_variables = variables.clone();
+                if let Some(parent_variables) = layer.parent.and_then(|p| back.get(p)) {
+                    importer_variables.append(&mut parent_variables.clone());
+                }
+                for (name, template) in &layer.bindings {
+                    let value = handlebars
+                        .render_template(template, &importer_variables)
+                        .map_err(|source| Error::Handlebars {
+                            when: format!("bind variable '{}' of {}", name, layer.loc.uri.raw),
+                            template: template.clone(),
+                            source,
+                        })?;
+                    layer_variables.insert(name.as_str(), Variables::value_from_str(&value)?)?;
                 }
             }
+            back.push(layer_variables);
         }
         Ok(back)
     }
@@ -84,19 +283,75 @@ impl TemplateComposite {
         let mut back = vec![];
         for layer in &self.layers {
             let ignores = &layer.cfg.find_ignores()?;
+            let copy_raws = &layer.cfg.find_copy_raws()?;
+            let encodings = &layer.cfg.find_encodings()?;
             let template_dir = if layer.cfg.use_template_dir {
                 "template"
             } else {
                 ""
             };
             let path = layer.loc.as_local_path()?.join(template_dir);
-            for childpath in files::find_childpaths(path, ignores) {
-                back.push(SourceFile::from((childpath, layer.order)));
+            let dotfiles = &layer.cfg.find_dotfiles_filter()?;
+            for childpath in
+                files::find_childpaths(path, ignores, !layer.cfg.keep_gitignored, dotfiles)
+            {
+                let is_copy_raw = childpath
+                    .relative
+                    .to_str()
+                    .map(|s| copy_raws.iter().any(|f| f.is_match(s)))
+                    .unwrap_or(false);
+                let file_encoding = childpath.relative.to_str().and_then(|s| {
+                    encodings
+                        .iter()
+                        .find(|(f, _)| f.is_match(s))
+                        .map(|(_, encoding)| *encoding)
+                });
+                let mut source_file = SourceFile::from((childpath, layer.order));
+                if is_copy_raw && source_file.metadata != SourceFileMetadata::Dir {
+                    source_file.metadata = SourceFileMetadata::RawFile;
+                }
+                if let SourceFileMetadata::RenderableFile { encoding, .. } =
+                    &mut source_file.metadata
+                {
+                    *encoding = file_encoding;
+                }
+                back.push(source_file);
             }
         }
         Ok(back)
     }
 
+    /// the files and folders of the templates ignored by a pattern of their `ignores`, with the pattern
+    pub fn find_ignored_sourcefiles(&self) -> Result<Vec<(ChildPath, String)>> {
+        let mut back = vec![];
+        for layer in &self.layers {
+            let ignores = layer.cfg.find_ignores()?;
+            let template_dir = if layer.cfg.use_template_dir {
+                "template"
+            } else {
+                ""
+            };
+            let path = layer.loc.as_local_path()?.join(template_dir);
+            // only the patterns defined by the template (not the configuration, the samples,...)
+            back.extend(
+                files::find_ignored_childpaths(path, &ignores)
+                    .into_iter()
+                    .filter(|(_, pattern)| {
+                        layer.cfg.ignores.iter().any(|i| i.trim() == pattern.raw)
+                    })
+                    .map(|(childpath, pattern)| (childpath, pattern.raw.clone())),
+            );
+        }
+        Ok(back)
+    }
+
+    pub fn find_rules(&self) -> Vec<(&SourceLoc, Vec<Rule>)> {
+        self.layers
+            .iter()
+            .map(|t| (&t.loc, t.cfg.find_rules()))
+            .collect()
+    }
+
     pub fn find_scripts(&self) -> Result<Vec<(&SourceLoc, Vec<Script>)>> {
         self.layers
             .iter()
@@ -123,27 +378,55 @@ fn deep_download(
     variables: &Variables,
     offline: bool,
     src: &SourceLoc,
+    lockfile: Option<&Lockfile>,
     templates: &mut HashMap<SourceLoc, TemplateCfg>,
+    scopes: &mut HashMap<SourceLoc, (SourceLoc, ImportScope)>,
 ) -> Result<()> {
     if !templates.contains_key(src) {
-        let template_base_path = &src.download(&ctx.logger, offline)?;
+        ctx.cancel.check("retrieving templates")?;
+        let template_base_path =
+            &src.download_with(&ctx.logger, offline, !ctx.cmd_opt.no_interaction)?;
         // update cfg with variables defined by user
         let mut template_cfg = TemplateCfg::from_template_folder(&template_base_path)?;
         // update cfg with variables defined by cli (use to update default_value)
         let mut variables_children = variables.clone();
         variables_children.insert("ffizer_src_uri", src.uri.raw.clone())?;
         variables_children.insert("ffizer_src_rev", src.rev.clone())?;
+        variables_children.insert_ffizer("src_uri", src.uri.raw.clone())?;
+        variables_children.insert_ffizer("src_rev", src.rev.clone())?;
         //variables_children.insert("ffizer_src_subfolder".to_owned(), src.subfolder.clone());
         template_cfg = render_cfg(&ctx, &template_cfg, &variables_children, false)?;
-        let children = template_cfg.find_sourcelocs()?;
+        let children = template_cfg.find_import_scopes()?;
         templates.insert(src.clone(), template_cfg);
-        for child in children {
-            deep_download(ctx, &variables_children, offline, &child, templates)?;
+        for (mut child, scope) in children {
+            // the imports are cloned like the template
+            child.full_history = src.full_history;
+            let child = lock(lockfile, &child)?;
+            // the first import of a template defines its scope
+            scopes
+                .entry(child.clone())
+                .or_insert_with(|| (src.clone(), scope));
+            deep_download(
+                ctx,
+                &variables_children,
+                offline,
+                &child,
+                lockfile,
+                templates,
+                scopes,
+            )?;
         }
     }
     Ok(())
 }
 
+fn lock(lockfile: Option<&Lockfile>, src: &SourceLoc) -> Result<SourceLoc> {
+    match lockfile {
+        Some(lockfile) => lockfile.lock(src),
+        None => Ok(src.clone()),
+    }
+}
+
 impl TransformsValues for TemplateComposite {
     /// transforms ignore, imports
     fn transforms_values<F>(&self, render: &F) -> Result<Self>
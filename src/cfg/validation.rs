@@ -0,0 +1,397 @@
+//! validation of the `.ffizer.yaml` of a template, with the location (file, line) of the issues
+//! and a suggestion when possible (eg for a misspelled field).
+
+use super::template_cfg::TemplateCfg;
+use super::variable_cfg::VariableCfg;
+use crate::error::*;
+use crate::path_pattern::PathPattern;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CfgIssue {
+    pub path: PathBuf,
+    /// line (1-based) of the issue, if found
+    pub line: Option<usize>,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for CfgIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{}: {}",
+                self.path.to_string_lossy(),
+                line,
+                self.message
+            )?,
+            None => write!(f, "{}: {}", self.path.to_string_lossy(), self.message)?,
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n  help: {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// check the requirement `ffizer_version` of the configuration `content` (read from `path`)
+/// against the version of the running ffizer (the pre-release of the running version is ignored),
+/// before the parsing of the rest of the configuration (fields unknown by older versions)
+pub(crate) fn check_ffizer_version(path: &Path, content: &str) -> Result<()> {
+    let required = match serde_yaml::from_str::<serde_yaml::Value>(content)
+        .ok()
+        .as_ref()
+        .and_then(|doc| doc.get("ffizer_version"))
+    {
+        Some(serde_yaml::Value::String(s)) => s.clone(),
+        Some(serde_yaml::Value::Number(n)) => n.to_string(),
+        _ => return Ok(()),
+    };
+    let current = env!("CARGO_PKG_VERSION");
+    let mut version = semver::Version::parse(current).map_err(|e| Error::Unknown(e.to_string()))?;
+    version.pre.clear();
+    let req = semver::VersionReq::parse(&required).map_err(|e| Error::InvalidTemplateCfg {
+        path: path.to_path_buf(),
+        issues: format!("invalid ffizer_version {:?}: {}", required, e),
+    })?;
+    if req.matches(&version) {
+        Ok(())
+    } else {
+        Err(Error::IncompatibleFfizerVersion {
+            path: path.to_path_buf(),
+            required,
+            current: current.to_owned(),
+        })
+    }
+}
+
+/// the issues of the configuration `content` (read from `path`), empty if valid
+pub(crate) fn validate(path: &Path, content: &str) -> Vec<CfgIssue> {
+    let issue = |line: Option<usize>, message: String, suggestion: Option<String>| CfgIssue {
+        path: path.to_path_buf(),
+        line,
+        message,
+        suggestion,
+    };
+    if let Err(err) = check_ffizer_version(path, content) {
+        let message = match err {
+            Error::InvalidTemplateCfg { issues, .. } => issues,
+            err => err.to_string(),
+        };
+        return vec![issue(
+            find_line(content, "ffizer_version:", 1),
+            message,
+            None,
+        )];
+    }
+    let cfg = match TemplateCfg::from_str(content) {
+        Ok(cfg) => cfg,
+        Err(Error::SerdeYaml { source }) => {
+            let line = source.location().map(|l| l.line());
+            let message = source.to_string();
+            // the location is reported as line
+            let message = match message.find(" at line ") {
+                Some(i) if line.is_some() => message[..i].to_owned(),
+                _ => message,
+            };
+            let suggestion = suggest_field(&message);
+            return vec![issue(line, message, suggestion)];
+        }
+        Err(err) => return vec![issue(None, err.to_string(), None)],
+    };
+    let mut issues = vec![];
+
+    // the variables accept unknown fields (ignored), often a misspelled field
+    if let Ok(doc) = serde_yaml::from_str::<serde_yaml::Value>(content) {
+        let features = doc.get("features").and_then(|v| v.as_sequence());
+        let variables = doc
+            .get("variables")
+            .and_then(|v| v.as_sequence())
+            .into_iter()
+            .chain(
+                features
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|f| f.get("variables").and_then(|v| v.as_sequence())),
+            )
+            .flatten()
+            .filter_map(|v| v.as_mapping());
+        for variable in variables {
+            for key in variable.iter().filter_map(|(k, _)| k.as_str()) {
+                if !VARIABLE_FIELDS.contains(&key) {
+                    issues.push(issue(
+                        find_line(content, &format!("{}:", key), 1),
+                        format!("unknown field `{}` of variable", key),
+                        suggest(key, VARIABLE_FIELDS.iter().copied()),
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut names = HashSet::new();
+    let mut occurrences = HashMap::new();
+    for v in &cfg.variables {
+        let nth = occurrences.entry(v.name.as_str()).or_insert(0);
+        *nth += 1;
+        if !names.insert(v.name.as_str()) {
+            issues.push(issue(
+                find_line(content, &format!("name: {}", v.name), *nth),
+                format!("duplicate variable {:?}", v.name),
+                Some("rename or remove one of the definitions".to_owned()),
+            ));
+        }
+    }
+    if !cfg.sections.is_empty() {
+        let sections = cfg
+            .sections
+            .iter()
+            .map(|s| s.name.trim())
+            .collect::<HashSet<_>>();
+        let unknowns = cfg
+            .variables
+            .iter()
+            .chain(cfg.features.iter().flat_map(|f| f.variables.iter()))
+            .filter_map(|v| v.section.as_deref().map(str::trim))
+            .filter(|s| !s.is_empty() && !sections.contains(s));
+        for unknown in unknowns {
+            issues.push(issue(
+                find_line(content, &format!("section: {}", unknown), 1),
+                format!("unknown section {:?} of variable", unknown),
+                suggest(unknown, sections.iter().copied())
+                    .or_else(|| Some("declare the section into `sections`".to_owned())),
+            ));
+        }
+    }
+    for f in &cfg.flags {
+        if names.contains(f.name.as_str()) {
+            issues.push(issue(
+                find_line(content, &format!("name: {}", f.name), 2),
+                format!("flag {:?} has the same name as a variable", f.name),
+                Some("rename the flag or the variable".to_owned()),
+            ));
+        }
+    }
+
+    let globs = cfg
+        .ignores
+        .iter()
+        .chain(cfg.copy_raw.iter())
+        .chain(cfg.encodings.iter().map(|e| &e.files))
+        .chain(cfg.managed.iter())
+        .chain(cfg.user_owned.iter())
+        .chain(cfg.features.iter().flat_map(|f| f.files.iter()))
+        // rendered before use
+        .filter(|v| !v.is_empty() && !v.contains("{{"));
+    for glob in globs {
+        if let Err(err) = PathPattern::from_str(glob) {
+            let message = match &err {
+                Error::ParsePathPattern { value, source } => {
+                    format!("invalid glob {:?}: {}", value, source)
+                }
+                _ => err.to_string(),
+            };
+            issues.push(issue(
+                find_line(content, glob, 1),
+                message,
+                Some("see https://docs.rs/globset for the syntax of globs".to_owned()),
+            ));
+        }
+    }
+
+    for e in &cfg.encodings {
+        if encoding_rs::Encoding::for_label(e.encoding.trim().as_bytes()).is_none() {
+            issues.push(issue(
+                find_line(content, &format!("encoding: {}", e.encoding), 1),
+                format!("unknown encoding {:?} of {:?}", e.encoding, e.files),
+                Some(
+                    "see https://encoding.spec.whatwg.org/#names-and-labels for the labels"
+                        .to_owned(),
+                ),
+            ));
+        }
+    }
+
+    let variables = cfg
+        .variables
+        .iter()
+        .chain(cfg.features.iter().flat_map(|f| f.variables.iter()));
+    for v in variables {
+        for (field, value) in templates_of_variable(v) {
+            if let Err(err) = handlebars::Template::compile(&value) {
+                issues.push(issue(
+                    value.lines().next().and_then(|l| find_line(content, l, 1)),
+                    format!("invalid handlebars into {} of {:?}: {}", field, v.name, err),
+                    Some("check the `{{` and `}}`, and the closing of the blocks".to_owned()),
+                ));
+            }
+        }
+    }
+    for f in &cfg.flags {
+        if let Err(err) = handlebars::Template::compile(&f.value) {
+            issues.push(issue(
+                find_line(content, &f.value, 1),
+                format!(
+                    "invalid handlebars into value of flag {:?}: {}",
+                    f.name, err
+                ),
+                None,
+            ));
+        }
+    }
+    issues
+}
+
+const VARIABLE_FIELDS: &[&str] = &[
+    "name",
+    "default_value",
+    "default_from_cmd",
+    "default_from_dotenv",
+    "ask",
+    "hidden",
+    "secret",
+    "select_in_values",
+    "type",
+    "section",
+];
+
+/// the rendered fields (with a handlebars template) of the variable
+fn templates_of_variable(v: &VariableCfg) -> Vec<(&'static str, String)> {
+    let mut templates = vec![];
+    if let Some(default_value) = &v.default_value {
+        collect_strings(&default_value.0, &mut |s| {
+            templates.push(("default_value", s.to_owned()))
+        });
+    }
+    if let Some(select_in_values) = &v.select_in_values {
+        collect_strings(&select_in_values.0, &mut |s| {
+            templates.push(("select_in_values", s.to_owned()))
+        });
+    }
+    if let Some(ask) = &v.ask {
+        for message in ask.messages() {
+            templates.push(("ask", message.clone()));
+        }
+    }
+    if let Some(hidden) = &v.hidden {
+        templates.push(("hidden", hidden.clone()));
+    }
+    templates
+}
+
+fn collect_strings<F>(value: &serde_yaml::Value, f: &mut F)
+where
+    F: FnMut(&str),
+{
+    match value {
+        serde_yaml::Value::String(s) => f(s),
+        serde_yaml::Value::Sequence(l) => l.iter().for_each(|v| collect_strings(v, f)),
+        serde_yaml::Value::Mapping(m) => m.iter().for_each(|(_, v)| collect_strings(v, f)),
+        _ => (),
+    }
+}
+
+/// the line (1-based) of the `nth` (1-based) occurrence of `text`
+fn find_line(content: &str, text: &str, nth: usize) -> Option<usize> {
+    let text = text.trim();
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| l.contains(text))
+        .nth(nth.saturating_sub(1))
+        .map(|(i, _)| i + 1)
+}
+
+/// suggest the closest field for a serde error like
+/// "unknown field `nam`, expected one of `name`, `default_value`"
+fn suggest_field(message: &str) -> Option<String> {
+    let rest = message.split("unknown field `").nth(1)?;
+    let unknown = rest.split('`').next()?;
+    let expected = rest.split("expected").nth(1)?;
+    suggest(unknown, expected.split('`').skip(1).step_by(2))
+}
+
+fn suggest<'a, I>(unknown: &str, candidates: I) -> Option<String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    candidates
+        .map(|candidate| (strsim::levenshtein(unknown, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2.max(unknown.len() / 3))
+        .min()
+        .map(|(_, candidate)| format!("did you mean `{}`?", candidate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_validate() {
+        let path = Path::new(".ffizer.yaml");
+        let issues = validate(
+            path,
+            r#"
+variables:
+  - name: project
+  - name: project
+    default_value: "{{#if foo}}bar"
+ignores:
+  - "src/[a-"
+"#,
+        );
+        let found = issues
+            .iter()
+            .map(|i| (i.line, i.message.split(':').next().unwrap_or_default()))
+            .collect::<Vec<_>>();
+        assert_that!(found).is_equal_to(vec![
+            (Some(4), "duplicate variable \"project\""),
+            (Some(7), "invalid glob \"src/[a-\""),
+            (
+                Some(5),
+                "invalid handlebars into default_value of \"project\"",
+            ),
+        ]);
+        assert_that!(validate(path, "variables:\n  - name: project\n")).is_empty();
+
+        let issues = validate(
+            path,
+            "encodings:\n  - files: \"*.bat\"\n    encoding: windows-1252\n  - files: \"*.txt\"\n    encoding: latin-42\n",
+        );
+        assert_that!(issues).has_length(1);
+        assert_that!(issues[0].line).is_equal_to(Some(5));
+
+        let issues = validate(
+            path,
+            "sections:\n  - name: database\nvariables:\n  - name: db_host\n    section: databse\n",
+        );
+        assert_that!(issues).has_length(1);
+        assert_that!(issues[0].line).is_equal_to(Some(5));
+        assert_that!(issues[0].suggestion).is_equal_to(Some("did you mean `database`?".to_owned()));
+    }
+
+    #[test]
+    fn test_validate_unknown_field() {
+        let path = Path::new(".ffizer.yaml");
+        let issues = validate(
+            path,
+            "variables:\n  - name: project\n    defaut_value: foo\n",
+        );
+        assert_that!(issues).has_length(1);
+        assert_that!(issues[0].line).is_equal_to(Some(3));
+        assert_that!(issues[0].suggestion)
+            .is_equal_to(Some("did you mean `default_value`?".to_owned()));
+        assert_that!(issues[0].to_string().as_str())
+            .starts_with(".ffizer.yaml:3: unknown field `defaut_value` of variable");
+
+        let issues = validate(path, "variables:\n  - name: project\nignore:\n  - foo\n");
+        assert_that!(issues).has_length(1);
+        assert_that!(issues[0].line).is_equal_to(Some(3));
+        assert_that!(issues[0].suggestion).is_equal_to(Some("did you mean `ignores`?".to_owned()));
+    }
+}
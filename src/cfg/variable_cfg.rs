@@ -1,4 +1,6 @@
+use super::ask_cfg::AskCfg;
 use super::transform_values::TransformsValues;
+use crate::variable_def::VariableType;
 use crate::Result;
 use schemars::gen::SchemaGenerator;
 use schemars::schema::Schema;
@@ -10,12 +12,30 @@ pub(crate) struct VariableCfg {
     pub name: String,
     /// optionnal default value
     pub default_value: Option<VariableValueCfg>,
-    /// sentence to ask the value (default to the name on variable)
-    pub ask: Option<String>,
-    /// is the variable hidden to the user (could be usefull to cache shared variable/data)
+    /// command whose output (trimmed) is the default value (if it succeeds), eg `git config user.email`,
+    /// run into the destination folder before the questions
+    pub default_from_cmd: Option<String>,
+    /// dotenv file (`KEY=VALUE` lines) where the default value is read (if the file and the key exist),
+    /// tried after `default_from_cmd`
+    pub default_from_dotenv: Option<DotenvCfg>,
+    /// sentence to ask the value (default to the name on variable), a handlebars template rendered
+    /// with the previous answers, or a sentence per locale (eg `en: ...`, `fr: ...`)
+    pub ask: Option<AskCfg>,
+    /// is the variable hidden to the user (could be usefull to cache shared variable/data),
+    /// a handlebars expression rendered before the questions (eg `{{ ne ffizer.target_os "windows" }}`)
     pub hidden: Option<String>,
+    /// is the value a secret (eg a password, a token): asked without echo, masked into the logs,
+    /// and not recorded into the files of ffizer (fingerprint, report), still usable into the template
+    #[serde(default)]
+    pub secret: bool,
     /// if non-empty then the value should selected into the list of value
     pub select_in_values: Option<VariableValueCfg>,
+    /// type of the value: `string` (default), `bool` (asked as a yes/no confirmation) or `list` (asked until an empty answer)
+    #[serde(rename = "type")]
+    pub value_type: Option<VariableType>,
+    /// name of the section of the variable (the variables are asked in the order of their declaration,
+    /// the title and the intro of the section are displayed when the section changes)
+    pub section: Option<String>,
 }
 
 impl TransformsValues for VariableCfg {
@@ -26,19 +46,33 @@ impl TransformsValues for VariableCfg {
     {
         let name = self.name.transforms_values(render)?;
         let default_value = self.default_value.transforms_values(render)?;
+        let default_from_cmd = self.default_from_cmd.transforms_values(render)?;
         let ask = self.ask.transforms_values(render)?;
         let hidden = self.hidden.transforms_values(render)?;
         let select_in_values = self.select_in_values.transforms_values(render)?;
         Ok(VariableCfg {
             name,
             default_value,
+            default_from_cmd,
+            default_from_dotenv: self.default_from_dotenv.clone(),
             ask,
             hidden,
+            secret: self.secret,
             select_in_values,
+            value_type: self.value_type,
+            section: self.section.clone(),
         })
     }
 }
 
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, JsonSchema)]
+pub(crate) struct DotenvCfg {
+    /// path of the file, relative to the destination folder (or to the home of the user with `~/`)
+    pub path: String,
+    /// the key into the file (default to the name of the variable)
+    pub key: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 pub struct VariableValueCfg(pub serde_yaml::Value);
 
@@ -0,0 +1,133 @@
+//! the changes of the templates since the commits applied (recorded into the lockfile), shown
+//! before applying a newer rev: the log of the commits between the commit applied and the new one,
+//! or an excerpt of the changelog of the template when the log is not available (eg the commit
+//! applied is not into a shallow clone).
+
+use crate::cfg::TemplateComposite;
+use crate::error::*;
+use crate::git;
+use crate::lockfile::Lockfile;
+use crate::Ctx;
+use slog::{debug, warn};
+use std::fs;
+use std::path::Path;
+
+/// the changelog files searched into the template (the first found is used)
+const CHANGELOG_FILENAMES: [&str; 3] = ["CHANGELOG.md", "CHANGELOG", "CHANGES.md"];
+/// the max number of commits listed
+const MAX_COMMITS: usize = 30;
+/// the max number of lines of the excerpt of the changelog
+const MAX_EXCERPT_LINES: usize = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TemplateChangelog {
+    pub uri: String,
+    /// the id of the commit applied
+    pub from: String,
+    /// the id of the commit to apply
+    pub to: String,
+    /// the commits from the commit applied to the new one (the most recent first)
+    pub commits: Vec<String>,
+    /// the top of the changelog of the template (if the commits are not available)
+    pub excerpt: Option<String>,
+}
+
+/// the changelogs of the templates (already retrieved) whose commit is not the one recorded into
+/// the lockfile of the destination (nothing if the destination has no lockfile).
+/// A changelog that can't be computed is skipped (the apply doesn't fail).
+pub(crate) fn find_changelogs(
+    ctx: &Ctx,
+    template_composite: &TemplateComposite,
+) -> Result<Vec<TemplateChangelog>> {
+    let lockfile = match Lockfile::load(&ctx.cmd_opt.dst_folder)? {
+        Some(lockfile) => lockfile,
+        None => return Ok(vec![]),
+    };
+    let mut changelogs = vec![];
+    for loc in template_composite.find_locs() {
+        let locked = match lockfile.find(loc) {
+            Some(locked) => locked,
+            None => continue,
+        };
+        let template = loc.retrieve(&ctx.logger, true)?;
+        let rev_info = match template.rev_info {
+            Some(rev_info) if rev_info.commit_id != locked.commit_id => rev_info,
+            _ => continue,
+        };
+        let commits = git::find_log(
+            &template.path,
+            &locked.commit_id,
+            &rev_info.commit_id,
+            MAX_COMMITS,
+        )
+        .unwrap_or_else(|err| {
+            debug!(ctx.logger, "log of the template not available"; "uri" => &locked.uri, "error" => ?&err);
+            vec![]
+        });
+        let excerpt = if commits.is_empty() {
+            read_excerpt(&template.path).unwrap_or_else(|err| {
+                warn!(ctx.logger, "failed to read the changelog of the template"; "uri" => &locked.uri, "error" => ?&err);
+                None
+            })
+        } else {
+            None
+        };
+        changelogs.push(TemplateChangelog {
+            uri: locked.uri.clone(),
+            from: locked.commit_id.clone(),
+            to: rev_info.commit_id,
+            commits,
+            excerpt,
+        });
+    }
+    Ok(changelogs)
+}
+
+/// the excerpt of the first changelog file found into `folder` (`None` if no changelog)
+fn read_excerpt(folder: &Path) -> Result<Option<String>> {
+    for name in CHANGELOG_FILENAMES.iter() {
+        let path = folder.join(name);
+        if path.is_file() {
+            let content =
+                fs::read_to_string(&path).map_err(|source| Error::ReadFile { path, source })?;
+            return Ok(Some(excerpt(&content)));
+        }
+    }
+    Ok(None)
+}
+
+/// the first release of the changelog: the lines from the first `## ` heading up to the next one
+/// (the top of the file if no such heading), at most `MAX_EXCERPT_LINES` lines
+fn excerpt(content: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let start = lines.iter().position(|l| l.starts_with("## ")).unwrap_or(0);
+    let end = lines
+        .iter()
+        .skip(start + 1)
+        .position(|l| l.starts_with("## "))
+        .map(|i| start + 1 + i)
+        .unwrap_or_else(|| lines.len());
+    lines[start..end.min(start + MAX_EXCERPT_LINES)]
+        .join("\n")
+        .trim_end()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_excerpt() {
+        let content = "# Changelog\n\n## 2.0.0\n\n- drop travis\n- add github actions\n\n## 1.0.0\n\n- initial\n";
+        assert_that!(excerpt(content))
+            .is_equal_to("## 2.0.0\n\n- drop travis\n- add github actions".to_owned());
+        assert_that!(excerpt("- fix typo\n- add license\n"))
+            .is_equal_to("- fix typo\n- add license".to_owned());
+        let long = (0..100)
+            .map(|i| format!("- change {}\n", i))
+            .collect::<String>();
+        assert_that!(excerpt(&long).lines().count()).is_equal_to(MAX_EXCERPT_LINES);
+    }
+}
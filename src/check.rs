@@ -0,0 +1,181 @@
+//! check a generated project against its template, without any change: the template is rendered
+//! again (at the commits recorded into the lockfile, with the answers recorded into the fingerprint
+//! and the `ffizer.vars.yaml`), then compared to the files of the project.
+
+use crate::error::*;
+use crate::fingerprint::Fingerprint;
+use crate::lockfile::Lockfile;
+use crate::manifest::{Manifest, Ownership};
+use crate::source_file::SourceFileMetadata;
+use crate::source_loc::SourceLoc;
+use crate::{extract_variables, prepare_plan_with, render_plan, Ctx, RenderedContent};
+use slog::debug;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Drift {
+    /// the file is not the one the template would produce
+    Modified,
+    /// the file or folder of the template is missing
+    Missing,
+    /// the file is recorded into the manifest, but no longer generated by the template
+    NotOwned,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Drift::Modified => "modified",
+            Drift::Missing => "missing",
+            Drift::NotOwned => "not owned",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// the drifts of the project `ctx.cmd_opt.dst_folder` from its template (`src` if the template
+/// is not recorded into the lockfile), with the path relative to the project
+pub(crate) fn check(ctx: &Ctx, src: Option<SourceLoc>) -> Result<Vec<(PathBuf, Drift)>> {
+    let dst_folder = &ctx.cmd_opt.dst_folder;
+    let lockfile = Lockfile::load(dst_folder)?;
+    let src = match src {
+        Some(src) => src,
+        None => {
+            let source = lockfile
+                .as_ref()
+                .and_then(|l| l.source.clone())
+                .ok_or_else(|| Error::NoLockedSource {
+                    lockfile: Lockfile::path_in(dst_folder),
+                })?;
+            SourceLoc {
+                uri: source.uri.parse()?,
+                rev: source.rev,
+                subfolder: source.subfolder,
+                full_history: false,
+            }
+        }
+    };
+    let mut ctx = ctx.clone();
+    ctx.cmd_opt.src = src;
+    ctx.cmd_opt.locked = lockfile.is_some();
+    ctx.cmd_opt.no_interaction = true;
+    ctx.allow_commands = false;
+
+    // the answers recorded, overridden by the `ffizer.vars.yaml` and the cli
+    let mut variables = Fingerprint::load(dst_folder)?
+        .map(|f| f.answers)
+        .unwrap_or_default();
+    variables.append(&mut extract_variables(&ctx)?);
+    debug!(ctx.logger, "rendering the template"; "src" => ?&ctx.cmd_opt.src);
+    let plan = prepare_plan_with(&ctx, variables)?;
+    let rendered = render_plan(&ctx, &plan)?;
+
+    // the user owned files, and the files edited in place (append, patch, merge) are only expected
+    // to exist, their content is not the one of the template
+    let not_compared = plan
+        .actions
+        .iter()
+        .filter(|a| {
+            a.ownership == Some(Ownership::UserOwned)
+                || a.src.iter().any(|s| {
+                    matches!(
+                        s.metadata,
+                        SourceFileMetadata::AppendFile
+                            | SourceFileMetadata::PatchFile
+                            | SourceFileMetadata::MergeFile { .. }
+                    )
+                })
+        })
+        .map(|a| a.dst_path.relative.clone())
+        .collect::<HashSet<_>>();
+
+    let mut drifts = vec![];
+    for (relative, content) in &rendered {
+        ctx.cancel.check("checking")?;
+        let path = dst_folder.join(relative);
+        let drift = match content {
+            RenderedContent::Dir if path.is_dir() => None,
+            RenderedContent::Dir => Some(Drift::Missing),
+            RenderedContent::File(_) if !path.is_file() => Some(Drift::Missing),
+            RenderedContent::File(_) if not_compared.contains(relative) => None,
+            RenderedContent::File(expected) => {
+                if &read(&path)? == expected {
+                    None
+                } else {
+                    Some(Drift::Modified)
+                }
+            }
+        };
+        if let Some(drift) = drift {
+            drifts.push((relative.clone(), drift));
+        }
+    }
+    if let Some(manifest) = Manifest::load(dst_folder)? {
+        for relative in manifest.files.keys() {
+            if !rendered.contains_key(relative) && dst_folder.join(relative).exists() {
+                drifts.push((relative.clone(), Drift::NotOwned));
+            }
+        }
+    }
+    Ok(drifts)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|source| Error::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ApplyOpts;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_check() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let template = tmp_dir.path().join("template");
+        fs::create_dir_all(template.join("docs"))?;
+        fs::write(template.join("README.md"), "hello\n")?;
+        fs::write(template.join("LICENSE"), "MIT\n")?;
+        fs::write(template.join("docs/index.md"), "index\n")?;
+        let project = tmp_dir.path().join("project");
+        fs::create_dir_all(project.join("docs"))?;
+        fs::write(project.join("README.md"), "hello\n")?;
+        fs::write(project.join("docs/index.md"), "changed\n")?;
+        fs::write(project.join("old.txt"), "old\n")?;
+        let mut manifest = Manifest::default();
+        manifest
+            .files
+            .insert(PathBuf::from("old.txt"), Ownership::Managed);
+        manifest.save(&project)?;
+
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: project.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let src = SourceLoc {
+            uri: template.to_string_lossy().parse()?,
+            ..Default::default()
+        };
+        let mut drifts = check(&ctx, Some(src))?;
+        drifts.sort();
+        assert_that!(drifts).is_equal_to(vec![
+            (PathBuf::from("LICENSE"), Drift::Missing),
+            (PathBuf::from("docs/index.md"), Drift::Modified),
+            (PathBuf::from("old.txt"), Drift::NotOwned),
+        ]);
+        // nothing changed
+        assert_that!(project.join("LICENSE").exists()).is_false();
+        Ok(())
+    }
+}
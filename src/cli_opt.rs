@@ -1,7 +1,10 @@
 use crate::source_loc::SourceLoc;
+use crate::source_uri::SourceUri;
 use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::clap::arg_enum;
 use structopt::clap::AppSettings;
+pub use structopt::clap::Shell;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug, Clone)]
@@ -21,6 +24,15 @@ pub struct CliOpts {
     #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
     pub verbose: usize,
 
+    /// format of the logs
+    #[structopt(long, default_value = "Text", possible_values = &LogFormat::variants(), case_insensitive = true)]
+    pub log_format: LogFormat,
+
+    /// levels of the logs per module, separated by `,` (eg `ffizer::git=trace,info`),
+    /// the level without module overrides the one set by the verbosity
+    #[structopt(long, env = "FFIZER_LOG")]
+    pub log_filter: Option<String>,
+
     #[structopt(subcommand)] // Note that we mark a field as a subcommand
     pub cmd: Command,
 }
@@ -30,26 +42,92 @@ pub enum Command {
     /// Apply a template into a target directory
     #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
     Apply(ApplyOpts),
-    /// Self upgrade ffizer executable
+    /// Create a new project: create the destination, apply a template, then init git with an initial commit
+    #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
+    New(NewOpts),
+    /// Self upgrade ffizer executable, or upgrade a project to an other rev of its template (with `--to`)
+    #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
+    Upgrade(UpgradeOpts),
+    /// Check a generated project against its template (drifted files), without any change
     #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
-    Upgrade,
+    Check(CheckOpts),
     /// Inspect configuration, caches,... (wip)
     #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
     Inspect,
+    /// Manage the cache of remote templates (list, clean, update)
+    #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
+    Cache(CacheCommand),
+    /// Manage the registry of templates (export)
+    #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
+    Registry(RegistryCommand),
     /// Show the json schema of the .ffizer.yaml files
     #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
     ShowJsonSchema,
     /// test a template against its samples
     #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
     TestSamples(TestSamplesOpts),
+    /// Check the configuration (.ffizer.yaml) of a template
+    #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
+    Lint(LintOpts),
+    /// Generate the completion script of a shell (on stdout)
+    #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
+    Completion(CompletionOpts),
+    /// Generate the man page (on stdout)
+    #[structopt(author = env!("CARGO_PKG_HOMEPAGE"))]
+    Man,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum CacheCommand {
+    /// List the cached templates (with disk usage, last refresh, resolved rev)
+    List,
+    /// Remove cached templates (every templates if no filter)
+    Clean(CacheFilterOpts),
+    /// Update (fetch and merge) cached git templates
+    Update(CacheFilterOpts),
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum RegistryCommand {
+    /// Export the catalog of the templates of the registry (metadata, variables, files)
+    Export(RegistryExportOpts),
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct RegistryExportOpts {
+    /// format of the catalog
+    #[structopt(long, default_value = "Json", possible_values = &ExportFormat::variants(), case_insensitive = true)]
+    pub format: ExportFormat,
+
+    /// in offline, only local templates or cached templates are used
+    #[structopt(long = "offline")]
+    pub offline: bool,
+
+    /// file to write the catalog into (default: stdout)
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug, Default, Clone)]
+pub struct CacheFilterOpts {
+    /// only the templates whose path in the cache contains one of the filters, as whole components (eg: "github.com/ffizer")
+    pub filters: Vec<String>,
 }
 
 #[derive(StructOpt, Debug, Default, Clone)]
 pub struct ApplyOpts {
-    /// ask for plan confirmation
+    /// ask for plan confirmation (`PerFile`: ask what to do for each existing file to update)
     #[structopt(long, default_value = "Never", possible_values = &AskConfirmation::variants(), case_insensitive = true)]
     pub confirm: AskConfirmation,
 
+    /// show the differences (unified diff) of the existing files to update, with the plan
+    #[structopt(long = "show-diff")]
+    pub show_diff: bool,
+
+    /// launch the diff tool (`diff.tool` of the git's configuration) for the existing files to update, with the plan
+    #[structopt(long = "diff-tool")]
+    pub diff_tool: bool,
+
     /// mode to update existing file
     #[structopt(long, default_value = "Ask", possible_values = &UpdateMode::variants(), case_insensitive = true)]
     pub update_mode: UpdateMode,
@@ -65,6 +143,19 @@ pub struct ApplyOpts {
     #[structopt(flatten)]
     pub src: SourceLoc,
 
+    /// template (uri / path) applied over the `--source` (repeatable, the later ones override the earlier ones)
+    #[structopt(long = "layer")]
+    pub layers: Vec<SourceUri>,
+
+    /// git revision of the `--layer` at the same position (repeatable, default to `master`)
+    #[structopt(long = "layer-rev", requires = "layers")]
+    pub layer_revs: Vec<String>,
+
+    /// yaml file with the list of templates (`uri`, `rev`, `subfolder`) applied over the `--source`
+    /// and under the `--layer` (the later ones override the earlier ones)
+    #[structopt(long = "stack", parse(from_os_str))]
+    pub stack: Option<PathBuf>,
+
     /// destination folder (created if doesn't exist)
     #[structopt(
         short = "d",
@@ -74,9 +165,148 @@ pub struct ApplyOpts {
     )]
     pub dst_folder: PathBuf,
 
-    /// set variable's value from cli ("key=value")
+    /// set variable's value from cli ("key=value"), or `-` to read the variables (yaml or json) from stdin
     #[structopt(short = "v", long = "variables", parse(from_str=parse_keyvalue))]
     pub key_value: Vec<(String, String)>,
+
+    /// the platform targeted by the generated project (an os like `windows`, `linux`, `macos`, or a
+    /// target triple like `x86_64-pc-windows-msvc`), exposed as `ffizer.target_os` (default: the os of the host)
+    #[structopt(long = "target")]
+    pub target: Option<String>,
+
+    /// do not keep a copy (`<file>.ffizer.bak`) of the existing files overridden
+    #[structopt(long = "no-backup")]
+    pub no_backup: bool,
+
+    /// on failure, restore the destination as before the apply (remove created files & folders, restore updated files)
+    #[structopt(long = "transactional")]
+    pub transactional: bool,
+
+    /// number of threads used to create the files (0: one per cpu)
+    #[structopt(short = "j", long = "jobs", default_value = "0")]
+    pub jobs: usize,
+
+    /// change the owner of the created & updated files and folders ("uid[:gid]", unix only),
+    /// eg when ffizer runs as root into a container
+    #[structopt(long = "chown")]
+    pub chown: Option<Owner>,
+
+    /// apply only the files and folders of the destination matching the glob (eg `.github/**`),
+    /// the other actions of the plan are ignored
+    #[structopt(long = "only", number_of_values = 1)]
+    pub only: Vec<String>,
+
+    /// do not apply the files and folders of the destination matching the glob (eg `docs/**`)
+    #[structopt(long = "exclude", number_of_values = 1)]
+    pub exclude: Vec<String>,
+
+    /// explain the plan: the reason of each action, the sources overridden by other templates,
+    /// the sources ignored (by `ignores` or by a feature not selected)
+    #[structopt(long = "explain")]
+    pub explain: bool,
+
+    /// write the plan (with the reason of each action and the sources skipped) as json into the file,
+    /// instead of applying it
+    #[structopt(long = "emit-plan", parse(from_os_str))]
+    pub emit_plan: Option<PathBuf>,
+
+    /// write the plan as a script (`sh`, or PowerShell if the extension is `.ps1`) into the file,
+    /// instead of applying it (to review and to run on a machine without ffizer)
+    #[structopt(long = "emit-script", parse(from_os_str))]
+    pub emit_script: Option<PathBuf>,
+
+    /// apply the commits of the templates recorded into `.ffizer.lock.yaml` of the destination
+    /// (by the previous apply), instead of the tip of the revs
+    #[structopt(long = "locked")]
+    pub locked: bool,
+
+    /// record the fingerprint of the apply (hash of the templates, answers and version of ffizer)
+    /// into `.ffizer.fingerprint.yaml` of the destination (always updated once recorded)
+    #[structopt(long = "fingerprint")]
+    pub fingerprint: bool,
+
+    /// record the report of the apply (templates, answers with the secrets masked, files created,
+    /// overwritten or kept) into `.ffizer/last_apply.yaml` of the destination (always updated once
+    /// recorded)
+    #[structopt(long = "report")]
+    pub report: bool,
+
+    /// record the hashes of the generated files into `.ffizer.manifest.yaml` of the destination
+    /// (always updated once recorded): on the next apply, the files not changed locally since are
+    /// updated without question
+    #[structopt(long = "track-changes")]
+    pub track_changes: bool,
+
+    /// max size (in bytes) of a rendered file (0: no limit)
+    #[structopt(long = "render-max-size", default_value = "104857600")]
+    pub render_max_size: u64,
+
+    /// max duration (in seconds) of the rendering of a file (0: no limit)
+    #[structopt(long = "render-timeout", default_value = "60")]
+    pub render_timeout: u64,
+}
+
+#[derive(StructOpt, Debug, Default, Clone)]
+pub struct NewOpts {
+    #[structopt(flatten)]
+    pub apply: ApplyOpts,
+
+    /// do not init a git repository (with an initial commit) into the destination
+    #[structopt(long = "no-git")]
+    pub no_git: bool,
+
+    /// message of the initial commit
+    #[structopt(
+        long = "commit-message",
+        default_value = "initial commit (generated by ffizer)"
+    )]
+    pub commit_message: String,
+}
+
+/// owner (user id and optional group id) to set on the generated files and folders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: Option<u32>,
+}
+
+impl FromStr for Owner {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::Error::ParseOwner {
+            value: s.to_owned(),
+        };
+        let mut parts = s.trim().split(':');
+        let uid = parts
+            .next()
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or_else(invalid)?;
+        let gid = match parts.next() {
+            None => None,
+            Some(v) => Some(v.parse::<u32>().map_err(|_| invalid())?),
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Owner { uid, gid })
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum LogFormat {
+        Text,
+        Json,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ExportFormat {
+        Json,
+        Html,
+    }
 }
 
 arg_enum! {
@@ -85,6 +315,8 @@ arg_enum! {
         Auto,
         Always,
         Never,
+        // ask what to do for each existing file to update
+        PerFile,
     }
 }
 
@@ -130,6 +362,65 @@ fn parse_keyvalue(src: &str) -> (String, String) {
     }
 }
 
+#[derive(StructOpt, Debug, Default, Clone)]
+pub struct UpgradeOpts {
+    /// upgrade the project (`--destination`) to this rev of its template, instead of the ffizer executable:
+    /// the rev applied (recorded into `.ffizer.lock.yaml`) and this rev are merged (3-way) with the local changes
+    #[structopt(long = "to")]
+    pub to: Option<String>,
+
+    /// folder of the project to upgrade
+    #[structopt(
+        short = "d",
+        long = "destination",
+        parse(from_os_str),
+        default_value = "."
+    )]
+    pub dst_folder: PathBuf,
+
+    /// set variable's value from cli ("key=value"), or `-` to read the variables (yaml or json) from stdin
+    #[structopt(short = "v", long = "variables", parse(from_str=parse_keyvalue))]
+    pub key_value: Vec<(String, String)>,
+
+    /// should not ask for confirmation (to use default value,...)
+    #[structopt(short = "y", long = "no-interaction")]
+    pub no_interaction: bool,
+
+    /// in offline, only local templates or cached templates are used
+    #[structopt(long = "offline")]
+    pub offline: bool,
+}
+
+#[derive(StructOpt, Debug, Default, Clone)]
+pub struct CheckOpts {
+    /// folder of the project to check
+    #[structopt(
+        short = "d",
+        long = "destination",
+        parse(from_os_str),
+        default_value = "."
+    )]
+    pub dst_folder: PathBuf,
+
+    /// uri / path of the template, if not recorded into `.ffizer.lock.yaml` of the project
+    /// (eg a local folder)
+    #[structopt(short = "s", long = "source")]
+    pub src: Option<SourceUri>,
+
+    /// path of the folder under the source uri to use for template
+    #[structopt(long = "source-subfolder", parse(from_os_str))]
+    pub subfolder: Option<PathBuf>,
+
+    /// set variable's value from cli ("key=value"), or `-` to read the variables (yaml or json) from stdin,
+    /// override the answers recorded
+    #[structopt(short = "v", long = "variables", parse(from_str=parse_keyvalue))]
+    pub key_value: Vec<(String, String)>,
+
+    /// in offline, only local templates or cached templates are used
+    #[structopt(long = "offline")]
+    pub offline: bool,
+}
+
 #[derive(StructOpt, Debug, Default, Clone)]
 pub struct TestSamplesOpts {
     #[structopt(flatten)]
@@ -138,3 +429,38 @@ pub struct TestSamplesOpts {
     #[structopt(long = "offline")]
     pub offline: bool,
 }
+
+#[derive(StructOpt, Debug, Default, Clone)]
+pub struct LintOpts {
+    /// folder of the template
+    #[structopt(parse(from_os_str))]
+    pub template: PathBuf,
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct CompletionOpts {
+    #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+    pub shell: Shell,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_owner_from_str() {
+        assert_that!(Owner::from_str("1000:100").ok()).is_equal_to(Some(Owner {
+            uid: 1000,
+            gid: Some(100),
+        }));
+        assert_that!(Owner::from_str("1000").ok()).is_equal_to(Some(Owner {
+            uid: 1000,
+            gid: None,
+        }));
+        assert!(Owner::from_str("").is_err());
+        assert!(Owner::from_str("root").is_err());
+        assert!(Owner::from_str("1000:").is_err());
+        assert!(Owner::from_str("1:2:3").is_err());
+    }
+}
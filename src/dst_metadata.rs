@@ -0,0 +1,123 @@
+//! the existing entries of the destination, listed once per folder of the plan (instead of a lookup
+//! of the file system per action), to plan the templates with lots of files quickly.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct DstMetadata {
+    /// the existing entries (relative to the destination folder), `true` for a folder
+    entries: HashMap<PathBuf, bool>,
+}
+
+impl DstMetadata {
+    /// list the entries of the folders (into `dst_folder`) of the `relatives` paths (and of their
+    /// ancestors), the folders that don't exist and the paths outside of `dst_folder` (with `..` or
+    /// absolute) are skipped
+    pub fn scan<'a, I>(dst_folder: &Path, relatives: I) -> DstMetadata
+    where
+        I: IntoIterator<Item = &'a Path>,
+    {
+        let mut folders = relatives
+            .into_iter()
+            .filter(|p| p.components().all(|c| matches!(c, Component::Normal(_))))
+            .flat_map(|p| p.ancestors().skip(1))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        // parents before children, to skip the children of the missing folders
+        folders.sort();
+        let mut entries = HashMap::new();
+        if dst_folder.is_dir() {
+            entries.insert(PathBuf::new(), true);
+        }
+        for folder in folders {
+            if entries.get(folder) != Some(&true) {
+                continue;
+            }
+            let read_dir = match fs::read_dir(dst_folder.join(folder)) {
+                Ok(read_dir) => read_dir,
+                Err(_) => continue,
+            };
+            for entry in read_dir.filter_map(|e| e.ok()) {
+                let is_dir = match entry.file_type() {
+                    // follow the symlinks (like `Path::is_dir`)
+                    Ok(t) if t.is_symlink() => entry.path().is_dir(),
+                    Ok(t) => t.is_dir(),
+                    Err(_) => continue,
+                };
+                entries.insert(folder.join(entry.file_name()), is_dir);
+            }
+        }
+        DstMetadata { entries }
+    }
+
+    /// is the entry an existing folder (`None` if the entry doesn't exist)
+    pub fn find_is_dir(&self, relative: &Path) -> Option<bool> {
+        self.entries.get(relative).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_scan() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let dst = tmp_dir.path();
+        fs::create_dir_all(dst.join("src/bin"))?;
+        fs::write(dst.join("README.md"), "")?;
+        fs::write(dst.join("src/main.rs"), "")?;
+        let relatives = [
+            "",
+            "README.md",
+            "LICENSE",
+            "src",
+            "src/main.rs",
+            "src/lib.rs",
+            "src/bin",
+            "docs/index.md",
+            "docs/api/index.md",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+        let metadata = DstMetadata::scan(dst, relatives.iter().map(|p| p.as_path()));
+        assert_that!(metadata.find_is_dir(Path::new(""))).is_equal_to(Some(true));
+        assert_that!(metadata.find_is_dir(Path::new("README.md"))).is_equal_to(Some(false));
+        assert_that!(metadata.find_is_dir(Path::new("LICENSE"))).is_none();
+        assert_that!(metadata.find_is_dir(Path::new("src"))).is_equal_to(Some(true));
+        assert_that!(metadata.find_is_dir(Path::new("src/main.rs"))).is_equal_to(Some(false));
+        assert_that!(metadata.find_is_dir(Path::new("src/lib.rs"))).is_none();
+        assert_that!(metadata.find_is_dir(Path::new("src/bin"))).is_equal_to(Some(true));
+        assert_that!(metadata.find_is_dir(Path::new("docs/index.md"))).is_none();
+
+        let missing =
+            DstMetadata::scan(&dst.join("missing"), relatives.iter().map(|p| p.as_path()));
+        assert_that!(missing.find_is_dir(Path::new(""))).is_none();
+        assert_that!(missing.find_is_dir(Path::new("README.md"))).is_none();
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let dst = tmp_dir.path().join("dst");
+        fs::create_dir_all(dst.join("src"))?;
+        std::os::unix::fs::symlink(tmp_dir.path(), dst.join("link"))?;
+        let relatives = ["src/main.rs", "link/foo/bar.rs", "../outside.rs"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        let metadata = DstMetadata::scan(&dst, relatives.iter().map(|p| p.as_path()));
+        assert_that!(metadata.find_is_dir(Path::new("link"))).is_equal_to(Some(true));
+        assert_that!(metadata.find_is_dir(Path::new("link/dst"))).is_equal_to(Some(true));
+        assert_that!(metadata.find_is_dir(Path::new("../dst"))).is_none();
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+//! write the plan as json, to review it or to check it into a CI: the actions (destination,
+//! operation, reason, sources, sources overridden) and the sources of the templates skipped.
+//! The paths of the destinations are relative to the destination folder.
+
+use crate::error::*;
+use crate::{Action, FileOperation, Reason, SkippedSource};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct PlanReport {
+    actions: Vec<ActionReport>,
+    skipped: Vec<SkippedReport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ActionReport {
+    dst: PathBuf,
+    operation: FileOperation,
+    reason: Reason,
+    srcs: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    overridden: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct SkippedReport {
+    src: PathBuf,
+    reason: Reason,
+}
+
+fn to_report(actions: &[Action], skipped: &[SkippedSource]) -> PlanReport {
+    PlanReport {
+        actions: actions
+            .iter()
+            .map(|a| ActionReport {
+                dst: a.dst_path.relative.clone(),
+                operation: a.operation.clone(),
+                reason: a.reason.clone(),
+                srcs: a.src.iter().map(|s| PathBuf::from(&s.childpath)).collect(),
+                overridden: a.overridden.iter().map(PathBuf::from).collect(),
+            })
+            .collect(),
+        skipped: skipped
+            .iter()
+            .map(|s| SkippedReport {
+                src: PathBuf::from(&s.src),
+                reason: s.reason.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// write the plan (`actions` and `skipped` sources) as json into `path`
+pub(crate) fn emit_plan(actions: &[Action], skipped: &[SkippedSource], path: &Path) -> Result<()> {
+    let content = serde_json::to_string_pretty(&to_report(actions, skipped))?;
+    fs::write(path, content).map_err(|source| Error::WriteFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::ChildPath;
+    use crate::source_file::SourceFile;
+    use crate::variables::Variables;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_to_report() -> Result<(), Box<dyn std::error::Error>> {
+        let actions = vec![Action {
+            src: vec![SourceFile::from((ChildPath::new("t1", "README.md"), 0))],
+            dst_path: ChildPath::new("dst", "README.md"),
+            operation: FileOperation::Nothing,
+            variables: Variables::default(),
+            ownership: None,
+            update_mode: None,
+            reason: Reason::SameContent,
+            overridden: vec![ChildPath::new("t2", "README.md")],
+        }];
+        let skipped = vec![SkippedSource {
+            src: ChildPath::new("t1", "docker/Dockerfile"),
+            reason: Reason::FeatureNotSelected {
+                feature: "docker".to_owned(),
+            },
+        }];
+        let json = serde_json::to_value(&to_report(&actions, &skipped))?;
+        assert_that!(json).is_equal_to(serde_json::json!({
+            "actions": [{
+                "dst": "README.md",
+                "operation": "nothing",
+                "reason": { "kind": "same_content" },
+                "srcs": ["t1/README.md"],
+                "overridden": ["t2/README.md"],
+            }],
+            "skipped": [{
+                "src": "t1/docker/Dockerfile",
+                "reason": { "kind": "feature_not_selected", "feature": "docker" },
+            }],
+        }));
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+//! write the plan as a script (shell or PowerShell), to review and to run on a machine where
+//! ffizer is not available. The script creates the folders and writes the files (the rendered
+//! content is embedded, as base64 for binary content), then runs the commands of the templates' scripts.
+//!
+//! - the kind of script is selected by the extension of the path: `.ps1` for PowerShell, else `sh`
+//! - the script applies into the folder given as first argument (default: the destination folder)
+//! - existing files are overridden (no merge, no backup), unchanged & ignored files are skipped
+
+use crate::cli_opt::Owner;
+use crate::error::*;
+use crate::hbs_helpers::new_hbs;
+use crate::scripts::Script;
+use crate::source_loc::SourceLoc;
+use crate::variables::Variables;
+use crate::{compute_content, Action, Ctx, FileOperation, RenderLimits};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HEREDOC_DELIMITER: &str = "FFIZER_EOF";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScriptKind {
+    Sh,
+    PowerShell,
+}
+
+impl ScriptKind {
+    pub fn from_path(path: &Path) -> ScriptKind {
+        match path.extension().and_then(|s| s.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("ps1") => ScriptKind::PowerShell,
+            _ => ScriptKind::Sh,
+        }
+    }
+}
+
+/// write the script equivalent to the `actions` (and the `scripts` of the templates) into `path`
+pub(crate) fn emit_script(
+    ctx: &Ctx,
+    actions: &[Action],
+    variables: &Variables,
+    scripts: &[(&SourceLoc, Vec<Script>)],
+    path: &Path,
+) -> Result<()> {
+    let kind = ScriptKind::from_path(path);
+    let mut handlebars = new_hbs();
+    let limits = RenderLimits::from(&ctx.cmd_opt);
+    let mut out = header(kind, &ctx.cmd_opt.src, &ctx.cmd_opt.dst_folder);
+    for a in actions {
+        let dst = &a.dst_path.relative;
+        match a.operation {
+            FileOperation::Nothing | FileOperation::Ignore => continue,
+            FileOperation::MkDir => {
+                if dst.as_os_str().is_empty() {
+                    continue;
+                }
+                out.push_str(&mkdir(kind, dst));
+            }
+            FileOperation::AddFile
+            | FileOperation::UpdateFile
+            | FileOperation::Append
+            | FileOperation::Patch
+            | FileOperation::Merge => {
+                let content = compute_content(&mut handlebars, &limits, variables, a)?;
+                let src = PathBuf::from(&a.src[0].childpath);
+                match String::from_utf8(content) {
+                    Ok(text) => out.push_str(&write_file(kind, dst, &text)),
+                    // binary content is embedded as base64 (the script doesn't need the template)
+                    Err(err) => out.push_str(&write_bytes(kind, dst, err.as_bytes())),
+                }
+                out.push_str(&set_permissions(kind, &src, dst)?);
+            }
+        }
+        if let Some(owner) = &ctx.cmd_opt.chown {
+            out.push_str(&chown(kind, dst, owner));
+        }
+    }
+    for (loc, scripts) in scripts {
+        for script in scripts {
+            if let Some(message) = &script.message {
+                out.push_str(&echo(kind, &format!("{}: {}", loc.uri.raw, message)));
+            }
+            if let Some(cmd) = &script.cmd {
+                out.push_str(cmd.trim_end());
+                out.push('\n');
+            }
+        }
+    }
+    fs::write(path, &out).map_err(|source| Error::WriteFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    set_executable(path)
+}
+
+fn header(kind: ScriptKind, src: &SourceLoc, dst_folder: &Path) -> String {
+    let comment = format!(
+        "# generated by {} {}\n# template: {}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        src.uri.raw
+    );
+    let dst_folder = quote(kind, &dst_folder.to_string_lossy());
+    match kind {
+        ScriptKind::Sh => format!(
+            "#!/bin/sh\n{}set -e\ndst=${{1:-{}}}\nmkdir -p \"$dst\"\ncd \"$dst\"\n",
+            comment, dst_folder
+        ),
+        ScriptKind::PowerShell => format!(
+            "{}param([string]$Destination = {})\n$ErrorActionPreference = 'Stop'\nNew-Item -ItemType Directory -Force -Path $Destination | Out-Null\nSet-Location $Destination\n",
+            comment, dst_folder
+        ),
+    }
+}
+
+fn mkdir(kind: ScriptKind, dst: &Path) -> String {
+    let dst = quote(kind, &dst.to_string_lossy());
+    match kind {
+        ScriptKind::Sh => format!("mkdir -p -- {}\n", dst),
+        ScriptKind::PowerShell => format!(
+            "New-Item -ItemType Directory -Force -Path {} | Out-Null\n",
+            dst
+        ),
+    }
+}
+
+fn write_file(kind: ScriptKind, dst: &Path, content: &str) -> String {
+    let dst = quote(kind, &dst.to_string_lossy());
+    match kind {
+        ScriptKind::Sh => {
+            // the heredoc always ends with a newline, else fallback to printf
+            let has_delimiter = content.lines().any(|l| l == HEREDOC_DELIMITER);
+            if content.ends_with('\n') && !has_delimiter {
+                format!(
+                    "cat > {} <<'{delim}'\n{}{delim}\n",
+                    dst,
+                    content,
+                    delim = HEREDOC_DELIMITER
+                )
+            } else {
+                format!("printf '%s' {} > {}\n", quote(kind, content), dst)
+            }
+        }
+        ScriptKind::PowerShell => {
+            // the here-string excludes the last newline before its end
+            let value = if content.contains("\n'@") || content.starts_with("'@") {
+                quote(kind, content)
+            } else {
+                format!("@'\n{}\n'@", content)
+            };
+            format!(
+                "[System.IO.File]::WriteAllText((Join-Path $PWD {}), {})\n",
+                dst, value
+            )
+        }
+    }
+}
+
+fn write_bytes(kind: ScriptKind, dst: &Path, content: &[u8]) -> String {
+    let dst = quote(kind, &dst.to_string_lossy());
+    match kind {
+        ScriptKind::Sh => {
+            // base64 never contains the delimiter, wrapped at 76 characters like `base64`
+            let encoded = base64::encode(content);
+            let mut lines = String::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+            for chunk in encoded.as_bytes().chunks(76) {
+                lines.push_str(&String::from_utf8_lossy(chunk));
+                lines.push('\n');
+            }
+            format!(
+                "base64 -d > {} <<'{delim}'\n{}{delim}\n",
+                dst,
+                lines,
+                delim = HEREDOC_DELIMITER
+            )
+        }
+        ScriptKind::PowerShell => format!(
+            "[System.IO.File]::WriteAllBytes((Join-Path $PWD {}), [Convert]::FromBase64String('{}'))\n",
+            dst,
+            base64::encode(content)
+        ),
+    }
+}
+
+/// keep the executable flag of the source (like `copy_file_permissions`)
+#[cfg(unix)]
+fn set_permissions(kind: ScriptKind, src: &Path, dst: &Path) -> Result<String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(src)
+        .map_err(|source| Error::ReadFile {
+            path: src.to_path_buf(),
+            source,
+        })?
+        .permissions()
+        .mode();
+    if kind == ScriptKind::Sh && mode & 0o111 != 0 {
+        Ok(format!(
+            "chmod {:o} -- {}\n",
+            mode & 0o7777,
+            quote(kind, &dst.to_string_lossy())
+        ))
+    } else {
+        Ok(String::new())
+    }
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_kind: ScriptKind, _src: &Path, _dst: &Path) -> Result<String> {
+    Ok(String::new())
+}
+
+fn chown(kind: ScriptKind, dst: &Path, owner: &Owner) -> String {
+    match kind {
+        ScriptKind::Sh => {
+            let owner = match owner.gid {
+                Some(gid) => format!("{}:{}", owner.uid, gid),
+                None => owner.uid.to_string(),
+            };
+            format!(
+                "chown -h {} -- {}\n",
+                owner,
+                quote(kind, &dst.to_string_lossy())
+            )
+        }
+        ScriptKind::PowerShell => String::new(),
+    }
+}
+
+fn echo(kind: ScriptKind, message: &str) -> String {
+    match kind {
+        ScriptKind::Sh => format!("echo {}\n", quote(kind, message)),
+        ScriptKind::PowerShell => format!("Write-Output {}\n", quote(kind, message)),
+    }
+}
+
+/// quote the value as a literal string (no expansion of variables)
+fn quote(kind: ScriptKind, value: &str) -> String {
+    match kind {
+        ScriptKind::Sh => format!("'{}'", value.replace('\'', "'\\''")),
+        ScriptKind::PowerShell => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(|source| {
+        Error::WriteFile {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::ChildPath;
+    use crate::source_file::{SourceFile, SourceFileMetadata};
+    use crate::{ApplyOpts, Reason};
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_script_kind_from_path() {
+        assert_that!(ScriptKind::from_path(Path::new("plan.sh"))).is_equal_to(ScriptKind::Sh);
+        assert_that!(ScriptKind::from_path(Path::new("plan"))).is_equal_to(ScriptKind::Sh);
+        assert_that!(ScriptKind::from_path(Path::new("plan.PS1")))
+            .is_equal_to(ScriptKind::PowerShell);
+    }
+
+    #[test]
+    fn test_write_file() {
+        let dst = Path::new("it's/foo.txt");
+        assert_that!(write_file(ScriptKind::Sh, dst, "hello\n$HOME\n")).is_equal_to(
+            "cat > 'it'\\''s/foo.txt' <<'FFIZER_EOF'\nhello\n$HOME\nFFIZER_EOF\n".to_owned(),
+        );
+        assert_that!(write_file(ScriptKind::Sh, dst, "no newline"))
+            .is_equal_to("printf '%s' 'no newline' > 'it'\\''s/foo.txt'\n".to_owned());
+        assert_that!(write_file(ScriptKind::PowerShell, dst, "hello\n")).is_equal_to(
+            "[System.IO.File]::WriteAllText((Join-Path $PWD 'it''s/foo.txt'), @'\nhello\n\n'@)\n"
+                .to_owned(),
+        );
+    }
+
+    #[test]
+    fn test_write_bytes() {
+        let dst = Path::new("logo.bin");
+        let content = [0u8, 159, 146, 150, 255];
+        assert_that!(write_bytes(ScriptKind::Sh, dst, &content)).is_equal_to(
+            "base64 -d > 'logo.bin' <<'FFIZER_EOF'\nAJ+Slv8=\nFFIZER_EOF\n".to_owned(),
+        );
+        assert_that!(write_bytes(ScriptKind::PowerShell, dst, &content)).is_equal_to(
+            "[System.IO.File]::WriteAllBytes((Join-Path $PWD 'logo.bin'), [Convert]::FromBase64String('AJ+Slv8='))\n"
+                .to_owned(),
+        );
+    }
+
+    #[test]
+    fn test_emit_script_raw_files() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let template = tmp_dir.path().join("template");
+        fs::create_dir_all(&template)?;
+        let binary = vec![0u8, 159, 146, 150, 255];
+        fs::write(template.join("logo.bin"), &binary)?;
+        fs::write(template.join("-README.md"), "hello\n")?;
+        let project = tmp_dir.path().join("project");
+        let actions: Vec<Action> = ["logo.bin", "-README.md"]
+            .iter()
+            .map(|name| {
+                let mut src = SourceFile::from((ChildPath::new(&template, name), 0));
+                src.metadata = SourceFileMetadata::RawFile;
+                Action {
+                    src: vec![src],
+                    dst_path: ChildPath::new(&project, name),
+                    operation: FileOperation::AddFile,
+                    variables: Variables::default(),
+                    ownership: None,
+                    update_mode: None,
+                    reason: Reason::NotExisting,
+                    overridden: vec![],
+                }
+            })
+            .collect();
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: project.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let script = tmp_dir.path().join("apply.sh");
+        emit_script(&ctx, &actions, &Variables::default(), &[], &script)?;
+        let content = fs::read_to_string(&script)?;
+        assert_that!(content).contains("base64 -d > 'logo.bin' <<'FFIZER_EOF'\nAJ+Slv8=\n");
+        assert_that!(content).contains("cat > '-README.md' <<'FFIZER_EOF'\nhello\n");
+        // the script doesn't depend on the template
+        assert_that!(content.contains(&template.to_string_lossy().to_string())).is_false();
+
+        let ps1 = tmp_dir.path().join("apply.ps1");
+        emit_script(&ctx, &actions, &Variables::default(), &[], &ps1)?;
+        assert_that!(fs::read_to_string(&ps1)?).contains("[Convert]::FromBase64String('AJ+Slv8=')");
+
+        if std::process::Command::new("base64")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            eprintln!("skip the run of the script because `base64` is not installed");
+            return Ok(());
+        }
+        let status = std::process::Command::new("sh")
+            .arg(&script)
+            .arg(&project)
+            .status()?;
+        assert_that!(status.success()).is_true();
+        assert_that!(fs::read(project.join("logo.bin"))?).is_equal_to(binary);
+        assert_that!(fs::read_to_string(project.join("-README.md"))?)
+            .is_equal_to("hello\n".to_owned());
+        Ok(())
+    }
+}
@@ -28,6 +28,47 @@ pub enum Error {
         rev: String,
         source: git2::Error,
     },
+    #[error("find commit of rev {rev:?} in git repository {path:?}")]
+    GitFindRev {
+        path: PathBuf,
+        rev: String,
+        source: git2::Error,
+    },
+    #[error("find url of remote origin in git repository {path:?}")]
+    GitFindRemoteUrl { path: PathBuf, source: git2::Error },
+    #[error("list the commits from {from:?} to {to:?} in git repository {path:?}")]
+    GitLog {
+        path: PathBuf,
+        from: String,
+        to: String,
+        source: git2::Error,
+    },
+    #[error("init git repository with an initial commit in folder {path:?}")]
+    GitInit { path: PathBuf, source: git2::Error },
+    #[error("download {url:?}")]
+    Download { url: String, source: reqwest::Error },
+    #[error("no backend to download {url:?} (supported: http, https, s3://<bucket>/<key>)")]
+    UnsupportedScheme { url: String },
+    #[error("failed to sign the request to {url:?} (no host)")]
+    SignRequest { url: String },
+    #[error("no provider to retrieve the template {uri:?}")]
+    NoSourceProvider { uri: String },
+    #[error("no source of template (`--source`), templates registered into {path:?}: {names:?}")]
+    MissingSource { path: PathBuf, names: Vec<String> },
+    #[error("post to {url:?}")]
+    Post { url: String, source: reqwest::Error },
+    #[error("checksum (sha256) of {url:?} is {actual:?}, expected {expected:?}")]
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("extract archive {url:?} into folder {dst:?}")]
+    ExtractArchive {
+        url: String,
+        dst: PathBuf,
+        source: std::io::Error,
+    },
     #[error("try to find git config '{key:?}'")]
     GitFindConfig { key: String, source: git2::Error },
 
@@ -81,6 +122,8 @@ pub enum Error {
         path: PathBuf,
         source: std::io::Error,
     },
+    #[error("read the variables from stdin")]
+    ReadStdin { source: std::io::Error },
     #[error("write file {path:?}")]
     WriteFile {
         path: PathBuf,
@@ -91,6 +134,79 @@ pub enum Error {
         path: PathBuf,
         source: std::io::Error,
     },
+    #[error("create pool of threads")]
+    CreateThreadPool { source: rayon::ThreadPoolBuildError },
+    #[error("{src1:?} and {src2:?} (from the same template) generate the same file {dst:?}")]
+    DuplicateDestination {
+        dst: PathBuf,
+        src1: PathBuf,
+        src2: PathBuf,
+    },
+    #[error("{src:?} generates the file {dst:?}, but {child_src:?} generates {child_dst:?} inside it (as a folder)")]
+    FileUsedAsFolder {
+        dst: PathBuf,
+        src: PathBuf,
+        child_dst: PathBuf,
+        child_src: PathBuf,
+    },
+    #[error("template {src:?} can not be applied into {dst:?}: {reason}")]
+    SelfApplication {
+        src: String,
+        dst: PathBuf,
+        reason: String,
+    },
+    #[error("{src:?} generates {dst:?}, a path outside of the destination folder {dst_folder:?}")]
+    PathOutsideDestination {
+        dst: PathBuf,
+        src: PathBuf,
+        dst_folder: PathBuf,
+    },
+    #[error("{src:?} is a symlink to {target:?}, outside of the template folder {template:?}")]
+    SymlinkOutsideTemplate {
+        src: PathBuf,
+        target: PathBuf,
+        template: PathBuf,
+    },
+    #[error("{src:?} generates {dst:?}, a path not valid on Windows: {reason}")]
+    UnportablePath {
+        dst: PathBuf,
+        src: PathBuf,
+        reason: String,
+    },
+    #[error("template {uri:?} is not recorded into {lockfile:?} (required by --locked)")]
+    TemplateNotLocked { uri: String, lockfile: PathBuf },
+    #[error("no template (hosted in a git repository) recorded into {lockfile:?}, apply the template again before an upgrade")]
+    NoLockedSource { lockfile: PathBuf },
+    #[error("3-way merge of {path:?}: {message}")]
+    ThreeWayMerge { path: PathBuf, message: String },
+    #[error("fail to apply the patch {path:?}: {message}")]
+    ApplyPatch { path: PathBuf, message: String },
+    #[error("fail to merge the fragment {path:?}: {message}")]
+    MergeFragment { path: PathBuf, message: String },
+    #[error("invalid owner {value:?}, expected \"uid[:gid]\"")]
+    ParseOwner { value: String },
+    #[error("change owner of {path:?}")]
+    ChangeOwner {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unknown encoding {label:?} (see https://encoding.spec.whatwg.org/#names-and-labels)")]
+    UnknownEncoding { label: String },
+    #[error("the plan was declined")]
+    ApplyAborted {},
+    #[error("{message} (rule of the template {template})")]
+    RuleViolated { template: String, message: String },
+    #[error("{count} file(s) or folder(s) drifted from the template")]
+    DriftDetected { count: usize },
+    #[error("no value for the variable {name:?} (no default value, and no interaction to ask it)")]
+    MissingVariable { name: String },
+    #[error("failed after {} files or folders created or updated", .done.len())]
+    PartialFailure {
+        done: Vec<PathBuf>,
+        source: Box<Error>,
+    },
+    #[error("cancelled while {when} ({} files or folders done)", .done.len())]
+    Cancelled { when: String, done: Vec<PathBuf> },
     #[error("run command '{cmd:?}'")]
     RunCommand { cmd: String, source: std::io::Error },
     #[error("fail to parse string as path '{value:?}'")]
@@ -126,11 +242,21 @@ pub enum Error {
         template: String,
         source: handlebars::TemplateRenderError,
     },
+    #[error("fail to render template '{template}': {limit}")]
+    RenderLimitExceeded { template: String, limit: String },
     #[error(transparent)]
     SerdeYaml {
         #[from]
         source: serde_yaml::Error,
     },
+    #[error("invalid template configuration {path:?}:\n{issues}")]
+    InvalidTemplateCfg { path: PathBuf, issues: String },
+    #[error("the template {path:?} requires ffizer {required}, the current version is {current}: upgrade ffizer (`ffizer upgrade`)")]
+    IncompatibleFfizerVersion {
+        path: PathBuf,
+        required: String,
+        current: String,
+    },
     #[error("fail to process script '{script}'")]
     ScriptError {
         script: String,
@@ -157,3 +283,138 @@ pub enum Error {
         source: clap::Error,
     },
 }
+
+/// the kinds of error, for the scripts and the CI (each kind has its exit code)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// any other failure (exit code 1)
+    Other,
+    /// the template (local folder, git repository, archive) is not found or can not be retrieved (exit code 2)
+    TemplateNotFound,
+    /// the authentication to the host of the template failed (exit code 3)
+    GitAuthFailed,
+    /// a variable has no value (without interaction), or a value not accepted (exit code 4)
+    VariableMissing,
+    /// the plan was declined by the user, violates a rule of the template, or the apply was cancelled
+    /// before any change (exit code 5)
+    ApplyAborted,
+    /// the apply failed (or was cancelled) after some files or folders were created or updated (exit code 6)
+    PartialFailure,
+    /// the project drifted from its template (`ffizer check`) (exit code 7)
+    DriftDetected,
+}
+
+impl ErrorKind {
+    /// the exit code of the process
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::TemplateNotFound => 2,
+            ErrorKind::GitAuthFailed => 3,
+            ErrorKind::VariableMissing => 4,
+            ErrorKind::ApplyAborted => 5,
+            ErrorKind::PartialFailure => 6,
+            ErrorKind::DriftDetected => 7,
+        }
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::LocalPathNotFound { .. }
+            | Error::GitFindRev { .. }
+            | Error::NoSourceProvider { .. }
+            | Error::MissingSource { .. } => ErrorKind::TemplateNotFound,
+            Error::GitRetrieve { .. } if crate::git::is_auth_error(self) => {
+                ErrorKind::GitAuthFailed
+            }
+            Error::GitRetrieve { .. } => ErrorKind::TemplateNotFound,
+            Error::Download { source, .. } => match source.status().map(|s| s.as_u16()) {
+                Some(401) | Some(403) => ErrorKind::GitAuthFailed,
+                _ => ErrorKind::TemplateNotFound,
+            },
+            Error::MissingVariable { .. } | Error::StringValueNotIn { .. } => {
+                ErrorKind::VariableMissing
+            }
+            Error::ApplyAborted {} | Error::RuleViolated { .. } => ErrorKind::ApplyAborted,
+            Error::Cancelled { done, .. } if done.is_empty() => ErrorKind::ApplyAborted,
+            Error::Cancelled { .. } | Error::PartialFailure { .. } => ErrorKind::PartialFailure,
+            Error::DriftDetected { .. } => ErrorKind::DriftDetected,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_kind_and_exit_code() {
+        let cases = vec![
+            (
+                Error::LocalPathNotFound {
+                    path: PathBuf::from("foo"),
+                    uri: "foo".to_owned(),
+                    subfolder: None,
+                },
+                2,
+            ),
+            (
+                Error::MissingSource {
+                    path: PathBuf::from("registry.yaml"),
+                    names: vec![],
+                },
+                2,
+            ),
+            (
+                Error::GitRetrieve {
+                    dst: PathBuf::from("foo"),
+                    url: "https://example.com/foo.git".to_owned(),
+                    rev: "master".to_owned(),
+                    source: git2::Error::new(
+                        git2::ErrorCode::Auth,
+                        git2::ErrorClass::Http,
+                        "authentication required",
+                    ),
+                },
+                3,
+            ),
+            (
+                Error::MissingVariable {
+                    name: "project".to_owned(),
+                },
+                4,
+            ),
+            (Error::ApplyAborted {}, 5),
+            (
+                Error::RuleViolated {
+                    template: "foo".to_owned(),
+                    message: "the destination folder is not empty".to_owned(),
+                },
+                5,
+            ),
+            (
+                Error::Cancelled {
+                    when: "executing plan".to_owned(),
+                    done: vec![],
+                },
+                5,
+            ),
+            (
+                Error::PartialFailure {
+                    done: vec![PathBuf::from("foo")],
+                    source: Box::new(Error::Unknown("boom".to_owned())),
+                },
+                6,
+            ),
+            (Error::DriftDetected { count: 2 }, 7),
+            (Error::Unknown("boom".to_owned()), 1),
+        ];
+        for (err, code) in cases {
+            assert_that!(err.kind().exit_code()).is_equal_to(code);
+        }
+    }
+}
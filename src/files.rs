@@ -1,11 +1,21 @@
+use crate::cli_opt::Owner;
 use crate::path_pattern::PathPattern;
 use crate::Result;
 use std::path::Path;
 use std::path::PathBuf;
-use walkdir::WalkDir;
 
 pub const FILEEXT_HANDLEBARS: &str = ".ffizer.hbs";
 pub const FILEEXT_RAW: &str = ".ffizer.raw";
+/// suffix of the lines to append (if missing) to the existing file
+pub const FILEEXT_APPEND: &str = ".ffizer.append";
+/// suffix of the patch (unified diff) to apply to the existing file
+pub const FILEEXT_PATCH: &str = ".ffizer.patch";
+/// suffix of the json fragment to deep-merge into the existing file
+pub const FILEEXT_MERGE_JSON: &str = ".ffizer.merge-json";
+/// suffix of the toml fragment to deep-merge into the existing file
+pub const FILEEXT_MERGE_TOML: &str = ".ffizer.merge-toml";
+/// suffix of the copy of a file before its override
+pub const FILEEXT_BACKUP: &str = ".ffizer.bak";
 
 #[derive(Debug, Clone, PartialEq, Eq, Default, Hash, Ord, PartialOrd)]
 pub struct ChildPath {
@@ -32,10 +42,19 @@ impl<'a> From<&'a ChildPath> for PathBuf {
     }
 }
 
+/// a file with `.ffizer.raw` (the escape marker) is never rendered, even if `.ffizer.hbs` is also present
 pub fn is_ffizer_handlebars(path: &Path) -> bool {
     path.file_name()
         .and_then(|s| s.to_str())
-        .map(|str| str.contains(FILEEXT_HANDLEBARS))
+        .map(|str| str.contains(FILEEXT_HANDLEBARS) && !str.contains(FILEEXT_RAW))
+        .unwrap_or(false)
+}
+
+/// a file with `.ffizer.append` or `.ffizer.patch` (and without `.ffizer.raw`) edits the existing file
+pub fn is_ffizer_edit(path: &Path, ext: &str) -> bool {
+    path.file_name()
+        .and_then(|s| s.to_str())
+        .map(|str| str.contains(ext) && !str.contains(FILEEXT_RAW))
         .unwrap_or(false)
 }
 
@@ -54,11 +73,29 @@ fn remove_special_suffix_on_filename(v: &str) -> String {
         v.replacen(FILEEXT_RAW, "", 1)
     } else if v.contains(FILEEXT_HANDLEBARS) {
         v.replacen(FILEEXT_HANDLEBARS, "", 1)
+    } else if v.contains(FILEEXT_APPEND) {
+        v.replacen(FILEEXT_APPEND, "", 1)
+    } else if v.contains(FILEEXT_PATCH) {
+        v.replacen(FILEEXT_PATCH, "", 1)
+    } else if v.contains(FILEEXT_MERGE_JSON) {
+        v.replacen(FILEEXT_MERGE_JSON, "", 1)
+    } else if v.contains(FILEEXT_MERGE_TOML) {
+        v.replacen(FILEEXT_MERGE_TOML, "", 1)
     } else {
         v.to_owned()
     }
 }
 
+/// `<path>.part` (not `with_extension`, the name could contains a `.`, eg: `v1.0`)
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = path
+        .file_name()
+        .map(|s| s.to_os_string())
+        .unwrap_or_default();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
 pub fn add_suffix<P>(path: P, suffix: &str) -> Result<PathBuf>
 where
     P: AsRef<Path>,
@@ -70,23 +107,322 @@ where
     )))
 }
 
-pub fn find_childpaths<P>(base: P, ignores: &[PathPattern]) -> Vec<ChildPath>
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// check that every segment of the relative path is a valid name on Windows:
+/// no reserved name (`CON`, `NUL.txt`,...), no trailing dot or space, no `<>:"/\|?*` or control characters
+pub fn check_portable_path(relative: &Path) -> std::result::Result<(), String> {
+    for segment in relative.iter() {
+        let segment = segment.to_string_lossy();
+        if let Some(c) = segment
+            .chars()
+            .find(|c| c.is_control() || "<>:\"/\\|?*".contains(*c))
+        {
+            return Err(format!("{:?} contains the character {:?}", segment, c));
+        }
+        if segment.ends_with('.') || segment.ends_with(' ') {
+            return Err(format!("{:?} ends with a dot or a space", segment));
+        }
+        let stem = segment.split('.').next().unwrap_or_default().trim_end();
+        if WINDOWS_RESERVED_NAMES
+            .iter()
+            .any(|n| n.eq_ignore_ascii_case(stem))
+        {
+            return Err(format!("{:?} is a reserved name", segment));
+        }
+    }
+    Ok(())
+}
+
+/// the absolute path, with symlinks resolved (like `canonicalize`) even if the path doesn't exist yet:
+/// the nearest existing ancestor is canonicalized, then joined with the remaining of the path
+pub fn absolute_path<P>(path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let path = std::env::current_dir()
+        .map(|cwd| cwd.join(path.as_ref()))
+        .unwrap_or_else(|_| path.as_ref().to_path_buf());
+    let mut remaining = vec![];
+    let mut ancestor = path.as_path();
+    loop {
+        if let Ok(canonical) = ancestor.canonicalize() {
+            return remaining.iter().rev().fold(canonical, |p, c| p.join(c));
+        }
+        match (ancestor.parent(), ancestor.file_name()) {
+            (Some(parent), Some(name)) => {
+                remaining.push(name.to_os_string());
+                ancestor = parent;
+            }
+            _ => return path,
+        }
+    }
+}
+
+/// the number of symlinks followed by `resolve_symlink` (against the loops)
+const MAX_SYMLINK_HOPS: usize = 8;
+
+/// the target of the symlink `path` (absolute, like `absolute_path`), the dangling symlinks (not
+/// resolved by `canonicalize`) are followed too
+pub fn resolve_symlink<P>(path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let mut path = path.as_ref().to_path_buf();
+    for _ in 0..MAX_SYMLINK_HOPS {
+        match std::fs::read_link(&path) {
+            Ok(target) => path = path.parent().map(|p| p.join(&target)).unwrap_or(target),
+            Err(_) => break,
+        }
+    }
+    absolute_path(path)
+}
+
+/// size of the chunks read to copy or to hash a file
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// copy the content of the file `src` into `dst` by chunks (the whole file is never loaded in memory),
+/// `on_progress` is called with the number of bytes copied after each chunk
+pub fn copy_file<F>(src: &Path, dst: &Path, mut on_progress: F) -> Result<u64>
+where
+    F: FnMut(u64),
+{
+    use std::io::{Read, Write};
+    let copy_error = |source| crate::Error::CopyFile {
+        src: src.to_path_buf(),
+        dst: dst.to_path_buf(),
+        source,
+    };
+    let mut reader = std::fs::File::open(src).map_err(copy_error)?;
+    let mut writer = std::io::BufWriter::with_capacity(
+        CHUNK_SIZE,
+        std::fs::File::create(dst).map_err(copy_error)?,
+    );
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut copied = 0;
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(copy_error(e)),
+        };
+        writer.write_all(&buffer[..n]).map_err(copy_error)?;
+        copied += n as u64;
+        on_progress(copied);
+    }
+    writer.flush().map_err(copy_error)?;
+    Ok(copied)
+}
+
+/// the sha256 (hex) of the content of the file, read by chunks
+pub fn sha256_file(path: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+    let read_error = |source| crate::Error::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut reader = std::fs::File::open(path).map_err(read_error)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(read_error(e)),
+        };
+        hasher.update(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// true if the files have the same content (same size, then same hash)
+pub fn is_same_file_content(a: &Path, b: &Path) -> Result<bool> {
+    let len = |path: &Path| {
+        std::fs::metadata(path)
+            .map(|m| m.len())
+            .map_err(|source| crate::Error::ReadFile {
+                path: path.to_path_buf(),
+                source,
+            })
+    };
+    Ok(len(a)? == len(b)? && sha256_file(a)? == sha256_file(b)?)
+}
+
+/// size of the head of a file sniffed to detect a binary content
+const SNIFF_SIZE: usize = 8 * 1024;
+
+/// true if the file looks binary (image, archive, jar,...), see [`looks_binary`]
+pub fn is_binary(path: &Path) -> Result<bool> {
+    use std::io::Read;
+    let read_error = |source| crate::Error::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut head = Vec::with_capacity(SNIFF_SIZE);
+    std::fs::File::open(path)
+        .map_err(read_error)?
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut head)
+        .map_err(read_error)?;
+    Ok(looks_binary(&head))
+}
+
+/// true if the `head` of a content has a NUL byte, or more than 10% of control characters
+/// (except tab, new line, form feed, carriage return and escape)
+pub fn looks_binary(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return true;
+    }
+    let controls = head
+        .iter()
+        .filter(|b| **b < 0x20 && !matches!(**b, b'\t' | b'\n' | 0x0c | b'\r' | 0x1b))
+        .count();
+    controls * 10 > head.len()
+}
+
+/// the text of the `content` encoded with `encoding` (the BOM, if any, is removed)
+pub fn decode_text(content: &[u8], encoding: &'static encoding_rs::Encoding) -> String {
+    let (text, _, _) = encoding.decode(content);
+    text.into_owned()
+}
+
+/// the `text` encoded with `encoding`, prefixed by the BOM of the encoding if `with_bom`
+pub fn encode_text(
+    text: &str,
+    encoding: &'static encoding_rs::Encoding,
+    with_bom: bool,
+) -> Vec<u8> {
+    let mut back = vec![];
+    // the encoder of encoding_rs only outputs UTF-8 for UTF-16
+    if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+        let le = encoding == encoding_rs::UTF_16LE;
+        let units = (if with_bom { Some(0xFEFF) } else { None })
+            .into_iter()
+            .chain(text.encode_utf16());
+        for unit in units {
+            back.extend_from_slice(&if le {
+                unit.to_le_bytes()
+            } else {
+                unit.to_be_bytes()
+            });
+        }
+    } else {
+        if with_bom && encoding == encoding_rs::UTF_8 {
+            back.extend_from_slice(b"\xEF\xBB\xBF");
+        }
+        let (bytes, _, _) = encoding.encode(text);
+        back.extend_from_slice(&bytes);
+    }
+    back
+}
+
+/// change the owner of the file or folder (the link itself for a symlink)
+#[cfg(all(unix, not(target_os = "redox")))]
+pub fn chown(path: &Path, owner: &Owner) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let to_error = |source| crate::Error::ChangeOwner {
+        path: path.to_path_buf(),
+        source,
+    };
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| to_error(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+    // `-1` keeps the group
+    let gid = owner
+        .gid
+        .map(|g| g as libc::gid_t)
+        .unwrap_or(libc::gid_t::MAX);
+    // safe: the path is a valid nul-terminated string for the duration of the call
+    if unsafe { libc::lchown(c_path.as_ptr(), owner.uid as libc::uid_t, gid) } == 0 {
+        Ok(())
+    } else {
+        Err(to_error(std::io::Error::last_os_error()))
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "redox"))))]
+pub fn chown(path: &Path, _owner: &Owner) -> Result<()> {
+    Err(crate::Error::ChangeOwner {
+        path: path.to_path_buf(),
+        source: std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "change of owner is only supported on unix",
+        ),
+    })
+}
+
+/// selection of the hidden files and folders (name starting with `.`),
+/// `excludes` win over `includes`, that win over `include_all`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DotfilesFilter {
+    pub include_all: bool,
+    pub includes: Vec<PathPattern>,
+    pub excludes: Vec<PathPattern>,
+}
+
+impl Default for DotfilesFilter {
+    fn default() -> Self {
+        DotfilesFilter {
+            include_all: true,
+            includes: vec![],
+            excludes: vec![],
+        }
+    }
+}
+
+impl DotfilesFilter {
+    pub fn accept(&self, relative: &str) -> bool {
+        if self.excludes.iter().any(|p| p.is_match(relative)) {
+            false
+        } else if self.includes.iter().any(|p| p.is_match(relative)) {
+            true
+        } else {
+            self.include_all
+        }
+    }
+}
+
+/// list the files and folders under `base` (included), except the ones matching `ignores`,
+/// the hidden ones rejected by `dotfiles` (with their content),
+/// and, if `use_gitignore`, the ones excluded by the `.gitignore` files under `base`
+pub fn find_childpaths<P>(
+    base: P,
+    ignores: &[PathPattern],
+    use_gitignore: bool,
+    dotfiles: &DotfilesFilter,
+) -> Vec<ChildPath>
 where
     P: AsRef<Path>,
 {
     let base = base.as_ref();
-    WalkDir::new(base)
+    let filter_base = base.to_path_buf();
+    let filter_ignores = ignores.to_vec();
+    let filter_dotfiles = dotfiles.clone();
+    ignore::WalkBuilder::new(base)
+        .standard_filters(false)
+        .git_ignore(use_gitignore)
+        .require_git(false)
         .follow_links(false)
-        .into_iter()
-        .filter_entry(|e| {
+        .filter_entry(move |e| {
             e.path()
-                .strip_prefix(base)
+                .strip_prefix(&filter_base)
                 .expect("scanned child path to be under base")
                 .to_str()
-                .map(|s| !ignores.iter().any(|f| f.is_match(s)))
+                .map(|s| {
+                    let is_hidden =
+                        e.depth() > 0 && e.file_name().to_string_lossy().starts_with('.');
+                    !filter_ignores.iter().any(|f| f.is_match(s))
+                        && (!is_hidden || filter_dotfiles.accept(s))
+                })
                 // .map(|s| true)
                 .unwrap_or(true)
         })
+        .build()
         .filter_map(|e| e.ok())
         .map(|entry| ChildPath {
             base: base.to_path_buf(),
@@ -99,10 +435,119 @@ where
         .collect::<Vec<_>>()
 }
 
+/// list the files and folders under `base` matching `ignores` (the content of an ignored folder
+/// is not listed), with the pattern matched
+pub fn find_ignored_childpaths<P>(
+    base: P,
+    ignores: &[PathPattern],
+) -> Vec<(ChildPath, &PathPattern)>
+where
+    P: AsRef<Path>,
+{
+    let base = base.as_ref();
+    let mut back = vec![];
+    if ignores.is_empty() {
+        return back;
+    }
+    let mut it = walkdir::WalkDir::new(base).min_depth(1).into_iter();
+    while let Some(entry) = it.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let relative = entry
+            .path()
+            .strip_prefix(base)
+            .expect("scanned child path to be under base")
+            .to_path_buf();
+        let pattern = relative
+            .to_str()
+            .and_then(|s| ignores.iter().find(|p| p.is_match(s)));
+        if let Some(pattern) = pattern {
+            if entry.file_type().is_dir() {
+                it.skip_current_dir();
+            }
+            back.push((
+                ChildPath {
+                    base: base.to_path_buf(),
+                    relative,
+                },
+                pattern,
+            ));
+        }
+    }
+    back
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use spectral::prelude::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_copy_file_and_compare() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let src = tmp_dir.path().join("src.bin");
+        let dst = tmp_dir.path().join("dst.bin");
+        let content = (0..(CHUNK_SIZE * 2 + 10))
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        std::fs::write(&src, &content)?;
+        let mut progress = vec![];
+        let copied = copy_file(&src, &dst, |n| progress.push(n))?;
+        assert_that!(copied).is_equal_to(content.len() as u64);
+        assert_that!(progress.last()).is_equal_to(Some(&(content.len() as u64)));
+        assert_that!(std::fs::read(&dst)?).is_equal_to(content);
+        assert_that!(is_same_file_content(&src, &dst)?).is_true();
+        assert_that!(sha256_file(&src)?).is_equal_to(sha256_file(&dst)?);
+
+        std::fs::write(&dst, b"other")?;
+        assert_that!(is_same_file_content(&src, &dst)?).is_false();
+        Ok(())
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        assert_that!(looks_binary(b"hello {{ name }}\r\n\tbye\n")).is_false();
+        assert_that!(looks_binary(b"")).is_false();
+        assert_that!(looks_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")).is_true();
+        assert_that!(looks_binary(b"ab\x01\x02\x03\x04")).is_true();
+    }
+
+    #[test]
+    fn test_encode_decode_text() {
+        let latin1 = encoding_rs::WINDOWS_1252;
+        assert_that!(encode_text("caf\u{e9}", latin1, false)).is_equal_to(b"caf\xE9".to_vec());
+        assert_that!(decode_text(b"caf\xE9", latin1)).is_equal_to("caf\u{e9}".to_owned());
+        let utf16 = encoding_rs::UTF_16LE;
+        let encoded = encode_text("a\u{e9}", utf16, true);
+        assert_that!(encoded).is_equal_to(b"\xFF\xFEa\0\xE9\0".to_vec());
+        assert_that!(decode_text(&encoded, utf16)).is_equal_to("a\u{e9}".to_owned());
+    }
+
+    #[test]
+    fn test_check_portable_path() {
+        assert_that!(check_portable_path(Path::new("src/main.rs"))).is_ok();
+        assert_that!(check_portable_path(Path::new("console/contact.txt"))).is_ok();
+        assert_that!(check_portable_path(Path::new("src/con"))).is_err();
+        assert_that!(check_portable_path(Path::new("Aux.rs"))).is_err();
+        assert_that!(check_portable_path(Path::new("lpt1.tar.gz"))).is_err();
+        assert_that!(check_portable_path(Path::new("a:b/c.txt"))).is_err();
+        assert_that!(check_portable_path(Path::new("doc./c.txt"))).is_err();
+        assert_that!(check_portable_path(Path::new("doc /c.txt"))).is_err();
+        assert_that!(check_portable_path(Path::new("what?.txt"))).is_err();
+    }
+
+    #[test]
+    fn test_absolute_path() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let base = tmp_dir.path().canonicalize()?;
+        std::fs::create_dir(base.join("a"))?;
+        assert_that!(absolute_path(tmp_dir.path().join("a"))).is_equal_to(base.join("a"));
+        assert_that!(absolute_path(tmp_dir.path().join("a/../b/c"))).is_equal_to(base.join("b/c"));
+        Ok(())
+    }
 
     #[test]
     fn test_is_ffizer_handlebars() {
@@ -110,6 +555,10 @@ mod tests {
         assert_that!(is_ffizer_handlebars(&PathBuf::from("foo.ffizer.hbs/bar"))).is_false();
         assert_that!(is_ffizer_handlebars(&PathBuf::from("foo_ffizer.hbs"))).is_false();
         assert_that!(is_ffizer_handlebars(&PathBuf::from("fooffizer.hbs"))).is_false();
+        assert_that!(is_ffizer_handlebars(&PathBuf::from(
+            "foo.ffizer.raw.ffizer.hbs"
+        )))
+        .is_false();
 
         assert_that!(is_ffizer_handlebars(&PathBuf::from("foo.ffizer.hbs"))).is_true();
         assert_that!(is_ffizer_handlebars(&PathBuf::from("bar/foo.ffizer.hbs"))).is_true();
@@ -127,12 +576,181 @@ mod tests {
             ("foo.ffizer.raw.ffizer.raw.json", "foo.ffizer.raw.json"),
             ("foo.ffizer.raw.ffizer.hbs.json", "foo.ffizer.hbs.json"),
             ("foo.json.ffizer.raw.ffizer.hbs", "foo.json.ffizer.hbs"),
+            (".gitignore.ffizer.append", ".gitignore"),
+            ("Cargo.toml.ffizer.patch", "Cargo.toml"),
+            ("foo.ffizer.raw.ffizer.patch", "foo.ffizer.patch"),
+            ("package.json.ffizer.merge-json", "package.json"),
+            ("Cargo.toml.ffizer.merge-toml", "Cargo.toml"),
         ] {
             assert_that!(remove_special_suffix_on_filename(input))
                 .is_equal_to(expected.to_string());
         }
     }
 
+    #[test]
+    fn test_part_path_keep_the_full_name() {
+        assert_that!(part_path(Path::new("/cache/foo/v1.0")))
+            .is_equal_to(PathBuf::from("/cache/foo/v1.0.part"));
+    }
+
+    #[test]
+    fn test_find_childpaths_with_dotfiles() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let base = tmp_dir.path();
+        for path in &[
+            ".editorconfig",
+            ".DS_Store",
+            ".idea/workspace.xml",
+            "a.txt",
+            "sub/.DS_Store",
+            "sub/.keep",
+        ] {
+            let path = base.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, "")?;
+        }
+        let find = |dotfiles: &DotfilesFilter| {
+            let mut actual = find_childpaths(base, &[], false, dotfiles)
+                .into_iter()
+                .map(|c| c.relative.to_string_lossy().to_string())
+                .collect::<Vec<_>>();
+            actual.sort();
+            actual
+        };
+        let exclude_some = DotfilesFilter {
+            include_all: true,
+            includes: vec![],
+            excludes: vec![
+                PathPattern::from_str("**/.DS_Store")?,
+                PathPattern::from_str(".idea")?,
+            ],
+        };
+        assert_that!(find(&exclude_some)).is_equal_to(
+            vec!["", ".editorconfig", "a.txt", "sub", "sub/.keep"]
+                .into_iter()
+                .map(|s| s.to_owned())
+                .collect::<Vec<_>>(),
+        );
+        let include_some = DotfilesFilter {
+            include_all: false,
+            includes: vec![PathPattern::from_str(".editorconfig")?],
+            excludes: vec![],
+        };
+        assert_that!(find(&include_some)).is_equal_to(
+            vec!["", ".editorconfig", "a.txt", "sub"]
+                .into_iter()
+                .map(|s| s.to_owned())
+                .collect::<Vec<_>>(),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_childpaths_with_gitignore() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let base = tmp_dir.path();
+        for (path, content) in &[
+            (".gitignore", "target/\n*.log\n"),
+            ("a.txt", ""),
+            ("b.log", ""),
+            ("target/x.txt", ""),
+            ("sub/.gitignore", "c.txt\n"),
+            ("sub/c.txt", ""),
+            ("sub/d.txt", ""),
+            ("ignored/e.txt", ""),
+        ] {
+            let path = base.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+        let ignores = vec![PathPattern::from_str("ignored")?];
+        let find = |use_gitignore| {
+            let mut actual =
+                find_childpaths(base, &ignores, use_gitignore, &DotfilesFilter::default())
+                    .into_iter()
+                    .map(|c| c.relative.to_string_lossy().to_string())
+                    .collect::<Vec<_>>();
+            actual.sort();
+            actual
+        };
+        assert_that!(find(true)).is_equal_to(
+            vec![
+                "",
+                ".gitignore",
+                "a.txt",
+                "sub",
+                "sub/.gitignore",
+                "sub/d.txt",
+            ]
+            .into_iter()
+            .map(|s| s.to_owned())
+            .collect::<Vec<_>>(),
+        );
+        assert_that!(find(false)).is_equal_to(
+            vec![
+                "",
+                ".gitignore",
+                "a.txt",
+                "b.log",
+                "sub",
+                "sub/.gitignore",
+                "sub/c.txt",
+                "sub/d.txt",
+                "target",
+                "target/x.txt",
+            ]
+            .into_iter()
+            .map(|s| s.to_owned())
+            .collect::<Vec<_>>(),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_ignored_childpaths() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let base = tmp_dir.path();
+        for path in &["a.txt", "b.log", "target/x.txt", "target/y.log"] {
+            let path = base.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, "")?;
+        }
+        let ignores = vec![
+            PathPattern::from_str("target")?,
+            PathPattern::from_str("**/*.log")?,
+        ];
+        let mut actual = find_ignored_childpaths(base, &ignores)
+            .into_iter()
+            .map(|(c, p)| (c.relative.to_string_lossy().to_string(), p.raw.clone()))
+            .collect::<Vec<_>>();
+        actual.sort();
+        assert_that!(actual).is_equal_to(vec![
+            ("b.log".to_owned(), "**/*.log".to_owned()),
+            ("target".to_owned(), "target".to_owned()),
+        ]);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_chown_to_the_current_owner() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::MetadataExt;
+        let tmp_dir = tempfile::tempdir()?;
+        let path = tmp_dir.path().join("foo.txt");
+        std::fs::write(&path, "")?;
+        let metadata = std::fs::metadata(&path)?;
+        let owner = Owner {
+            uid: metadata.uid(),
+            gid: Some(metadata.gid()),
+        };
+        chown(&path, &owner)?;
+        assert_that!(std::fs::metadata(&path)?.uid()).is_equal_to(owner.uid);
+        // the group is kept
+        chown(&path, &Owner { gid: None, ..owner })?;
+        assert_that!(std::fs::metadata(&path)?.gid()).is_equal_to(metadata.gid());
+        Ok(())
+    }
+
     #[test]
     fn test_add_suffix() -> Result<(), Box<dyn std::error::Error>> {
         assert_that!(add_suffix(&PathBuf::from("foo.ext1"), "")?)
@@ -0,0 +1,169 @@
+//! record, into the destination folder, a deterministic hash of the inputs of the generation
+//! (templates with their commits and configurations, answers, version of ffizer), with the inputs
+//! (the answers are frozen into the record): two projects with the same fingerprint were generated
+//! identically, and a regeneration with the same inputs produces the same files.
+
+use crate::cfg::TemplateComposite;
+use crate::error::*;
+use crate::variables::Variables;
+use crate::Ctx;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FINGERPRINT_FILENAME: &str = ".ffizer.fingerprint.yaml";
+
+/// variables that depend on the destination, not part of the fingerprint
+const EXCLUDED_VARIABLES: &[&str] = &["ffizer_dst_folder", crate::variables::FFIZER_VARIABLE];
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Fingerprint {
+    /// sha256 of the other fields
+    pub hash: String,
+    pub ffizer_version: String,
+    /// the template applied, then its imports
+    pub templates: Vec<FingerprintTemplate>,
+    pub answers: Variables,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct FingerprintTemplate {
+    pub uri: String,
+    pub rev: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subfolder: Option<PathBuf>,
+    /// the id (sha) of the commit applied (`None` if not hosted in a git repository)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit_id: Option<String>,
+    /// sha256 of the configuration (`.ffizer.yaml`) of the template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfg_sha256: Option<String>,
+}
+
+impl Fingerprint {
+    pub fn new(
+        ffizer_version: &str,
+        templates: Vec<FingerprintTemplate>,
+        variables: &Variables,
+    ) -> Result<Fingerprint> {
+        // the secrets are not recorded (nor part of the hash)
+        let mut answers = variables.without_secrets();
+        for name in EXCLUDED_VARIABLES {
+            answers.remove(*name);
+        }
+        let mut fingerprint = Fingerprint {
+            hash: String::new(),
+            ffizer_version: ffizer_version.to_owned(),
+            templates,
+            answers,
+        };
+        // the inputs are serialized in a stable order (the answers are sorted by name)
+        let inputs = serde_yaml::to_string(&fingerprint)?;
+        fingerprint.hash = format!("{:x}", Sha256::digest(inputs.as_bytes()));
+        Ok(fingerprint)
+    }
+
+    /// the fingerprint of the apply of the templates with the answers `variables`
+    pub(crate) fn compute(
+        ctx: &Ctx,
+        template_composite: &TemplateComposite,
+        variables: &Variables,
+    ) -> Result<Fingerprint> {
+        let mut templates = vec![];
+        for loc in template_composite.find_locs() {
+            // the template is already retrieved
+            let local = loc.retrieve(&ctx.logger, true)?;
+            let cfg_sha256 = if let Some(cfg_path) = crate::cfg::find_cfg_file(&local.path) {
+                let content = fs::read(&cfg_path).map_err(|source| Error::ReadFile {
+                    path: cfg_path.clone(),
+                    source,
+                })?;
+                Some(format!("{:x}", Sha256::digest(&content)))
+            } else {
+                None
+            };
+            templates.push(FingerprintTemplate {
+                uri: loc.uri.raw.clone(),
+                rev: loc.rev.clone(),
+                subfolder: loc.subfolder.clone(),
+                commit_id: local.rev_info.map(|r| r.commit_id),
+                cfg_sha256,
+            });
+        }
+        Self::new(env!("CARGO_PKG_VERSION"), templates, variables)
+    }
+
+    pub fn path_in<P>(dst_folder: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        dst_folder.as_ref().join(FINGERPRINT_FILENAME)
+    }
+
+    /// load the fingerprint of the destination folder, `None` if not present
+    pub fn load<P>(dst_folder: P) -> Result<Option<Fingerprint>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Self::path_in(dst_folder);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path).map_err(|source| Error::ReadFile {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Some(serde_yaml::from_str(&content)?))
+    }
+
+    pub fn save<P>(&self, dst_folder: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Self::path_in(dst_folder);
+        let content = serde_yaml::to_string(self)?;
+        fs::write(&path, content).map_err(|source| Error::WriteFile { path, source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_hash_is_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+        let templates = vec![FingerprintTemplate {
+            uri: "https://github.com/ffizer/template_sample.git".to_owned(),
+            rev: "master".to_owned(),
+            commit_id: Some("0123456789abcdef0123456789abcdef01234567".to_owned()),
+            ..Default::default()
+        }];
+        let mut variables = Variables::default();
+        variables.insert("project", "foo")?;
+        variables.insert("ffizer_dst_folder", "/home/alice/foo")?;
+        let fingerprint = Fingerprint::new("2.1.0", templates.clone(), &variables)?;
+        assert_that!(fingerprint.answers.get("ffizer_dst_folder")).is_none();
+
+        // same inputs into an other folder
+        variables.insert("ffizer_dst_folder", "/home/bob/foo")?;
+        let same = Fingerprint::new("2.1.0", templates.clone(), &variables)?;
+        assert_that!(same.hash).is_equal_to(fingerprint.hash.clone());
+
+        variables.insert("project", "bar")?;
+        let other = Fingerprint::new("2.1.0", templates.clone(), &variables)?;
+        assert_that!(other.hash).is_not_equal_to(fingerprint.hash.clone());
+        let other = Fingerprint::new("2.2.0", templates, &variables)?;
+        assert_that!(other.hash).is_not_equal_to(fingerprint.hash.clone());
+
+        let tmp_dir = tempdir()?;
+        assert_that!(Fingerprint::load(tmp_dir.path())?).is_none();
+        fingerprint.save(tmp_dir.path())?;
+        assert_that!(Fingerprint::load(tmp_dir.path())?).is_equal_to(Some(fingerprint));
+        Ok(())
+    }
+}
@@ -1,94 +1,513 @@
+//! retrieval of templates hosted in git repositories (clone or update of the cached copy).
+//! see [`SourceLoc::retrieve`](crate::SourceLoc::retrieve) to resolve the local path of any template.
+
+use crate::auth::HostAuth;
 use crate::error::*;
+use crate::files;
 use git2::build::{CheckoutBuilder, RepoBuilder};
-use git2::{Config, FetchOptions, Repository};
+use git2::{BranchType, Config, FetchOptions, Repository, RepositoryState, Signature};
 use git2_credentials;
+use indicatif::ProgressBar;
 use slog::{debug, info, warn, Logger};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// the rev used when no rev is defined (the default value of cli's `--rev`)
+pub(crate) const DEFAULT_REV: &str = "master";
+
+/// the commit used for the requested rev of a git repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevInfo {
+    /// the requested rev (branch, tag, sha,...)
+    pub rev: String,
+    /// the id (sha) of the commit
+    pub commit_id: String,
+    /// the time of the commit (seconds since Unix epoch)
+    pub commit_time: i64,
+}
+
+/// the policy of the network operations (clone, fetch) on the git repositories,
+/// defined by the environment variables:
+/// - `FFIZER_GIT_TIMEOUT`: max duration (in seconds) of a retrieve, 0 for no limit (default: 300)
+/// - `FFIZER_GIT_RETRIES`: number of retries of a failed retrieve, except authentication failures (default: 2)
+/// - `FFIZER_GIT_RETRY_DELAY`: delay (in seconds) before the first retry, doubled on each retry (default: 1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkPolicy {
+    pub timeout: Option<Duration>,
+    pub retries: u32,
+    pub retry_delay: Duration,
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        NetworkPolicy {
+            timeout: Some(Duration::from_secs(300)),
+            retries: 2,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl NetworkPolicy {
+    pub fn from_env() -> NetworkPolicy {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+        let default = NetworkPolicy::default();
+        NetworkPolicy {
+            timeout: match var("FFIZER_GIT_TIMEOUT") {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => default.timeout,
+            },
+            retries: var("FFIZER_GIT_RETRIES")
+                .map(|v| v as u32)
+                .unwrap_or(default.retries),
+            retry_delay: var("FFIZER_GIT_RETRY_DELAY")
+                .map(Duration::from_secs)
+                .unwrap_or(default.retry_delay),
+        }
+    }
+
+    /// the delay before the retry `attempt` (starting at 1), doubled on each retry
+    pub fn delay_before(&self, attempt: u32) -> Duration {
+        self.retry_delay * 2u32.saturating_pow(attempt.saturating_sub(1))
+    }
+}
+
+/// the failure could be temporary (network, timeout,...), so the retrieve can be retried
+fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::GitRetrieve { source, .. } if !is_auth_error(err) => {
+            source.code() == git2::ErrorCode::User
+                || matches!(
+                    source.class(),
+                    git2::ErrorClass::Net
+                        | git2::ErrorClass::Http
+                        | git2::ErrorClass::Ssh
+                        | git2::ErrorClass::Os
+                        | git2::ErrorClass::Callback
+                )
+        }
+        _ => false,
+    }
+}
 
-/// clone a repository at a rev to a directory
-// TODO id the directory is already present then fetch and rebase (if not in offline mode)
+/// clone a repository at a rev to a directory,
+/// if the directory is already present (and valid) then fetch and merge the rev.
+/// The clone is shallow when possible, with the credentials of the git's configuration
+/// (see [`retrieve_with_auth`]).
 pub fn retrieve<P, U, R>(logger: &Logger, dst: P, url: U, rev: R) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    R: AsRef<str>,
+    U: AsRef<str>,
+{
+    retrieve_with_auth(logger, dst, url, rev, None, false)
+}
+
+/// like [`retrieve`], but the credentials `auth` (if defined) are tried before the credentials of
+/// the git's configuration.
+/// Unless `full_history`, the clone is shallow (only the commit of the rev) when possible.
+/// The retrieve is aborted after the timeout and retried on temporary failures (see [`NetworkPolicy`]).
+pub fn retrieve_with_auth<P, U, R>(
+    logger: &Logger,
+    dst: P,
+    url: U,
+    rev: R,
+    auth: Option<&HostAuth>,
+    full_history: bool,
+) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    R: AsRef<str>,
+    U: AsRef<str>,
+{
+    let policy = NetworkPolicy::from_env();
+    let mut attempt = 0;
+    loop {
+        let deadline = policy.timeout.map(|t| Instant::now() + t);
+        match retrieve_once(
+            logger,
+            dst.as_ref(),
+            url.as_ref(),
+            rev.as_ref(),
+            auth,
+            full_history,
+            deadline,
+        ) {
+            Err(err) if attempt < policy.retries && is_retryable(&err) => {
+                attempt += 1;
+                let delay = policy.delay_before(attempt);
+                warn!(logger, "failed to retrieve the git repository, retry"; "url" => url.as_ref(), "attempt" => attempt, "delay" => ?&delay, "error" => ?&err);
+                std::thread::sleep(delay);
+            }
+            res => return res,
+        }
+    }
+}
+
+fn retrieve_once(
+    logger: &Logger,
+    dst: &Path,
+    url: &str,
+    rev: &str,
+    auth: Option<&HostAuth>,
+    full_history: bool,
+    deadline: Option<Instant>,
+) -> Result<(), Error> {
+    let progress = ProgressBar::new(0);
+    let fetch_options = || {
+        make_fetch_options(logger, auth.cloned(), rev, deadline, progress.clone()).map_err(
+            |source| Error::GitRetrieve {
+                dst: dst.to_path_buf(),
+                url: url.to_owned(),
+                rev: rev.to_owned(),
+                source,
+            },
+        )
+    };
+    let is_valid = dst.exists() && is_cache_valid(logger, dst);
+    let res = if is_valid && looks_like_sha(rev) && checkout(dst, &rev).is_ok() {
+        // the commit is already in the cache, nothing to fetch
+        info!(logger, "git checkout cached commit"; "folder" => ?&dst);
+        Ok(())
+    } else if is_valid && !is_shallow(dst) && !looks_like_sha(rev) {
+        match update_cache(logger, dst, &rev, &mut fetch_options()?) {
+            // a timeout is not an unexpected change of the cache
+            Err(source) if source.code() == git2::ErrorCode::User => Err(Error::GitRetrieve {
+                dst: dst.to_path_buf(),
+                url: url.to_owned(),
+                rev: rev.to_owned(),
+                source,
+            }),
+            Err(err) => {
+                // eg: merge conflicts, detached state,... (unexpected changes of the cache)
+                warn!(logger, "failed to update the cached template, it will be cloned again"; "folder" => ?&dst, "error" => err.message());
+                clone_then_swap(
+                    logger,
+                    dst,
+                    &url,
+                    &rev,
+                    fetch_options()?,
+                    !full_history,
+                    deadline,
+                )
+            }
+            Ok(_) => Ok(()),
+        }
+    } else {
+        // libgit2 can't fetch into a shallow clone, so a shallow cache is cloned again (cheap),
+        // like a commit not in the cache
+        info!(logger, "git clone into cached template"; "folder" => ?&dst, "full_history" => full_history);
+        clone_then_swap(
+            logger,
+            dst,
+            &url,
+            &rev,
+            fetch_options()?,
+            !full_history,
+            deadline,
+        )
+    };
+    progress.finish_and_clear();
+    res
+}
+
+/// update the cached template to the tip of the rev (fetch and merge)
+fn update_cache<R>(
+    logger: &Logger,
+    dst: &Path,
+    rev: R,
+    fo: &mut FetchOptions<'_>,
+) -> Result<(), git2::Error>
+where
+    R: AsRef<str>,
+{
+    info!(logger, "git reset cached template"; "folder" => ?&dst);
+    checkout(dst, &rev)?;
+    info!(logger, "git pull cached template"; "folder" => ?&dst);
+    pull(logger, dst, &rev, fo)?;
+    // pull update the working dir to the head of the branch, restore the requested rev (eg: tag)
+    checkout(dst, &rev)
+}
+
+/// clone into a sibling `.part` folder, then replace the destination by it,
+/// so an interrupted clone never leaves a partial cached template.
+/// If `shallow`, a shallow clone is tried first (fallback to a full clone on failure).
+fn clone_then_swap<P, U, R>(
+    logger: &Logger,
+    dst: P,
+    url: U,
+    rev: R,
+    fo: FetchOptions<'_>,
+    shallow: bool,
+    deadline: Option<Instant>,
+) -> Result<(), Error>
 where
     P: AsRef<Path>,
     R: AsRef<str>,
     U: AsRef<str>,
 {
     let dst = dst.as_ref();
-    let mut fo = make_fetch_options().map_err(|source| Error::GitRetrieve {
-        dst: dst.to_path_buf(),
-        url: url.as_ref().to_owned(),
-        rev: rev.as_ref().to_owned(),
-        source,
-    })?;
-    if dst.exists() {
-        info!(logger, "git reset cached template"; "folder" => ?&dst);
-        checkout(dst, &rev).map_err(|source| Error::GitRetrieve {
-            dst: dst.to_path_buf(),
+    let tmp = files::part_path(dst);
+    let shallow_done = shallow
+        && !looks_like_sha(rev.as_ref())
+        && match clone_shallow(&tmp, url.as_ref(), rev.as_ref(), deadline) {
+            Ok(_) => true,
+            Err(err) => {
+                debug!(logger, "git shallow clone failed, fallback to a full clone"; "error" => ?&err);
+                false
+            }
+        };
+    if !shallow_done {
+        remove_dir_if_exists(&tmp)?;
+        clone(&tmp, &url, &rev, fo)?;
+        checkout(&tmp, &rev).map_err(|source| Error::GitRetrieve {
+            dst: tmp.clone(),
             url: url.as_ref().to_owned(),
             rev: rev.as_ref().to_owned(),
             source,
         })?;
-        info!(logger, "git pull cached template"; "folder" => ?&dst);
-        pull(logger, dst, &rev, &mut fo).map_err(|source| Error::GitRetrieve {
-            dst: dst.to_path_buf(),
-            url: url.as_ref().to_owned(),
-            rev: rev.as_ref().to_owned(),
+    }
+    if dst.exists() {
+        std::fs::remove_dir_all(&dst).map_err(|source| Error::RemoveFolder {
+            path: dst.to_path_buf(),
+            source,
+        })?;
+    }
+    std::fs::rename(&tmp, &dst).map_err(|source| Error::RenameFile {
+        src: tmp.clone(),
+        dst: dst.to_path_buf(),
+        source,
+    })?;
+    Ok(())
+}
+
+fn remove_dir_if_exists(path: &Path) -> Result<(), Error> {
+    if path.exists() {
+        std::fs::remove_dir_all(path).map_err(|source| Error::RemoveFolder {
+            path: path.to_path_buf(),
             source,
         })?;
-    //until pull is fixed and work as expected
-    // let mut tmp = dst.to_path_buf().clone();
-    // tmp.set_extension("part");
-    // if tmp.exists() {
-    //     std::fs::remove_dir_all(&tmp)?;
-    // }
-    // clone(&tmp, url, "master", fo)?;
-    // checkout(&tmp, rev)?;
-    // std::fs::remove_dir_all(&dst)?;
-    // std::fs::rename(&tmp, &dst)?;
+    }
+    Ok(())
+}
+
+/// clone only the commit of the rev (branch or tag) with the `git` command line
+/// (shallow clones are not supported by libgit2).
+/// The default rev (`master`) fallback to the default branch of the remote (eg: `main`).
+/// The command is killed at the `deadline`.
+fn clone_shallow(dst: &Path, url: &str, rev: &str, deadline: Option<Instant>) -> Result<(), Error> {
+    let branches = if rev == DEFAULT_REV {
+        vec![Some(rev), None]
     } else {
-        info!(logger, "git clone into cached template"; "folder" => ?&dst);
-        clone(&dst, &url, "master", fo)?;
-        checkout(&dst, &rev).map_err(|source| Error::GitRetrieve {
-            dst: dst.to_path_buf(),
-            url: url.as_ref().to_owned(),
-            rev: rev.as_ref().to_owned(),
+        vec![Some(rev)]
+    };
+    let mut last_err = None;
+    for branch in branches {
+        remove_dir_if_exists(dst)?;
+        let mut cmd = std::process::Command::new("git");
+        cmd.args(&["clone", "--quiet", "--depth", "1", "--single-branch"]);
+        // only the tag of the rev (if it's a tag)
+        if !looks_like_tag(rev) {
+            cmd.arg("--no-tags");
+        }
+        if let Some(branch) = branch {
+            cmd.args(&["--branch", branch]);
+        }
+        cmd.arg("--").arg(url).arg(dst);
+        // never prompt (credentials are handled by the fallback to libgit2)
+        cmd.env("GIT_TERMINAL_PROMPT", "0");
+        if std::env::var_os("GIT_SSH_COMMAND").is_none() {
+            cmd.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+        }
+        let cmd_str = format!("{:?}", cmd);
+        let output = output_until(cmd, deadline).map_err(|source| Error::RunCommand {
+            cmd: cmd_str.clone(),
             source,
         })?;
+        let checked = match output {
+            Some(output) if output.status.success() => checkout(dst, rev),
+            Some(output) => Err(git2::Error::from_str(
+                String::from_utf8_lossy(&output.stderr).trim(),
+            )),
+            None => Err(timeout_error()),
+        };
+        match checked {
+            Ok(_) => return Ok(()),
+            Err(source) => {
+                last_err = Some(Error::GitRetrieve {
+                    dst: dst.to_path_buf(),
+                    url: url.to_owned(),
+                    rev: rev.to_owned(),
+                    source,
+                })
+            }
+        }
     }
+    Err(last_err.expect("at least one attempt of shallow clone"))
+}
+
+/// the output of the command, `None` if it was killed at the `deadline`
+fn output_until(
+    mut cmd: std::process::Command,
+    deadline: Option<Instant>,
+) -> std::io::Result<Option<std::process::Output>> {
+    use std::process::Stdio;
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return cmd.output().map(Some),
+    };
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    child.wait_with_output().map(Some)
+}
+
+fn timeout_error() -> git2::Error {
+    git2::Error::new(
+        git2::ErrorCode::User,
+        git2::ErrorClass::Net,
+        "timeout of the git transfer (see FFIZER_GIT_TIMEOUT)",
+    )
+}
+
+fn is_shallow(dst: &Path) -> bool {
+    Repository::open(dst)
+        .map(|r| r.is_shallow())
+        .unwrap_or(false)
+}
+
+/// the rev is (the prefix of) the sha of a commit, it can't be cloned shallowly
+fn looks_like_sha(rev: &str) -> bool {
+    rev.len() >= 7 && rev.len() <= 40 && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// the rev is like a version (eg `v1.2.0`, `1.0`), so tags are fetched
+fn looks_like_tag(rev: &str) -> bool {
+    rev.trim_start_matches(&['v', 'V'][..])
+        .starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn is_cache_valid(logger: &Logger, dst: &Path) -> bool {
+    match check_cache(dst) {
+        Ok(_) => true,
+        Err(err) => {
+            warn!(logger, "cached template is corrupted or in an unfinished state, it will be cloned again"; "folder" => ?&dst, "reason" => err.message());
+            false
+        }
+    }
+}
+
+/// the cached template should be a git repository, without pending operation (merge, rebase,...)
+/// or lock files (left by an interrupted git command), with an `origin` and a valid HEAD
+fn check_cache(dst: &Path) -> Result<(), git2::Error> {
+    let repository = Repository::open(dst)?;
+    let state = repository.state();
+    if state != RepositoryState::Clean {
+        return Err(git2::Error::from_str(&format!(
+            "operation in progress: {:?}",
+            state
+        )));
+    }
+    for lock in &["index.lock", "HEAD.lock", "config.lock", "packed-refs.lock"] {
+        if repository.path().join(lock).exists() {
+            return Err(git2::Error::from_str(&format!("lock file found: {}", lock)));
+        }
+    }
+    repository.find_remote("origin")?;
+    repository.head()?.peel_to_commit()?;
     Ok(())
 }
 
 /// a best attempt effort is made to authenticate
 /// requests when required to support private
-/// git repositories
-fn make_fetch_options<'a>() -> Result<FetchOptions<'a>, git2::Error> {
+/// git repositories.
+/// The transfer is displayed by the `progress` bar (and logged), and aborted after the `deadline`
+/// (checked on each progress of the transfer).
+fn make_fetch_options<'a>(
+    logger: &Logger,
+    auth: Option<HostAuth>,
+    rev: &str,
+    deadline: Option<Instant>,
+    progress: ProgressBar,
+) -> Result<FetchOptions<'a>, git2::Error> {
     let mut cb = git2::RemoteCallbacks::new();
     let git_config = git2::Config::open_default()?;
     let mut ch = git2_credentials::CredentialHandler::new(git_config);
-    cb.credentials(move |url, username, allowed| ch.try_next_credential(url, username, allowed));
+    // the credentials of ffizer are tried once (the callback is called again on failure)
+    let mut auth = auth;
+    cb.credentials(move |url, username, allowed| {
+        if let Some(cred) = auth
+            .take()
+            .and_then(|a| a.git_credential(username, allowed))
+        {
+            return cred;
+        }
+        ch.try_next_credential(url, username, allowed)
+    });
+    let is_expired = move || deadline.map(|d| Instant::now() >= d).unwrap_or(false);
+    let logger = logger.clone();
+    let mut logged_percent = 0;
+    cb.transfer_progress(move |stats| {
+        let total = stats.total_objects();
+        let received = stats.received_objects();
+        progress.set_length(total as u64);
+        progress.set_position(received as u64);
+        progress.set_message(&format!(
+            "git fetch {}",
+            indicatif::HumanBytes(stats.received_bytes() as u64)
+        ));
+        let percent = (received * 100).checked_div(total).unwrap_or(0);
+        if percent >= logged_percent + 10 || (received == total && percent > logged_percent) {
+            logged_percent = percent;
+            debug!(logger, "git transfer"; "objects" => received, "total_objects" => total, "bytes" => stats.received_bytes());
+        }
+        !is_expired()
+    });
+    cb.sideband_progress(move |_| !is_expired());
 
     let mut fo = FetchOptions::new();
     let mut proxy_options = git2::ProxyOptions::new();
     proxy_options.auto();
     fo.proxy_options(proxy_options)
         .remote_callbacks(cb)
-        .download_tags(git2::AutotagOption::All)
+        // every tags only if the rev could be a tag (else only the tags of the fetched commits)
+        .download_tags(if looks_like_tag(rev) {
+            git2::AutotagOption::All
+        } else {
+            git2::AutotagOption::Auto
+        })
         .update_fetchhead(true);
     Ok(fo)
 }
 
+/// clone the default branch of the remote (eg: `master`, `main`,...), the `rev` (checked out
+/// later) is only reported on failure
 fn clone<P, U, R>(dst: P, url: U, rev: R, fo: FetchOptions<'_>) -> Result<(), Error>
 where
     P: AsRef<Path>,
-    R: AsRef<str>,
     U: AsRef<str>,
+    R: AsRef<str>,
 {
     std::fs::create_dir_all(&dst.as_ref()).map_err(|source| Error::CreateFolder {
         path: dst.as_ref().to_path_buf(),
         source,
     })?;
     RepoBuilder::new()
-        .branch(rev.as_ref())
         .fetch_options(fo)
         .clone(url.as_ref(), dst.as_ref())
         .map_err(|source| Error::GitRetrieve {
@@ -116,13 +535,39 @@ where
     // fetch
     let revref = rev.as_ref();
     let mut remote = repository.find_remote("origin")?;
-    remote.fetch(&[revref], Some(fo), None)?;
+    let branch = find_branch_to_update(&repository, revref);
+    let fetch_rev = if revref == DEFAULT_REV
+        && repository
+            .find_branch(&format!("origin/{}", revref), BranchType::Remote)
+            .is_err()
+    {
+        // the current branch is the default branch of the remote (set at clone)
+        debug!(logger, "git fetch: rev not found, use the default branch of the remote"; "rev" => revref, "branch" => &branch);
+        branch.as_str()
+    } else {
+        revref
+    };
+    remote.fetch(&[fetch_rev], Some(fo), None)?;
     let reference = repository.find_reference("FETCH_HEAD")?;
     let fetch_head_commit = repository.reference_to_annotated_commit(&reference)?;
-    do_merge(logger, &repository, "master", fetch_head_commit)?;
+    debug!(logger, "git merge: update branch"; "branch" => &branch);
+    do_merge(logger, &repository, &branch, fetch_head_commit)?;
     Ok(())
 }
 
+/// the local branch named as the rev (if exists) else the current branch (the default branch of the remote after clone)
+fn find_branch_to_update(repository: &Repository, rev: &str) -> String {
+    if repository.find_branch(rev, BranchType::Local).is_ok() {
+        return rev.to_owned();
+    }
+    repository
+        .head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.shorthand().map(|s| s.to_owned()))
+        .unwrap_or_else(|| rev.to_owned())
+}
+
 // from https://github.com/rust-lang/git2-rs/blob/master/examples/pull.rs
 fn fast_forward(
     logger: &Logger,
@@ -164,13 +609,14 @@ fn normal_merge(
 
     if idx.has_conflicts() {
         warn!(logger, "merge conficts detected...");
-        repo.checkout_index(Some(&mut idx), None)?;
-        return Ok(());
+        return Err(git2::Error::from_str(
+            "merge conflicts between the cached template and the remote",
+        ));
     }
     let result_tree = repo.find_tree(idx.write_tree_to(repo)?)?;
     // now create the merge commit
     let msg = format!("Merge: {} into {}", remote.id(), local.id());
-    let sig = repo.signature()?;
+    let sig = find_signature(logger, repo)?;
     let local_commit = repo.find_commit(local.id())?;
     let remote_commit = repo.find_commit(remote.id())?;
     // Do our merge commit and set current branch head to that commit.
@@ -187,6 +633,33 @@ fn normal_merge(
     Ok(())
 }
 
+/// identity used to commit the merge into the cached template.
+/// the identity from the git's configuration (user.name, user.email) is used if defined,
+/// else a fallback identity "ffizer <ffizer@local>" (overridable via environment variables
+/// `FFIZER_GIT_NAME` and `FFIZER_GIT_EMAIL`) is used (eg: on fresh CI containers).
+fn find_signature(logger: &Logger, repo: &Repository) -> Result<Signature<'static>, git2::Error> {
+    match repo.signature() {
+        Ok(sig) => Ok(sig.to_owned()),
+        Err(err) => {
+            let (name, email) = fallback_identity();
+            debug!(logger, "git identity not configured, use fallback"; "name" => &name, "email" => &email, "error" => ?&err);
+            Signature::now(&name, &email).map_err(|e| {
+                git2::Error::from_str(&format!(
+                    "failed to define a git identity to merge the cached template, configure git (user.name, user.email) or set FFIZER_GIT_NAME and FFIZER_GIT_EMAIL: {} ({})",
+                    e.message(),
+                    err.message()
+                ))
+            })
+        }
+    }
+}
+
+fn fallback_identity() -> (String, String) {
+    let name = std::env::var("FFIZER_GIT_NAME").unwrap_or_else(|_| "ffizer".to_owned());
+    let email = std::env::var("FFIZER_GIT_EMAIL").unwrap_or_else(|_| "ffizer@local".to_owned());
+    (name, email)
+}
+
 // from https://github.com/rust-lang/git2-rs/blob/master/examples/pull.rs
 fn do_merge<'a>(
     logger: &Logger,
@@ -245,11 +718,202 @@ where
     let repository = Repository::discover(dst.as_ref())?;
     let mut co = CheckoutBuilder::new();
     co.force().remove_ignored(true).remove_untracked(true);
-    let treeish = repository.revparse_single(rev)?;
+    let treeish = resolve_rev(&repository, rev)?;
     repository.checkout_tree(&treeish, Some(&mut co))?;
     Ok(())
 }
 
+/// resolve the rev as local rev, or remote branch,
+/// the default rev (`master`) fallback to the default branch of the remote (eg: `main`)
+fn resolve_rev<'a>(repository: &'a Repository, rev: &str) -> Result<git2::Object<'a>, git2::Error> {
+    repository
+        .revparse_single(rev)
+        .or_else(|_| repository.revparse_single(&format!("origin/{}", rev)))
+        .or_else(|err| {
+            if rev == DEFAULT_REV {
+                repository.revparse_single("HEAD")
+            } else {
+                Err(err)
+            }
+        })
+}
+
+/// find the commit of the rev in the repository at `dst` (same resolution of the rev as `retrieve`)
+pub fn find_rev_info<P, R>(dst: P, rev: R) -> Result<RevInfo, Error>
+where
+    P: AsRef<Path>,
+    R: AsRef<str>,
+{
+    let rev = rev.as_ref();
+    let find = || -> Result<RevInfo, git2::Error> {
+        let repository = Repository::discover(dst.as_ref())?;
+        let commit = resolve_rev(&repository, rev)?.peel_to_commit()?;
+        Ok(RevInfo {
+            rev: rev.to_owned(),
+            commit_id: commit.id().to_string(),
+            commit_time: commit.time().seconds(),
+        })
+    };
+    find().map_err(|source| Error::GitFindRev {
+        path: dst.as_ref().to_path_buf(),
+        rev: rev.to_owned(),
+        source,
+    })
+}
+
+/// the commits after the commit `from` up to the commit `to` (the most recent first, at most `max`),
+/// as `<short id> <summary>`, in the repository at `dst`
+/// (fail if `from` is not into the repository, eg a shallow clone)
+pub fn find_log<P>(dst: P, from: &str, to: &str, max: usize) -> Result<Vec<String>, Error>
+where
+    P: AsRef<Path>,
+{
+    let find = || -> Result<Vec<String>, git2::Error> {
+        let repository = Repository::discover(dst.as_ref())?;
+        let mut revwalk = repository.revwalk()?;
+        revwalk.push(git2::Oid::from_str(to)?)?;
+        revwalk.hide(repository.find_commit(git2::Oid::from_str(from)?)?.id())?;
+        let mut log = vec![];
+        for oid in revwalk.take(max) {
+            let commit = repository.find_commit(oid?)?;
+            let id = commit.id().to_string();
+            log.push(format!(
+                "{} {}",
+                &id[..7],
+                commit.summary().unwrap_or_default()
+            ));
+        }
+        Ok(log)
+    };
+    find().map_err(|source| Error::GitLog {
+        path: dst.as_ref().to_path_buf(),
+        from: from.to_owned(),
+        to: to.to_owned(),
+        source,
+    })
+}
+
+/// find the url of the remote `origin` of the repository at `dst` (the url used by `retrieve`)
+pub fn find_remote_url<P>(dst: P) -> Result<String, Error>
+where
+    P: AsRef<Path>,
+{
+    let find = || -> Result<String, git2::Error> {
+        let repository = Repository::open(dst.as_ref())?;
+        let remote = repository.find_remote("origin")?;
+        remote
+            .url()
+            .map(|s| s.to_owned())
+            .ok_or_else(|| git2::Error::from_str("url of remote origin is not valid utf-8"))
+    };
+    find().map_err(|source| Error::GitFindRemoteUrl {
+        path: dst.as_ref().to_path_buf(),
+        source,
+    })
+}
+
+/// the url of the remote `origin` of the git repository that contains `folder` (or its nearest
+/// existing parent), none if not into a git repository or without remote `origin`
+pub fn find_enclosing_remote_url(folder: &Path) -> Option<String> {
+    let folder = files::absolute_path(folder);
+    let existing = folder.ancestors().find(|p| p.exists())?;
+    let repository = Repository::discover(existing).ok()?;
+    let remote = repository.find_remote("origin").ok()?;
+    remote.url().map(|s| s.to_owned())
+}
+
+/// the `folder` (or its nearest existing parent, if not created yet) is into a git repository
+pub fn is_in_repository(folder: &Path) -> bool {
+    folder
+        .ancestors()
+        .find(|p| p.exists())
+        .map(|p| Repository::discover(p).is_ok())
+        .unwrap_or(false)
+}
+
+/// init a git repository into `folder` with an initial commit of its content (except the files
+/// ignored by the `.gitignore`), nothing is done if `folder` is already into a git repository.
+/// return true if the repository was created
+pub fn init_with_commit(logger: &Logger, folder: &Path, message: &str) -> Result<bool, Error> {
+    if Repository::discover(folder).is_ok() {
+        debug!(logger, "already into a git repository, skip git init"; "folder" => ?folder);
+        return Ok(false);
+    }
+    let init = || -> Result<(), git2::Error> {
+        let repository = Repository::init(folder)?;
+        let mut index = repository.index()?;
+        index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        let tree = repository.find_tree(index.write_tree()?)?;
+        let sig = find_signature(logger, &repository)?;
+        repository.commit(Some("HEAD"), &sig, &sig, message, &tree, &[])?;
+        Ok(())
+    };
+    init().map_err(|source| Error::GitInit {
+        path: folder.to_path_buf(),
+        source,
+    })?;
+    info!(logger, "git repository initialized"; "folder" => ?folder);
+    Ok(true)
+}
+
+/// the error is a failure of authentication (to ask other credentials)
+pub fn is_auth_error(err: &Error) -> bool {
+    match err {
+        Error::GitRetrieve { source, .. } => {
+            source.code() == git2::ErrorCode::Auth
+                || source.message().to_lowercase().contains("authentication")
+        }
+        _ => false,
+    }
+}
+
+/// compare urls of git repository, ignoring the trailing `/` and `.git`
+pub fn is_same_remote_url(url1: &str, url2: &str) -> bool {
+    let normalize = |url: &str| {
+        url.trim_end_matches('/')
+            .trim_end_matches(".git")
+            .to_lowercase()
+    };
+    normalize(url1) == normalize(url2)
+}
+
+/// the value of the `key` in the git's configuration (global, system,...)
+pub fn find_config_string(key: &str) -> Result<String, git2::Error> {
+    Config::open_default()?.get_string(key)
+}
+
+/// the paths (relative to `folder`) ignored by the `.gitignore` files of the git repository
+/// that contains `folder` (none if `folder`, or its nearest existing parent, is not into a git repository)
+pub fn find_ignored_paths(
+    folder: &Path,
+    relatives: &[PathBuf],
+) -> Result<Vec<PathBuf>, git2::Error> {
+    let folder = files::absolute_path(folder);
+    let existing = match folder.ancestors().find(|p| p.exists()) {
+        Some(existing) => existing,
+        None => return Ok(vec![]),
+    };
+    let repository = match Repository::discover(existing) {
+        Ok(repository) => repository,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    let workdir = match repository.workdir() {
+        Some(workdir) => files::absolute_path(workdir),
+        None => return Ok(vec![]),
+    };
+    let mut ignored = vec![];
+    for relative in relatives {
+        if let Ok(in_repository) = folder.join(relative).strip_prefix(&workdir) {
+            if repository.is_path_ignored(in_repository)? {
+                ignored.push(relative.clone());
+            }
+        }
+    }
+    Ok(ignored)
+}
+
 /// kind can be "merge" or "diff"
 pub fn find_cmd_tool(kind: &str) -> Result<String, git2::Error> {
     let config = Config::open_default()?;
@@ -264,106 +928,349 @@ mod tests {
     use std::fs;
     use tempfile::tempdir;
 
-    #[cfg(not(target_os = "windows"))]
     #[test]
-    fn retrieve_should_update_existing_template() -> Result<(), Box<dyn std::error::Error>> {
+    fn find_signature_should_be_defined_without_git_identity(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let tmp_dir = tempdir()?;
+        let repo = Repository::init(tmp_dir.path())?;
+        let mut config = repo.config()?;
+        // an empty identity is rejected by git2, like a missing identity
+        config.set_str("user.name", "")?;
+        config.set_str("user.email", "")?;
+        let sig = find_signature(&logger, &repo)?;
+        let (name, email) = fallback_identity();
+        assert_eq!(sig.name(), Some(name.as_str()));
+        assert_eq!(sig.email(), Some(email.as_str()));
+        Ok(())
+    }
+
+    #[test]
+    fn network_policy_should_double_the_delay_and_retry_only_temporary_failures() {
+        let policy = NetworkPolicy::default();
+        assert_eq!(policy.delay_before(1), Duration::from_secs(1));
+        assert_eq!(policy.delay_before(3), Duration::from_secs(4));
+        let retrieve_error = |source| Error::GitRetrieve {
+            dst: PathBuf::from("foo"),
+            url: "https://example.com/foo.git".to_owned(),
+            rev: "master".to_owned(),
+            source,
+        };
+        assert!(is_retryable(&retrieve_error(timeout_error())));
+        assert!(is_retryable(&retrieve_error(git2::Error::new(
+            git2::ErrorCode::GenericError,
+            git2::ErrorClass::Net,
+            "failed to connect",
+        ))));
+        assert!(!is_retryable(&retrieve_error(git2::Error::new(
+            git2::ErrorCode::Auth,
+            git2::ErrorClass::Http,
+            "authentication required",
+        ))));
+        assert!(!is_retryable(&Error::Unknown("boom".to_owned())));
+    }
+
+    #[test]
+    fn init_with_commit_should_commit_the_content_once() -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let tmp_dir = tempdir()?;
+        let folder = tmp_dir.path().join("project");
+        fs::create_dir_all(folder.join("src"))?;
+        fs::write(folder.join("src/main.rs"), "fn main() {}")?;
+        fs::write(folder.join(".gitignore"), "target\n")?;
+        fs::create_dir_all(folder.join("target"))?;
+        fs::write(folder.join("target/out"), "")?;
+
+        assert!(init_with_commit(&logger, &folder, "initial commit")?);
+        let repo = Repository::open(&folder)?;
+        let commit = repo.head()?.peel_to_commit()?;
+        assert_eq!(commit.message(), Some("initial commit"));
+        assert_eq!(commit.parent_count(), 0);
+        let tree = commit.tree()?;
+        assert!(tree.get_path(Path::new("src/main.rs")).is_ok());
+        assert!(tree.get_path(Path::new(".gitignore")).is_ok());
+        assert!(tree.get_path(Path::new("target/out")).is_err());
+
+        assert!(!init_with_commit(&logger, &folder.join("src"), "again")?);
+        Ok(())
+    }
+
+    #[test]
+    fn find_log_should_list_the_commits_after_the_commit_applied(
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let logger = slog::Logger::root(slog::Discard, slog::o!());
-        if std::process::Command::new("git")
+        let tmp_dir = tempdir()?;
+        let folder = tmp_dir.path().join("template");
+        fs::create_dir_all(&folder)?;
+        fs::write(folder.join("README.md"), "v1")?;
+        init_with_commit(&logger, &folder, "v1")?;
+        let repo = Repository::open(&folder)?;
+        let sig = Signature::now("Test Name", "test@example.com")?;
+        let commit = |message: &str| -> Result<String, git2::Error> {
+            let parent = repo.head()?.peel_to_commit()?;
+            let tree = parent.tree()?;
+            let oid = repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[&parent])?;
+            Ok(oid.to_string())
+        };
+        let from = repo.head()?.peel_to_commit()?.id().to_string();
+        commit("add ci")?;
+        let to = commit("drop travis")?;
+
+        let log = find_log(&folder, &from, &to, 10)?;
+        assert_eq!(log.len(), 2);
+        assert!(log[0].starts_with(&to[..7]));
+        assert!(log[0].ends_with(" drop travis"));
+        assert!(log[1].ends_with(" add ci"));
+        assert_eq!(find_log(&folder, &from, &to, 1)?.len(), 1);
+        assert!(find_log(&folder, &to, &to, 10)?.is_empty());
+        assert!(find_log(&folder, "0123456789abcdef0123456789abcdef01234567", &to, 10).is_err());
+        Ok(())
+    }
+
+    /// true if `git` is installed (the tests using the git cli are skipped otherwise)
+    fn git_installed() -> bool {
+        let installed = std::process::Command::new("git")
             .arg("version")
             .output()
-            .is_err()
-        {
+            .is_ok();
+        if !installed {
             eprintln!("skip the test because `git` is not installed");
-            return Ok(());
         }
+        installed
+    }
 
-        let tmp_dir = tempdir()?;
+    /// run the shell `script` into the folder `path` (created if missing)
+    fn run_git_script(path: &Path, script: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(path)?;
+        let mut options = run_script::ScriptOptions::new();
+        options.working_directory = Some(path.to_path_buf());
+        let (code, output, error) = run_script::run(script, &vec![], &options)?;
+        if code != 0 {
+            eprintln!("---output:\n{}\n---error:\n{}\n---", output, error);
+        }
+        assert_eq!(code, 0);
+        Ok(())
+    }
 
-        // template v1
-        let src_path = tmp_dir.path().join("src");
-        let options = run_script::ScriptOptions::new();
-        let args = vec![];
-        let (code, output, error) = run_script::run(
+    /// commit the `content` of `foo.txt` on the `branch` of the git repository `path` (initialized
+    /// if needed)
+    fn commit_foo(
+        path: &Path,
+        branch: &str,
+        content: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        run_git_script(
+            path,
             &format!(
                 r#"
-            mkdir -p {}
-            cd {}
             git init
             git config user.email "test@example.com"
             git config user.name "Test Name"
-            echo "v1: Lorem ipsum" > foo.txt
+            git checkout -B {branch}
+            echo "{content}" > foo.txt
             git add foo.txt
-            git commit -m "add foo.txt"
+            git commit -m "update foo.txt"
             "#,
-                src_path.to_str().unwrap(),
-                src_path.to_str().unwrap()
+                branch = branch,
+                content = content
             ),
-            &args,
-            &options,
-        )?;
-        if code != 0 {
-            eprintln!("---output:\n{}\n---error:\n{}\n---", output, error);
+        )
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn retrieve_should_update_existing_template_with_main_as_default_branch(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        if !git_installed() {
+            return Ok(());
         }
-        assert_eq!(code, 0);
+        let tmp_dir = tempdir()?;
+        let src_path = tmp_dir.path().join("src");
+        commit_foo(&src_path, "main", "v1")?;
+
+        // explicit rev & default rev ("master") use the "main" branch
+        for rev in &["main", "master"] {
+            let dst_path = tmp_dir.path().join(format!("dst_{}", rev));
+            retrieve(&logger, &dst_path, src_path.to_str().unwrap(), rev)?;
+            assert_eq!(fs::read_to_string(dst_path.join("foo.txt"))?, "v1\n");
+        }
+
+        commit_foo(&src_path, "main", "v2")?;
+        for rev in &["main", "master"] {
+            let dst_path = tmp_dir.path().join(format!("dst_{}", rev));
+            retrieve(&logger, &dst_path, src_path.to_str().unwrap(), rev)?;
+            assert_eq!(fs::read_to_string(dst_path.join("foo.txt"))?, "v2\n");
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn retrieve_should_clone_again_a_corrupted_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        if !git_installed() {
+            return Ok(());
+        }
+        let tmp_dir = tempdir()?;
+        let src_path = tmp_dir.path().join("src");
+        commit_foo(&src_path, "master", "v1")?;
 
         let dst_path = tmp_dir.path().join("dst");
         retrieve(&logger, &dst_path, src_path.to_str().unwrap(), "master")?;
-        assert_eq!(
-            fs::read_to_string(&dst_path.join("foo.txt"))?,
-            "v1: Lorem ipsum\n"
-        );
+        assert_eq!(fs::read_to_string(dst_path.join("foo.txt"))?, "v1\n");
+        assert!(check_cache(&dst_path).is_ok());
 
-        // template v2
-        let (code, output, error) = run_script::run(
-            &format!(
-                r#"
-            cd {}
-            echo "v2: Hello" > foo.txt
-            git add foo.txt
-            git commit -m "add foo.txt"
-            "#,
-                src_path.to_str().unwrap()
-            ),
-            &args,
-            &options,
-        )?;
-        if code != 0 {
-            eprintln!("---output:\n{}\n---error:\n{}\n---", output, error);
+        // simulate an interrupted git command, and leftover of an interrupted clone
+        fs::write(dst_path.join(".git").join("index.lock"), "")?;
+        fs::create_dir_all(files::part_path(&dst_path))?;
+        assert!(check_cache(&dst_path).is_err());
+
+        commit_foo(&src_path, "master", "v2")?;
+        retrieve(&logger, &dst_path, src_path.to_str().unwrap(), "master")?;
+        assert_eq!(fs::read_to_string(dst_path.join("foo.txt"))?, "v2\n");
+        assert!(check_cache(&dst_path).is_ok());
+        assert!(!files::part_path(&dst_path).exists());
+
+        let src_info = find_rev_info(&src_path, "master")?;
+        let dst_info = find_rev_info(&dst_path, "master")?;
+        assert_eq!(dst_info.commit_id, src_info.commit_id);
+        assert_eq!(dst_info.commit_time, src_info.commit_time);
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn retrieve_should_clone_again_when_update_of_cache_fails(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        if !git_installed() {
+            return Ok(());
         }
-        assert_eq!(code, 0);
+        let tmp_dir = tempdir()?;
+        let src_path = tmp_dir.path().join("src");
+        let dst_path = tmp_dir.path().join("dst");
+        commit_foo(&src_path, "master", "v1")?;
+        retrieve(&logger, &dst_path, src_path.to_str().unwrap(), "master")?;
+        // a change committed into the cache conflicts with the next version of the template
+        commit_foo(&dst_path, "master", "local")?;
+        commit_foo(&src_path, "master", "v2")?;
 
         retrieve(&logger, &dst_path, src_path.to_str().unwrap(), "master")?;
-        assert_eq!(
-            fs::read_to_string(&dst_path.join("foo.txt"))?,
-            "v2: Hello\n"
+        assert_eq!(fs::read_to_string(dst_path.join("foo.txt"))?, "v2\n");
+        assert!(check_cache(&dst_path).is_ok());
+        assert!(!files::part_path(&dst_path).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn clone_should_report_the_rev_on_failure() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let url = tmp_dir.path().join("missing").to_string_lossy().to_string();
+        let err = clone(
+            tmp_dir.path().join("dst"),
+            &url,
+            "1.2.0",
+            FetchOptions::new(),
+        )
+        .expect_err("clone of a missing repository");
+        assert!(
+            matches!(&err, Error::GitRetrieve { rev, .. } if rev == "1.2.0"),
+            "{:?}",
+            err
         );
+        Ok(())
+    }
 
-        // template v3
-        let (code, output, error) = run_script::run(
-            &format!(
-                r#"
-            cd {}
-            echo "v3: Hourra" > foo.txt
-            git add foo.txt
-            git commit -m "add foo.txt"
-            "#,
-                src_path.to_str().unwrap()
-            ),
-            &args,
-            &options,
-        )?;
-        if code != 0 {
-            eprintln!("---output:\n{}\n---error:\n{}\n---", output, error);
+    #[test]
+    fn test_looks_like_sha_or_tag() {
+        assert!(looks_like_sha("a1b2c3d"));
+        assert!(looks_like_sha("0123456789abcdef0123456789abcdef01234567"));
+        assert!(!looks_like_sha("master"));
+        assert!(!looks_like_sha("abc"));
+        assert!(looks_like_tag("v1.2.0"));
+        assert!(looks_like_tag("1.0"));
+        assert!(!looks_like_tag("master"));
+        assert!(!looks_like_tag("vendor"));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn retrieve_should_clone_shallow_unless_full_history() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        if !git_installed() {
+            return Ok(());
         }
-        assert_eq!(code, 0);
+        let tmp_dir = tempdir()?;
+        let src_path = tmp_dir.path().join("src");
+        commit_foo(&src_path, "master", "v1")?;
+        commit_foo(&src_path, "master", "v2")?;
+        // `--depth` is ignored for local path (but not for `file://`)
+        let url = format!("file://{}", src_path.to_str().unwrap());
 
-        retrieve(&logger, &dst_path, src_path.to_str().unwrap(), "master")?;
+        let dst_shallow = tmp_dir.path().join("dst_shallow");
+        retrieve(&logger, &dst_shallow, &url, "master")?;
+        assert_eq!(fs::read_to_string(dst_shallow.join("foo.txt"))?, "v2\n");
+        assert!(is_shallow(&dst_shallow));
+
+        let dst_full = tmp_dir.path().join("dst_full");
+        retrieve_with_auth(&logger, &dst_full, &url, "master", None, true)?;
+        assert_eq!(fs::read_to_string(dst_full.join("foo.txt"))?, "v2\n");
+        assert!(!is_shallow(&dst_full));
+
+        // a shallow cache is cloned again to be updated
+        commit_foo(&src_path, "master", "v3")?;
+        retrieve(&logger, &dst_shallow, &url, "master")?;
+        assert_eq!(fs::read_to_string(dst_shallow.join("foo.txt"))?, "v3\n");
+        assert!(is_shallow(&dst_shallow));
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn retrieve_should_update_existing_template() -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        if !git_installed() {
+            return Ok(());
+        }
+        let tmp_dir = tempdir()?;
+        let src_path = tmp_dir.path().join("src");
+        let dst_path = tmp_dir.path().join("dst");
+        for content in &["v1: Lorem ipsum", "v2: Hello", "v3: Hourra"] {
+            commit_foo(&src_path, "master", content)?;
+            retrieve(&logger, &dst_path, src_path.to_str().unwrap(), "master")?;
+            assert_eq!(
+                fs::read_to_string(dst_path.join("foo.txt"))?,
+                format!("{}\n", content)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn find_ignored_paths_should_use_gitignore_of_the_repository(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        Repository::init(tmp_dir.path())?;
+        fs::write(tmp_dir.path().join(".gitignore"), "target\n*.log\n")?;
+        // the destination doesn't exist yet
+        let dst = tmp_dir.path().join("project");
+        let relatives = vec![
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("build.log"),
+            PathBuf::from("target/foo"),
+        ];
+        assert_eq!(
+            find_ignored_paths(&dst, &relatives)?,
+            vec![PathBuf::from("build.log"), PathBuf::from("target/foo")]
+        );
+
+        let outside = tempdir()?;
         assert_eq!(
-            fs::read_to_string(&dst_path.join("foo.txt"))?,
-            "v3: Hourra\n"
+            find_ignored_paths(outside.path(), &relatives)?,
+            Vec::<PathBuf>::new()
         );
-        //TODO always remove
-        fs::remove_dir_all(tmp_dir)?;
         Ok(())
     }
 }
@@ -0,0 +1,208 @@
+//! handlebars used to render the templates, the paths and the configurations:
+//! the helpers of `handlebars_misc_helpers`, plus the helpers of ffizer.
+//!
+//! - `env`: the value of an environment variable (empty if not defined), eg `{{ env "USER" }}`
+//! - `git_config`: the value of a key of the git's configuration (empty if not defined),
+//!   eg `{{ git_config "user.name" }}`
+//! - `semver_bump`: increment a part (`major`, `minor`, `patch`) of a semantic version,
+//!   eg `{{ semver_bump version "minor" }}` (`1.2.3` -> `1.3.0`)
+//! - `semver_major`, `semver_minor`, `semver_patch`: a part of a semantic version, eg `{{ semver_major version }}`
+//!
+//! The block helpers (`each`, `with`, `if`, `unless`) fail once the deadline of the rendering (see
+//! `--render-timeout`) is expired, even if the template doesn't write anything.
+
+use crate::git;
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
+    RenderError, ScopedJson,
+};
+use lazy_static::lazy_static;
+use semver::Version;
+use serde_json::Value as Json;
+use std::cell::Cell;
+use std::time::Instant;
+
+/// the block helpers of handlebars that check the deadline of the rendering
+const BLOCK_HELPERS: [&str; 4] = ["each", "with", "if", "unless"];
+
+lazy_static! {
+    /// the built-in helpers of handlebars (wrapped by `WithDeadline`)
+    static ref BUILTINS: Handlebars<'static> = Handlebars::new();
+}
+
+thread_local! {
+    /// the deadline of the rendering in progress on the thread
+    static RENDER_DEADLINE: Cell<Option<Instant>> = Cell::new(None);
+}
+
+/// set (or clear with `None`) the deadline of the renderings of the current thread
+pub(crate) fn set_render_deadline(deadline: Option<Instant>) {
+    RENDER_DEADLINE.with(|d| d.set(deadline));
+}
+
+/// true if the deadline of the rendering (if any) of the current thread is expired
+pub(crate) fn is_render_expired() -> bool {
+    RENDER_DEADLINE.with(|d| d.get().map(|d| Instant::now() >= d).unwrap_or(false))
+}
+
+pub(crate) fn new_hbs<'reg>() -> Handlebars<'reg> {
+    let mut handlebars = handlebars_misc_helpers::new_hbs();
+    handlebars_helper!(env: |key: str| std::env::var(key).unwrap_or_default());
+    handlebars.register_helper("env", Box::new(env));
+    handlebars_helper!(git_config: |key: str| git::find_config_string(key).unwrap_or_default());
+    handlebars.register_helper("git_config", Box::new(git_config));
+    handlebars.register_helper("semver_bump", Box::new(SemverBump));
+    handlebars.register_helper("semver_major", Box::new(SemverPart(|v| v.major)));
+    handlebars.register_helper("semver_minor", Box::new(SemverPart(|v| v.minor)));
+    handlebars.register_helper("semver_patch", Box::new(SemverPart(|v| v.patch)));
+    for name in &BLOCK_HELPERS {
+        handlebars.register_helper(name, Box::new(WithDeadline(name)));
+    }
+    handlebars
+}
+
+/// the built-in helper `.0`, that fails if the deadline of the rendering is expired
+struct WithDeadline(&'static str);
+
+impl WithDeadline {
+    fn check(&self) -> Result<(), RenderError> {
+        if is_render_expired() {
+            Err(RenderError::new(format!(
+                "{}: deadline of the rendering expired",
+                self.0
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn builtin(&self) -> &'static (dyn HelperDef + Send + Sync) {
+        BUILTINS.get_helper(self.0).expect("built-in helper")
+    }
+}
+
+impl HelperDef for WithDeadline {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        self.check()?;
+        self.builtin().call(h, r, ctx, rc, out)
+    }
+
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<Option<ScopedJson<'reg, 'rc>>, RenderError> {
+        self.check()?;
+        self.builtin().call_inner(h, r, ctx, rc)
+    }
+}
+
+fn find_version_param(h: &Helper<'_, '_>) -> Result<Version, RenderError> {
+    let value = h.param(0).and_then(|v| v.value().as_str()).ok_or_else(|| {
+        RenderError::new(format!("{}: param 0 (the version) not found", h.name()))
+    })?;
+    // allow the prefix `v` (eg the name of a tag)
+    Version::parse(value.trim().trim_start_matches('v')).map_err(|e| {
+        RenderError::new(format!(
+            "{}: {:?} is not a semantic version: {}",
+            h.name(),
+            value,
+            e
+        ))
+    })
+}
+
+struct SemverBump;
+
+impl HelperDef for SemverBump {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<Option<ScopedJson<'reg, 'rc>>, RenderError> {
+        let mut version = find_version_param(h)?;
+        match h.param(1).and_then(|v| v.value().as_str()) {
+            Some("major") => version.increment_major(),
+            Some("minor") => version.increment_minor(),
+            Some("patch") => version.increment_patch(),
+            part => {
+                return Err(RenderError::new(format!(
+                    "semver_bump: param 1 (the part) should be \"major\", \"minor\" or \"patch\", not {:?}",
+                    part
+                )))
+            }
+        }
+        Ok(Some(ScopedJson::Derived(Json::String(version.to_string()))))
+    }
+}
+
+struct SemverPart(fn(&Version) -> u64);
+
+impl HelperDef for SemverPart {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<Option<ScopedJson<'reg, 'rc>>, RenderError> {
+        let version = find_version_param(h)?;
+        Ok(Some(ScopedJson::Derived(Json::from((self.0)(&version)))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_env() -> Result<(), Box<dyn std::error::Error>> {
+        std::env::set_var("FFIZER_TEST_HBS_ENV", "value");
+        let handlebars = new_hbs();
+        let data = serde_json::json!({});
+        assert_that!(handlebars.render_template(r#"{{ env "FFIZER_TEST_HBS_ENV" }}"#, &data)?)
+            .is_equal_to("value".to_owned());
+        assert_that!(handlebars.render_template(r#"{{ env "FFIZER_TEST_HBS_UNDEFINED" }}"#, &data)?)
+            .is_equal_to("".to_owned());
+        assert_that!(handlebars.render_template(
+            r#"{{ first_non_empty (env "FFIZER_TEST_HBS_UNDEFINED") "fallback" }}"#,
+            &data
+        )?)
+        .is_equal_to("fallback".to_owned());
+        Ok(())
+    }
+
+    #[test]
+    fn test_semver() -> Result<(), Box<dyn std::error::Error>> {
+        let handlebars = new_hbs();
+        let data = serde_json::json!({"version": "1.2.3-beta.1"});
+        for (tmpl, expected) in &[
+            (r#"{{ semver_bump version "major" }}"#, "2.0.0"),
+            (r#"{{ semver_bump version "minor" }}"#, "1.3.0"),
+            (r#"{{ semver_bump version "patch" }}"#, "1.2.4"),
+            (r#"{{ semver_bump "v0.9.0" "minor" }}"#, "0.10.0"),
+            ("{{ semver_major version }}", "1"),
+            ("{{ semver_minor version }}", "2"),
+            ("{{ semver_patch version }}", "3"),
+        ] {
+            assert_that!(handlebars.render_template(tmpl, &data)?)
+                .is_equal_to(expected.to_string());
+        }
+        assert_that!(handlebars.render_template(r#"{{ semver_bump version "build" }}"#, &data))
+            .is_err();
+        assert_that!(handlebars.render_template(r#"{{ semver_major "latest" }}"#, &data)).is_err();
+        Ok(())
+    }
+}
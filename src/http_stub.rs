@@ -0,0 +1,77 @@
+//! a minimal http server for the tests: it answers the requests with the responses given (in order,
+//! one per connection), and records the head of the requests received
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StubResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl StubResponse {
+    pub fn ok<B: Into<Vec<u8>>>(body: B) -> StubResponse {
+        StubResponse {
+            status: 200,
+            body: body.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> StubResponse {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+pub(crate) struct HttpStub {
+    /// the url of the server, without trailing `/` (eg `http://127.0.0.1:1234`)
+    pub url: String,
+    server: JoinHandle<Vec<String>>,
+}
+
+impl HttpStub {
+    pub fn start(responses: Vec<StubResponse>) -> HttpStub {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind a local port");
+        let url = format!("http://{}", listener.local_addr().expect("local address"));
+        let server = thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (stream, _) = listener.accept().expect("accept a connection");
+                let mut reader = BufReader::new(stream);
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                requests.push(request);
+                let mut stream = reader.into_inner();
+                let mut head = format!(
+                    "HTTP/1.1 {} STUB\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response.status,
+                    response.body.len()
+                );
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                head.push_str("\r\n");
+                let _ = stream
+                    .write_all(head.as_bytes())
+                    .and_then(|_| stream.write_all(&response.body));
+            }
+            requests
+        });
+        HttpStub { url, server }
+    }
+
+    /// wait for the responses to be sent, and return the requests received
+    pub fn requests(self) -> Vec<String> {
+        self.server.join().expect("stub server")
+    }
+}
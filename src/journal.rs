@@ -0,0 +1,146 @@
+//! journal of the changes done into the destination by `execute` (transactional mode),
+//! to restore the destination as before the apply on failure.
+
+use crate::error::*;
+use slog::{debug, warn, Logger};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tempfile::TempDir;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Entry {
+    /// a file or a folder created
+    Created(PathBuf),
+    /// an existing file, with a copy of its content before the update
+    Updated { path: PathBuf, backup: PathBuf },
+}
+
+#[derive(Debug)]
+pub(crate) struct Journal {
+    backup_dir: TempDir,
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Journal {
+    pub fn new() -> Result<Journal> {
+        let backup_dir = tempfile::Builder::new()
+            .prefix("ffizer-backup")
+            .tempdir()
+            .map_err(|source| Error::CreateFolder {
+                path: std::env::temp_dir(),
+                source,
+            })?;
+        Ok(Journal {
+            backup_dir,
+            entries: Mutex::new(vec![]),
+        })
+    }
+
+    /// record a file or a folder to create (to call before its creation)
+    pub fn record_created(&self, path: &Path) {
+        self.lock().push(Entry::Created(path.to_path_buf()));
+    }
+
+    /// copy the file before its update (to call before any change of the file), the leftovers of
+    /// the update (eg `.REMOTE` & `.LOCAL` of the merge, backup) not already existing are recorded
+    /// as created
+    pub fn backup(&self, path: &Path) -> Result<()> {
+        let mut entries = self.lock();
+        for ext in &[".REMOTE", ".LOCAL", crate::files::FILEEXT_BACKUP] {
+            let leftover = crate::files::add_suffix(path, ext)?;
+            if fs::symlink_metadata(&leftover).is_err() {
+                entries.push(Entry::Created(leftover));
+            }
+        }
+        let backup = self.backup_dir.path().join(entries.len().to_string());
+        fs::copy(path, &backup).map_err(|source| Error::CopyFile {
+            src: path.to_path_buf(),
+            dst: backup.clone(),
+            source,
+        })?;
+        entries.push(Entry::Updated {
+            path: path.to_path_buf(),
+            backup,
+        });
+        Ok(())
+    }
+
+    /// undo the changes, in the reverse order: restore updated files, remove created files and folders
+    /// (a created folder is removed only if empty: it could contain files created after the apply)
+    pub fn rollback(self, logger: &Logger) -> Result<()> {
+        let entries = self
+            .entries
+            .into_inner()
+            .expect("lock the entries of the journal");
+        for entry in entries.into_iter().rev() {
+            debug!(logger, "rollback"; "entry" => ?&entry);
+            match entry {
+                Entry::Updated { path, backup } => {
+                    fs::copy(&backup, &path).map_err(|source| Error::CopyFile {
+                        src: backup.clone(),
+                        dst: path.clone(),
+                        source,
+                    })?;
+                }
+                Entry::Created(path) if path.is_dir() => {
+                    if let Err(err) = fs::remove_dir(&path) {
+                        warn!(logger, "fail to remove created folder"; "path" => ?&path, "err" => ?err);
+                    }
+                }
+                Entry::Created(path) => match fs::remove_file(&path) {
+                    // recorded before its creation, that failed
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                    res => res.map_err(|source| Error::RemoveFile { path, source })?,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Entry>> {
+        self.entries
+            .lock()
+            .expect("lock the entries of the journal")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rollback() -> Result<(), Box<dyn std::error::Error>> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let tmp_dir = tempdir()?;
+        let existing = tmp_dir.path().join("existing.txt");
+        fs::write(&existing, "v1")?;
+        let folder = tmp_dir.path().join("folder");
+        let created = folder.join("created.txt");
+
+        let journal = Journal::new()?;
+        fs::create_dir(&folder)?;
+        journal.record_created(&folder);
+        fs::write(&created, "new")?;
+        journal.record_created(&created);
+        // a file of the user, not created by the apply
+        let local = crate::files::add_suffix(&existing, ".LOCAL")?;
+        fs::write(&local, "mine")?;
+        journal.backup(&existing)?;
+        fs::write(&existing, "v2")?;
+        fs::write(crate::files::add_suffix(&existing, ".REMOTE")?, "v2")?;
+
+        // the creation of the file failed
+        journal.record_created(&folder.join("failed.txt"));
+
+        journal.rollback(&logger)?;
+        assert_that!(fs::read_to_string(&existing)?).is_equal_to("v1".to_owned());
+        assert_that!(created.exists()).is_false();
+        assert_that!(folder.exists()).is_false();
+        assert_that!(crate::files::add_suffix(&existing, ".REMOTE")?.exists()).is_false();
+        assert_that!(fs::read_to_string(&local)?).is_equal_to("mine".to_owned());
+        Ok(())
+    }
+}
@@ -1,42 +1,94 @@
 #[macro_use]
 extern crate serde;
 
+pub mod analytics;
+pub mod archive;
+pub mod auth;
+pub mod cache;
+pub mod cancel;
+pub mod download;
 pub mod error;
+pub mod git;
+pub mod logging;
+pub mod raw_file;
+pub mod registry;
+pub mod source_provider;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod tools;
 
+mod apply_report;
 mod cfg;
+mod changelog;
+mod check;
 mod cli_opt;
+mod dst_metadata;
+mod emit_plan;
+mod emit_script;
 mod files;
-mod git;
+mod fingerprint;
 mod graph;
+mod hbs_helpers;
+#[cfg(test)]
+mod http_stub;
+mod journal;
+mod lockfile;
+mod man;
+mod manifest;
+mod patch;
 mod path_pattern;
+mod rules;
 mod scripts;
 mod source_file;
 mod source_loc;
 mod source_uri;
 mod ui;
+mod upgrade;
+mod value_provider;
 mod variable_def;
 mod variables;
 
+pub use crate::cancel::CancelToken;
 pub use crate::cfg::provide_json_schema;
+pub use crate::cfg::CfgIssue;
 pub use crate::cli_opt::*;
-pub use crate::source_loc::SourceLoc;
+pub use crate::error::ErrorKind;
+pub use crate::man::render_man;
+pub use crate::manifest::Ownership;
+pub use crate::source_loc::{LocalTemplate, SourceLoc};
+pub use crate::source_provider::SourceProvider;
 pub use crate::source_uri::SourceUri;
 
-use crate::cfg::{render_composite, TemplateComposite};
+use crate::apply_report::ApplyReport;
+use crate::cfg::{render_composite, FeatureDef, MigrationCfg, TemplateComposite};
+use crate::dst_metadata::DstMetadata;
 use crate::error::*;
 use crate::files::ChildPath;
+use crate::fingerprint::Fingerprint;
+use crate::hbs_helpers::new_hbs;
+use crate::journal::Journal;
+use crate::lockfile::{LockedTemplate, Lockfile};
+use crate::manifest::Manifest;
+use crate::path_pattern::PathPattern;
 use crate::source_file::{SourceFile, SourceFileMetadata};
+use crate::value_provider::ValueProvider;
+use crate::variable_def::VariableDef;
 use crate::variables::Variables;
-use handlebars_misc_helpers::new_hbs;
-use slog::{debug, o, warn};
+use slog::{debug, info, o, warn};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
 pub struct Ctx {
     pub logger: slog::Logger,
     pub cmd_opt: ApplyOpts,
+    /// to cancel the processing (eg from an other thread)
+    pub cancel: CancelToken,
+    /// run the commands of the template that provide default values (`default_from_cmd`), else
+    /// the plain default values are used (eg for the renderings without side effect)
+    pub allow_commands: bool,
 }
 
 impl Default for Ctx {
@@ -44,17 +96,95 @@ impl Default for Ctx {
         Ctx {
             logger: slog::Logger::root(slog::Discard, o!()),
             cmd_opt: ApplyOpts::default(),
+            cancel: CancelToken::default(),
+            allow_commands: true,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FileOperation {
     Nothing,
     Ignore,
     MkDir,
     AddFile,
     UpdateFile,
+    /// append the missing lines to the existing file (`.ffizer.append`)
+    Append,
+    /// apply a patch to the existing file (`.ffizer.patch`)
+    Patch,
+    /// deep-merge a fragment into the existing file (`.ffizer.merge-json`, `.ffizer.merge-toml`)
+    Merge,
+}
+
+impl FileOperation {
+    /// true if the operation changes the content of an existing file
+    fn is_change_of_existing_file(&self) -> bool {
+        matches!(
+            self,
+            FileOperation::UpdateFile
+                | FileOperation::Append
+                | FileOperation::Patch
+                | FileOperation::Merge
+        )
+    }
+}
+
+/// why an action is planned (or a source of the templates is skipped), to explain the plan
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Reason {
+    /// the destination doesn't exist
+    NotExisting,
+    /// the folder already exists
+    ExistingFolder,
+    /// the existing file has the content generated
+    SameContent,
+    /// the existing file has an other content than the one generated
+    ContentChanged,
+    /// the existing file is the one generated by the previous apply (see `--track-changes`),
+    /// the template changed it
+    UnchangedSinceGeneration,
+    /// the existing file was modified since the previous apply (see `--track-changes`)
+    ModifiedLocally,
+    /// the template edits the existing file (`.ffizer.append`, `.ffizer.patch`, `.ffizer.merge-*`)
+    EditOfExisting,
+    /// the existing file is owned by the user (`user_owned` of the templates)
+    UserOwned,
+    /// the source matches a pattern of the `ignores` of its template
+    IgnoredByGlob { pattern: String },
+    /// the source belongs to a feature not selected
+    FeatureNotSelected { feature: String },
+    /// the destination doesn't match the `--only` globs, or matches an `--exclude` glob
+    FilteredOut,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::NotExisting => write!(f, "not existing"),
+            Reason::ExistingFolder => write!(f, "existing folder"),
+            Reason::SameContent => write!(f, "same content"),
+            Reason::ContentChanged => write!(f, "content changed"),
+            Reason::UnchangedSinceGeneration => write!(f, "not modified since the last apply"),
+            Reason::ModifiedLocally => write!(f, "modified locally"),
+            Reason::EditOfExisting => write!(f, "edit of the existing file"),
+            Reason::UserOwned => write!(f, "owned by the user"),
+            Reason::IgnoredByGlob { pattern } => write!(f, "ignored by {:?}", pattern),
+            Reason::FeatureNotSelected { feature } => {
+                write!(f, "feature {:?} not selected", feature)
+            }
+            Reason::FilteredOut => write!(f, "filtered out by --only / --exclude"),
+        }
+    }
+}
+
+/// a file or folder of the templates not used by the plan
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedSource {
+    pub src: ChildPath,
+    pub reason: Reason,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -63,35 +193,362 @@ pub struct Action {
     pub dst_path: ChildPath,
     // template: TemplateDef,
     pub operation: FileOperation,
+    /// variables specific to the action (eg the item of a foreach), override the global variables
+    pub variables: Variables,
+    /// ownership of the destination file (from `managed` and `user_owned` of the templates)
+    pub ownership: Option<Ownership>,
+    /// mode to update the existing file, chosen while confirming the plan (else `--update-mode`)
+    pub update_mode: Option<UpdateMode>,
+    /// why the operation is selected
+    pub reason: Reason,
+    /// the sources (of other templates) overridden by the sources of the action
+    pub overridden: Vec<ChildPath>,
+}
+
+/// the actions to apply the templates, with the answers
+struct Plan {
+    template_composite: TemplateComposite,
+    variables: Variables,
+    actions: Vec<Action>,
+    has_ownerships: bool,
+    /// the sources not used (only listed with `--explain` or `--emit-plan`)
+    skipped: Vec<SkippedSource>,
 }
 
+/// apply the templates
 pub fn process(ctx: &Ctx) -> Result<()> {
+    process_with_usage(ctx, &mut analytics::Usage::default())
+}
+
+/// like [`process`], and collect what the apply did into `usage` (to report it with
+/// [`analytics::report_apply`])
+pub fn process_with_usage(ctx: &Ctx, usage: &mut analytics::Usage) -> Result<()> {
+    let Plan {
+        template_composite,
+        variables,
+        mut actions,
+        has_ownerships,
+        skipped,
+    } = prepare_plan(ctx)?;
+    usage.features = analytics::selected_features(&variables);
+    debug!(ctx.logger, "checking rules");
+    check_rules(ctx, &template_composite)?;
+    if let Some(path) = &ctx.cmd_opt.emit_plan {
+        debug!(ctx.logger, "emitting the plan as json"; "path" => ?&path);
+        emit_plan::emit_plan(&actions, &skipped, path)?;
+        return Ok(());
+    }
+    if let Some(path) = &ctx.cmd_opt.emit_script {
+        debug!(ctx.logger, "emitting script of the plan"; "path" => ?&path);
+        emit_script::emit_script(
+            ctx,
+            &actions,
+            &variables,
+            &template_composite.find_scripts()?,
+            path,
+        )?;
+        return Ok(());
+    }
+    if !ctx.cmd_opt.locked {
+        let changelogs = changelog::find_changelogs(ctx, &template_composite)?;
+        ui::show_changelogs(ctx, &changelogs)?;
+    }
+    let diffs = compute_plan_diffs(ctx, &actions, &variables)?;
+    let ignored_by_git = find_ignored_by_git(ctx, &actions);
+    if !ui::confirm_plan(&ctx, &mut actions, &skipped, &diffs, &ignored_by_git)? {
+        return Err(Error::ApplyAborted {});
+    }
+    debug!(ctx.logger, "executing plan of rendering");
+    let summary = execute(ctx, &actions, &variables)?;
+    usage.actions = analytics::ActionCounts {
+        created: summary.created,
+        updated: summary.updated,
+        skipped: summary.skipped,
+        filtered: summary.filtered,
+    };
+    ui::show_summary(&ctx, &summary)?;
+    update_manifest(ctx, &actions, has_ownerships)?;
+    if !ctx.cmd_opt.locked {
+        update_lockfile(ctx, &template_composite)?;
+    }
+    let fingerprint = update_fingerprint(ctx, &template_composite, &variables)?;
+    update_apply_report(ctx, &fingerprint, &summary)?;
+    debug!(ctx.logger, "running scripts");
+    run_scripts(ctx, &template_composite)?;
+    Ok(())
+}
+
+/// create a new project: the destination folder (`ctx.cmd_opt.dst_folder`) is created if missing,
+/// the templates are applied, then a git repository is initialized with an initial commit of
+/// `commit_message` (none to not init git, nothing is done if the destination is already into
+/// a git repository or if the plan is only emitted), what the apply did is collected into `usage`
+pub fn new_project(
+    ctx: &Ctx,
+    commit_message: Option<&str>,
+    usage: &mut analytics::Usage,
+) -> Result<()> {
+    let dst_folder = &ctx.cmd_opt.dst_folder;
+    fs::create_dir_all(dst_folder).map_err(|source| Error::CreateFolder {
+        path: dst_folder.clone(),
+        source,
+    })?;
+    process_with_usage(ctx, usage)?;
+    let emitted = ctx.cmd_opt.emit_plan.is_some() || ctx.cmd_opt.emit_script.is_some();
+    if let (Some(message), false) = (commit_message, emitted) {
+        git::init_with_commit(&ctx.logger, dst_folder, message)?;
+    }
+    Ok(())
+}
+
+/// upgrade the project (`ctx.cmd_opt.dst_folder`) to the rev `to` of its template (recorded into
+/// the lockfile of the project): the files are merged (3-way) with the local changes
+pub fn upgrade_project(ctx: &Ctx, to: &str) -> Result<()> {
+    let outcomes = upgrade::upgrade(ctx, to)?;
+    ui::show_upgrade_outcomes(ctx, &outcomes)
+}
+
+/// check the project (`ctx.cmd_opt.dst_folder`) against its template (`src` if the template is not
+/// recorded into the lockfile of the project), without any change: fail if files drifted
+pub fn check_project(ctx: &Ctx, src: Option<SourceLoc>) -> Result<()> {
+    let drifts = check::check(ctx, src)?;
+    ui::show_drifts(ctx, &drifts)?;
+    if drifts.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::DriftDetected {
+            count: drifts.len(),
+        })
+    }
+}
+
+/// the content generated for a path of the destination (see [`render_in_memory`])
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderedContent {
+    Dir,
+    File(Vec<u8>),
+}
+
+/// render the templates in memory, without any change of the destination folder (no file written,
+/// no script run), eg for test frameworks or previews.
+/// The answers are the `--variables` of `ctx`, else the default values (nothing is asked).
+/// The paths are relative to the destination folder (used as input by some templates).
+pub fn render_in_memory(ctx: &Ctx) -> Result<BTreeMap<PathBuf, RenderedContent>> {
+    let mut ctx = ctx.clone();
+    ctx.cmd_opt.no_interaction = true;
+    ctx.allow_commands = false;
+    let plan = prepare_plan(&ctx)?;
+    render_plan(&ctx, &plan)
+}
+
+fn render_plan(ctx: &Ctx, plan: &Plan) -> Result<BTreeMap<PathBuf, RenderedContent>> {
+    let mut handlebars = new_hbs();
+    let limits = RenderLimits::from(&ctx.cmd_opt);
+    let mut rendered = BTreeMap::new();
+    for a in &plan.actions {
+        ctx.cancel.check("rendering")?;
+        let content = if a.src[0].metadata == SourceFileMetadata::Dir {
+            if a.dst_path.relative.as_os_str().is_empty() {
+                continue;
+            }
+            RenderedContent::Dir
+        } else {
+            RenderedContent::File(compute_content(
+                &mut handlebars,
+                &limits,
+                &plan.variables,
+                a,
+            )?)
+        };
+        rendered.insert(a.dst_path.relative.clone(), content);
+    }
+    Ok(rendered)
+}
+
+fn prepare_plan(ctx: &Ctx) -> Result<Plan> {
     debug!(ctx.logger, "extracting variables from cli");
-    let variables_from_cli = extract_variables(&ctx)?;
+    let variables = extract_variables(&ctx)?;
+    prepare_plan_with(ctx, variables)
+}
+
+/// the templates to apply, from the base to the top: the `--source`, the templates of the `--stack`,
+/// the `--layer` (at their `--layer-rev`)
+fn find_srcs(ctx: &Ctx) -> Result<Vec<SourceLoc>> {
+    let mut srcs = vec![ctx.cmd_opt.src.clone()];
+    if let Some(stack) = &ctx.cmd_opt.stack {
+        srcs.extend(cfg::load_stack(stack)?);
+    }
+    srcs.extend(ctx.cmd_opt.layers.iter().enumerate().map(|(i, uri)| {
+        SourceLoc {
+            uri: uri.clone(),
+            rev: ctx
+                .cmd_opt
+                .layer_revs
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| git::DEFAULT_REV.to_owned()),
+            subfolder: None,
+            full_history: false,
+        }
+    }));
+    for src in srcs.iter_mut() {
+        src.full_history = ctx.cmd_opt.src.full_history;
+    }
+    Ok(srcs)
+}
+
+/// the plan with the `variables` as initial answers (the missing answers are asked)
+fn prepare_plan_with(ctx: &Ctx, mut variables: Variables) -> Result<Plan> {
     debug!(ctx.logger, "compositing templates");
-    let mut template_composite = TemplateComposite::from_src(
-        &ctx,
-        &variables_from_cli,
-        ctx.cmd_opt.offline,
-        &ctx.cmd_opt.src,
-    )?;
-    debug!(ctx.logger, "asking variables");
-    let variables = ui::ask_variables(
-        &ctx,
-        &template_composite.find_variabledefs()?,
-        variables_from_cli,
-    )?;
+    let srcs = find_srcs(ctx)?;
+    let mut template_composite =
+        TemplateComposite::from_srcs(&ctx, &variables, ctx.cmd_opt.offline, &srcs)?;
+    check_no_self_application(&ctx.cmd_opt.dst_folder, &template_composite)?;
+    debug!(ctx.logger, "migrating answers");
+    migrate_variables(&template_composite.find_migrations(), &mut variables)?;
+    let mut selected_features;
+    loop {
+        debug!(ctx.logger, "asking features");
+        let features = template_composite.find_featuredefs()?;
+        selected_features = ui::ask_features(&ctx, &features, &mut variables)?;
+        debug!(ctx.logger, "asking variables"; "features" => ?&selected_features);
+        let mut variabledefs = template_composite.find_variabledefs()?;
+        for feature in features {
+            if selected_features.contains(&feature.name) {
+                variabledefs.extend(feature.variables);
+            }
+        }
+        provide_default_values(ctx, &mut variabledefs, &variables)?;
+        variables = ui::ask_variables(&ctx, &variabledefs, variables)?;
+        debug!(ctx.logger, "computing flags");
+        compute_flags(&template_composite, &mut variables)?;
+        if !template_composite.has_disabled_imports() {
+            break;
+        }
+        // imports disabled before the questions could be enabled by the answers (or the flags)
+        debug!(ctx.logger, "compositing templates with answers");
+        let next = TemplateComposite::from_srcs(&ctx, &variables, ctx.cmd_opt.offline, &srcs)?;
+        if next.has_same_layers(&template_composite) {
+            break;
+        }
+        template_composite = next;
+    }
     // update cfg(s) with variables defined by user (use to update ignore, scripts,...)
     template_composite = render_composite(&ctx, &template_composite, &variables, true)?;
     debug!(ctx.logger, "listing files from templates");
-    let source_files = template_composite.find_sourcefiles()?;
+    let (source_files, mut skipped) = exclude_unselected_features(
+        template_composite.find_sourcefiles()?,
+        &template_composite.find_featuredefs()?,
+        &selected_features,
+    );
+    if ctx.cmd_opt.explain || ctx.cmd_opt.emit_plan.is_some() {
+        skipped.extend(
+            template_composite
+                .find_ignored_sourcefiles()?
+                .into_iter()
+                .map(|(src, pattern)| SkippedSource {
+                    src,
+                    reason: Reason::IgnoredByGlob { pattern },
+                }),
+        );
+    } else {
+        skipped.clear();
+    }
+    let source_files = skip_rendering_of_binaries(ctx, source_files)?;
     debug!(ctx.logger, "defining plan of rendering");
-    let actions = plan(ctx, source_files, &variables)?;
-    if ui::confirm_plan(&ctx, &actions)? {
-        debug!(ctx.logger, "executing plan of rendering");
-        execute(ctx, &actions, &variables)?;
-        debug!(ctx.logger, "running scripts");
-        run_scripts(ctx, &template_composite)?;
+    let ownerships = template_composite.find_ownerships()?;
+    let actions = plan(
+        ctx,
+        source_files,
+        &variables,
+        &template_composite.find_layer_variables(&variables)?,
+        &template_composite.find_foreachs(),
+        &ownerships,
+    )?;
+    Ok(Plan {
+        template_composite,
+        variables,
+        actions,
+        has_ownerships: !ownerships.is_empty(),
+        skipped,
+    })
+}
+
+/// ignore the files and folders of the features not selected (except if they belong to a selected feature),
+/// the ignored ones are returned as skipped
+fn exclude_unselected_features(
+    source_files: Vec<SourceFile>,
+    features: &[FeatureDef],
+    selected: &[String],
+) -> (Vec<SourceFile>, Vec<SkippedSource>) {
+    let (selected, unselected): (Vec<&FeatureDef>, Vec<&FeatureDef>) =
+        features.iter().partition(|f| selected.contains(&f.name));
+    if unselected.is_empty() {
+        return (source_files, vec![]);
+    }
+    let mut kept = Vec::with_capacity(source_files.len());
+    let mut skipped = vec![];
+    for source_file in source_files {
+        let relative = &source_file.childpath.relative;
+        let excluded_by = unselected.iter().find(|f| f.contains(relative));
+        match excluded_by {
+            Some(feature) if !selected.iter().any(|f| f.contains(relative)) => {
+                skipped.push(SkippedSource {
+                    src: source_file.childpath,
+                    reason: Reason::FeatureNotSelected {
+                        feature: feature.name.clone(),
+                    },
+                })
+            }
+            _ => kept.push(source_file),
+        }
+    }
+    (kept, skipped)
+}
+
+/// the binary files (images, jars,...) named as templates are copied as is (without rendering), except
+/// the ones with an encoding defined by the template
+fn skip_rendering_of_binaries(
+    ctx: &Ctx,
+    mut source_files: Vec<SourceFile>,
+) -> Result<Vec<SourceFile>> {
+    for source_file in source_files.iter_mut() {
+        if let SourceFileMetadata::RenderableFile { encoding: None, .. } = source_file.metadata {
+            let path = PathBuf::from(&source_file.childpath);
+            if files::is_binary(&path)? {
+                warn!(ctx.logger, "binary file copied without rendering"; "path" => ?&path);
+                source_file.metadata = SourceFileMetadata::RawFile;
+            }
+        }
+    }
+    Ok(source_files)
+}
+
+/// refuse to apply a template into itself: the destination is the folder of a template (or inside it),
+/// or the destination is a clone of the template's repository (a project pointing back at the template)
+fn check_no_self_application(
+    dst_folder: &Path,
+    template_composite: &TemplateComposite,
+) -> Result<()> {
+    let dst = files::absolute_path(dst_folder);
+    let dst_remote_url = git::find_remote_url(&dst).ok();
+    for loc in template_composite.find_locs() {
+        let error = |reason: &str| Error::SelfApplication {
+            src: loc.uri.raw.clone(),
+            dst: dst_folder.to_path_buf(),
+            reason: reason.to_owned(),
+        };
+        let template_dir = files::absolute_path(loc.as_local_path()?);
+        if dst == template_dir {
+            return Err(error("the destination is the template"));
+        }
+        if dst.starts_with(&template_dir) {
+            return Err(error("the destination is inside the template"));
+        }
+        if let (Some(_), Some(url)) = (&loc.uri.host, &dst_remote_url) {
+            if git::is_same_remote_url(url, &loc.uri.raw) {
+                return Err(error("the destination is a clone of the template"));
+            }
+        }
     }
     Ok(())
 }
@@ -112,8 +569,21 @@ where
     res
 }
 
+/// the issues of the configuration (`.ffizer.yaml`) of the template into `template_folder`
+pub fn lint_template(template_folder: &Path) -> Result<Vec<CfgIssue>> {
+    if !template_folder.is_dir() {
+        return Err(Error::LocalPathNotFound {
+            path: template_folder.to_path_buf(),
+            uri: template_folder.to_string_lossy().into_owned(),
+            subfolder: None,
+        });
+    }
+    cfg::TemplateCfg::lint(template_folder)
+}
+
 pub fn extract_variables(ctx: &Ctx) -> Result<Variables> {
-    let mut variables = Variables::default();
+    // answers of the `ffizer.vars.yaml` of the destination, overridden by the cli
+    let mut variables = variables::load_vars_files(&ctx.cmd_opt.dst_folder)?;
     variables.insert(
         "ffizer_dst_folder",
         ctx.cmd_opt
@@ -123,111 +593,496 @@ pub fn extract_variables(ctx: &Ctx) -> Result<Variables> {
     )?;
     variables.insert("ffizer_src_uri", ctx.cmd_opt.src.uri.raw.clone())?;
     variables.insert("ffizer_src_rev", ctx.cmd_opt.src.rev.clone())?;
-
-    ctx.cmd_opt
-        .key_value
-        .iter()
-        .map(|(k, v)| variables.insert(k, Variables::value_from_str(&v)?))
-        .collect::<Result<Vec<()>>>()?;
+    let dst_folder_path = files::absolute_path(&ctx.cmd_opt.dst_folder);
+    variables.insert_ffizer(
+        "dst_folder_name",
+        dst_folder_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    )?;
+    variables.insert_ffizer("dst_folder_path", dst_folder_path.to_string_lossy())?;
+    variables.insert_ffizer("src_uri", ctx.cmd_opt.src.uri.raw.clone())?;
+    variables.insert_ffizer("src_rev", ctx.cmd_opt.src.rev.clone())?;
+    variables.insert_ffizer(
+        "dst_git_remote_url",
+        git::find_enclosing_remote_url(&dst_folder_path).unwrap_or_default(),
+    )?;
+    variables.insert_ffizer("host_os", std::env::consts::OS)?;
+    variables.insert_ffizer("host_arch", std::env::consts::ARCH)?;
+    let target = ctx
+        .cmd_opt
+        .target
+        .clone()
+        .unwrap_or_else(|| std::env::consts::OS.to_owned());
+    let target_os = target_os(&target);
+    variables.insert_ffizer("target_family", target_family(&target_os))?;
+    variables.insert_ffizer("target_os", target_os)?;
+    variables.insert_ffizer("target", target)?;
+
+    for (k, v) in &ctx.cmd_opt.key_value {
+        if k == variables::STDIN_VARIABLES && v.is_empty() {
+            debug!(ctx.logger, "reading variables from stdin");
+            variables.append(&mut variables::read_vars_from_stdin()?);
+        } else {
+            variables.insert(k, Variables::value_from_str(&v)?)?;
+        }
+    }
     Ok(variables)
 }
 
+/// the os of the `target` (an os, or a target triple like `x86_64-pc-windows-msvc`), named like
+/// `std::env::consts::OS`
+fn target_os(target: &str) -> String {
+    let target = target.trim().to_lowercase();
+    if !target.contains('-') {
+        return match target.as_str() {
+            "win" | "win32" | "win64" => "windows".to_owned(),
+            "osx" | "darwin" | "mac" => "macos".to_owned(),
+            _ => target,
+        };
+    }
+    let parts = target.split('-').collect::<Vec<_>>();
+    let known = [
+        "windows", "linux", "android", "freebsd", "netbsd", "openbsd", "ios", "solaris",
+    ];
+    if parts.contains(&"darwin") {
+        "macos".to_owned()
+    } else if let Some(os) = known.iter().find(|os| parts.contains(os)) {
+        (*os).to_owned()
+    } else {
+        target
+    }
+}
+
+/// the family of the os (`windows` or `unix`), like `std::env::consts::FAMILY`
+fn target_family(target_os: &str) -> &'static str {
+    if target_os == "windows" {
+        "windows"
+    } else {
+        "unix"
+    }
+}
+
+/// migrate the answers given for an older version of the template (rename variables, replace values)
+fn migrate_variables(migrations: &[MigrationCfg], variables: &mut Variables) -> Result<()> {
+    for migration in migrations {
+        let value = match variables.get(&migration.variable) {
+            Some(v) => v.clone(),
+            None => continue,
+        };
+        let as_str = match &value {
+            serde_yaml::Value::String(s) => Some(s.clone()),
+            serde_yaml::Value::Bool(b) => Some(b.to_string()),
+            serde_yaml::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        };
+        let value = match as_str.and_then(|s| migration.values.get(&s)) {
+            Some(new_value) => Variables::value_from_str(new_value)?,
+            None => value,
+        };
+        match &migration.rename_to {
+            Some(name) if !name.trim().is_empty() => {
+                variables.remove(&migration.variable);
+                // an answer given with the new name is kept
+                if !variables.contains_key(name.trim()) {
+                    variables.insert(name.trim(), value)?;
+                }
+            }
+            _ => variables.insert(&migration.variable, value)?,
+        }
+    }
+    Ok(())
+}
+
+/// evaluate the flags (from the templates) and store them as boolean variables
+fn compute_flags(template_composite: &TemplateComposite, variables: &mut Variables) -> Result<()> {
+    let handlebars = new_hbs();
+    for (name, expr) in template_composite.find_flagdefs() {
+        let rendered = handlebars
+            .render_template(&expr, &variables)
+            .map_err(|source| Error::Handlebars {
+                when: format!("compute flag '{}'", &name),
+                template: expr.clone(),
+                source,
+            })?;
+        let value = match rendered.trim() {
+            "" | "false" => false,
+            "true" => true,
+            v => {
+                return Err(Error::StringValueNotIn {
+                    value_name: name,
+                    value: v.to_owned(),
+                    accepted: vec!["true".to_owned(), "false".to_owned(), "".to_owned()],
+                })
+            }
+        };
+        variables.insert(name, value)?;
+    }
+    Ok(())
+}
+
 /// list actions to execute
-fn plan(ctx: &Ctx, source_files: Vec<SourceFile>, variables: &Variables) -> Result<Vec<Action>> {
+fn plan(
+    ctx: &Ctx,
+    source_files: Vec<SourceFile>,
+    variables: &Variables,
+    layer_variables: &[Variables],
+    foreachs: &[(String, String)],
+    ownerships: &[(PathPattern, Ownership)],
+) -> Result<Vec<Action>> {
     // TODO create a map (dst_path, Vec<src_path>) src_path keep the order of application (from template layer)
     // TODO change Action into enum ?
     // TODO AddFile/UpdateFile can support a list of src_path
-    let list_dst_and_src = source_files
-        .into_iter()
-        .map(|source_file| {
-            compute_dst_path(ctx, &source_file.childpath(), variables)
-                .map(|dst_path| (dst_path, source_file))
+    let mut handlebars = new_hbs();
+    // the hashes of the files generated by the previous apply (see `--track-changes`)
+    let generated_hashes = Manifest::load(&ctx.cmd_opt.dst_folder)?
+        .map(|m| m.hashes)
+        .unwrap_or_default();
+    // the variables with the variables specific to each template (namespace, bindings),
+    // merged once per template (not per file)
+    let with_layers = layer_variables
+        .iter()
+        .map(|layer| {
+            if layer.is_empty() {
+                None
+            } else {
+                let mut v = variables.clone();
+                v.append(&mut layer.clone());
+                Some(v)
+            }
         })
-        .collect::<Result<Vec<_>>>()?;
+        .collect::<Vec<_>>();
     // group by destination
-    let srcs_by_dst = list_dst_and_src.into_iter().fold(
-        std::collections::HashMap::<ChildPath, Vec<SourceFile>>::new(),
-        |mut acc, l| {
-            if let Some(x) = acc.get_mut(&l.0) {
-                x.push(l.1);
-            } else {
-                acc.insert(l.0, vec![l.1]);
+    let mut srcs_by_dst =
+        std::collections::HashMap::<ChildPath, (Variables, Vec<SourceFile>)>::new();
+    let mut template_folders = std::collections::HashMap::new();
+    for source_file in source_files {
+        ctx.cancel.check("planning")?;
+        check_inside_template(&mut template_folders, &source_file)?;
+        let layer = layer_variables
+            .get(source_file.layer_order)
+            .filter(|v| !v.is_empty());
+        let variables = with_layers
+            .get(source_file.layer_order)
+            .and_then(|v| v.as_ref())
+            .unwrap_or(variables);
+        for (dst_path, mut action_variables) in compute_dst_paths(
+            ctx,
+            &handlebars,
+            &source_file.childpath(),
+            variables,
+            foreachs,
+        )? {
+            if let Some(layer) = layer {
+                let mut v = layer.clone();
+                v.append(&mut action_variables);
+                action_variables = v;
             }
-            acc
-        },
-    );
+            srcs_by_dst
+                .entry(dst_path)
+                .or_insert_with(|| (action_variables, vec![]))
+                .1
+                .push(source_file.clone());
+        }
+    }
     //actions.dedup_by(|a, b| PathBuf::from(&a.dst_path) == PathBuf::from(&b.dst_path));
+    // sort to report collisions in a stable order
+    // (and to have folder before files inside it, mkdir before create file)
+    let mut srcs_by_dst = srcs_by_dst.into_iter().collect::<Vec<_>>();
+    srcs_by_dst.sort_unstable_by(|a, b| a.0.relative.cmp(&b.0.relative));
+    check_inside_dst(&ctx.cmd_opt.dst_folder, &srcs_by_dst, ownerships)?;
+    // the existing destination is listed once per folder (not a lookup per action)
+    let dst_metadata = DstMetadata::scan(
+        &ctx.cmd_opt.dst_folder,
+        srcs_by_dst
+            .iter()
+            .map(|(dst_path, _)| dst_path.relative.as_path()),
+    );
     let mut actions = srcs_by_dst
         .into_iter()
-        .map(|(dst_path, mut src)| {
+        .map(|(dst_path, (action_variables, mut src))| {
+            if let Some((a, b)) = source_file::find_conflict(&src) {
+                return Err(Error::DuplicateDestination {
+                    dst: PathBuf::from(&dst_path),
+                    src1: PathBuf::from(&a.childpath),
+                    src2: PathBuf::from(&b.childpath),
+                });
+            }
+            let all_src = src.clone();
             source_file::optimize_sourcefiles(&mut src);
-            let operation = select_operation(ctx, &src, &dst_path);
-            Action {
+            if src.len() > 1 {
+                debug!(ctx.logger, "collision resolved by precedence"; "dst" => ?&dst_path.relative, "src" => ?&src[0].childpath.relative);
+            }
+            // the folders are merged, not overridden
+            let overridden = all_src
+                .into_iter()
+                .filter(|s| s.metadata != SourceFileMetadata::Dir && !src.contains(s))
+                .map(|s| s.childpath)
+                .collect::<Vec<_>>();
+            let ownership = if src[0].metadata == SourceFileMetadata::Dir {
+                None
+            } else {
+                find_ownership(&dst_path.relative, ownerships)
+            };
+            let mut action = Action {
                 //TODO reduce src (remove useless source) + test
                 //TODO add SourceFile of existing file
-                //TODO select the right operation
                 dst_path,
                 src,
-                operation,
-            }
+                operation: FileOperation::Nothing,
+                variables: action_variables,
+                ownership,
+                update_mode: None,
+                reason: Reason::NotExisting,
+                overridden,
+            };
+            let (operation, reason) = select_operation(
+                ctx,
+                &mut handlebars,
+                variables,
+                &action,
+                dst_metadata.find_is_dir(&action.dst_path.relative),
+                generated_hashes.get(&action.dst_path.relative),
+            );
+            action.operation = operation;
+            action.reason = reason;
+            Ok(action)
         })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
         .filter(|a| !a.src.is_empty())
         .collect::<Vec<_>>();
-    // sort to have folder before files inside it (and mkdir berfore create file)
-    actions.sort_by_key(|a| a.dst_path.relative.clone());
+    filter_actions(ctx, &mut actions)?;
+    check_no_file_used_as_folder(&actions)?;
+    check_portable_paths(&actions)?;
     Ok(actions)
 }
 
-//TODO accumulate Result (and error)
-fn execute(ctx: &Ctx, actions: &[Action], variables: &Variables) -> Result<()> {
-    use indicatif::ProgressBar;
+/// ignore the actions filtered out by `--only` and `--exclude` (globs of the path into the destination),
+/// the folders of the actions kept are kept (to create them)
+fn filter_actions(ctx: &Ctx, actions: &mut [Action]) -> Result<()> {
+    if ctx.cmd_opt.only.is_empty() && ctx.cmd_opt.exclude.is_empty() {
+        return Ok(());
+    }
+    let to_patterns = |globs: &[String]| {
+        globs
+            .iter()
+            .map(|g| g.parse::<PathPattern>())
+            .collect::<Result<Vec<_>>>()
+    };
+    let only = to_patterns(&ctx.cmd_opt.only)?;
+    let exclude = to_patterns(&ctx.cmd_opt.exclude)?;
+    let is_selected = |a: &Action| {
+        a.dst_path
+            .relative
+            .to_str()
+            .map(|s| {
+                (only.is_empty() || only.iter().any(|p| p.is_match(s)))
+                    && !exclude.iter().any(|p| p.is_match(s))
+            })
+            .unwrap_or(false)
+    };
+    let mut kept_folders = HashSet::new();
+    for a in actions.iter() {
+        if a.src[0].metadata != SourceFileMetadata::Dir && is_selected(a) {
+            kept_folders.extend(a.dst_path.relative.ancestors().skip(1).map(PathBuf::from));
+        }
+    }
+    for a in actions.iter_mut() {
+        let kept = if a.src[0].metadata == SourceFileMetadata::Dir {
+            kept_folders.contains(&a.dst_path.relative) || is_selected(a)
+        } else {
+            is_selected(a)
+        };
+        if !kept {
+            a.operation = FileOperation::Ignore;
+            a.reason = Reason::FilteredOut;
+        }
+    }
+    Ok(())
+}
 
-    let pb = ProgressBar::new(actions.len() as u64);
+/// the current and the generated content of a file to update, to review the plan
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PlanDiff {
+    pub dst_path: ChildPath,
+    pub local: Vec<u8>,
+    pub remote: Vec<u8>,
+}
+
+/// compute the contents of the files to update (only if requested by `--show-diff`, `--diff-tool`
+/// or `--confirm PerFile`)
+fn compute_plan_diffs(
+    ctx: &Ctx,
+    actions: &[Action],
+    variables: &Variables,
+) -> Result<Vec<PlanDiff>> {
+    let per_file = ctx.cmd_opt.confirm == AskConfirmation::PerFile && !ctx.cmd_opt.no_interaction;
+    if !ctx.cmd_opt.show_diff && !ctx.cmd_opt.diff_tool && !per_file {
+        return Ok(vec![]);
+    }
     let mut handlebars = new_hbs();
-    debug!(ctx.logger, "execute"; "variables" => ?&variables);
-
-    for a in pb.wrap_iter(actions.iter()) {
-        match a.operation {
-            FileOperation::Nothing => (),
-            FileOperation::Ignore => (),
-            // TODO bench performance vs create_dir (and keep create_dir_all for root aka relative is empty)
-            FileOperation::MkDir => {
-                let path = PathBuf::from(&a.dst_path);
-                fs::create_dir_all(&path).map_err(|source| Error::CreateFolder { path, source })?;
-                copy_file_permissions(
-                    PathBuf::from(a.src[0].childpath()),
-                    PathBuf::from(&a.dst_path),
-                )?
-            }
-            FileOperation::AddFile => {
-                mk_file_on_action(&mut handlebars, variables, &a, "").map(|_| ())?
-            }
-            FileOperation::UpdateFile => {
-                //TODO what to do if .LOCAL, .REMOTE already exist ?
-                let (local, remote) = mk_file_on_action(&mut handlebars, variables, &a, ".REMOTE")?;
-                let local_digest =
-                    md5::compute(fs::read(&local).map_err(|source| Error::ReadFile {
-                        path: local.clone(),
-                        source,
-                    })?);
-                let remote_digest =
-                    md5::compute(fs::read(&remote).map_err(|source| Error::ReadFile {
-                        path: remote.clone(),
-                        source,
-                    })?);
-                if local_digest == remote_digest {
-                    fs::remove_file(&remote).map_err(|source| Error::RemoveFile {
-                        path: remote.clone(),
-                        source,
-                    })?
-                } else {
-                    update_file(
-                        //FIXME to use all the source
-                        &PathBuf::from(a.src[0].childpath()),
-                        &local,
-                        &remote,
-                        &ctx.cmd_opt.update_mode,
-                    )?
+    let limits = RenderLimits::from(&ctx.cmd_opt);
+    actions
+        .iter()
+        .filter(|a| a.operation.is_change_of_existing_file())
+        .map(|a| {
+            let path = PathBuf::from(&a.dst_path);
+            let local = fs::read(&path).map_err(|source| Error::ReadFile { path, source })?;
+            let remote = compute_content(&mut handlebars, &limits, variables, a)?;
+            Ok(PlanDiff {
+                dst_path: a.dst_path.clone(),
+                local,
+                remote,
+            })
+        })
+        .collect()
+}
+
+/// the files and folders to generate that are ignored by the `.gitignore` of the destination
+/// (if it is into a git repository), likely a mistake (eg generated into `target/`),
+/// the content of an ignored folder is not listed
+fn find_ignored_by_git(ctx: &Ctx, actions: &[Action]) -> Vec<PathBuf> {
+    let relatives = actions
+        .iter()
+        .filter(|a| {
+            a.operation != FileOperation::Nothing
+                && a.operation != FileOperation::Ignore
+                && !a.dst_path.relative.as_os_str().is_empty()
+        })
+        .map(|a| a.dst_path.relative.clone())
+        .collect::<Vec<_>>();
+    match git::find_ignored_paths(&ctx.cmd_opt.dst_folder, &relatives) {
+        Ok(ignored) => {
+            let mut reported: Vec<PathBuf> = vec![];
+            for path in ignored {
+                if !reported.iter().any(|p| path.starts_with(p)) {
+                    reported.push(path);
+                }
+            }
+            reported
+        }
+        Err(err) => {
+            debug!(ctx.logger, "fail to find the paths ignored by git"; "err" => ?err);
+            vec![]
+        }
+    }
+}
+
+/// record the ownership of the generated files into the manifest of the destination,
+/// and report the files with a different ownership than on the previous apply
+fn update_manifest(ctx: &Ctx, actions: &[Action], has_ownerships: bool) -> Result<()> {
+    let previous = Manifest::load(&ctx.cmd_opt.dst_folder)?;
+    let track_changes = ctx.cmd_opt.track_changes
+        || previous
+            .as_ref()
+            .map(|p| !p.hashes.is_empty())
+            .unwrap_or(false);
+    if previous.is_none() && !has_ownerships && !track_changes {
+        return Ok(());
+    }
+    let mut next = Manifest::default();
+    for a in actions {
+        if let Some(ownership) = a.ownership {
+            next.files.insert(a.dst_path.relative.clone(), ownership);
+        }
+    }
+    if track_changes {
+        next.hashes = find_generated_hashes(ctx, actions, previous.as_ref())?;
+    }
+    if let Some(previous) = previous {
+        ui::show_ownership_changes(ctx, &previous.changes_to(&next))?;
+    }
+    next.save(&ctx.cmd_opt.dst_folder)
+}
+
+/// the hashes of the files as generated: the files created, kept or overridden with the generated
+/// content (for the other files, the content generated is unknown, the previous hash is kept)
+fn find_generated_hashes(
+    ctx: &Ctx,
+    actions: &[Action],
+    previous: Option<&Manifest>,
+) -> Result<BTreeMap<PathBuf, String>> {
+    let mut hashes = BTreeMap::new();
+    for a in actions {
+        let dst = PathBuf::from(&a.dst_path);
+        let is_generated = match a.operation {
+            FileOperation::AddFile => true,
+            FileOperation::Nothing => a.reason == Reason::SameContent,
+            FileOperation::UpdateFile => matches!(
+                find_update_mode(ctx, a).0,
+                UpdateMode::Override | UpdateMode::CurrentAsLocal
+            ),
+            _ => false,
+        };
+        let hash = if is_generated && dst.is_file() {
+            Some(files::sha256_file(&dst)?)
+        } else {
+            previous.and_then(|p| p.hashes.get(&a.dst_path.relative).cloned())
+        };
+        if let Some(hash) = hash {
+            hashes.insert(a.dst_path.relative.clone(), hash);
+        }
+    }
+    Ok(hashes)
+}
+
+/// check the rules of the templates (after the questions, before any change of the destination),
+/// fail with the message of the first rule violated (the commands are run only if confirmed)
+fn check_rules(ctx: &Ctx, template_composite: &TemplateComposite) -> Result<()> {
+    for (loc, rules) in template_composite.find_rules() {
+        for rule in rules {
+            let run_cmd = match &rule.cmd {
+                Some(cmd) => ui::confirm_run_script(ctx, loc, cmd)?,
+                None => false,
+            };
+            if rule.cmd.is_some() && !run_cmd {
+                warn!(ctx.logger, "command of the rule not run, the rule is not fully checked"; "cmd" => &rule.cmd);
+            }
+            if let Some(reason) = rule.find_violation(&ctx.cmd_opt.dst_folder, run_cmd)? {
+                return Err(Error::RuleViolated {
+                    template: loc.uri.raw.clone(),
+                    message: match &rule.message {
+                        Some(message) => format!("{}: {}", message, reason),
+                        None => reason,
+                    },
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// replace the default value of the variables (not already defined) by the first value of their
+/// providers, a provider that fails is skipped (the default value is kept)
+fn provide_default_values(
+    ctx: &Ctx,
+    variabledefs: &mut [VariableDef],
+    variables: &Variables,
+) -> Result<()> {
+    for variabledef in variabledefs
+        .iter_mut()
+        .filter(|v| variables.get(v.name.as_str()).is_none())
+    {
+        for provider in &variabledef.default_providers {
+            let run_cmd = match provider {
+                ValueProvider::Cmd(_) if !ctx.allow_commands => continue,
+                ValueProvider::Cmd(cmd) => ui::confirm_run_script(
+                    ctx,
+                    format!("default value of '{}'", variabledef.name),
+                    cmd,
+                )?,
+                ValueProvider::Dotenv { .. } => false,
+            };
+            match provider.provide(&ctx.cmd_opt.dst_folder, run_cmd) {
+                Ok(Some(value)) => {
+                    variabledef.default_value = Some(serde_yaml::Value::String(value));
+                    break;
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    warn!(ctx.logger, "failed to provide the default value"; "variable" => &variabledef.name, "provider" => ?provider, "error" => ?err)
                 }
             }
         }
@@ -235,78 +1090,703 @@ fn execute(ctx: &Ctx, actions: &[Action], variables: &Variables) -> Result<()> {
     Ok(())
 }
 
+/// record the commits of the templates (hosted in git repositories) into the lockfile of the destination
+fn update_lockfile(ctx: &Ctx, template_composite: &TemplateComposite) -> Result<()> {
+    let previous = Lockfile::load(&ctx.cmd_opt.dst_folder)?;
+    let mut next = Lockfile::default();
+    // the first layer is the template applied, the others are its imports
+    for (i, loc) in template_composite.find_locs().into_iter().enumerate() {
+        // the template is already retrieved
+        if let Some(rev_info) = loc.retrieve(&ctx.logger, true)?.rev_info {
+            let locked = LockedTemplate {
+                uri: loc.uri.raw.clone(),
+                rev: loc.rev.clone(),
+                subfolder: loc.subfolder.clone(),
+                commit_id: rev_info.commit_id,
+            };
+            if i == 0 {
+                next.source = Some(locked);
+            } else {
+                next.imports.push(locked);
+            }
+        }
+    }
+    if next.templates().next().is_none() && previous.is_none() {
+        return Ok(());
+    }
+    for t in next.templates() {
+        let before = previous.as_ref().and_then(|p| {
+            p.templates()
+                .find(|b| b.uri == t.uri && b.subfolder == t.subfolder)
+        });
+        match before {
+            Some(b) if b.commit_id != t.commit_id => {
+                info!(ctx.logger, "template updated"; "uri" => &t.uri, "from" => &b.commit_id, "to" => &t.commit_id)
+            }
+            _ => (),
+        }
+    }
+    next.save(&ctx.cmd_opt.dst_folder)
+}
+
+/// report the fingerprint of the apply (with its comparison to the fingerprint of the previous apply),
+/// and record it into the destination (if requested or already recorded)
+fn update_fingerprint(
+    ctx: &Ctx,
+    template_composite: &TemplateComposite,
+    variables: &Variables,
+) -> Result<Fingerprint> {
+    let previous = Fingerprint::load(&ctx.cmd_opt.dst_folder)?;
+    let next = Fingerprint::compute(ctx, template_composite, variables)?;
+    ui::show_fingerprint(ctx, &next, previous.as_ref())?;
+    if ctx.cmd_opt.fingerprint || previous.is_some() {
+        next.save(&ctx.cmd_opt.dst_folder)?;
+    }
+    Ok(next)
+}
+
+/// record the report of the apply with `--report` (always updated once recorded)
+fn update_apply_report(
+    ctx: &Ctx,
+    fingerprint: &Fingerprint,
+    summary: &ExecuteSummary,
+) -> Result<()> {
+    if ctx.cmd_opt.report || ApplyReport::path_in(&ctx.cmd_opt.dst_folder).exists() {
+        ApplyReport::new(fingerprint, summary).save(&ctx.cmd_opt.dst_folder)?;
+    }
+    Ok(())
+}
+
+/// reject the files and folders to create with a path not valid on Windows (even on other platforms,
+/// to keep the generated project usable on every platform)
+fn check_portable_paths(actions: &[Action]) -> Result<()> {
+    for a in actions {
+        if a.operation != FileOperation::MkDir && a.operation != FileOperation::AddFile {
+            continue;
+        }
+        if let Err(reason) = files::check_portable_path(&a.dst_path.relative) {
+            return Err(Error::UnportablePath {
+                dst: PathBuf::from(&a.dst_path),
+                src: PathBuf::from(&a.src[0].childpath),
+                reason,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// reject the paths outside of the destination folder: a path (rendered from the template) absolute or
+/// with a `..` segment, or a path under a symlink (of the destination, even dangling) to an other
+/// folder. Checked before any read of the destination, the existing symlinks are looked up once per
+/// path. The folders and the user owned files are not written: only their parents are checked.
+fn check_inside_dst(
+    dst_folder: &Path,
+    srcs_by_dst: &[(ChildPath, (Variables, Vec<SourceFile>))],
+    ownerships: &[(PathPattern, Ownership)],
+) -> Result<()> {
+    // resolved only if the destination contains symlinks
+    let mut dst_folder_resolved = None;
+    let mut checked = HashSet::new();
+    for (dst_path, (_, src)) in srcs_by_dst {
+        let relative = &dst_path.relative;
+        let mut inside = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        let is_written = src.iter().any(|s| s.metadata != SourceFileMetadata::Dir)
+            && find_ownership(relative, ownerships) != Some(Ownership::UserOwned);
+        let paths = relative
+            .ancestors()
+            .skip(if is_written { 0 } else { 1 })
+            .filter(|p| !p.as_os_str().is_empty());
+        for path in paths {
+            if !inside {
+                break;
+            }
+            if !checked.insert(path.to_path_buf()) {
+                continue;
+            }
+            let full_path = dst_folder.join(path);
+            let is_symlink = fs::symlink_metadata(&full_path)
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(false);
+            if is_symlink {
+                let resolved =
+                    dst_folder_resolved.get_or_insert_with(|| files::absolute_path(dst_folder));
+                inside = files::resolve_symlink(full_path).starts_with(&resolved);
+            }
+        }
+        if !inside {
+            return Err(Error::PathOutsideDestination {
+                dst: relative.clone(),
+                src: PathBuf::from(&src[0].childpath),
+                dst_folder: dst_folder.to_path_buf(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// reject the source that is a symlink (even dangling) to a path outside of its template folder (eg
+/// `key -> ~/.ssh/id_rsa`), the symlink is followed on copy. `template_folders` caches the
+/// absolute path of the template folders (resolved only if the template contains symlinks)
+fn check_inside_template(
+    template_folders: &mut std::collections::HashMap<PathBuf, PathBuf>,
+    source_file: &SourceFile,
+) -> Result<()> {
+    let src = PathBuf::from(&source_file.childpath);
+    let is_symlink = fs::symlink_metadata(&src)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false);
+    if is_symlink {
+        let base = &source_file.childpath.base;
+        let template = template_folders
+            .entry(base.clone())
+            .or_insert_with(|| files::absolute_path(base));
+        let target = files::resolve_symlink(&src);
+        if !target.starts_with(&template) {
+            return Err(Error::SymlinkOutsideTemplate {
+                src,
+                target,
+                template: template.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// the ownership of the destination (the first pattern matching)
+fn find_ownership(relative: &Path, ownerships: &[(PathPattern, Ownership)]) -> Option<Ownership> {
+    relative.to_str().and_then(|s| {
+        ownerships
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(s))
+            .map(|(_, ownership)| *ownership)
+    })
+}
+
+/// a file (from a layer) can not be the parent of a path (from an other layer, where it is a folder),
+/// `actions` should be sorted (the children of a path are just after it)
+fn check_no_file_used_as_folder(actions: &[Action]) -> Result<()> {
+    for pair in actions.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if a.src[0].metadata != SourceFileMetadata::Dir
+            && b.dst_path.relative.starts_with(&a.dst_path.relative)
+        {
+            return Err(Error::FileUsedAsFolder {
+                dst: PathBuf::from(&a.dst_path),
+                src: PathBuf::from(&a.src[0].childpath),
+                child_dst: PathBuf::from(&b.dst_path),
+                child_src: PathBuf::from(&b.src[0].childpath),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// counters of the actions done by `execute`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ExecuteSummary {
+    /// files and folders created
+    pub created: usize,
+    /// existing files updated (according to the update mode)
+    pub updated: usize,
+    /// actions without change (nothing to do, ignored, same content)
+    pub skipped: usize,
+    /// actions ignored by `--only` and `--exclude` (not counted as skipped)
+    pub filtered: usize,
+    pub elapsed: std::time::Duration,
+    /// the outcome of each action (not filtered) by path (relative to the destination)
+    pub outcomes: Vec<(PathBuf, Outcome)>,
+}
+
+impl ExecuteSummary {
+    fn add(&mut self, a: &Action, outcome: Outcome) {
+        match outcome {
+            Outcome::Created => self.created += 1,
+            Outcome::Updated => self.updated += 1,
+            Outcome::Skipped => self.skipped += 1,
+        }
+        self.outcomes.push((a.dst_path.relative.clone(), outcome));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Outcome {
+    Created,
+    Updated,
+    Skipped,
+}
+
+//TODO accumulate Result (and error)
+/// execute the actions in 3 steps:
+/// 1. create folders (serial, in the order of the plan: parent before children)
+/// 2. add new files (in parallel with `--jobs` threads, their folders already exist)
+/// 3. update existing files (serial, because it could ask the user)
+///
+/// If `ctx.cancel` is cancelled, the remaining actions are not executed and
+/// `Error::Cancelled` lists the files and folders created or updated.
+///
+/// In transactional mode, on failure (or cancellation), the changes already done are rolled back.
+fn execute(ctx: &Ctx, actions: &[Action], variables: &Variables) -> Result<ExecuteSummary> {
+    use indicatif::{ProgressBar, ProgressStyle};
+    use rayon::prelude::*;
+
+    let start = std::time::Instant::now();
+    let mut summary = ExecuteSummary::default();
+    let (filtered, actions): (Vec<&Action>, Vec<&Action>) = actions
+        .iter()
+        .partition(|a| a.reason == Reason::FilteredOut);
+    summary.filtered = filtered.len();
+    let pb = ProgressBar::new(actions.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:30} {pos}/{len} (eta {eta}) {wide_msg}")
+            .progress_chars("=> "),
+    );
+    let mut handlebars = new_hbs();
+    debug!(ctx.logger, "execute"; "variables" => ?&variables, "jobs" => ctx.cmd_opt.jobs);
+    let done = std::sync::Mutex::new(Vec::<PathBuf>::new());
+    let journal = if ctx.cmd_opt.transactional {
+        Some(Journal::new()?)
+    } else {
+        None
+    };
+    let run = |handlebars: &mut handlebars::Handlebars, a: &Action| {
+        ctx.cancel.check("executing plan")?;
+        pb.set_message(&a.dst_path.relative.to_string_lossy());
+        let dst = PathBuf::from(&a.dst_path);
+        if let Some(journal) = &journal {
+            if a.operation.is_change_of_existing_file() {
+                journal.backup(&dst)?;
+            } else if matches!(a.operation, FileOperation::MkDir | FileOperation::AddFile)
+                && fs::symlink_metadata(&dst).is_err()
+            {
+                // recorded before the write, to remove a partially written file on failure
+                journal.record_created(&dst);
+            }
+        }
+        let outcome = execute_action(ctx, handlebars, variables, a, Some(&pb))?;
+        if let Some(owner) = &ctx.cmd_opt.chown {
+            if outcome != Outcome::Skipped {
+                files::chown(&dst, owner)?;
+            }
+        }
+        if outcome != Outcome::Skipped {
+            done.lock()
+                .expect("lock the list of done actions")
+                .push(dst);
+        }
+        pb.inc(1);
+        Ok(outcome)
+    };
+
+    let (adds, others): (Vec<&Action>, Vec<&Action>) = actions
+        .into_iter()
+        .partition(|a| a.operation == FileOperation::AddFile);
+    let (updates, mkdirs): (Vec<&Action>, Vec<&Action>) = others
+        .into_iter()
+        .partition(|a| a.operation.is_change_of_existing_file());
+    let mut run_all = || -> Result<()> {
+        for a in &mkdirs {
+            summary.add(a, run(&mut handlebars, a)?);
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(ctx.cmd_opt.jobs)
+            .build()
+            .map_err(|source| Error::CreateThreadPool { source })?;
+        let outcomes = pool.install(|| {
+            adds.par_iter()
+                .map_init(new_hbs, |handlebars, a| run(handlebars, a).map(|o| (*a, o)))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        for (a, outcome) in outcomes {
+            summary.add(a, outcome);
+        }
+        for a in &updates {
+            summary.add(a, run(&mut handlebars, a)?);
+        }
+        Ok(())
+    };
+    let res = run_all();
+    pb.finish_and_clear();
+    if let (Err(err), Some(journal)) = (&res, journal) {
+        warn!(ctx.logger, "rollback the changes"; "err" => ?err);
+        journal.rollback(&ctx.logger)?;
+        done.lock().expect("lock the list of done actions").clear();
+    }
+    match res {
+        Err(Error::Cancelled { when, .. }) => {
+            let mut done = done.into_inner().expect("lock the list of done actions");
+            done.sort();
+            Err(Error::Cancelled { when, done })
+        }
+        Err(e) => {
+            let mut done = done.into_inner().expect("lock the list of done actions");
+            if done.is_empty() {
+                Err(e)
+            } else {
+                done.sort();
+                Err(Error::PartialFailure {
+                    done,
+                    source: Box::new(e),
+                })
+            }
+        }
+        Ok(()) => {
+            summary.elapsed = start.elapsed();
+            Ok(summary)
+        }
+    }
+}
+
+fn execute_action(
+    ctx: &Ctx,
+    handlebars: &mut handlebars::Handlebars,
+    variables: &Variables,
+    a: &Action,
+    progress: Option<&indicatif::ProgressBar>,
+) -> Result<Outcome> {
+    let limits = RenderLimits::from(&ctx.cmd_opt);
+    let outcome = match a.operation {
+        FileOperation::Nothing => Outcome::Skipped,
+        FileOperation::Ignore => Outcome::Skipped,
+        FileOperation::MkDir => {
+            mk_dir(&a.dst_path)?;
+            copy_file_permissions(
+                PathBuf::from(a.src[0].childpath()),
+                PathBuf::from(&a.dst_path),
+            )?;
+            Outcome::Created
+        }
+        FileOperation::AddFile => {
+            mk_file_on_action(handlebars, &limits, variables, &a, "", progress)?;
+            Outcome::Created
+        }
+        // the template edits the file on purpose: no question, no backup
+        FileOperation::Append | FileOperation::Patch | FileOperation::Merge => {
+            mk_file_on_action(handlebars, &limits, variables, &a, "", progress)?;
+            Outcome::Updated
+        }
+        // the raw file is not copied if already the same (eg a big asset on reapply)
+        FileOperation::UpdateFile
+            if a.src[0].metadata == SourceFileMetadata::RawFile
+                && files::is_same_file_content(
+                    &PathBuf::from(&a.src[0].childpath),
+                    &PathBuf::from(&a.dst_path),
+                )? =>
+        {
+            Outcome::Skipped
+        }
+        FileOperation::UpdateFile => {
+            let (update_mode, backup) = find_update_mode(ctx, a);
+            //TODO what to do if .LOCAL, .REMOTE already exist ?
+            let (local, remote) =
+                mk_file_on_action(handlebars, &limits, variables, &a, ".REMOTE", progress)?;
+            if files::is_same_file_content(&local, &remote)? {
+                fs::remove_file(&remote).map_err(|source| Error::RemoveFile {
+                    path: remote.clone(),
+                    source,
+                })?;
+                Outcome::Skipped
+            } else {
+                update_file(
+                    //FIXME to use all the source
+                    &PathBuf::from(a.src[0].childpath()),
+                    &local,
+                    &remote,
+                    &update_mode,
+                    backup,
+                )?;
+                Outcome::Updated
+            }
+        }
+    };
+    Ok(outcome)
+}
+
+/// create the folder `dst` of a `MkDir` action: its parent is already created by the previous actions
+/// (the plan is sorted), so only the root of the destination is created with its missing parents
+/// (a rendered name with several segments falls back on the creation of the parents too)
+fn mk_dir(dst: &ChildPath) -> Result<()> {
+    let path = PathBuf::from(dst);
+    let res = if dst.relative.as_os_str().is_empty() {
+        fs::create_dir_all(&path)
+    } else {
+        match fs::create_dir(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => fs::create_dir_all(&path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+            res => res,
+        }
+    };
+    res.map_err(|source| Error::CreateFolder { path, source })
+}
+
+/// the mode to update the existing file of the action, and if a backup is kept
+fn find_update_mode(ctx: &Ctx, a: &Action) -> (UpdateMode, bool) {
+    match (a.ownership, &a.update_mode) {
+        // managed files are always regenerated (without backup)
+        (Some(Ownership::Managed), _) => (UpdateMode::Override, false),
+        (_, Some(update_mode)) => (update_mode.clone(), !ctx.cmd_opt.no_backup),
+        // not modified since generated, nothing to lose
+        _ if a.reason == Reason::UnchangedSinceGeneration => (UpdateMode::Override, false),
+        _ => (ctx.cmd_opt.update_mode.clone(), !ctx.cmd_opt.no_backup),
+    }
+}
+
+/// size (in bytes) from which the progress of the copy of a file is displayed
+const LARGE_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
 fn mk_file_on_action(
     handlebars: &mut handlebars::Handlebars,
+    limits: &RenderLimits,
     variables: &Variables,
     a: &Action,
     dest_suffix_ext: &str,
+    progress: Option<&indicatif::ProgressBar>,
 ) -> Result<(PathBuf, PathBuf)> {
-    let mut variables = variables.clone();
     let dest_full_path_target = PathBuf::from(&a.dst_path);
     let dest_full_path = files::add_suffix(&dest_full_path_target, dest_suffix_ext)?;
+    // the source applied at last (the first of the list)
+    let src_full_path = PathBuf::from(&a.src[0].childpath);
+    if a.src[0].metadata == SourceFileMetadata::RawFile {
+        let size = fs::metadata(&src_full_path)
+            .map(|m| m.len())
+            .unwrap_or_default();
+        // the progress of the copy of the large files is displayed (bytes copied)
+        let progress = progress.filter(|_| size >= LARGE_FILE_SIZE);
+        files::copy_file(&src_full_path, &dest_full_path, |copied| {
+            if let Some(pb) = progress {
+                pb.set_message(&format!(
+                    "{} ({} / {})",
+                    a.dst_path.relative.to_string_lossy(),
+                    indicatif::HumanBytes(copied),
+                    indicatif::HumanBytes(size)
+                ));
+            }
+        })?;
+    } else {
+        let content = compute_content(handlebars, limits, variables, a)?;
+        fs::write(&dest_full_path, &content).map_err(|source| Error::WriteFile {
+            path: dest_full_path.clone(),
+            source,
+        })?;
+    }
+    copy_file_permissions(&src_full_path, &dest_full_path)?;
+    Ok((dest_full_path_target, dest_full_path))
+}
+
+/// the content of the destination file as generated from the sources of the action (nothing is written)
+fn compute_content(
+    handlebars: &mut handlebars::Handlebars,
+    limits: &RenderLimits,
+    variables: &Variables,
+    a: &Action,
+) -> Result<Vec<u8>> {
+    let mut variables = variables.clone();
+    variables.append(&mut a.variables.clone());
+    let dest_full_path_target = PathBuf::from(&a.dst_path);
     let mut srcs = a.src.clone();
     srcs.reverse();
     let mut input_content: Vec<u8> = Vec::with_capacity(0);
-    let index_latest = srcs.len() - 1;
     // based of the fact that list of source_files follow one of this configuration
     // - [RawFile]
-    // - [RenderableFile+,RawFile{0,1}]
+    // - [(RenderableFile|AppendFile|PatchFile|MergeFile)+,RawFile{0,1}]
     for (i, source_file) in srcs.into_iter().enumerate() {
         let src_full_path = PathBuf::from(&source_file.childpath);
+        if i == 0 && source_file.metadata.is_applied_on_content() && dest_full_path_target.exists()
+        {
+            input_content = fs::read(&dest_full_path_target).map_err(|source| Error::ReadFile {
+                path: dest_full_path_target.clone(),
+                source,
+            })?;
+        }
         match source_file.metadata {
             SourceFileMetadata::RawFile => {
-                if i == index_latest {
-                    fs::copy(&src_full_path, &dest_full_path).map_err(|source| {
-                        Error::CopyFile {
-                            src: src_full_path.clone(),
-                            dst: dest_full_path.clone(),
-                            source,
-                        }
-                    })?;
-                } else {
-                    input_content = fs::read(&src_full_path).map_err(|source| Error::ReadFile {
-                        path: src_full_path.clone(),
-                        source,
-                    })?;
-                }
+                input_content = fs::read(&src_full_path).map_err(|source| Error::ReadFile {
+                    path: src_full_path.clone(),
+                    source,
+                })?;
             }
-            SourceFileMetadata::RenderableFile { .. } => {
-                if i == 0 && dest_full_path_target.exists() {
-                    input_content =
-                        fs::read(&dest_full_path_target).map_err(|source| Error::ReadFile {
-                            path: dest_full_path_target.clone(),
-                            source,
-                        })?;
-                }
+            SourceFileMetadata::RenderableFile {
+                encoding: Some(encoding),
+                ..
+            } => {
+                let with_bom =
+                    register_encoded_template_file(handlebars, encoding, &src_full_path)?;
+                variables.insert(
+                    "input_content",
+                    files::decode_text(&input_content, encoding),
+                )?;
+                render_template(
+                    handlebars,
+                    limits,
+                    &variables,
+                    &src_full_path,
+                    &mut input_content,
+                )?;
+                let rendered = String::from_utf8_lossy(&input_content).into_owned();
+                input_content = files::encode_text(&rendered, encoding, with_bom);
+            }
+            SourceFileMetadata::RenderableFile { encoding: None, .. } => {
                 variables.insert("input_content", String::from_utf8_lossy(&input_content))?;
-                render_template(handlebars, &variables, &src_full_path, &mut input_content)?;
-                if i == index_latest {
-                    fs::write(&dest_full_path, &input_content).map_err(|source| {
-                        Error::WriteFile {
-                            path: dest_full_path.clone(),
-                            source,
-                        }
-                    })?;
+                render_template(
+                    handlebars,
+                    limits,
+                    &variables,
+                    &src_full_path,
+                    &mut input_content,
+                )?;
+            }
+            SourceFileMetadata::AppendFile | SourceFileMetadata::PatchFile => {
+                let current = String::from_utf8_lossy(&input_content).into_owned();
+                variables.insert("input_content", &current)?;
+                let mut edit = vec![];
+                render_template(handlebars, limits, &variables, &src_full_path, &mut edit)?;
+                let edit = String::from_utf8_lossy(&edit);
+                input_content = if source_file.metadata == SourceFileMetadata::AppendFile {
+                    patch::append_lines(&current, &edit)
+                } else {
+                    patch::apply_patch(&current, &edit, &src_full_path)?
                 }
+                .into_bytes();
+            }
+            SourceFileMetadata::MergeFile { format } => {
+                let current = String::from_utf8_lossy(&input_content).into_owned();
+                variables.insert("input_content", &current)?;
+                let mut fragment = vec![];
+                render_template(
+                    handlebars,
+                    limits,
+                    &variables,
+                    &src_full_path,
+                    &mut fragment,
+                )?;
+                let fragment = String::from_utf8_lossy(&fragment);
+                input_content = patch::merge_fragment(format, &current, &fragment, &src_full_path)?
+                    .into_bytes();
             }
             _ => (), // TODO return error,
         }
-        if i == index_latest {
-            copy_file_permissions(&src_full_path, &dest_full_path)?;
+    }
+    Ok(input_content)
+}
+
+/// limits of the rendering of a file (see `--render-max-size` and `--render-timeout`),
+/// a pathological template (eg an unbounded loop over a huge list) fails instead of hanging
+#[derive(Debug, Clone, Default)]
+struct RenderLimits {
+    /// max size of the output in bytes (0: no limit)
+    max_size: u64,
+    /// max duration of the rendering of a file
+    timeout: Option<Duration>,
+}
+
+impl From<&ApplyOpts> for RenderLimits {
+    fn from(opts: &ApplyOpts) -> Self {
+        RenderLimits {
+            max_size: opts.render_max_size,
+            timeout: Some(opts.render_timeout)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        }
+    }
+}
+
+/// writer of the rendered content, that fails as soon as a limit is exceeded
+struct LimitedWriter<'a> {
+    output: &'a mut Vec<u8>,
+    limits: &'a RenderLimits,
+    start: Instant,
+    /// the limit exceeded (if any)
+    exceeded: Option<String>,
+}
+
+impl<'a> std::io::Write for LimitedWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = (self.output.len() + buf.len()) as u64;
+        if self.limits.max_size > 0 && size > self.limits.max_size {
+            self.exceeded = Some(format!(
+                "output larger than {} bytes (--render-max-size)",
+                self.limits.max_size
+            ));
+        } else if let Some(timeout) = self.limits.timeout.filter(|t| self.start.elapsed() > *t) {
+            self.exceeded = Some(timeout_message(&timeout));
+        }
+        if let Some(limit) = &self.exceeded {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                limit.clone(),
+            ));
         }
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
-    Ok((PathBuf::from(&dest_full_path_target), dest_full_path))
 }
 
 fn render_template(
     handlebars: &mut handlebars::Handlebars,
+    limits: &RenderLimits,
     variables: &Variables,
     src_full_path: &PathBuf,
     output: &mut Vec<u8>,
 ) -> Result<()> {
     let src_name = &src_full_path.to_string_lossy();
+    // the same template could be rendered several times (plan, foreach,...), load it once
+    if handlebars.get_template(&src_name).is_none() {
+        register_template_file(handlebars, &src_name, src_full_path)?;
+    }
+    output.clear(); //vec![u8] writer appends content if not clear
+    let start = Instant::now();
+    // checked by the block helpers too (eg a loop without output)
+    hbs_helpers::set_render_deadline(limits.timeout.map(|t| start + t));
+    let mut writer = LimitedWriter {
+        output,
+        limits,
+        start,
+        exceeded: None,
+    };
+    let res = handlebars.render_to_write(&src_name, &variables, &mut writer);
+    let timed_out = res.is_err() && hbs_helpers::is_render_expired();
+    hbs_helpers::set_render_deadline(None);
+    if let Some(limit) = writer.exceeded.or_else(|| {
+        limits
+            .timeout
+            .filter(|_| timed_out)
+            .map(|t| timeout_message(&t))
+    }) {
+        return Err(Error::RenderLimitExceeded {
+            template: src_name.to_string(),
+            limit,
+        });
+    }
+    res.map_err(handlebars::TemplateRenderError::from)
+        .map_err(|source| Error::Handlebars {
+            when: "render template into buffer".into(),
+            template: src_name.to_string(),
+            source,
+        })?;
+    Ok(())
+}
+
+/// the limit exceeded by a rendering longer than `timeout`
+fn timeout_message(timeout: &Duration) -> String {
+    format!(
+        "rendering longer than {}s (--render-timeout)",
+        timeout.as_secs()
+    )
+}
+
+fn register_template_file(
+    handlebars: &mut handlebars::Handlebars,
+    src_name: &str,
+    src_full_path: &PathBuf,
+) -> Result<()> {
     handlebars
-        .register_template_file(&src_name, &src_full_path)
+        .register_template_file(src_name, src_full_path)
         .map_err(|e| match e {
             handlebars::TemplateFileError::TemplateError(err) => {
                 handlebars::TemplateRenderError::from(err)
@@ -316,20 +1796,34 @@ fn render_template(
             }
         })
         .map_err(|source| Error::Handlebars {
-            when: format!("load content of template '{:?}'", &src_full_path),
-            template: src_name.to_string(),
-            source,
-        })?;
-    output.clear(); //vec![u8] writer appends content if not clear
-    handlebars
-        .render_to_write(&src_name, &variables, output)
-        .map_err(handlebars::TemplateRenderError::from)
-        .map_err(|source| Error::Handlebars {
-            when: "render template into buffer".into(),
+            when: format!("load content of template '{:?}'", src_full_path),
             template: src_name.to_string(),
             source,
-        })?;
-    Ok(())
+        })
+}
+
+/// register the template (not encoded in UTF-8) decoded with `encoding`,
+/// return true if the template starts with a BOM (kept into the rendered content)
+fn register_encoded_template_file(
+    handlebars: &mut handlebars::Handlebars,
+    encoding: &'static encoding_rs::Encoding,
+    src_full_path: &PathBuf,
+) -> Result<bool> {
+    let content = fs::read(src_full_path).map_err(|source| Error::ReadFile {
+        path: src_full_path.clone(),
+        source,
+    })?;
+    let src_name = &src_full_path.to_string_lossy();
+    if handlebars.get_template(&src_name).is_none() {
+        handlebars
+            .register_template_string(&src_name, files::decode_text(&content, encoding))
+            .map_err(|source| Error::Handlebars {
+                when: format!("load content of template '{:?}'", src_full_path),
+                template: src_name.to_string(),
+                source: source.into(),
+            })?;
+    }
+    Ok(encoding_rs::Encoding::for_bom(&content).is_some())
 }
 
 fn copy_file_permissions<P1, P2>(src: P1, dst: P2) -> Result<()>
@@ -354,7 +1848,9 @@ where
     Ok(())
 }
 
-fn update_file<P>(src: P, local: P, remote: P, mode_init: &UpdateMode) -> Result<()>
+/// update the `local` file with the generated `remote` file, according to the mode,
+/// on override, the `local` file is kept as `<local>.ffizer.bak` if `backup`
+fn update_file<P>(src: P, local: P, remote: P, mode_init: &UpdateMode, backup: bool) -> Result<()>
 where
     P: AsRef<std::path::Path>,
 {
@@ -373,10 +1869,25 @@ where
                 mode = UpdateMode::Ask;
             }
             UpdateMode::Override => {
-                fs::remove_file(&local).map_err(|source| Error::RemoveFile {
-                    path: local.into(),
-                    source,
-                })?;
+                if backup {
+                    let bak = files::add_suffix(&local, files::FILEEXT_BACKUP)?;
+                    if bak.exists() {
+                        fs::remove_file(&bak).map_err(|source| Error::RemoveFile {
+                            path: bak.clone(),
+                            source,
+                        })?;
+                    }
+                    fs::rename(&local, &bak).map_err(|source| Error::RenameFile {
+                        src: local.into(),
+                        dst: bak,
+                        source,
+                    })?;
+                } else {
+                    fs::remove_file(&local).map_err(|source| Error::RemoveFile {
+                        path: local.into(),
+                        source,
+                    })?;
+                }
                 fs::rename(&remote, &local).map_err(|source| Error::RenameFile {
                     src: remote.into(),
                     dst: local.into(),
@@ -467,7 +1978,12 @@ where
 }
 
 //TODO optimize / bench to avoid re-creation of handlebars at each call
-fn compute_dst_path(ctx: &Ctx, src: &ChildPath, variables: &Variables) -> Result<ChildPath> {
+fn compute_dst_path(
+    ctx: &Ctx,
+    handlebars: &handlebars::Handlebars,
+    src: &ChildPath,
+    variables: &Variables,
+) -> Result<ChildPath> {
     let rendered_relative = src
         .relative
         .to_str()
@@ -476,7 +1992,6 @@ fn compute_dst_path(ctx: &Ctx, src: &ChildPath, variables: &Variables) -> Result
             let p = if !s.contains('{') {
                 s.to_owned()
             } else {
-                let handlebars = new_hbs();
                 handlebars
                     .render_template(&s, variables)
                     .map_err(|source| Error::Handlebars {
@@ -495,27 +2010,155 @@ fn compute_dst_path(ctx: &Ctx, src: &ChildPath, variables: &Variables) -> Result
     })
 }
 
-fn select_operation(_ctx: &Ctx, sources: &[SourceFile], dst_path: &ChildPath) -> FileOperation {
-    //FIXME to use all the sources
-    let src_full_path = PathBuf::from(sources[0].childpath());
-    let dest_full_path = PathBuf::from(dst_path);
-    if dest_full_path.exists() {
-        if dest_full_path.is_dir() {
-            FileOperation::Nothing
-        } else {
-            FileOperation::UpdateFile
-        }
-    } else if src_full_path.is_dir() {
-        FileOperation::MkDir
+/// compute the destination paths of a source, a source path using the item of a foreach
+/// is expanded into one destination per value of the list (with the item as variables of the action)
+fn compute_dst_paths(
+    ctx: &Ctx,
+    handlebars: &handlebars::Handlebars,
+    src: &ChildPath,
+    variables: &Variables,
+    foreachs: &[(String, String)],
+) -> Result<Vec<(ChildPath, Variables)>> {
+    let mut expansions = vec![Variables::default()];
+    if let Some(relative) = src.relative.to_str() {
+        for (item, list) in foreachs {
+            if !is_using_variable(relative, item) {
+                continue;
+            }
+            let values = match variables.get(list.as_str()) {
+                Some(serde_yaml::Value::Sequence(values)) => values.clone(),
+                _ => vec![],
+            };
+            let mut next = Vec::with_capacity(expansions.len() * values.len());
+            for expansion in &expansions {
+                for (idx, value) in values.iter().enumerate() {
+                    let mut expansion = expansion.clone();
+                    expansion.insert(item.as_str(), value)?;
+                    expansion.insert(format!("{}__idx", item), idx)?;
+                    next.push(expansion);
+                }
+            }
+            expansions = next;
+        }
+    }
+    expansions
+        .into_iter()
+        .map(|action_variables| {
+            let dst_path = if action_variables.is_empty() {
+                compute_dst_path(ctx, handlebars, src, variables)?
+            } else {
+                let mut variables = variables.clone();
+                variables.append(&mut action_variables.clone());
+                compute_dst_path(ctx, handlebars, src, &variables)?
+            };
+            Ok((dst_path, action_variables))
+        })
+        .collect()
+}
+
+/// true if the handlebars expressions of the path use the variable `name`
+fn is_using_variable(path: &str, name: &str) -> bool {
+    path.split("{{")
+        .skip(1)
+        .filter_map(|s| s.split("}}").next())
+        .any(|expr| {
+            expr.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .any(|token| token == name)
+        })
+}
+
+/// `dst_is_dir` tells if the destination is a folder (`None` if it doesn't exist),
+/// `generated_hash` is the hash of the destination as generated by the previous apply (if tracked)
+fn select_operation(
+    ctx: &Ctx,
+    handlebars: &mut handlebars::Handlebars,
+    variables: &Variables,
+    a: &Action,
+    dst_is_dir: Option<bool>,
+    generated_hash: Option<&String>,
+) -> (FileOperation, Reason) {
+    //FIXME to use all the sources
+    let src_is_dir = a.src[0].metadata == SourceFileMetadata::Dir;
+    if let Some(dst_is_dir) = dst_is_dir {
+        if dst_is_dir || src_is_dir {
+            (FileOperation::Nothing, Reason::ExistingFolder)
+        } else if a.ownership == Some(Ownership::UserOwned) {
+            (FileOperation::Ignore, Reason::UserOwned)
+        } else {
+            let dest_full_path = PathBuf::from(&a.dst_path);
+            let update = match a.src[0].metadata {
+                SourceFileMetadata::AppendFile => (FileOperation::Append, Reason::EditOfExisting),
+                SourceFileMetadata::PatchFile => (FileOperation::Patch, Reason::EditOfExisting),
+                SourceFileMetadata::MergeFile { .. } => {
+                    (FileOperation::Merge, Reason::EditOfExisting)
+                }
+                _ => match generated_hash.map(|_| files::sha256_file(&dest_full_path)) {
+                    Some(Ok(hash)) if Some(&hash) == generated_hash => {
+                        (FileOperation::UpdateFile, Reason::UnchangedSinceGeneration)
+                    }
+                    Some(Ok(_)) => (FileOperation::UpdateFile, Reason::ModifiedLocally),
+                    _ => (FileOperation::UpdateFile, Reason::ContentChanged),
+                },
+            };
+            if update.0 == FileOperation::UpdateFile && !is_content_compared(ctx, a, &update.1) {
+                return update;
+            }
+            match is_same_content(handlebars, &RenderLimits::from(&ctx.cmd_opt), variables, a) {
+                Ok(true) => (FileOperation::Nothing, Reason::SameContent),
+                Ok(false) => update,
+                Err(err) => {
+                    // the error will be raised (again) on execution
+                    debug!(ctx.logger, "fail to compare content"; "dst" => ?&dest_full_path, "err" => ?err);
+                    update
+                }
+            }
+        }
+    } else if src_is_dir {
+        (FileOperation::MkDir, Reason::NotExisting)
+    } else {
+        (FileOperation::AddFile, Reason::NotExisting)
+    }
+}
+
+/// true if the existing file to update is compared with the generated content at plan time (the
+/// file is read and the template rendered): only when the update could involve the user (question,
+/// diff, merge, `.REMOTE` or `.LOCAL` file). With `keep` the file is left as is, and with
+/// `override` an identical file is skipped on execution.
+fn is_content_compared(ctx: &Ctx, a: &Action, reason: &Reason) -> bool {
+    let update_mode = if *reason == Reason::UnchangedSinceGeneration {
+        UpdateMode::Override
     } else {
-        FileOperation::AddFile
+        find_update_mode(ctx, a).0
+    };
+    !matches!(update_mode, UpdateMode::Keep | UpdateMode::Override)
+}
+
+/// compare (via digest) the existing destination with the content generated from the sources
+fn is_same_content(
+    handlebars: &mut handlebars::Handlebars,
+    limits: &RenderLimits,
+    variables: &Variables,
+    a: &Action,
+) -> Result<bool> {
+    let dest_full_path = PathBuf::from(&a.dst_path);
+    if a.src[0].metadata == SourceFileMetadata::RawFile {
+        // the raw files (possibly large) are compared without loading them in memory
+        return files::is_same_file_content(&PathBuf::from(&a.src[0].childpath), &dest_full_path);
     }
+    let local_digest =
+        md5::compute(fs::read(&dest_full_path).map_err(|source| Error::ReadFile {
+            path: dest_full_path.clone(),
+            source,
+        })?);
+    let remote_content = compute_content(handlebars, limits, variables, a)?;
+    Ok(local_digest == md5::compute(remote_content))
 }
 
 fn run_scripts(ctx: &Ctx, template_composite: &TemplateComposite) -> Result<()> {
     do_in_folder(&ctx.cmd_opt.dst_folder, || {
         for (loc, scripts) in template_composite.find_scripts()? {
             for script in &scripts {
+                ctx.cancel.check("running scripts")?;
                 if let Some(message) = &script.message {
                     ui::show_message(ctx, loc, message)?;
                 }
@@ -567,7 +2210,7 @@ mod tests {
         let variables = new_variables_for_test();
         let src = ChildPath::new("test/src", "hello/sample.txt");
         let expected = ChildPath::new(DST_FOLDER_STR, "hello/sample.txt");
-        let actual = compute_dst_path(&ctx, &src, &variables).unwrap();
+        let actual = compute_dst_path(&ctx, &new_hbs(), &src, &variables).unwrap();
         assert_that!(&actual).is_equal_to(&expected);
     }
 
@@ -577,7 +2220,7 @@ mod tests {
         let variables = new_variables_for_test();
         let src = ChildPath::new("test/src", "hello/sample.txt.ffizer.hbs");
         let expected = ChildPath::new(DST_FOLDER_STR, "hello/sample.txt");
-        let actual = compute_dst_path(&ctx, &src, &variables).unwrap();
+        let actual = compute_dst_path(&ctx, &new_hbs(), &src, &variables).unwrap();
         assert_that!(&actual).is_equal_to(&expected);
     }
 
@@ -588,7 +2231,7 @@ mod tests {
 
         let src = ChildPath::new("test/src", "hello/{{ prj }}.txt");
         let expected = ChildPath::new(DST_FOLDER_STR, "hello/myprj.txt");
-        let actual = compute_dst_path(&ctx, &src, &variables).unwrap();
+        let actual = compute_dst_path(&ctx, &new_hbs(), &src, &variables).unwrap();
         assert_that!(&actual).is_equal_to(&expected);
     }
 
@@ -599,10 +2242,43 @@ mod tests {
 
         let src = ChildPath::new("test/src", "hello/{{ prj }}/sample.txt");
         let expected = ChildPath::new(DST_FOLDER_STR, "hello/myprj/sample.txt");
-        let actual = compute_dst_path(&ctx, &src, &variables).unwrap();
+        let actual = compute_dst_path(&ctx, &new_hbs(), &src, &variables).unwrap();
         assert_that!(&actual).is_equal_to(&expected);
     }
 
+    #[test]
+    fn test_compute_dst_paths_foreach() {
+        let ctx = new_ctx_for_test();
+        let mut variables = new_variables_for_test();
+        variables.insert("modules", vec!["m1", "m2"]).unwrap();
+        let foreachs = vec![("module".to_owned(), "modules".to_owned())];
+
+        let src = ChildPath::new("test/src", "hello/{{ module }}/mod.rs");
+        let actual = compute_dst_paths(&ctx, &new_hbs(), &src, &variables, &foreachs).unwrap();
+        let actual_paths = actual.iter().map(|v| v.0.clone()).collect::<Vec<_>>();
+        assert_that!(&actual_paths).is_equal_to(&vec![
+            ChildPath::new(DST_FOLDER_STR, "hello/m1/mod.rs"),
+            ChildPath::new(DST_FOLDER_STR, "hello/m2/mod.rs"),
+        ]);
+        assert_that!(actual[1].1.get("module").cloned())
+            .is_equal_to(Some(serde_yaml::Value::String("m2".to_owned())));
+
+        let src = ChildPath::new("test/src", "hello/{{ prj }}.txt");
+        let actual = compute_dst_paths(&ctx, &new_hbs(), &src, &variables, &foreachs).unwrap();
+        assert_that!(&actual).is_equal_to(&vec![(
+            ChildPath::new(DST_FOLDER_STR, "hello/myprj.txt"),
+            Variables::default(),
+        )]);
+    }
+
+    #[test]
+    fn test_is_using_variable() {
+        assert_that!(is_using_variable("{{ module }}.rs", "module")).is_true();
+        assert_that!(is_using_variable("{{ to_snake_case module }}.rs", "module")).is_true();
+        assert_that!(is_using_variable("{{ modules }}.rs", "module")).is_false();
+        assert_that!(is_using_variable("module.rs", "module")).is_false();
+    }
+
     #[test]
     fn test_path_extension_extraction() {
         use std::ffi::OsStr;
@@ -618,7 +2294,7 @@ mod tests {
         let variables = new_variables_for_test();
 
         let sources: Vec<SourceFile> = vec![];
-        let actions = plan(&ctx, sources, &variables)?;
+        let actions = plan(&ctx, sources, &variables, &[], &[], &[])?;
         assert_that!(&actions).is_empty();
         Ok(())
     }
@@ -632,7 +2308,7 @@ mod tests {
             SourceFile::from((ChildPath::new("test/src1", "hello/file1.txt"), 1)),
             SourceFile::from((ChildPath::new("test/src2", "hello/file1.txt"), 2)),
         ];
-        let actions = plan(&ctx, sources, &variables)?;
+        let actions = plan(&ctx, sources, &variables, &[], &[], &[])?;
         let expected = vec![Action {
             src: vec![SourceFile::from((
                 ChildPath::new("test/src1", "hello/file1.txt"),
@@ -640,11 +2316,799 @@ mod tests {
             ))],
             dst_path: ChildPath::new(DST_FOLDER_STR, "hello/file1.txt"),
             operation: FileOperation::AddFile,
+            variables: Variables::default(),
+            ownership: None,
+            update_mode: None,
+            reason: Reason::NotExisting,
+            overridden: vec![ChildPath::new("test/src2", "hello/file1.txt")],
         }];
         assert_that!(&actions).is_equal_to(&expected);
         Ok(())
     }
 
+    #[test]
+    fn test_plan_with_duplicate_from_same_template() {
+        let ctx = new_ctx_for_test();
+        let variables = new_variables_for_test();
+
+        let sources: Vec<SourceFile> = vec![
+            SourceFile::from((ChildPath::new("test/src1", "hello/myprj.txt"), 1)),
+            SourceFile::from((ChildPath::new("test/src1", "hello/{{ prj }}.txt"), 1)),
+        ];
+        assert!(plan(&ctx, sources, &variables, &[], &[], &[]).is_err());
+    }
+
+    #[test]
+    fn test_plan_with_collision_rendered_wins() -> Result<(), Box<dyn std::error::Error>> {
+        let ctx = new_ctx_for_test();
+        let variables = new_variables_for_test();
+
+        let raw = SourceFile::from((ChildPath::new("test/src1", "README.md"), 0));
+        let rendered = SourceFile::from((ChildPath::new("test/src1", "README.md.ffizer.hbs"), 0));
+        let actions = plan(
+            &ctx,
+            vec![raw.clone(), rendered.clone()],
+            &variables,
+            &[],
+            &[],
+            &[],
+        )?;
+        assert_that!(actions.len()).is_equal_to(1);
+        assert_that!(&actions[0].src).is_equal_to(&vec![rendered, raw]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_with_tracked_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src = tmp_dir.path().join("src");
+        let dst = tmp_dir.path().join("dst");
+        fs::create_dir_all(&src)?;
+        fs::create_dir_all(&dst)?;
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            fs::write(src.join(name), "v2\n")?;
+            fs::write(dst.join(name), "v1\n")?;
+        }
+        fs::write(dst.join("b.txt"), "v1 edited\n")?;
+        let mut manifest = Manifest::default();
+        for name in &["a.txt", "b.txt"] {
+            manifest
+                .hashes
+                .insert(PathBuf::from(name), files::sha256_file(&dst.join("a.txt"))?);
+        }
+        manifest.save(&dst)?;
+
+        let mut ctx = new_ctx_for_test();
+        ctx.cmd_opt.dst_folder = dst.clone();
+        let sources = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(|name| SourceFile::from((ChildPath::new(&src, name), 0)))
+            .collect();
+        let actions = plan(&ctx, sources, &new_variables_for_test(), &[], &[], &[])?;
+        let reasons = actions
+            .iter()
+            .map(|a| (a.operation.clone(), a.reason.clone()))
+            .collect::<Vec<_>>();
+        assert_that!(reasons).is_equal_to(vec![
+            (FileOperation::UpdateFile, Reason::UnchangedSinceGeneration),
+            (FileOperation::UpdateFile, Reason::ModifiedLocally),
+            (FileOperation::UpdateFile, Reason::ContentChanged),
+        ]);
+        assert_that!(find_update_mode(&ctx, &actions[0]))
+            .is_equal_to((UpdateMode::Override, false));
+        assert_that!(find_update_mode(&ctx, &actions[1])).is_equal_to((UpdateMode::Ask, true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_with_only_and_exclude() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src = tmp_dir.path().join("src");
+        fs::create_dir_all(src.join(".github/workflows"))?;
+        fs::create_dir_all(src.join("docs"))?;
+        fs::write(src.join(".github/workflows/ci.yml"), "")?;
+        fs::write(src.join(".github/workflows/release.yml"), "")?;
+        fs::write(src.join("docs/index.md"), "")?;
+        fs::write(src.join("README.md"), "")?;
+
+        let mut ctx = new_ctx_for_test();
+        ctx.cmd_opt.dst_folder = tmp_dir.path().join("dst");
+        ctx.cmd_opt.only = vec![".github/**".to_owned()];
+        ctx.cmd_opt.exclude = vec!["**/release.yml".to_owned()];
+        let sources = files::find_childpaths(&src, &[], false, &Default::default())
+            .into_iter()
+            .map(|childpath| SourceFile::from((childpath, 0)))
+            .collect();
+        let actions = plan(&ctx, sources, &new_variables_for_test(), &[], &[], &[])?;
+        let kept = actions
+            .iter()
+            .filter(|a| a.reason != Reason::FilteredOut)
+            .map(|a| a.dst_path.relative.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_that!(kept).is_equal_to(vec![
+            "".to_owned(),
+            ".github".to_owned(),
+            ".github/workflows".to_owned(),
+            ".github/workflows/ci.yml".to_owned(),
+        ]);
+        assert!(actions
+            .iter()
+            .filter(|a| a.reason == Reason::FilteredOut)
+            .all(|a| a.operation == FileOperation::Ignore));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_with_file_used_as_folder_by_other_template() {
+        let ctx = new_ctx_for_test();
+        let variables = new_variables_for_test();
+
+        let sources: Vec<SourceFile> = vec![
+            SourceFile::from((ChildPath::new("test/src1", "hello"), 0)),
+            SourceFile {
+                childpath: ChildPath::new("test/src2", "hello"),
+                layer_order: 1,
+                metadata: SourceFileMetadata::Dir,
+            },
+            SourceFile::from((ChildPath::new("test/src2", "hello/foo.txt"), 1)),
+        ];
+        assert!(matches!(
+            plan(&ctx, sources, &variables, &[], &[], &[]),
+            Err(Error::FileUsedAsFolder { .. })
+        ));
+    }
+
+    /// the sources of the `paths`, the folders end with `/`
+    fn to_sources(paths: &[&str]) -> Vec<SourceFile> {
+        paths
+            .iter()
+            .map(|p| {
+                let mut src =
+                    SourceFile::from((ChildPath::new("test/src", p.trim_end_matches('/')), 0));
+                if p.ends_with('/') {
+                    src.metadata = SourceFileMetadata::Dir;
+                }
+                src
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_with_path_outside_dst() -> Result<(), Box<dyn std::error::Error>> {
+        let ctx = new_ctx_for_test();
+        let cases = vec![
+            ("../evil.txt", vec!["{{ prj }}"]),
+            ("hello/../../evil.txt", vec!["{{ prj }}.ffizer.hbs"]),
+            ("../../../../../../tmp/evil.txt", vec!["{{ prj }}"]),
+            ("/tmp/evil.txt", vec!["{{ prj }}"]),
+            ("../../evil.txt", vec!["hello/", "hello/{{ prj }}"]),
+            ("..", vec!["{{ prj }}/", "{{ prj }}/evil.txt"]),
+        ];
+        for (name, paths) in cases {
+            let mut variables = new_variables_for_test();
+            variables.insert("prj", name)?;
+            let actual = plan(&ctx, to_sources(&paths), &variables, &[], &[], &[]);
+            assert!(
+                matches!(actual, Err(Error::PathOutsideDestination { .. })),
+                "{:?}: {:?}",
+                name,
+                actual
+            );
+        }
+
+        // the item of a foreach
+        let mut variables = new_variables_for_test();
+        variables.insert("modules", vec!["m1", "../m2"])?;
+        let foreachs = vec![("module".to_owned(), "modules".to_owned())];
+        assert!(matches!(
+            plan(
+                &ctx,
+                to_sources(&["{{ module }}.rs"]),
+                &variables,
+                &[],
+                &foreachs,
+                &[]
+            ),
+            Err(Error::PathOutsideDestination { .. })
+        ));
+
+        // an absolute path into a folder is relative
+        let mut variables = new_variables_for_test();
+        variables.insert("prj", "/tmp/evil.txt")?;
+        let actions = plan(
+            &ctx,
+            to_sources(&["hello/", "hello/{{ prj }}"]),
+            &variables,
+            &[],
+            &[],
+            &[],
+        )?;
+        assert_that!(PathBuf::from(&actions[1].dst_path))
+            .is_equal_to(Path::new(DST_FOLDER_STR).join("hello/tmp/evil.txt"));
+
+        // `..` into a name is not a segment
+        let mut variables = new_variables_for_test();
+        variables.insert("prj", "my..prj")?;
+        let actions = plan(&ctx, to_sources(&["{{ prj }}"]), &variables, &[], &[], &[])?;
+        assert_that!(actions[0].dst_path.relative).is_equal_to(PathBuf::from("my..prj"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plan_with_path_under_symlink() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let dst_dir = tmp_dir.path().join("dst");
+        let outside_dir = tmp_dir.path().join("outside");
+        fs::create_dir_all(dst_dir.join("inside"))?;
+        fs::create_dir_all(&outside_dir)?;
+        fs::write(outside_dir.join("same.txt"), "")?;
+        std::os::unix::fs::symlink(&outside_dir, dst_dir.join("to_outside"))?;
+        std::os::unix::fs::symlink(dst_dir.join("inside"), dst_dir.join("to_inside"))?;
+        std::os::unix::fs::symlink(outside_dir.join("evil.txt"), dst_dir.join("to_outside.txt"))?;
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: dst_dir,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let variables = new_variables_for_test();
+        for paths in [
+            vec!["to_outside/evil.txt"],
+            vec!["to_outside/", "to_outside/sub/", "to_outside/sub/evil.txt"],
+            vec!["to_outside.txt"],
+        ]
+        .iter()
+        {
+            let actual = plan(&ctx, to_sources(paths), &variables, &[], &[], &[]);
+            assert!(
+                matches!(actual, Err(Error::PathOutsideDestination { .. })),
+                "{:?}: {:?}",
+                paths,
+                actual
+            );
+        }
+        let actions = plan(
+            &ctx,
+            to_sources(&["to_inside/foo.txt"]),
+            &variables,
+            &[],
+            &[],
+            &[],
+        )?;
+        assert_that!(actions[0].operation).is_equal_to(FileOperation::AddFile);
+
+        // the existing folders and the user owned files are not written
+        let ownerships = vec![("to_outside.txt".parse()?, Ownership::UserOwned)];
+        let actions = plan(
+            &ctx,
+            to_sources(&["to_outside/", "to_outside.txt"]),
+            &variables,
+            &[],
+            &[],
+            &ownerships,
+        )?;
+        assert_that!(actions
+            .iter()
+            .map(|a| a.operation.clone())
+            .collect::<Vec<_>>())
+        .is_equal_to(vec![FileOperation::Nothing, FileOperation::Ignore]);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plan_with_source_symlink() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_dir = tmp_dir.path().join("src");
+        let outside_dir = tmp_dir.path().join("outside");
+        fs::create_dir_all(src_dir.join("inside"))?;
+        fs::create_dir_all(&outside_dir)?;
+        fs::write(src_dir.join("inside/file.txt"), "")?;
+        fs::write(outside_dir.join("id_rsa"), "secret")?;
+        std::os::unix::fs::symlink(outside_dir.join("id_rsa"), src_dir.join("key"))?;
+        std::os::unix::fs::symlink(&outside_dir, src_dir.join("to_outside"))?;
+        std::os::unix::fs::symlink("../outside/missing", src_dir.join("dangling"))?;
+        std::os::unix::fs::symlink("inside/file.txt", src_dir.join("to_inside.txt"))?;
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: tmp_dir.path().join("dst"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let variables = new_variables_for_test();
+        for name in &["key", "to_outside", "dangling"] {
+            let sources = vec![SourceFile::from((ChildPath::new(&src_dir, name), 0))];
+            let actual = plan(&ctx, sources, &variables, &[], &[], &[]);
+            assert!(
+                matches!(actual, Err(Error::SymlinkOutsideTemplate { .. })),
+                "{:?}: {:?}",
+                name,
+                actual
+            );
+        }
+        let sources = vec![SourceFile::from((
+            ChildPath::new(&src_dir, "to_inside.txt"),
+            0,
+        ))];
+        let actions = plan(&ctx, sources, &variables, &[], &[], &[])?;
+        assert_that!(actions[0].operation).is_equal_to(FileOperation::AddFile);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_srcs_with_layer_revs() -> Result<(), Box<dyn std::error::Error>> {
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                layers: vec![
+                    "https://example.com/ci.git".parse()?,
+                    "/tmp/docker".parse()?,
+                ],
+                layer_revs: vec!["v2".to_owned()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let revs: Vec<String> = find_srcs(&ctx)?.into_iter().map(|s| s.rev).collect();
+        assert_that!(revs).is_equal_to(vec!["".to_owned(), "v2".to_owned(), "master".to_owned()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_provide_default_values_without_commands() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let marker = tmp_dir.path().join("ran");
+        let variabledefs = vec![VariableDef {
+            name: "author".to_owned(),
+            default_value: Some("plain".into()),
+            default_providers: vec![ValueProvider::Cmd(format!(
+                "touch {:?} && echo from_cmd",
+                marker
+            ))],
+            ..Default::default()
+        }];
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: tmp_dir.path().to_path_buf(),
+                no_interaction: true,
+                ..Default::default()
+            },
+            allow_commands: false,
+            ..Default::default()
+        };
+        let mut provided = variabledefs.clone();
+        provide_default_values(&ctx, &mut provided, &Variables::default())?;
+        assert_that!(provided[0].default_value).is_equal_to(Some("plain".into()));
+        assert_that!(marker.exists()).is_false();
+
+        let ctx = Ctx {
+            allow_commands: true,
+            ..ctx
+        };
+        let mut provided = variabledefs;
+        provide_default_values(&ctx, &mut provided, &Variables::default())?;
+        assert_that!(provided[0].default_value).is_equal_to(Some("from_cmd".into()));
+        assert_that!(marker.exists()).is_true();
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_variables() -> Result<(), Box<dyn std::error::Error>> {
+        let migrations = vec![
+            MigrationCfg {
+                variable: "kind".to_owned(),
+                rename_to: Some("project_kind".to_owned()),
+                values: vec![("lib".to_owned(), "library".to_owned())]
+                    .into_iter()
+                    .collect(),
+            },
+            MigrationCfg {
+                variable: "ci".to_owned(),
+                rename_to: None,
+                values: vec![("1".to_owned(), "true".to_owned())]
+                    .into_iter()
+                    .collect(),
+            },
+            MigrationCfg {
+                variable: "prj".to_owned(),
+                rename_to: Some("base".to_owned()),
+                ..Default::default()
+            },
+        ];
+        let mut variables = new_variables_for_test();
+        variables.insert("kind", "lib")?;
+        variables.insert("ci", 1)?;
+        migrate_variables(&migrations, &mut variables)?;
+
+        let mut expected = Variables::default();
+        expected.insert("project_kind", "library")?;
+        expected.insert("ci", true)?;
+        // an answer given with the new name is kept
+        expected.insert("base", "remote")?;
+        assert_that!(&variables).is_equal_to(&expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_variables_predefined() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let dst_folder = tmp_dir.path().join("my-project");
+        git2::Repository::init(&tmp_dir)?.remote("origin", "https://example.com/acme/app.git")?;
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: dst_folder.clone(),
+                src: SourceLoc {
+                    rev: "v2".to_owned(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let variables = extract_variables(&ctx)?;
+        let rendered = new_hbs().render_template(
+            "{{ ffizer.dst_folder_name }}|{{ ffizer.src_rev }}|{{ ffizer.dst_git_remote_url }}",
+            &variables,
+        )?;
+        assert_that!(rendered.as_str())
+            .is_equal_to("my-project|v2|https://example.com/acme/app.git");
+        assert_that!(variables
+            .get("ffizer")
+            .and_then(|v| v.get("dst_folder_path")))
+        .is_equal_to(Some(&serde_yaml::Value::String(
+            files::absolute_path(&dst_folder)
+                .to_string_lossy()
+                .into_owned(),
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_variables_target() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let render = |target: Option<&str>| -> Result<String, Box<dyn std::error::Error>> {
+            let ctx = Ctx {
+                cmd_opt: ApplyOpts {
+                    dst_folder: tmp_dir.path().to_path_buf(),
+                    target: target.map(|t| t.to_owned()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let variables = extract_variables(&ctx)?;
+            Ok(new_hbs().render_template(
+                "{{ ffizer.target }}|{{ ffizer.target_os }}|{{ ffizer.target_family }}",
+                &variables,
+            )?)
+        };
+        assert_that!(render(Some("x86_64-pc-windows-msvc"))?.as_str())
+            .is_equal_to("x86_64-pc-windows-msvc|windows|windows");
+        assert_that!(render(Some("aarch64-apple-darwin"))?.as_str())
+            .is_equal_to("aarch64-apple-darwin|macos|unix");
+        assert_that!(render(Some("linux"))?.as_str()).is_equal_to("linux|linux|unix");
+        let host = std::env::consts::OS;
+        assert_that!(render(None)?).is_equal_to(format!(
+            "{}|{}|{}",
+            host,
+            host,
+            std::env::consts::FAMILY
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_and_execute_when_cancelled() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_dir = tmp_dir.path().join("src");
+        fs::create_dir_all(&src_dir)?;
+        fs::write(src_dir.join("file.txt"), CONTENT_LOCAL)?;
+
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: tmp_dir.path().join("dst"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let variables = new_variables_for_test();
+        let sources = vec![SourceFile::from((ChildPath::new(&src_dir, "file.txt"), 0))];
+        let actions = plan(&ctx, sources.clone(), &variables, &[], &[], &[])?;
+
+        ctx.cancel.cancel();
+        assert!(matches!(
+            plan(&ctx, sources, &variables, &[], &[], &[]),
+            Err(Error::Cancelled { .. })
+        ));
+        match execute(&ctx, &actions, &variables) {
+            Err(Error::Cancelled { done, .. }) => assert_that!(done).is_empty(),
+            r => panic!("expected Cancelled, got {:?}", r),
+        }
+        assert_that!(tmp_dir.path().join("dst").join("file.txt").exists()).is_false();
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_no_self_application() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_dir = tmp_dir.path().join("template");
+        fs::create_dir_all(&src_dir)?;
+        fs::write(src_dir.join("file.txt"), CONTENT_LOCAL)?;
+        let ctx = Ctx::default();
+        let src = SourceLoc {
+            uri: SourceUri {
+                raw: src_dir.to_string_lossy().to_string(),
+                path: src_dir.clone(),
+                host: None,
+                subfolder: None,
+            },
+            rev: "master".to_owned(),
+            subfolder: None,
+            full_history: false,
+        };
+        let template_composite =
+            TemplateComposite::from_src(&ctx, &Variables::default(), true, &src)?;
+
+        check_no_self_application(&tmp_dir.path().join("dst"), &template_composite)?;
+        assert!(matches!(
+            check_no_self_application(&src_dir, &template_composite),
+            Err(Error::SelfApplication { .. })
+        ));
+        assert!(matches!(
+            check_no_self_application(&src_dir.join("dst"), &template_composite),
+            Err(Error::SelfApplication { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_dir = tmp_dir.path().join("template");
+        fs::create_dir_all(src_dir.join("{{ name }}"))?;
+        fs::write(
+            src_dir.join("{{ name }}/hello.txt.ffizer.hbs"),
+            "Hello {{ name }}",
+        )?;
+        fs::write(src_dir.join("raw.txt"), CONTENT_LOCAL)?;
+        let dst_dir = tmp_dir.path().join("dst");
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                src: SourceLoc {
+                    uri: src_dir.to_string_lossy().parse()?,
+                    rev: "master".to_owned(),
+                    subfolder: None,
+                    full_history: false,
+                },
+                dst_folder: dst_dir.clone(),
+                key_value: vec![("name".to_owned(), "world".to_owned())],
+                offline: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let rendered = render_in_memory(&ctx)?;
+        assert_that!(rendered.len()).is_equal_to(3);
+        assert_that!(rendered.get(&PathBuf::from("world")))
+            .is_equal_to(Some(&RenderedContent::Dir));
+        assert_that!(rendered.get(&PathBuf::from("world/hello.txt")))
+            .is_equal_to(Some(&RenderedContent::File(b"Hello world".to_vec())));
+        assert_that!(rendered.get(&PathBuf::from("raw.txt"))).is_equal_to(Some(
+            &RenderedContent::File(CONTENT_LOCAL.as_bytes().to_vec()),
+        ));
+        assert_that!(dst_dir.exists()).is_false();
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_in_memory_with_features() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_dir = tmp_dir.path().join("template");
+        fs::create_dir_all(src_dir.join("ci"))?;
+        fs::write(src_dir.join("ci/build.sh"), "cargo build")?;
+        fs::write(src_dir.join("Dockerfile.ffizer.hbs"), "FROM {{ image }}")?;
+        fs::write(
+            src_dir.join("README.md.ffizer.hbs"),
+            "{{#if features.docker}}docker{{/if}}",
+        )?;
+        fs::write(
+            src_dir.join(".ffizer.yaml"),
+            r#"
+            features:
+              - name: docker
+                files:
+                  - Dockerfile.ffizer.hbs
+                variables:
+                  - name: image
+                    default_value: alpine
+              - name: ci
+                default: true
+                files:
+                  - ci
+            "#,
+        )?;
+        let mut ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                src: SourceLoc {
+                    uri: src_dir.to_string_lossy().parse()?,
+                    rev: "master".to_owned(),
+                    subfolder: None,
+                    full_history: false,
+                },
+                dst_folder: tmp_dir.path().join("dst"),
+                offline: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // the features selected by default
+        let rendered = render_in_memory(&ctx)?;
+        assert_that!(rendered.keys().cloned().collect::<Vec<_>>()).is_equal_to(vec![
+            PathBuf::from("README.md"),
+            PathBuf::from("ci"),
+            PathBuf::from("ci/build.sh"),
+        ]);
+        assert_that!(rendered.get(&PathBuf::from("README.md")))
+            .is_equal_to(Some(&RenderedContent::File(vec![])));
+
+        ctx.cmd_opt.key_value = vec![("features".to_owned(), "docker".to_owned())];
+        let rendered = render_in_memory(&ctx)?;
+        assert_that!(rendered.keys().cloned().collect::<Vec<_>>()).is_equal_to(vec![
+            PathBuf::from("Dockerfile"),
+            PathBuf::from("README.md"),
+        ]);
+        assert_that!(rendered.get(&PathBuf::from("Dockerfile")))
+            .is_equal_to(Some(&RenderedContent::File(b"FROM alpine".to_vec())));
+        assert_that!(rendered.get(&PathBuf::from("README.md")))
+            .is_equal_to(Some(&RenderedContent::File(b"docker".to_vec())));
+
+        ctx.cmd_opt.key_value = vec![("features".to_owned(), "unknown".to_owned())];
+        assert!(matches!(
+            render_in_memory(&ctx),
+            Err(Error::StringValueNotIn { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_use_update_mode_of_action() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_dir = tmp_dir.path().join("src");
+        let dst_dir = tmp_dir.path().join("dst");
+        fs::create_dir_all(&src_dir)?;
+        fs::create_dir_all(&dst_dir)?;
+        for name in &["kept.txt", "overridden.txt"] {
+            fs::write(src_dir.join(name), CONTENT_REMOTE)?;
+            fs::write(dst_dir.join(name), CONTENT_LOCAL)?;
+        }
+
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: dst_dir.clone(),
+                update_mode: UpdateMode::Override,
+                no_backup: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let variables = new_variables_for_test();
+        let sources: Vec<SourceFile> = ["kept.txt", "overridden.txt"]
+            .iter()
+            .map(|p| SourceFile::from((ChildPath::new(&src_dir, p), 0)))
+            .collect();
+        let mut actions = plan(&ctx, sources, &variables, &[], &[], &[])?;
+        // as chosen while confirming the plan
+        actions[0].update_mode = Some(UpdateMode::Keep);
+
+        execute(&ctx, &actions, &variables)?;
+        assert_that!(fs::read_to_string(dst_dir.join("kept.txt"))?)
+            .is_equal_to(CONTENT_LOCAL.to_owned());
+        assert_that!(fs::read_to_string(dst_dir.join("overridden.txt"))?)
+            .is_equal_to(CONTENT_REMOTE.to_owned());
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_transactional_rollback_on_failure() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_dir = tmp_dir.path().join("src");
+        let dst_dir = tmp_dir.path().join("dst");
+        fs::create_dir_all(src_dir.join("folder"))?;
+        fs::create_dir_all(&dst_dir)?;
+        fs::write(src_dir.join("folder").join("added.txt"), CONTENT_LOCAL)?;
+        fs::write(src_dir.join("a_updated.txt"), CONTENT_REMOTE)?;
+        fs::write(src_dir.join("missing.txt"), CONTENT_REMOTE)?;
+        fs::write(dst_dir.join("a_updated.txt"), CONTENT_LOCAL)?;
+        fs::write(dst_dir.join("missing.txt"), CONTENT_LOCAL)?;
+
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: dst_dir.clone(),
+                update_mode: UpdateMode::Override,
+                transactional: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let variables = new_variables_for_test();
+        let sources: Vec<SourceFile> =
+            ["folder", "folder/added.txt", "a_updated.txt", "missing.txt"]
+                .iter()
+                .map(|p| SourceFile::from((ChildPath::new(&src_dir, p), 0)))
+                .collect();
+        let actions = plan(&ctx, sources, &variables, &[], &[], &[])?;
+        // the source is removed after the plan, to fail the execution
+        fs::remove_file(src_dir.join("missing.txt"))?;
+
+        assert!(execute(&ctx, &actions, &variables).is_err());
+        assert_that!(fs::read_to_string(dst_dir.join("a_updated.txt"))?)
+            .is_equal_to(CONTENT_LOCAL.to_owned());
+        assert_that!(dst_dir.join("folder").exists()).is_false();
+        assert_that!(fs::read_to_string(dst_dir.join("missing.txt"))?)
+            .is_equal_to(CONTENT_LOCAL.to_owned());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_with_existing_files_identical_or_different(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_dir = tmp_dir.path().join("src");
+        let dst_dir = tmp_dir.path().join("dst");
+        fs::create_dir_all(&src_dir)?;
+        fs::create_dir_all(&dst_dir)?;
+        fs::write(src_dir.join("raw.txt"), CONTENT_LOCAL)?;
+        fs::write(src_dir.join("same.txt.ffizer.hbs"), CONTENT_BASE)?;
+        fs::write(src_dir.join("diff.txt.ffizer.hbs"), CONTENT_BASE)?;
+        fs::write(dst_dir.join("raw.txt"), CONTENT_LOCAL)?;
+        fs::write(dst_dir.join("same.txt"), CONTENT_REMOTE)?;
+        fs::write(dst_dir.join("diff.txt"), CONTENT_LOCAL)?;
+
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: dst_dir,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let variables = new_variables_for_test();
+        let sources: Vec<SourceFile> = ["raw.txt", "same.txt.ffizer.hbs", "diff.txt.ffizer.hbs"]
+            .iter()
+            .map(|p| SourceFile::from((ChildPath::new(&src_dir, p), 0)))
+            .collect();
+        let actions = plan(&ctx, sources.clone(), &variables, &[], &[], &[])?;
+        let operations = actions
+            .iter()
+            .map(|a| (a.dst_path.relative.clone(), a.operation.clone()))
+            .collect::<Vec<_>>();
+        assert_that!(&operations).is_equal_to(&vec![
+            (PathBuf::from("diff.txt"), FileOperation::UpdateFile),
+            (PathBuf::from("raw.txt"), FileOperation::Nothing),
+            (PathBuf::from("same.txt"), FileOperation::Nothing),
+        ]);
+
+        // not compared when the update doesn't involve the user
+        for update_mode in &[UpdateMode::Keep, UpdateMode::Override] {
+            let ctx = Ctx {
+                cmd_opt: ApplyOpts {
+                    update_mode: update_mode.clone(),
+                    ..ctx.cmd_opt.clone()
+                },
+                ..Default::default()
+            };
+            let actions = plan(&ctx, sources.clone(), &variables, &[], &[], &[])?;
+            assert_that!(actions
+                .iter()
+                .all(|a| a.operation == FileOperation::UpdateFile))
+            .is_true();
+        }
+        Ok(())
+    }
+
     #[cfg(unix)]
     #[test]
     fn test_copy_file_permissions() {
@@ -691,12 +3155,25 @@ mod tests {
             dst_path: dst,
             src: vec![SourceFile::from((ChildPath::from(src), 0))],
             operation: FileOperation::AddFile,
+            variables: Variables::default(),
+            ownership: None,
+            update_mode: None,
+            reason: Reason::NotExisting,
+            overridden: vec![],
         };
 
         let mut handlebars = new_hbs();
         let variables = new_variables_for_test();
 
-        mk_file_on_action(&mut handlebars, &variables, &action, "").expect("mk_file is ok");
+        mk_file_on_action(
+            &mut handlebars,
+            &RenderLimits::default(),
+            &variables,
+            &action,
+            "",
+            None,
+        )
+        .expect("mk_file is ok");
         assert_that!(&dst_path).exists();
         assert_that!(fs::read_to_string(&dst_path).unwrap()).is_equal_to(CONTENT_BASE.to_owned());
         assert_that!(fs::metadata(&dst_path).unwrap().permissions())
@@ -719,18 +3196,88 @@ mod tests {
             dst_path: dst,
             src: vec![SourceFile::from((ChildPath::from(src), 0))],
             operation: FileOperation::AddFile,
+            variables: Variables::default(),
+            ownership: None,
+            update_mode: None,
+            reason: Reason::NotExisting,
+            overridden: vec![],
         };
 
         let mut handlebars = new_hbs();
         let variables = new_variables_for_test();
 
-        mk_file_on_action(&mut handlebars, &variables, &action, "").expect("mk_file is ok");
+        mk_file_on_action(
+            &mut handlebars,
+            &RenderLimits::default(),
+            &variables,
+            &action,
+            "",
+            None,
+        )
+        .expect("mk_file is ok");
         assert_that!(&dst_path).exists();
         assert_that!(fs::read_to_string(&dst_path).unwrap()).is_equal_to(CONTENT_REMOTE.to_owned());
         assert_that!(fs::metadata(&dst_path).unwrap().permissions())
             .is_equal_to(fs::metadata(&src_path).unwrap().permissions());
     }
 
+    #[test]
+    fn test_render_template_with_limits() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new()?;
+        let src_path = tmp_dir.path().join("src.txt.ffizer.hbs");
+        fs::write(&src_path, "{{#each items}}{{ this }}\n{{/each}}")?;
+        let mut variables = Variables::default();
+        variables.insert("items", (0..100).map(|i| i.to_string()).collect::<Vec<_>>())?;
+        let mut handlebars = new_hbs();
+        let mut output = vec![];
+
+        render_template(
+            &mut handlebars,
+            &RenderLimits::default(),
+            &variables,
+            &src_path,
+            &mut output,
+        )?;
+        assert_that!(output.len()).is_equal_to(290);
+
+        let limits = RenderLimits {
+            max_size: 100,
+            timeout: None,
+        };
+        let res = render_template(&mut handlebars, &limits, &variables, &src_path, &mut output);
+        assert!(matches!(res, Err(Error::RenderLimitExceeded { .. })));
+        assert_that!(output.len() as u64).is_less_than_or_equal_to(100);
+
+        let limits = RenderLimits {
+            max_size: 0,
+            timeout: Some(Duration::from_secs(0)),
+        };
+        let res = render_template(&mut handlebars, &limits, &variables, &src_path, &mut output);
+        assert!(matches!(res, Err(Error::RenderLimitExceeded { .. })));
+
+        // a long loop without output
+        let silent_path = tmp_dir.path().join("silent.txt.ffizer.hbs");
+        fs::write(
+            &silent_path,
+            "{{#each items}}{{#each ../items}}{{#each ../../items}}{{/each}}{{/each}}{{/each}}",
+        )?;
+        let limits = RenderLimits {
+            max_size: 0,
+            timeout: Some(Duration::from_millis(50)),
+        };
+        let start = Instant::now();
+        let res = render_template(
+            &mut handlebars,
+            &limits,
+            &variables,
+            &silent_path,
+            &mut output,
+        );
+        assert!(matches!(res, Err(Error::RenderLimitExceeded { .. })));
+        assert_that!(start.elapsed()).is_less_than(Duration::from_secs(1));
+        Ok(())
+    }
+
     fn setup_for_test_update() -> (TempDir, PathBuf, PathBuf, PathBuf) {
         // Create a directory inside of `std::env::temp_dir()`
         let tmp_dir = TempDir::new().expect("create a temp dir");
@@ -751,20 +3298,50 @@ mod tests {
     fn test_update_file_override() {
         // grab _tmp_dir, because Drop will delete it and its files
         let (_tmp_dir, local_path, remote_path, src_path) = setup_for_test_update();
-        update_file(&src_path, &local_path, &remote_path, &UpdateMode::Override)
-            .expect("update without error");
+        update_file(
+            &src_path,
+            &local_path,
+            &remote_path,
+            &UpdateMode::Override,
+            false,
+        )
+        .expect("update without error");
         assert_that!(&local_path).exists();
         assert_that!(fs::read_to_string(&local_path).unwrap())
             .is_equal_to(CONTENT_REMOTE.to_owned());
         assert_that!(&remote_path).does_not_exist();
     }
 
+    #[test]
+    fn test_update_file_override_with_backup() -> Result<(), Box<dyn std::error::Error>> {
+        // grab _tmp_dir, because Drop will delete it and its files
+        let (_tmp_dir, local_path, remote_path, src_path) = setup_for_test_update();
+        update_file(
+            &src_path,
+            &local_path,
+            &remote_path,
+            &UpdateMode::Override,
+            true,
+        )?;
+        assert_that!(fs::read_to_string(&local_path)?).is_equal_to(CONTENT_REMOTE.to_owned());
+        let bak_path = files::add_suffix(&local_path, files::FILEEXT_BACKUP)?;
+        assert_that!(fs::read_to_string(&bak_path)?).is_equal_to(CONTENT_LOCAL.to_owned());
+        assert_that!(&remote_path).does_not_exist();
+        Ok(())
+    }
+
     #[test]
     fn test_update_file_keep() {
         // grab _tmp_dir, because Drop will delete it and its files
         let (_tmp_dir, local_path, remote_path, src_path) = setup_for_test_update();
-        update_file(&src_path, &local_path, &remote_path, &UpdateMode::Keep)
-            .expect("update without error");
+        update_file(
+            &src_path,
+            &local_path,
+            &remote_path,
+            &UpdateMode::Keep,
+            false,
+        )
+        .expect("update without error");
         assert_that!(&local_path).exists();
         assert_that!(fs::read_to_string(&local_path).unwrap())
             .is_equal_to(CONTENT_LOCAL.to_owned());
@@ -780,6 +3357,7 @@ mod tests {
             &local_path,
             &remote_path,
             &UpdateMode::UpdateAsRemote,
+            false,
         )
         .expect("update without error");
         assert_that!(&local_path).exists();
@@ -799,6 +3377,7 @@ mod tests {
             &local_path,
             &remote_path,
             &UpdateMode::CurrentAsLocal,
+            false,
         )
         .expect("update without error");
         assert_that!(&local_path).exists();
@@ -0,0 +1,142 @@
+//! record, into the destination folder, the commits of the templates (hosted in git repositories)
+//! applied, to apply again the same commits with `--locked` (instead of the tip of the revs).
+
+use crate::error::*;
+use crate::source_loc::SourceLoc;
+use crate::source_provider;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const LOCKFILE_FILENAME: &str = ".ffizer.lock.yaml";
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Lockfile {
+    /// the template applied (`None` if not hosted in a git repository)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<LockedTemplate>,
+    /// the templates imported (only the ones hosted in git repositories)
+    pub imports: Vec<LockedTemplate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockedTemplate {
+    pub uri: String,
+    /// the requested rev (branch, tag,...)
+    pub rev: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subfolder: Option<PathBuf>,
+    /// the id (sha) of the commit applied
+    pub commit_id: String,
+}
+
+impl Lockfile {
+    pub fn path_in<P>(dst_folder: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        dst_folder.as_ref().join(LOCKFILE_FILENAME)
+    }
+
+    /// load the lockfile of the destination folder, `None` if not present
+    pub fn load<P>(dst_folder: P) -> Result<Option<Lockfile>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Self::path_in(dst_folder);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path).map_err(|source| Error::ReadFile {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Some(serde_yaml::from_str(&content)?))
+    }
+
+    pub fn save<P>(&self, dst_folder: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Self::path_in(dst_folder);
+        let content = serde_yaml::to_string(self)?;
+        fs::write(&path, content).map_err(|source| Error::WriteFile { path, source })
+    }
+
+    pub fn templates(&self) -> impl Iterator<Item = &LockedTemplate> {
+        self.source.iter().chain(self.imports.iter())
+    }
+
+    pub fn find(&self, src: &SourceLoc) -> Option<&LockedTemplate> {
+        self.templates()
+            .find(|t| t.uri == src.uri.raw && t.subfolder == src.subfolder)
+    }
+
+    /// the source at the commit of the lockfile (only the versioned sources are locked, not the
+    /// local folders, the archives,...)
+    pub fn lock(&self, src: &SourceLoc) -> Result<SourceLoc> {
+        if !source_provider::provider_for(&src.uri)?.is_versioned() {
+            return Ok(src.clone());
+        }
+        let locked = self.find(src).ok_or_else(|| Error::TemplateNotLocked {
+            uri: src.uri.raw.clone(),
+            lockfile: PathBuf::from(LOCKFILE_FILENAME),
+        })?;
+        Ok(SourceLoc {
+            rev: locked.commit_id.clone(),
+            ..src.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_save_load_and_lock() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        assert_that!(Lockfile::load(tmp_dir.path())?).is_none();
+
+        let lockfile = Lockfile {
+            source: Some(LockedTemplate {
+                uri: "https://github.com/ffizer/template_sample.git".to_owned(),
+                rev: "master".to_owned(),
+                subfolder: None,
+                commit_id: "0123456789abcdef0123456789abcdef01234567".to_owned(),
+            }),
+            imports: vec![],
+        };
+        lockfile.save(tmp_dir.path())?;
+        let lockfile = Lockfile::load(tmp_dir.path())?.expect("lockfile saved");
+
+        let src = SourceLoc {
+            uri: "https://github.com/ffizer/template_sample.git".parse()?,
+            rev: "master".to_owned(),
+            subfolder: None,
+            full_history: false,
+        };
+        assert_that!(lockfile.lock(&src)?.rev)
+            .is_equal_to("0123456789abcdef0123456789abcdef01234567".to_owned());
+
+        let other = SourceLoc {
+            uri: "https://github.com/ffizer/other.git".parse()?,
+            ..src.clone()
+        };
+        assert!(matches!(
+            lockfile.lock(&other),
+            Err(Error::TemplateNotLocked { .. })
+        ));
+
+        let local = SourceLoc {
+            uri: tmp_dir.path().to_string_lossy().parse()?,
+            ..src
+        };
+        assert_that!(lockfile.lock(&local)?).is_equal_to(local);
+        Ok(())
+    }
+}
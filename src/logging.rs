@@ -0,0 +1,268 @@
+//! drains of the logs (on stderr): a filter of the level per module, and a json format (one object
+//! per line, for the CI and the log collectors).
+//!
+//! The levels per module are defined by directives separated by `,`, like `ffizer::git=trace,debug`:
+//! a directive `<module>=<level>` applies to the module and its sub-modules (the longest match wins),
+//! a directive `<level>` replaces the default level (set by the verbosity).
+
+use crate::error::*;
+use slog::{Drain, Key, Level, OwnedKVList, Record, KV};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleFilter {
+    pub default_level: Level,
+    /// (module, level), a module is a path like `ffizer::git`
+    pub modules: Vec<(String, Level)>,
+}
+
+impl ModuleFilter {
+    pub fn new(default_level: Level) -> ModuleFilter {
+        ModuleFilter {
+            default_level,
+            modules: vec![],
+        }
+    }
+
+    /// add the `directives` (eg `ffizer::git=trace,debug`)
+    pub fn parse(mut self, directives: &str) -> Result<ModuleFilter> {
+        for directive in directives
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+        {
+            match directive.split_once('=') {
+                Some((module, level)) => self
+                    .modules
+                    .push((module.trim().to_owned(), parse_level(level)?)),
+                None => self.default_level = parse_level(directive)?,
+            }
+        }
+        Ok(self)
+    }
+
+    /// the minimal level of the logs of the `module`
+    pub fn level_for(&self, module: &str) -> Level {
+        self.modules
+            .iter()
+            .filter(|(m, _)| {
+                module == m
+                    || (module.starts_with(m.as_str()) && module[m.len()..].starts_with("::"))
+            })
+            .max_by_key(|(m, _)| m.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default_level)
+    }
+}
+
+impl FromStr for ModuleFilter {
+    type Err = Error;
+
+    /// the directives with `warn` as default level
+    fn from_str(s: &str) -> Result<Self> {
+        ModuleFilter::new(Level::Warning).parse(s)
+    }
+}
+
+fn parse_level(s: &str) -> Result<Level> {
+    match s.trim().to_lowercase().as_str() {
+        "trace" => Ok(Level::Trace),
+        "debug" => Ok(Level::Debug),
+        "info" => Ok(Level::Info),
+        "warn" | "warning" => Ok(Level::Warning),
+        "error" => Ok(Level::Error),
+        "critical" => Ok(Level::Critical),
+        _ => Err(Error::Unknown(format!(
+            "invalid log level {:?} (expected one of trace, debug, info, warn, error, critical)",
+            s
+        ))),
+    }
+}
+
+/// keep the records at (or above) the level of their module
+pub struct ModuleFilterDrain<D> {
+    drain: D,
+    filter: ModuleFilter,
+}
+
+impl<D> ModuleFilterDrain<D> {
+    pub fn new(drain: D, filter: ModuleFilter) -> Self {
+        ModuleFilterDrain { drain, filter }
+    }
+}
+
+impl<D> Drain for ModuleFilterDrain<D>
+where
+    D: Drain,
+{
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        if record
+            .level()
+            .is_at_least(self.filter.level_for(record.module()))
+        {
+            self.drain.log(record, values).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// write the records as json, one object per line with the fields `ts`, `level`, `module`, `msg`
+/// and the key-values of the record and of the logger
+pub struct JsonDrain<W: Write> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write> JsonDrain<W> {
+    pub fn new(writer: W) -> Self {
+        JsonDrain {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl<W: Write> Drain for JsonDrain<W> {
+    type Ok = ();
+    type Err = std::io::Error;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let line = to_json(record, values)?;
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "lock poisoned"))?;
+        writeln!(writer, "{}", line)?;
+        writer.flush()
+    }
+}
+
+fn to_json(record: &Record, values: &OwnedKVList) -> std::io::Result<String> {
+    let mut ser = JsonSerializer(serde_json::Map::new());
+    ser.0.insert(
+        "ts".to_owned(),
+        chrono::Utc::now()
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+            .into(),
+    );
+    ser.0
+        .insert("level".to_owned(), record.level().as_str().into());
+    ser.0.insert("module".to_owned(), record.module().into());
+    ser.0
+        .insert("msg".to_owned(), record.msg().to_string().into());
+    // the key-values of the logger first, so the ones of the record override them
+    values.serialize(record, &mut ser)?;
+    record.kv().serialize(record, &mut ser)?;
+    serde_json::to_string(&ser.0).map_err(std::io::Error::from)
+}
+
+struct JsonSerializer(serde_json::Map<String, serde_json::Value>);
+
+impl JsonSerializer {
+    fn insert<V: Into<serde_json::Value>>(&mut self, key: Key, value: V) -> slog::Result {
+        self.0.insert(key.to_string(), value.into());
+        Ok(())
+    }
+}
+
+impl slog::Serializer for JsonSerializer {
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+        self.insert(key, val.to_string())
+    }
+    fn emit_bool(&mut self, key: Key, val: bool) -> slog::Result {
+        self.insert(key, val)
+    }
+    fn emit_i32(&mut self, key: Key, val: i32) -> slog::Result {
+        self.insert(key, val)
+    }
+    fn emit_u32(&mut self, key: Key, val: u32) -> slog::Result {
+        self.insert(key, val)
+    }
+    fn emit_i64(&mut self, key: Key, val: i64) -> slog::Result {
+        self.insert(key, val)
+    }
+    fn emit_u64(&mut self, key: Key, val: u64) -> slog::Result {
+        self.insert(key, val)
+    }
+    fn emit_usize(&mut self, key: Key, val: usize) -> slog::Result {
+        self.insert(key, val as u64)
+    }
+    fn emit_isize(&mut self, key: Key, val: isize) -> slog::Result {
+        self.insert(key, val as i64)
+    }
+    fn emit_f64(&mut self, key: Key, val: f64) -> slog::Result {
+        self.insert(key, val)
+    }
+    fn emit_str(&mut self, key: Key, val: &str) -> slog::Result {
+        self.insert(key, val)
+    }
+    fn emit_unit(&mut self, key: Key) -> slog::Result {
+        self.insert(key, serde_json::Value::Null)
+    }
+    fn emit_none(&mut self, key: Key) -> slog::Result {
+        self.insert(key, serde_json::Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{debug, info, o, Logger};
+    use spectral::prelude::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_module_filter() -> Result<(), Box<dyn std::error::Error>> {
+        let filter =
+            ModuleFilter::new(Level::Info).parse("ffizer::git=trace, ffizer::git::cache=error")?;
+        assert_that!(filter.level_for("ffizer")).is_equal_to(Level::Info);
+        assert_that!(filter.level_for("ffizer::git")).is_equal_to(Level::Trace);
+        assert_that!(filter.level_for("ffizer::git::cache")).is_equal_to(Level::Error);
+        assert_that!(filter.level_for("ffizer::github")).is_equal_to(Level::Info);
+        let filter = filter.parse("debug")?;
+        assert_that!(filter.level_for("ffizer")).is_equal_to(Level::Debug);
+        assert!(ModuleFilter::from_str("ffizer=verbose").is_err());
+        Ok(())
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_drain() -> Result<(), Box<dyn std::error::Error>> {
+        let buffer = Buffer::default();
+        let drain = ModuleFilterDrain::new(
+            JsonDrain::new(buffer.clone()),
+            ModuleFilter::new(Level::Info),
+        )
+        .fuse();
+        let logger = Logger::root(drain, o!("sub-cmd" => "apply"));
+        info!(logger, "plan computed"; "actions" => 3, "dry_run" => false);
+        debug!(logger, "filtered");
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone())?;
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_that!(lines).has_length(1);
+        let json: serde_json::Value = serde_json::from_str(lines[0])?;
+        assert_that!(json["level"]).is_equal_to(serde_json::json!("INFO"));
+        assert_that!(json["module"]).is_equal_to(serde_json::json!("ffizer::logging::tests"));
+        assert_that!(json["msg"]).is_equal_to(serde_json::json!("plan computed"));
+        assert_that!(json["actions"]).is_equal_to(serde_json::json!(3));
+        assert_that!(json["dry_run"]).is_equal_to(serde_json::json!(false));
+        assert_that!(json["sub-cmd"]).is_equal_to(serde_json::json!("apply"));
+        Ok(())
+    }
+}
@@ -1,28 +1,99 @@
+use ffizer::analytics::{self, Usage};
+use ffizer::logging::{JsonDrain, ModuleFilter, ModuleFilterDrain};
 use ffizer::provide_json_schema;
 use ffizer::ApplyOpts;
+use ffizer::CacheCommand;
+use ffizer::CancelToken;
+use ffizer::CheckOpts;
 use ffizer::CliOpts;
 use ffizer::Command;
+use ffizer::CompletionOpts;
 use ffizer::Ctx;
+use ffizer::ErrorKind;
+use ffizer::LintOpts;
+use ffizer::LogFormat;
+use ffizer::NewOpts;
+use ffizer::RegistryCommand;
 use ffizer::SourceLoc;
 use ffizer::TestSamplesOpts;
+use ffizer::UpgradeOpts;
 use self_update;
 use slog::Drain;
-use slog::{debug, error, info, o, trace};
+use slog::{debug, error, info, o, trace, warn};
 use std::error::Error;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 
-fn init_log(level_min: slog::Level) -> slog::Logger {
-    let drain = slog_term::PlainSyncDecorator::new(std::io::stderr());
-    let drain = slog_term::FullFormat::new(drain)
-        .build()
-        .filter_level(level_min)
-        .fuse();
-    let log = slog::Logger::root(drain, o!());
-    slog_stdlog::init().unwrap();
+/// the max duration to wait for the report of the analytics before the exit
+const ANALYTICS_MAX_WAIT: Duration = Duration::from_secs(1);
+
+fn init_log(cli_opts: &CliOpts) -> Result<slog::Logger, Box<dyn Error>> {
+    let level_min = slog::Level::from_usize(3 + cli_opts.verbose).unwrap_or(slog::Level::Trace);
+    let filter =
+        ModuleFilter::new(level_min).parse(cli_opts.log_filter.as_deref().unwrap_or(""))?;
+    let log = match cli_opts.log_format {
+        LogFormat::Json => {
+            let drain = JsonDrain::new(std::io::stderr()).fuse();
+            slog::Logger::root(ModuleFilterDrain::new(drain, filter).fuse(), o!())
+        }
+        LogFormat::Text => {
+            // colored if stderr is a terminal
+            let decorator = slog_term::TermDecorator::new().stderr().build();
+            let drain = slog_term::FullFormat::new(decorator).build().fuse();
+            let drain = std::sync::Mutex::new(drain).fuse();
+            slog::Logger::root(ModuleFilterDrain::new(drain, filter).fuse(), o!())
+        }
+    };
+    slog_stdlog::init()?;
     info!(log, "start"; "version" => env!("CARGO_PKG_VERSION"));
     debug!(log, "debug enabled");
     trace!(log, "trace enabled");
-    log
+    Ok(log)
+}
+
+/// a token cancelled on the first Ctrl-C (the apply stops after the current action), the second
+/// Ctrl-C exits immediately. In both cases, the leftovers of the interrupted downloads are removed
+/// from the cache.
+fn cancel_on_interrupt(logger: &slog::Logger) -> CancelToken {
+    let cancel = CancelToken::default();
+    let token = cancel.clone();
+    let handler_logger = logger.clone();
+    let res = ctrlc::set_handler(move || {
+        if token.is_cancelled() {
+            remove_cache_partials(&handler_logger);
+            std::process::exit(ErrorKind::PartialFailure.exit_code());
+        }
+        warn!(
+            handler_logger,
+            "interrupted, stop after the current action (Ctrl-C again to stop immediately)"
+        );
+        token.cancel();
+    });
+    if let Err(err) = res {
+        debug!(logger, "failed to set the handler of Ctrl-C"; "err" => ?err);
+    }
+    cancel
+}
+
+/// run the apply, then report its usage if the analytics are enabled (see `ffizer::analytics`),
+/// the exit waits at most `ANALYTICS_MAX_WAIT` for the report
+fn with_analytics<F>(ctx: &Ctx, apply: F) -> Result<(), ffizer::error::Error>
+where
+    F: FnOnce(&mut Usage) -> Result<(), ffizer::error::Error>,
+{
+    let start = Instant::now();
+    let mut usage = Usage::default();
+    let res = apply(&mut usage);
+    analytics::report_apply(ctx, start.elapsed(), res.is_ok(), &usage).wait(ANALYTICS_MAX_WAIT);
+    res
+}
+
+fn remove_cache_partials(logger: &slog::Logger) {
+    let res = SourceLoc::find_remote_cache_folder()
+        .and_then(|folder| ffizer::cache::remove_partials(logger, &folder));
+    if let Err(err) = res {
+        warn!(logger, "failed to remove the leftovers of the interrupted downloads"; "err" => ?err);
+    }
 }
 
 fn upgrade(logger: slog::Logger) -> Result<(), Box<dyn Error>> {
@@ -42,9 +113,95 @@ fn upgrade(logger: slog::Logger) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn apply(logger: slog::Logger, cmd_opt: ApplyOpts) -> Result<(), Box<dyn Error>> {
-    let ctx = Ctx { logger, cmd_opt };
-    ffizer::process(&ctx)?;
+fn upgrade_project(
+    logger: slog::Logger,
+    cmd_opt: &UpgradeOpts,
+    to: &str,
+) -> Result<(), Box<dyn Error>> {
+    let ctx = Ctx {
+        cmd_opt: ApplyOpts {
+            dst_folder: cmd_opt.dst_folder.clone(),
+            key_value: cmd_opt.key_value.clone(),
+            no_interaction: cmd_opt.no_interaction,
+            offline: cmd_opt.offline,
+            ..Default::default()
+        },
+        cancel: cancel_on_interrupt(&logger),
+        logger,
+        ..Default::default()
+    };
+    ffizer::upgrade_project(&ctx, to)?;
+    Ok(())
+}
+
+fn check(logger: slog::Logger, cmd_opt: &CheckOpts) -> Result<(), Box<dyn Error>> {
+    let ctx = Ctx {
+        logger,
+        cmd_opt: ApplyOpts {
+            dst_folder: cmd_opt.dst_folder.clone(),
+            key_value: cmd_opt.key_value.clone(),
+            no_interaction: true,
+            offline: cmd_opt.offline,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let src = cmd_opt.src.as_ref().map(|uri| SourceLoc {
+        uri: uri.clone(),
+        rev: "master".to_owned(),
+        subfolder: cmd_opt.subfolder.clone(),
+        full_history: false,
+    });
+    ffizer::check_project(&ctx, src)?;
+    Ok(())
+}
+
+fn apply(logger: slog::Logger, mut cmd_opt: ApplyOpts) -> Result<(), Box<dyn Error>> {
+    ffizer::registry::pick_missing_source(
+        &logger,
+        &mut cmd_opt.src,
+        cmd_opt.no_interaction,
+        cmd_opt.offline,
+    )?;
+    let ctx = Ctx {
+        cancel: cancel_on_interrupt(&logger),
+        logger,
+        cmd_opt,
+        ..Default::default()
+    };
+    let res = with_analytics(&ctx, |usage| ffizer::process_with_usage(&ctx, usage));
+    if ctx.cancel.is_cancelled() {
+        remove_cache_partials(&ctx.logger);
+    }
+    res?;
+    Ok(())
+}
+
+fn new(logger: slog::Logger, mut cmd_opt: NewOpts) -> Result<(), Box<dyn Error>> {
+    ffizer::registry::pick_missing_source(
+        &logger,
+        &mut cmd_opt.apply.src,
+        cmd_opt.apply.no_interaction,
+        cmd_opt.apply.offline,
+    )?;
+    let ctx = Ctx {
+        cancel: cancel_on_interrupt(&logger),
+        logger,
+        cmd_opt: cmd_opt.apply,
+        ..Default::default()
+    };
+    let commit_message = if cmd_opt.no_git {
+        None
+    } else {
+        Some(cmd_opt.commit_message.as_str())
+    };
+    let res = with_analytics(&ctx, |usage| {
+        ffizer::new_project(&ctx, commit_message, usage)
+    });
+    if ctx.cancel.is_cancelled() {
+        remove_cache_partials(&ctx.logger);
+    }
+    res?;
     Ok(())
 }
 
@@ -56,6 +213,74 @@ fn inspect() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn cache(logger: slog::Logger, cmd: &CacheCommand) -> Result<(), Box<dyn Error>> {
+    use ffizer::cache;
+    let folder = SourceLoc::find_remote_cache_folder()?;
+    match cmd {
+        CacheCommand::List => {
+            println!("remote cache folder: {}", folder.to_string_lossy());
+            for entry in cache::list(&folder)? {
+                print_cache_entry(&entry);
+            }
+        }
+        CacheCommand::Clean(opts) => {
+            for entry in cache::clean(&logger, &folder, &opts.filters)? {
+                println!("removed: {}", entry.name.to_string_lossy());
+            }
+        }
+        CacheCommand::Update(opts) => {
+            for entry in cache::update(&logger, &folder, &opts.filters)? {
+                print_cache_entry(&entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_cache_entry(entry: &ffizer::cache::CacheEntry) {
+    let refreshed = entry
+        .last_refresh
+        .and_then(|t| t.elapsed().ok())
+        .map(|d| format!("{} ago", indicatif::HumanDuration(d)))
+        .unwrap_or_else(|| "-".to_owned());
+    let rev = entry
+        .rev_info
+        .as_ref()
+        .map(|r| format!("{} ({})", r.rev, &r.commit_id[..r.commit_id.len().min(10)]))
+        .unwrap_or_else(|| "-".to_owned());
+    println!(
+        "{}\t{}\t{}\t{}",
+        entry.name.to_string_lossy(),
+        indicatif::HumanBytes(entry.disk_usage),
+        refreshed,
+        rev
+    );
+}
+
+fn registry(logger: slog::Logger, cmd: &RegistryCommand) -> Result<(), Box<dyn Error>> {
+    use ffizer::registry;
+    match cmd {
+        RegistryCommand::Export(opts) => {
+            let ctx = Ctx {
+                logger,
+                ..Default::default()
+            };
+            let mut cfg = registry::RegistryCfg::load()?;
+            cfg.load_indexes(&ctx.logger, opts.offline)?;
+            let catalog = registry::build_catalog(&ctx, &cfg, opts.offline)?;
+            let content = match opts.format {
+                ffizer::ExportFormat::Json => registry::to_json(&catalog)?,
+                ffizer::ExportFormat::Html => registry::to_html(&catalog),
+            };
+            match &opts.output {
+                Some(path) => std::fs::write(path, content)?,
+                None => println!("{}", content),
+            }
+        }
+    }
+    Ok(())
+}
+
 fn show_json_schema() -> Result<(), Box<dyn Error>> {
     let schema = provide_json_schema()?;
     println!("{}", schema);
@@ -63,7 +288,32 @@ fn show_json_schema() -> Result<(), Box<dyn Error>> {
 }
 
 fn test_samples(logger: slog::Logger, cfg: &TestSamplesOpts) -> Result<(), Box<dyn Error>> {
-    ffizer::tools::test_samples(&logger, cfg)?;
+    let mut cfg = cfg.clone();
+    ffizer::registry::pick_missing_source(&logger, &mut cfg.src, false, cfg.offline)?;
+    ffizer::tools::test_samples(&logger, &cfg)?;
+    Ok(())
+}
+
+fn lint(cfg: &LintOpts) -> Result<(), Box<dyn Error>> {
+    let issues = ffizer::lint_template(&cfg.template)?;
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if issues.is_empty() {
+        println!("no issue found");
+        Ok(())
+    } else {
+        Err(format!("{} issue(s) found", issues.len()).into())
+    }
+}
+
+fn completion(cfg: &CompletionOpts) -> Result<(), Box<dyn Error>> {
+    CliOpts::clap().gen_completions_to("ffizer", cfg.shell, &mut std::io::stdout());
+    Ok(())
+}
+
+fn man() -> Result<(), Box<dyn Error>> {
+    print!("{}", ffizer::render_man());
     Ok(())
 }
 
@@ -71,20 +321,53 @@ fn main() {
     human_panic::setup_panic!();
     let cli_opts = CliOpts::from_args();
 
-    let log_level = slog::Level::from_usize(3 + cli_opts.verbose).unwrap_or(slog::Level::Warning);
-    let logger = init_log(log_level);
+    let logger = match init_log(&cli_opts) {
+        Ok(logger) => logger,
+        Err(e) => {
+            eprintln!("failed to init the logs: {}", e);
+            std::process::exit(ErrorKind::Other.exit_code())
+        }
+    };
     debug!(logger, "parsed args"; "cmd" => ?&cli_opts);
 
     let r = match &cli_opts.cmd {
         Command::Apply(g) => apply(logger.new(o!("sub-cmd" => "apply")), g.clone()),
-        Command::Upgrade => upgrade(logger.new(o!("sub-cmd" => "upgrade"))),
+        Command::New(g) => new(logger.new(o!("sub-cmd" => "new")), g.clone()),
+        Command::Upgrade(g) => match &g.to {
+            Some(to) => upgrade_project(logger.new(o!("sub-cmd" => "upgrade")), g, to),
+            None => upgrade(logger.new(o!("sub-cmd" => "upgrade"))),
+        },
+        Command::Check(g) => check(logger.new(o!("sub-cmd" => "check")), g),
         Command::Inspect => inspect(),
+        Command::Cache(g) => cache(logger.new(o!("sub-cmd" => "cache")), g),
+        Command::Registry(g) => registry(logger.new(o!("sub-cmd" => "registry")), g),
         Command::ShowJsonSchema => show_json_schema(),
         Command::TestSamples(g) => test_samples(logger.new(o!("sub-cmd" => "test-samples")), g),
+        Command::Lint(g) => lint(g),
+        Command::Completion(g) => completion(g),
+        Command::Man => man(),
     };
     if let Err(e) = r {
-        error!(logger, "cmd: {:#?}", &cli_opts);
-        error!(logger, "failed: {:#?}", &e);
-        std::process::exit(1)
+        let kind = e
+            .downcast_ref::<ffizer::error::Error>()
+            .map(|e| e.kind())
+            .unwrap_or(ErrorKind::Other);
+        if let Some(ffizer::error::Error::Cancelled { when, done }) = e.downcast_ref() {
+            warn!(logger, "interrupted while {}", when);
+            if !done.is_empty() {
+                println!("applied before the interruption:");
+                for path in done {
+                    println!("  {}", path.to_string_lossy());
+                }
+            }
+        } else if kind == ErrorKind::ApplyAborted {
+            info!(logger, "aborted by the user");
+        } else if kind == ErrorKind::DriftDetected {
+            error!(logger, "{}", &e);
+        } else {
+            error!(logger, "cmd: {:#?}", &cli_opts);
+            error!(logger, "failed: {:#?}", &e);
+        }
+        std::process::exit(kind.exit_code())
     }
 }
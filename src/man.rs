@@ -0,0 +1,101 @@
+//! man page (roff) generated from the definition of the cli, a section per (sub-)command with its help
+
+use crate::cli_opt::CliOpts;
+use structopt::clap::{App, AppSettings, ErrorKind};
+use structopt::StructOpt;
+
+const BIN_NAME: &str = "ffizer";
+
+/// the man page (section 1) of ffizer
+pub fn render_man() -> String {
+    let mut out = format!(
+        ".TH {} 1 \"\" \"{} {}\" \"User Commands\"\n",
+        BIN_NAME.to_uppercase(),
+        BIN_NAME,
+        env!("CARGO_PKG_VERSION")
+    );
+    out.push_str(".SH NAME\n");
+    out.push_str(&format!(
+        "{} \\- {}\n",
+        BIN_NAME,
+        escape(
+            env!("CARGO_PKG_DESCRIPTION")
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches("ffizer is a ")
+        )
+    ));
+    let app = cli();
+    let mut commands = vec![];
+    collect_commands(&app, &mut vec![], &mut commands);
+    for command in commands {
+        let title = if command.is_empty() {
+            "DESCRIPTION".to_owned()
+        } else {
+            format!("\"{} {}\"", BIN_NAME, command.join(" ")).to_uppercase()
+        };
+        out.push_str(&format!(".SH {}\n.nf\n", title));
+        for line in help_of(&command).lines() {
+            out.push_str(&escape(line));
+            out.push('\n');
+        }
+        out.push_str(".fi\n");
+    }
+    out
+}
+
+fn cli<'a, 'b>() -> App<'a, 'b> {
+    CliOpts::clap()
+        .global_setting(AppSettings::ColorNever)
+        .set_term_width(100)
+}
+
+/// the paths of the commands (the root is the empty path), except the `help` subcommands
+fn collect_commands(app: &App, path: &mut Vec<String>, commands: &mut Vec<Vec<String>>) {
+    commands.push(path.clone());
+    for sub in app.p.subcommands.iter().filter(|s| s.get_name() != "help") {
+        path.push(sub.get_name().to_owned());
+        collect_commands(sub, path, commands);
+        path.pop();
+    }
+}
+
+/// the long help of the command (as displayed by `--help`)
+fn help_of(command: &[String]) -> String {
+    let args = std::iter::once(BIN_NAME.to_owned())
+        .chain(command.iter().cloned())
+        .chain(std::iter::once("--help".to_owned()));
+    match cli().get_matches_from_safe(args) {
+        Err(e) if e.kind == ErrorKind::HelpDisplayed => e.message,
+        _ => String::new(),
+    }
+}
+
+/// escape the text for roff (backslash, leading control characters, hyphens)
+fn escape(line: &str) -> String {
+    let line = line.replace('\\', "\\e").replace('-', "\\-");
+    if line.starts_with('.') || line.starts_with('\'') {
+        format!("\\&{}", line)
+    } else {
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_render_man() {
+        let man = render_man();
+        assert_that!(man.as_str()).starts_with(".TH FFIZER 1 ");
+        assert_that!(man).contains("\n.SH DESCRIPTION\n");
+        assert_that!(man).contains("\n.SH \"FFIZER APPLY\"\n");
+        assert_that!(man).contains("\n.SH \"FFIZER CACHE LIST\"\n");
+        assert_that!(man).contains("\\-\\-destination");
+        assert_that!(man.contains("FFIZER HELP")).is_false();
+        assert_that!(man.contains('\u{1b}')).is_false();
+    }
+}
@@ -0,0 +1,147 @@
+//! record, into the destination folder, the class of ownership of the generated files
+//! (only when the template declares `managed` or `user_owned` files), and the hashes of the
+//! generated files (with `--track-changes`) to detect the local changes on the next apply.
+
+use crate::error::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILENAME: &str = ".ffizer.manifest.yaml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ownership {
+    /// regenerated (overridden) on every apply of the template
+    Managed,
+    /// created once, then never updated by the template
+    UserOwned,
+}
+
+impl fmt::Display for Ownership {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ownership::Managed => write!(f, "managed"),
+            Ownership::UserOwned => write!(f, "user_owned"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Manifest {
+    /// ownership of the files (path relative to the destination folder)
+    pub files: BTreeMap<PathBuf, Ownership>,
+    /// sha256 of the files as generated (path relative to the destination folder)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub hashes: BTreeMap<PathBuf, String>,
+}
+
+/// a file with a different ownership than on the previous apply
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnershipChange {
+    pub path: PathBuf,
+    pub previous: Ownership,
+    /// `None` if the file has no more ownership
+    pub next: Option<Ownership>,
+}
+
+impl Manifest {
+    pub fn path_in<P>(dst_folder: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        dst_folder.as_ref().join(MANIFEST_FILENAME)
+    }
+
+    /// load the manifest of the destination folder, `None` if not present
+    pub fn load<P>(dst_folder: P) -> Result<Option<Manifest>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Self::path_in(dst_folder);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path).map_err(|source| Error::ReadFile {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Some(serde_yaml::from_str(&content)?))
+    }
+
+    pub fn save<P>(&self, dst_folder: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = Self::path_in(dst_folder);
+        let content = serde_yaml::to_string(self)?;
+        fs::write(&path, content).map_err(|source| Error::WriteFile { path, source })
+    }
+
+    /// list the files of the manifest with a different ownership into the `next` manifest
+    pub fn changes_to(&self, next: &Manifest) -> Vec<OwnershipChange> {
+        self.files
+            .iter()
+            .filter_map(|(path, previous)| {
+                let next = next.files.get(path).copied();
+                if next == Some(*previous) {
+                    None
+                } else {
+                    Some(OwnershipChange {
+                        path: path.clone(),
+                        previous: *previous,
+                        next,
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_save_load_and_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        assert_that!(Manifest::load(tmp_dir.path())?).is_none();
+
+        let mut previous = Manifest::default();
+        previous
+            .files
+            .insert(PathBuf::from("a.txt"), Ownership::Managed);
+        previous
+            .files
+            .insert(PathBuf::from("b.txt"), Ownership::Managed);
+        previous
+            .hashes
+            .insert(PathBuf::from("a.txt"), "0123456789abcdef".to_owned());
+        previous.save(tmp_dir.path())?;
+        assert_that!(Manifest::load(tmp_dir.path())?).is_equal_to(Some(previous.clone()));
+
+        let mut next = Manifest::default();
+        next.files
+            .insert(PathBuf::from("a.txt"), Ownership::UserOwned);
+        next.files
+            .insert(PathBuf::from("c.txt"), Ownership::Managed);
+        assert_that!(previous.changes_to(&next)).is_equal_to(vec![
+            OwnershipChange {
+                path: PathBuf::from("a.txt"),
+                previous: Ownership::Managed,
+                next: Some(Ownership::UserOwned),
+            },
+            OwnershipChange {
+                path: PathBuf::from("b.txt"),
+                previous: Ownership::Managed,
+                next: None,
+            },
+        ]);
+        Ok(())
+    }
+}
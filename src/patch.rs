@@ -0,0 +1,307 @@
+//! edition of an existing file by a template: append lines (`.ffizer.append`), apply a patch
+//! (unified diff, `.ffizer.patch`) or deep-merge a fragment (`.ffizer.merge-json`,
+//! `.ffizer.merge-toml`), all are idempotent (an edition already done is skipped).
+
+use crate::error::*;
+use crate::source_file::MergeFormat;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// append the lines of `addition` not already present into `current` (empty lines are skipped)
+pub(crate) fn append_lines(current: &str, addition: &str) -> String {
+    let mut present = current.lines().map(str::trim_end).collect::<HashSet<_>>();
+    let mut out = current.to_owned();
+    for line in addition.lines().map(str::trim_end) {
+        if line.trim().is_empty() || present.contains(line) {
+            continue;
+        }
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(line);
+        out.push('\n');
+        present.insert(line);
+    }
+    out
+}
+
+#[derive(Debug, Default)]
+struct Hunk {
+    /// line (1-based) of the start of the hunk into the original file
+    old_start: usize,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+/// apply the `patch` (unified diff of a single file, the headers are ignored) to `current`,
+/// the hunks already applied are skipped, the context of the hunks is searched into the whole file
+/// (the line numbers are only used to insert hunks without context)
+pub(crate) fn apply_patch(current: &str, patch: &str, patch_path: &Path) -> Result<String> {
+    let fail = |message: String| Error::ApplyPatch {
+        path: patch_path.to_path_buf(),
+        message,
+    };
+    let mut lines = current.lines().map(|l| l.to_owned()).collect::<Vec<_>>();
+    for (i, hunk) in parse_hunks(patch).map_err(fail)?.iter().enumerate() {
+        // already applied (the result of the hunk is present)
+        if !hunk.after.is_empty() && find_lines(&lines, &hunk.after).is_some() {
+            continue;
+        }
+        if hunk.before.is_empty() {
+            let at = hunk.old_start.min(lines.len());
+            lines.splice(at..at, hunk.after.iter().cloned());
+        } else if let Some(at) = find_lines(&lines, &hunk.before) {
+            lines.splice(at..at + hunk.before.len(), hunk.after.iter().cloned());
+        } else {
+            return Err(fail(format!(
+                "hunk #{} (at line {}) doesn't match the file",
+                i + 1,
+                hunk.old_start
+            )));
+        }
+    }
+    let mut out = lines.join("\n");
+    if !lines.is_empty() && (current.is_empty() || current.ends_with('\n')) {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn find_lines(lines: &[String], search: &[String]) -> Option<usize> {
+    if search.len() > lines.len() {
+        return None;
+    }
+    (0..=(lines.len() - search.len())).find(|i| &lines[*i..*i + search.len()] == search)
+}
+
+fn parse_hunks(patch: &str) -> std::result::Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = vec![];
+    let mut in_hunk = false;
+    for line in patch.lines() {
+        if let Some(header) = line.strip_prefix("@@ ") {
+            // eg "@@ -12,3 +12,4 @@"
+            let old_start = header
+                .split_whitespace()
+                .next()
+                .and_then(|s| s.strip_prefix('-'))
+                .and_then(|s| s.split(',').next())
+                .and_then(|s| s.parse::<usize>().ok())
+                .ok_or_else(|| format!("invalid header of hunk: {}", line))?;
+            hunks.push(Hunk {
+                old_start,
+                ..Default::default()
+            });
+            in_hunk = true;
+            continue;
+        }
+        let hunk = match hunks.last_mut() {
+            Some(hunk) if in_hunk => hunk,
+            // headers of the diff (`diff`, `index`, `---`, `+++`,...)
+            _ => continue,
+        };
+        if line.starts_with("diff ") || line.starts_with("--- ") || line.starts_with("+++ ") {
+            in_hunk = false;
+        } else if let Some(l) = line.strip_prefix('+') {
+            hunk.after.push(l.to_owned());
+        } else if let Some(l) = line.strip_prefix('-') {
+            hunk.before.push(l.to_owned());
+        } else if line.starts_with('\\') {
+            // "\ No newline at end of file"
+        } else {
+            // context (the leading space could be trimmed by an editor on empty lines)
+            let l = line.strip_prefix(' ').unwrap_or(line);
+            hunk.before.push(l.to_owned());
+            hunk.after.push(l.to_owned());
+        }
+    }
+    if hunks.is_empty() {
+        return Err("no hunk found".to_owned());
+    }
+    Ok(hunks)
+}
+
+/// deep-merge the `fragment` into `current` (an empty content is an empty document):
+/// the tables/objects are merged recursively, the missing items are appended to the arrays,
+/// the other values of the fragment replace the current ones.
+/// The keys keep their order, but the comments and the formatting of a toml file are lost.
+pub(crate) fn merge_fragment(
+    format: MergeFormat,
+    current: &str,
+    fragment: &str,
+    fragment_path: &Path,
+) -> Result<String> {
+    let fail = |message: String| Error::MergeFragment {
+        path: fragment_path.to_path_buf(),
+        message,
+    };
+    match format {
+        MergeFormat::Json => {
+            let mut doc = if current.trim().is_empty() {
+                serde_json::Value::Object(serde_json::Map::new())
+            } else {
+                serde_json::from_str(current)
+                    .map_err(|e| fail(format!("invalid existing file: {}", e)))?
+            };
+            let fragment = serde_json::from_str(fragment)
+                .map_err(|e| fail(format!("invalid fragment: {}", e)))?;
+            merge_json(&mut doc, fragment);
+            let mut out = serde_json::to_string_pretty(&doc).map_err(|e| fail(e.to_string()))?;
+            out.push('\n');
+            Ok(out)
+        }
+        MergeFormat::Toml => {
+            let mut doc = toml::from_str(current)
+                .map_err(|e| fail(format!("invalid existing file: {}", e)))?;
+            let fragment =
+                toml::from_str(fragment).map_err(|e| fail(format!("invalid fragment: {}", e)))?;
+            merge_toml(&mut doc, fragment);
+            toml::to_string(&doc).map_err(|e| fail(e.to_string()))
+        }
+    }
+}
+
+fn merge_json(current: &mut serde_json::Value, fragment: serde_json::Value) {
+    use serde_json::Value;
+    match (current, fragment) {
+        (Value::Object(current), Value::Object(fragment)) => {
+            for (k, v) in fragment {
+                match current.get_mut(&k) {
+                    Some(c) => merge_json(c, v),
+                    None => {
+                        current.insert(k, v);
+                    }
+                }
+            }
+        }
+        (Value::Array(current), Value::Array(fragment)) => {
+            for v in fragment {
+                if !current.contains(&v) {
+                    current.push(v);
+                }
+            }
+        }
+        (current, fragment) => *current = fragment,
+    }
+}
+
+fn merge_toml(current: &mut toml::Value, fragment: toml::Value) {
+    use toml::Value;
+    match (current, fragment) {
+        (Value::Table(current), Value::Table(fragment)) => {
+            for (k, v) in fragment {
+                match current.get_mut(&k) {
+                    Some(c) => merge_toml(c, v),
+                    None => {
+                        current.insert(k, v);
+                    }
+                }
+            }
+        }
+        (Value::Array(current), Value::Array(fragment)) => {
+            for v in fragment {
+                if !current.contains(&v) {
+                    current.push(v);
+                }
+            }
+        }
+        (current, fragment) => *current = fragment,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_append_lines() {
+        let addition = "# rust\n/target\n\n**/*.rs.bk\n";
+        let expected = "/node_modules\n# rust\n/target\n**/*.rs.bk\n";
+        assert_that!(append_lines("/node_modules", addition)).is_equal_to(expected.to_owned());
+        assert_that!(append_lines(expected, addition)).is_equal_to(expected.to_owned());
+        assert_that!(append_lines("/target\n", addition))
+            .is_equal_to("/target\n# rust\n**/*.rs.bk\n".to_owned());
+    }
+
+    #[test]
+    fn test_apply_patch() -> Result<(), Box<dyn std::error::Error>> {
+        let patch = r#"--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -3,3 +3,4 @@
+
+ [dependencies]
++serde = "1"
+ slog = "2"
+"#;
+        let current = "[package]\nname = \"foo\"\n\n[dependencies]\nslog = \"2\"\n";
+        let expected = "[package]\nname = \"foo\"\n\n[dependencies]\nserde = \"1\"\nslog = \"2\"\n";
+        let path = Path::new("Cargo.toml.ffizer.patch");
+        assert_that!(apply_patch(current, patch, path)?).is_equal_to(expected.to_owned());
+        // already applied
+        assert_that!(apply_patch(expected, patch, path)?).is_equal_to(expected.to_owned());
+        assert!(matches!(
+            apply_patch("[package]\n", patch, path),
+            Err(Error::ApplyPatch { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_patch_without_context() -> Result<(), Box<dyn std::error::Error>> {
+        let patch = "@@ -0,0 +1,2 @@\n+#!/bin/sh\n+set -e\n";
+        let path = Path::new("run.sh.ffizer.patch");
+        assert_that!(apply_patch("echo hello\n", patch, path)?)
+            .is_equal_to("#!/bin/sh\nset -e\necho hello\n".to_owned());
+        assert_that!(apply_patch("#!/bin/sh\nset -e\necho hello\n", patch, path)?)
+            .is_equal_to("#!/bin/sh\nset -e\necho hello\n".to_owned());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_fragment_json() -> Result<(), Box<dyn std::error::Error>> {
+        let current = r#"{
+  "name": "foo",
+  "scripts": { "test": "jest" },
+  "files": ["dist"]
+}"#;
+        let fragment =
+            r#"{"scripts": {"lint": "eslint ."}, "files": ["dist", "types"], "private": true}"#;
+        let expected = r#"{
+  "name": "foo",
+  "scripts": {
+    "test": "jest",
+    "lint": "eslint ."
+  },
+  "files": [
+    "dist",
+    "types"
+  ],
+  "private": true
+}
+"#;
+        let path = Path::new("package.json.ffizer.merge-json");
+        let merged = merge_fragment(MergeFormat::Json, current, fragment, path)?;
+        assert_that!(merged).is_equal_to(expected.to_owned());
+        assert_that!(merge_fragment(MergeFormat::Json, expected, fragment, path)?)
+            .is_equal_to(expected.to_owned());
+        assert!(matches!(
+            merge_fragment(MergeFormat::Json, "{", fragment, path),
+            Err(Error::MergeFragment { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_fragment_toml() -> Result<(), Box<dyn std::error::Error>> {
+        let current = "[package]\nname = \"foo\"\n\n[dependencies]\nslog = \"2\"\n";
+        let fragment = "[dependencies]\nserde = \"1\"\nslog = \"2.5\"\n";
+        let expected =
+            "[package]\nname = \"foo\"\n\n[dependencies]\nslog = \"2.5\"\nserde = \"1\"\n";
+        let path = Path::new("Cargo.toml.ffizer.merge-toml");
+        assert_that!(merge_fragment(MergeFormat::Toml, current, fragment, path)?)
+            .is_equal_to(expected.to_owned());
+        assert_that!(merge_fragment(MergeFormat::Toml, "", fragment, path)?)
+            .is_equal_to("[dependencies]\nserde = \"1\"\nslog = \"2.5\"\n".to_owned());
+        Ok(())
+    }
+}
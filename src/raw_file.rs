@@ -0,0 +1,179 @@
+//! retrieval of templates published as a single raw file via http(s) (`raw+https://<host>/<path>`),
+//! eg on an artifact server.
+//!
+//! The file is cached into the folder `<dst>` (as `<dst>/<name of the file>`), and its `ETag` into
+//! the file `<dst>.etag`: the next retrievals are conditional (`If-None-Match`), so the file is only
+//! downloaded again when it changed on the server.
+
+use crate::auth::HostAuth;
+use crate::error::*;
+use crate::files;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use slog::{debug, info, Logger};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// the file with the `ETag` of the cached template `dst`
+pub fn etag_path(dst: &Path) -> PathBuf {
+    let mut name = dst
+        .file_name()
+        .map(|s| s.to_os_string())
+        .unwrap_or_default();
+    name.push(".etag");
+    dst.with_file_name(name)
+}
+
+/// the cached file of the template `dst` (the folder contains only the file, with the same name)
+pub fn cached_file(dst: &Path) -> Option<PathBuf> {
+    dst.file_name().map(|name| dst.join(name))
+}
+
+/// download the file at `url` (with the credentials of its host, if defined) into the folder `dst`,
+/// unless the cached file is still the one of the server (same `ETag`)
+/// (downloaded into `<dst>.part` then swapped, so `dst` is never partially written)
+pub fn retrieve<P, U>(logger: &Logger, dst: P, url: U, auth: Option<&HostAuth>) -> Result<()>
+where
+    P: AsRef<Path>,
+    U: AsRef<str>,
+{
+    let dst = dst.as_ref();
+    let url = url.as_ref();
+    let file = cached_file(dst)
+        .ok_or_else(|| Error::Unknown(format!("no file name in the url: {}", url)))?;
+    let etag_path = etag_path(dst);
+    let etag = if file.exists() {
+        fs::read_to_string(&etag_path).ok()
+    } else {
+        None
+    };
+    let mut request = reqwest::blocking::Client::new().get(url);
+    if let Some(auth) = auth {
+        request = auth.apply(request);
+    }
+    if let Some(etag) = &etag {
+        request = request.header(IF_NONE_MATCH, etag.trim());
+    }
+    info!(logger, "download raw file"; "url" => url);
+    let to_error = |source| Error::Download {
+        url: url.to_owned(),
+        source,
+    };
+    let resp = request.send().map_err(to_error)?;
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        debug!(logger, "cached raw file is up to date"; "folder" => ?&dst, "etag" => ?&etag);
+        return Ok(());
+    }
+    let resp = resp.error_for_status().map_err(to_error)?;
+    let new_etag = resp
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_owned());
+    let content = resp.bytes().map_err(to_error)?;
+
+    let tmp = files::part_path(dst);
+    if tmp.exists() {
+        fs::remove_dir_all(&tmp).map_err(|source| Error::RemoveFolder {
+            path: tmp.clone(),
+            source,
+        })?;
+    }
+    fs::create_dir_all(&tmp).map_err(|source| Error::CreateFolder {
+        path: tmp.clone(),
+        source,
+    })?;
+    let tmp_file = tmp.join(file.file_name().unwrap_or_default());
+    fs::write(&tmp_file, &content).map_err(|source| Error::WriteFile {
+        path: tmp_file.clone(),
+        source,
+    })?;
+    info!(logger, "store raw file into cached template"; "folder" => ?&dst);
+    if dst.exists() {
+        fs::remove_dir_all(&dst).map_err(|source| Error::RemoveFolder {
+            path: dst.to_path_buf(),
+            source,
+        })?;
+    }
+    fs::rename(&tmp, &dst).map_err(|source| Error::RenameFile {
+        src: tmp.clone(),
+        dst: dst.to_path_buf(),
+        source,
+    })?;
+    match new_etag {
+        Some(etag) => fs::write(&etag_path, etag).map_err(|source| Error::WriteFile {
+            path: etag_path.clone(),
+            source,
+        }),
+        None if etag_path.exists() => {
+            fs::remove_file(&etag_path).map_err(|source| Error::RemoveFile {
+                path: etag_path.clone(),
+                source,
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_stub::{HttpStub, StubResponse};
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_paths_of_cached_raw_file() {
+        let dst = Path::new("/cache/example.com/templates/README.md");
+        assert_that!(etag_path(dst))
+            .is_equal_to(PathBuf::from("/cache/example.com/templates/README.md.etag"));
+        assert_that!(cached_file(dst)).is_equal_to(Some(PathBuf::from(
+            "/cache/example.com/templates/README.md/README.md",
+        )));
+    }
+
+    #[test]
+    fn test_retrieve_with_etag() -> Result<(), Box<dyn std::error::Error>> {
+        let stub = HttpStub::start(vec![
+            StubResponse::ok("v1\n").header("ETag", "\"e1\""),
+            StubResponse {
+                status: 304,
+                ..Default::default()
+            },
+            StubResponse::ok("v2\n").header("ETag", "\"e2\""),
+            StubResponse::ok("v3\n"),
+        ]);
+        let url = format!("{}/templates/README.md", stub.url);
+        let tmp_dir = tempdir()?;
+        let dst = tmp_dir.path().join("README.md");
+        let file = dst.join("README.md");
+        let logger = crate::Ctx::default().logger;
+
+        // first download
+        retrieve(&logger, &dst, &url, None)?;
+        assert_that!(fs::read_to_string(&file)?).is_equal_to("v1\n".to_owned());
+        assert_that!(fs::read_to_string(etag_path(&dst))?).is_equal_to("\"e1\"".to_owned());
+
+        // not modified, the cached file is reused
+        retrieve(&logger, &dst, &url, None)?;
+        assert_that!(fs::read_to_string(&file)?).is_equal_to("v1\n".to_owned());
+
+        // modified, the file and its etag are swapped
+        retrieve(&logger, &dst, &url, None)?;
+        assert_that!(fs::read_to_string(&file)?).is_equal_to("v2\n".to_owned());
+        assert_that!(fs::read_to_string(etag_path(&dst))?).is_equal_to("\"e2\"".to_owned());
+        assert_that!(files::part_path(&dst).exists()).is_false();
+
+        // no etag anymore
+        retrieve(&logger, &dst, &url, None)?;
+        assert_that!(fs::read_to_string(&file)?).is_equal_to("v3\n".to_owned());
+        assert_that!(etag_path(&dst).exists()).is_false();
+
+        let requests: Vec<String> = stub.requests().iter().map(|r| r.to_lowercase()).collect();
+        assert_that!(requests[0].contains("if-none-match")).is_false();
+        assert_that!(requests[1]).contains("if-none-match: \"e1\"");
+        assert_that!(requests[2]).contains("if-none-match: \"e1\"");
+        assert_that!(requests[3]).contains("if-none-match: \"e2\"");
+        Ok(())
+    }
+}
@@ -0,0 +1,529 @@
+//! registry of the templates available to the user (or to a team), defined into `registry.yaml`
+//! of the ffizer's config folder (eg `~/.config/ffizer/registry.yaml` on linux),
+//! or into the file set by `FFIZER_REGISTRY_FILE`.
+//!
+//! ```yaml
+//! templates:
+//!   - name: rust-cli
+//!     description: a command line application in rust
+//!     source: https://github.com/ffizer/template_sample.git
+//!     rev: master # optional, default "master"
+//!     subfolder: cli # optional
+//! indexes: # optional, urls of remote registries (same format, `templates` only)
+//!   - https://templates.example.com/registry.yaml
+//! ```
+//!
+//! The indexes (http, https, s3) are downloaded with the credentials of their host defined into
+//! `auth.yaml` (see [`crate::auth`]), like the archives they reference, so a team can host a private
+//! catalog. They are not downloaded when offline, and an index unavailable is skipped with a warning.
+//!
+//! The registry can be exported as a catalog (json or html), with the variables and
+//! a preview of the files of every template. When a template is applied without `--source`,
+//! the template is selected into the registry.
+
+use crate::auth::AuthCfg;
+use crate::cfg::{TemplateCfg, TemplateComposite};
+use crate::download;
+use crate::error::*;
+use crate::files;
+use crate::source_file::SourceFileMetadata;
+use crate::source_loc::SourceLoc;
+use crate::source_uri::SourceUri;
+use crate::ui;
+use crate::{Ctx, Variables};
+use rayon::prelude::*;
+use slog::{info, warn, Logger};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const REGISTRY_FILE_ENV: &str = "FFIZER_REGISTRY_FILE";
+const REGISTRY_FILENAME: &str = "registry.yaml";
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RegistryCfg {
+    pub templates: Vec<RegistryEntry>,
+    /// urls of the remote registries, their templates are appended by [`RegistryCfg::load_indexes`]
+    pub indexes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegistryEntry {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub source: SourceUri,
+    #[serde(default = "default_rev")]
+    pub rev: String,
+    #[serde(default)]
+    pub subfolder: Option<PathBuf>,
+}
+
+fn default_rev() -> String {
+    "master".to_owned()
+}
+
+impl RegistryCfg {
+    /// path of the file of the user, `FFIZER_REGISTRY_FILE` if defined
+    pub fn find_file() -> Result<PathBuf> {
+        if let Ok(path) = std::env::var(REGISTRY_FILE_ENV) {
+            return Ok(PathBuf::from(path));
+        }
+        let project_dirs = directories::ProjectDirs::from("", "", env!("CARGO_PKG_NAME"))
+            .ok_or(Error::ApplicationPathNotFound {})?;
+        Ok(project_dirs.config_dir().join(REGISTRY_FILENAME))
+    }
+
+    /// load the registry of the user (empty if the file doesn't exist)
+    pub fn load() -> Result<RegistryCfg> {
+        Self::from_file(Self::find_file()?)
+    }
+
+    pub fn from_file<P>(path: P) -> Result<RegistryCfg>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(RegistryCfg::default());
+        }
+        let content = fs::read_to_string(path).map_err(|source| Error::ReadFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    /// append the templates of the `indexes` (not downloaded when `offline`)
+    pub fn load_indexes(&mut self, logger: &Logger, offline: bool) -> Result<()> {
+        if offline || self.indexes.is_empty() {
+            return Ok(());
+        }
+        self.append_indexes(logger, &AuthCfg::load()?);
+        Ok(())
+    }
+
+    fn append_indexes(&mut self, logger: &Logger, auth_cfg: &AuthCfg) {
+        for url in &self.indexes {
+            match download_index(url, auth_cfg) {
+                Ok(mut index) => self.templates.append(&mut index.templates),
+                Err(err) => {
+                    warn!(logger, "failed to download the index of templates"; "url" => url, "error" => ?&err)
+                }
+            }
+        }
+    }
+}
+
+/// download the index at `url`, with the credentials of its host
+fn download_index(url: &str, auth_cfg: &AuthCfg) -> Result<RegistryCfg> {
+    let host = url.parse::<SourceUri>()?.host;
+    let auth = host.as_deref().and_then(|h| auth_cfg.find(h));
+    let content = download::backend_for(url)?.download(url, auth)?;
+    Ok(serde_yaml::from_slice(&content)?)
+}
+
+impl RegistryEntry {
+    pub fn source_loc(&self) -> SourceLoc {
+        SourceLoc {
+            uri: self.source.clone(),
+            rev: self.rev.clone(),
+            subfolder: self.subfolder.clone(),
+            full_history: false,
+        }
+    }
+}
+
+/// the description of the template: the one of the registry, else the one of its configuration
+/// (`description` of `.ffizer.yaml`), `None` if the template can't be retrieved
+pub fn find_description(logger: &Logger, entry: &RegistryEntry, offline: bool) -> Option<String> {
+    if entry.description.is_some() {
+        return entry.description.clone();
+    }
+    let cfg = entry
+        .source_loc()
+        .download(logger, offline)
+        .and_then(|path| TemplateCfg::from_template_folder(&path));
+    match cfg {
+        Ok(cfg) => cfg.description,
+        Err(err) => {
+            warn!(logger, "failed to read the description of the template"; "template" => &entry.name, "error" => ?&err);
+            None
+        }
+    }
+}
+
+/// when the source of the template is missing (no `--source`), select a template of the registry:
+/// the only one registered, else the one chosen by the user (searched by name and description).
+/// The rev and the subfolder of the registry are used (the `--source-subfolder` overrides the subfolder)
+pub fn pick_missing_source(
+    logger: &Logger,
+    src: &mut SourceLoc,
+    no_interaction: bool,
+    offline: bool,
+) -> Result<()> {
+    if !src.uri.raw.is_empty() {
+        return Ok(());
+    }
+    let path = RegistryCfg::find_file()?;
+    let mut registry = RegistryCfg::from_file(&path)?;
+    registry.load_indexes(logger, offline)?;
+    let entry = match registry.templates.as_slice() {
+        [entry] => entry,
+        templates if templates.is_empty() || no_interaction => {
+            return Err(Error::MissingSource {
+                path,
+                names: templates.iter().map(|t| t.name.clone()).collect(),
+            });
+        }
+        templates => {
+            let labels = templates
+                .par_iter()
+                .map(|t| match find_description(logger, t, offline) {
+                    Some(description) => format!("{} - {}", t.name, description),
+                    None => t.name.clone(),
+                })
+                .collect::<Vec<_>>();
+            &templates[ui::ask_template(&labels)?]
+        }
+    };
+    info!(logger, "select the template of the registry"; "name" => &entry.name, "source" => &entry.source.raw);
+    let subfolder = src.subfolder.take().or_else(|| entry.subfolder.clone());
+    *src = SourceLoc {
+        subfolder,
+        full_history: src.full_history,
+        ..entry.source_loc()
+    };
+    Ok(())
+}
+
+/// the indexes of the `labels` matching the `query` (its characters in the same order, ignoring the
+/// case and the spaces), the best matches first (the closest characters, then the earliest)
+pub(crate) fn fuzzy_filter(query: &str, labels: &[String]) -> Vec<usize> {
+    let mut scored = labels
+        .iter()
+        .enumerate()
+        .filter_map(|(i, label)| fuzzy_score(query, label).map(|score| (score, i)))
+        .collect::<Vec<_>>();
+    scored.sort();
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// the span and the start of the characters of the `query` into the `text`, `None` if the `text`
+/// doesn't contain all of them
+fn fuzzy_score(query: &str, text: &str) -> Option<(usize, usize)> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut start = None;
+    let mut end = 0;
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = end + text[end..].iter().position(|t| *t == c)?;
+        start.get_or_insert(found);
+        end = found + 1;
+    }
+    let start = start.unwrap_or_default();
+    Some((end.saturating_sub(start), start))
+}
+
+/// a template of the catalog
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CatalogEntry {
+    pub name: String,
+    pub description: Option<String>,
+    pub source: String,
+    pub rev: String,
+    pub subfolder: Option<PathBuf>,
+    /// the variables asked to the user (hidden variables are excluded)
+    pub variables: Vec<CatalogVariable>,
+    /// preview of the generated files and folders (paths are not rendered, eg `{{ project_name }}/README.md`)
+    pub tree: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CatalogVariable {
+    pub name: String,
+    pub ask: Option<String>,
+    pub default_value: Option<serde_yaml::Value>,
+    /// the accepted values (empty means any value)
+    pub values: Vec<serde_yaml::Value>,
+}
+
+/// retrieve (if remote and not offline) every template of the registry, to describe them
+pub fn build_catalog(
+    ctx: &Ctx,
+    registry: &RegistryCfg,
+    offline: bool,
+) -> Result<Vec<CatalogEntry>> {
+    registry
+        .templates
+        .iter()
+        .map(|entry| {
+            let src = entry.source_loc();
+            let template_composite =
+                TemplateComposite::from_src(ctx, &Variables::default(), offline, &src)?;
+            let variables = template_composite
+                .find_variabledefs()?
+                .into_iter()
+                .filter(|v| !v.hidden)
+                .map(|v| CatalogVariable {
+                    name: v.name,
+                    ask: v.ask,
+                    default_value: v.default_value,
+                    values: v.select_in_values,
+                })
+                .collect();
+            let mut tree = BTreeSet::new();
+            for source_file in template_composite.find_sourcefiles()? {
+                let relative = files::remove_special_suffix(&source_file.childpath.relative)?;
+                if relative.as_os_str().is_empty() {
+                    continue;
+                }
+                let mut path = relative.to_string_lossy().replace('\\', "/");
+                if source_file.metadata == SourceFileMetadata::Dir {
+                    path.push('/');
+                }
+                tree.insert(path);
+            }
+            let description = match &entry.description {
+                Some(description) => Some(description.clone()),
+                None => TemplateCfg::from_template_folder(&src.as_local_path()?)?.description,
+            };
+            Ok(CatalogEntry {
+                name: entry.name.clone(),
+                description,
+                source: entry.source.raw.clone(),
+                rev: entry.rev.clone(),
+                subfolder: entry.subfolder.clone(),
+                variables,
+                tree: tree.into_iter().collect(),
+            })
+        })
+        .collect()
+}
+
+pub fn to_json(catalog: &[CatalogEntry]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(catalog)?)
+}
+
+/// a standalone html page (without external resources)
+pub fn to_html(catalog: &[CatalogEntry]) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>Templates</title>\n</head>\n<body>\n<h1>Templates</h1>\n<ul>\n");
+    for entry in catalog {
+        out.push_str(&format!(
+            "<li><a href=\"#{0}\">{0}</a></li>\n",
+            escape_html(&entry.name)
+        ));
+    }
+    out.push_str("</ul>\n");
+    for entry in catalog {
+        out.push_str(&format!(
+            "<section id=\"{0}\">\n<h2>{0}</h2>\n",
+            escape_html(&entry.name)
+        ));
+        if let Some(description) = &entry.description {
+            out.push_str(&format!("<p>{}</p>\n", escape_html(description)));
+        }
+        let subfolder = entry
+            .subfolder
+            .as_ref()
+            .map(|s| format!(" --source-subfolder {}", s.to_string_lossy()))
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "<pre>ffizer apply --source {} --rev {}{} --destination &lt;folder&gt;</pre>\n",
+            escape_html(&entry.source),
+            escape_html(&entry.rev),
+            escape_html(&subfolder)
+        ));
+        if !entry.variables.is_empty() {
+            out.push_str("<h3>Variables</h3>\n<table>\n<tr><th>name</th><th>question</th><th>default</th><th>values</th></tr>\n");
+            for v in &entry.variables {
+                let values = v
+                    .values
+                    .iter()
+                    .map(yaml_to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                out.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    escape_html(&v.name),
+                    escape_html(v.ask.as_deref().unwrap_or("")),
+                    escape_html(
+                        &v.default_value
+                            .as_ref()
+                            .map(yaml_to_string)
+                            .unwrap_or_default()
+                    ),
+                    escape_html(&values),
+                ));
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("<h3>Files</h3>\n<pre>\n");
+        for path in &entry.tree {
+            out.push_str(&escape_html(path));
+            out.push('\n');
+        }
+        out.push_str("</pre>\n</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn yaml_to_string(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(s) => s.clone(),
+        v => serde_yaml::to_string(v)
+            .map(|s| s.trim_start_matches("---").trim().to_owned())
+            .unwrap_or_default(),
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::HostAuth;
+    use crate::http_stub::{HttpStub, StubResponse};
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_build_catalog() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let template = tmp_dir.path().join("template");
+        fs::create_dir_all(template.join("{{ project }}"))?;
+        fs::write(
+            template.join("{{ project }}/README.md.ffizer.hbs"),
+            "{{ project }}",
+        )?;
+        fs::write(
+            template.join(".ffizer.yaml"),
+            r#"
+            variables:
+              - name: project
+                ask: Name of the project
+                default_value: demo
+              - name: internal
+                hidden: true
+            "#,
+        )?;
+        let path = tmp_dir.path().join(REGISTRY_FILENAME);
+        fs::write(
+            &path,
+            format!(
+                "templates:\n  - name: demo\n    description: a <demo>\n    source: {}\n",
+                template.to_string_lossy()
+            ),
+        )?;
+        let registry = RegistryCfg::from_file(&path)?;
+        let catalog = build_catalog(&Ctx::default(), &registry, true)?;
+
+        assert_that!(catalog.len()).is_equal_to(1);
+        assert_that!(catalog[0].rev).is_equal_to("master".to_owned());
+        assert_that!(catalog[0]
+            .variables
+            .iter()
+            .map(|v| v.name.clone())
+            .collect::<Vec<_>>())
+        .is_equal_to(vec!["project".to_owned()]);
+        assert_that!(catalog[0].tree).is_equal_to(vec![
+            "{{ project }}/".to_owned(),
+            "{{ project }}/README.md".to_owned(),
+        ]);
+        assert_that!(to_html(&catalog)).contains("a &lt;demo&gt;");
+        assert_that!(to_json(&catalog)?).contains("\"default_value\": \"demo\"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_private_index() -> Result<(), Box<dyn std::error::Error>> {
+        let stub = HttpStub::start(vec![StubResponse::ok(
+            "templates:\n  - name: internal\n    source: https://templates.example.com/internal.zip\n",
+        )]);
+        let mut registry = RegistryCfg {
+            indexes: vec![format!("{}/registry.yaml", stub.url)],
+            ..Default::default()
+        };
+        let auth_cfg = AuthCfg {
+            hosts: vec![HostAuth {
+                host: "127.0.0.1".to_owned(),
+                token: Some("t0ken".to_owned()),
+                ..Default::default()
+            }],
+        };
+        registry.append_indexes(&Ctx::default().logger, &auth_cfg);
+        assert_that!(registry
+            .templates
+            .iter()
+            .map(|t| t.name.clone())
+            .collect::<Vec<_>>())
+        .is_equal_to(vec!["internal".to_owned()]);
+        let requests = stub.requests();
+        assert_that!(requests[0].starts_with("GET /registry.yaml ")).is_true();
+        assert_that!(requests[0].to_lowercase()).contains("authorization: bearer t0ken");
+
+        // an index unavailable is skipped
+        let stub = HttpStub::start(vec![StubResponse {
+            status: 401,
+            ..Default::default()
+        }]);
+        let mut registry = RegistryCfg {
+            indexes: vec![format!("{}/registry.yaml", stub.url)],
+            ..Default::default()
+        };
+        registry.append_indexes(&Ctx::default().logger, &AuthCfg::default());
+        assert_that!(registry.templates).is_empty();
+        stub.requests();
+        Ok(())
+    }
+
+    #[test]
+    fn test_fuzzy_filter() {
+        let labels = vec![
+            "rust-cli - a command line application in rust".to_owned(),
+            "rust-lib - a library in rust".to_owned(),
+            "web - a static site".to_owned(),
+        ];
+        assert_that!(fuzzy_filter("", &labels)).is_equal_to(vec![0, 1, 2]);
+        assert_that!(fuzzy_filter("rlib", &labels)).is_equal_to(vec![1]);
+        assert_that!(fuzzy_filter("Rust CLI", &labels)).is_equal_to(vec![0]);
+        assert_that!(fuzzy_filter("site", &labels)).is_equal_to(vec![2]);
+        assert_that!(fuzzy_filter("a", &labels)).is_equal_to(vec![2, 0, 1]);
+        assert_that!(fuzzy_filter("go", &labels)).is_empty();
+    }
+
+    #[test]
+    fn test_find_description_from_template() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        fs::write(
+            tmp_dir.path().join(".ffizer.yaml"),
+            "description: a static site\n",
+        )?;
+        let mut entry = RegistryEntry {
+            name: "web".to_owned(),
+            description: None,
+            source: tmp_dir.path().to_string_lossy().parse()?,
+            rev: default_rev(),
+            subfolder: None,
+        };
+        let logger = Ctx::default().logger;
+        assert_that!(find_description(&logger, &entry, true))
+            .is_equal_to(Some("a static site".to_owned()));
+        entry.description = Some("the site of the team".to_owned());
+        assert_that!(find_description(&logger, &entry, true))
+            .is_equal_to(Some("the site of the team".to_owned()));
+        entry.source = tmp_dir.path().join("missing").to_string_lossy().parse()?;
+        entry.description = None;
+        assert_that!(find_description(&logger, &entry, true)).is_none();
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use crate::error::*;
+use crate::git;
+use run_script::ScriptOptions;
+use std::fs;
+use std::path::Path;
+
+/// a rule of the template (with its values rendered), checked after the questions
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub message: Option<String>,
+    pub dst_empty: bool,
+    pub dst_in_git: bool,
+    pub condition: Option<String>,
+    pub cmd: Option<String>,
+}
+
+impl Rule {
+    /// the reason why the rule is violated by the destination `dst_folder` (`None` if the rule is
+    /// respected), the command is only run if `run_cmd`
+    pub(crate) fn find_violation(
+        &self,
+        dst_folder: &Path,
+        run_cmd: bool,
+    ) -> Result<Option<String>> {
+        if self.dst_empty && !is_empty_folder(dst_folder)? {
+            return Ok(Some("the destination folder is not empty".to_owned()));
+        }
+        if self.dst_in_git && !git::is_in_repository(dst_folder) {
+            return Ok(Some(
+                "the destination folder is not into a git repository".to_owned(),
+            ));
+        }
+        if let Some(condition) = &self.condition {
+            match condition.trim() {
+                "true" => (),
+                "" | "false" => return Ok(Some("the condition is false".to_owned())),
+                v => {
+                    return Err(Error::StringValueNotIn {
+                        value_name: "condition of rule".to_owned(),
+                        value: v.to_owned(),
+                        accepted: vec!["true".to_owned(), "false".to_owned(), "".to_owned()],
+                    })
+                }
+            }
+        }
+        if let (Some(cmd), true) = (&self.cmd, run_cmd) {
+            let mut options = ScriptOptions::new();
+            if dst_folder.is_dir() {
+                options.working_directory = Some(dst_folder.to_path_buf());
+            }
+            let (code, _, stderr) =
+                run_script::run(cmd, &vec![], &options).map_err(|source| Error::ScriptError {
+                    script: cmd.clone(),
+                    source,
+                })?;
+            if code != 0 {
+                return Ok(Some(format!(
+                    "the command {:?} failed (exit code {}): {}",
+                    cmd,
+                    code,
+                    stderr.trim()
+                )));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn is_empty_folder(folder: &Path) -> Result<bool> {
+    if !folder.exists() {
+        return Ok(true);
+    }
+    let mut entries = fs::read_dir(folder).map_err(|source| Error::ListFolder {
+        path: folder.to_path_buf(),
+        source,
+    })?;
+    Ok(entries.next().is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_violation() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let dst = tmp_dir.path().join("project");
+        let dst_empty = Rule {
+            dst_empty: true,
+            ..Default::default()
+        };
+        assert_that!(dst_empty.find_violation(&dst, true)?).is_none();
+        fs::create_dir_all(&dst)?;
+        assert_that!(dst_empty.find_violation(&dst, true)?).is_none();
+        fs::write(dst.join("README.md"), "")?;
+        assert_that!(dst_empty.find_violation(&dst, true)?).is_some();
+
+        let dst_in_git = Rule {
+            dst_in_git: true,
+            ..Default::default()
+        };
+        assert_that!(dst_in_git.find_violation(&dst, true)?).is_some();
+        git2::Repository::init(&dst)?;
+        assert_that!(dst_in_git.find_violation(&dst.join("not/yet/created"), true)?).is_none();
+
+        let condition = |v: &str| Rule {
+            condition: Some(v.to_owned()),
+            ..Default::default()
+        };
+        assert_that!(condition("true").find_violation(&dst, true)?).is_none();
+        assert_that!(condition("false").find_violation(&dst, true)?).is_some();
+        assert_that!(condition("").find_violation(&dst, true)?).is_some();
+        assert!(matches!(
+            condition("yes").find_violation(&dst, true),
+            Err(Error::StringValueNotIn { .. })
+        ));
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_find_violation_of_cmd() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        fs::write(tmp_dir.path().join("Cargo.toml"), "")?;
+        let cmd = |v: &str| Rule {
+            cmd: Some(v.to_owned()),
+            ..Default::default()
+        };
+        assert_that!(cmd("test -f Cargo.toml").find_violation(tmp_dir.path(), true)?).is_none();
+        assert_that!(cmd("test -f package.json").find_violation(tmp_dir.path(), true)?).is_some();
+        assert_that!(cmd("test -f package.json").find_violation(tmp_dir.path(), false)?).is_none();
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use crate::files;
 use crate::ChildPath;
+use encoding_rs::Encoding;
 use std::cmp::{Ord, Ordering};
 use std::path::PathBuf;
 
@@ -8,7 +9,25 @@ pub enum SourceFileMetadata {
     Dir,
     // TODO Symlink { target: String },
     RawFile,
-    RenderableFile { extension: &'static str },
+    RenderableFile {
+        extension: &'static str,
+        /// encoding of the content (`None` for UTF-8)
+        encoding: Option<&'static Encoding>,
+    },
+    /// lines (rendered) to append to the existing file
+    AppendFile,
+    /// patch (rendered) to apply to the existing file
+    PatchFile,
+    /// fragment (rendered) to deep-merge into the existing file
+    MergeFile {
+        format: MergeFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MergeFormat {
+    Json,
+    Toml,
 }
 
 impl SourceFileMetadata {
@@ -16,10 +35,24 @@ impl SourceFileMetadata {
         match self {
             // Self::Symlink { .. } => 0,
             Self::Dir => 1,
-            Self::RenderableFile { .. } => 2,
+            Self::RenderableFile { .. }
+            | Self::AppendFile
+            | Self::PatchFile
+            | Self::MergeFile { .. } => 2,
             Self::RawFile => 3,
         }
     }
+
+    /// true if the source is applied on the previous content (of the layer below or the existing file)
+    pub fn is_applied_on_content(&self) -> bool {
+        matches!(
+            self,
+            Self::RenderableFile { .. }
+                | Self::AppendFile
+                | Self::PatchFile
+                | Self::MergeFile { .. }
+        )
+    }
 }
 
 impl Ord for SourceFileMetadata {
@@ -89,22 +122,50 @@ impl SourceFile {
 // }
 
 impl From<(ChildPath, usize)> for SourceFile {
-    //TODO manage symlink
+    /// the symlinks are followed (the ones to a path outside of the template are rejected by the plan)
     fn from((childpath, layer_order): (ChildPath, usize)) -> Self {
         let path = PathBuf::from(&childpath);
-        //let metadata = std::fs::symlink_metadata(path)?;
         if path.is_dir() {
             SourceFile {
                 childpath,
                 layer_order,
                 metadata: SourceFileMetadata::Dir,
             }
+        } else if files::is_ffizer_edit(&path, files::FILEEXT_APPEND) {
+            SourceFile {
+                childpath,
+                layer_order,
+                metadata: SourceFileMetadata::AppendFile,
+            }
+        } else if files::is_ffizer_edit(&path, files::FILEEXT_PATCH) {
+            SourceFile {
+                childpath,
+                layer_order,
+                metadata: SourceFileMetadata::PatchFile,
+            }
+        } else if files::is_ffizer_edit(&path, files::FILEEXT_MERGE_JSON) {
+            SourceFile {
+                childpath,
+                layer_order,
+                metadata: SourceFileMetadata::MergeFile {
+                    format: MergeFormat::Json,
+                },
+            }
+        } else if files::is_ffizer_edit(&path, files::FILEEXT_MERGE_TOML) {
+            SourceFile {
+                childpath,
+                layer_order,
+                metadata: SourceFileMetadata::MergeFile {
+                    format: MergeFormat::Toml,
+                },
+            }
         } else if files::is_ffizer_handlebars(&path) {
             SourceFile {
                 childpath,
                 layer_order,
                 metadata: SourceFileMetadata::RenderableFile {
                     extension: files::FILEEXT_HANDLEBARS,
+                    encoding: None,
                 },
             }
         } else {
@@ -119,19 +180,35 @@ impl From<(ChildPath, usize)> for SourceFile {
 
 pub(crate) fn optimize_sourcefiles(sources: &mut Vec<SourceFile>) {
     sources.sort();
-    // take until not Renderable
+    // take until not Renderable (or edit of the previous content)
     // Because apply Dir, Symlink, RawFile erase previous change
-    if let Some(pos) = sources.iter().position(|x| {
-        if let SourceFileMetadata::RenderableFile { .. } = x.metadata {
-            false
-        } else {
-            true
-        }
-    }) {
+    if let Some(pos) = sources
+        .iter()
+        .position(|x| !x.metadata.is_applied_on_content())
+    {
         sources.truncate(pos + 1);
     }
 }
 
+/// two sources from the same layer can not target the same destination,
+/// except folders (merged) and a renderable file with a raw file (used as input of the rendering)
+pub(crate) fn find_conflict(sources: &[SourceFile]) -> Option<(&SourceFile, &SourceFile)> {
+    for (i, a) in sources.iter().enumerate() {
+        for b in sources.iter().skip(i + 1) {
+            if a.layer_order != b.layer_order {
+                continue;
+            }
+            let is_dir_a = a.metadata == SourceFileMetadata::Dir;
+            let is_dir_b = b.metadata == SourceFileMetadata::Dir;
+            if is_dir_a != is_dir_b || (!is_dir_a && a.metadata.kind_idx() == b.metadata.kind_idx())
+            {
+                return Some((a, b));
+            }
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -158,4 +235,25 @@ mod tests {
         optimize_sourcefiles(&mut input);
         assert_that!(&input).is_equal_to(&expected);
     }
+
+    #[test]
+    fn test_find_conflict() {
+        let base = "./tests/test_1/template";
+        let raw = SourceFile::from((ChildPath::new(base, "file_2.txt"), 0));
+        let renderable = SourceFile::from((ChildPath::new(base, "file_2.txt.ffizer.hbs"), 0));
+        let raw_generated = SourceFile::from((ChildPath::new(base, "{{ name }}.txt"), 0));
+        let raw_other_layer = SourceFile::from((ChildPath::new(base, "file_2.txt"), 1));
+        let append = SourceFile::from((ChildPath::new(base, "file_2.txt.ffizer.append"), 0));
+        assert_that!(find_conflict(&[raw.clone(), renderable.clone()])).is_none();
+        assert_that!(find_conflict(&[raw.clone(), append.clone()])).is_none();
+        assert_that!(find_conflict(&[renderable.clone(), append.clone()]))
+            .is_equal_to(Some((&renderable, &append)));
+        assert_that!(find_conflict(&[raw.clone(), raw_other_layer])).is_none();
+        assert_that!(find_conflict(&[
+            raw.clone(),
+            renderable,
+            raw_generated.clone()
+        ]))
+        .is_equal_to(Some((&raw, &raw_generated)));
+    }
 }
@@ -1,17 +1,27 @@
+use crate::auth::AuthCfg;
 use crate::error::*;
 use crate::git;
+use crate::source_provider;
 use crate::source_uri::SourceUri;
-use slog::{warn, Logger};
+use slog::{info, warn, Logger};
 use std::fmt;
 use std::fs;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+/// number of times the user is asked for credentials, when the authentication fails
+const MAX_CREDENTIALS_ATTEMPTS: usize = 3;
+
 #[derive(StructOpt, Debug, Default, Clone, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[serde(deny_unknown_fields, default)]
 pub struct SourceLoc {
-    /// uri / path of the template
-    #[structopt(short = "s", long = "source")]
+    /// uri / path of the template (if missing, a template of the registry is selected)
+    #[structopt(
+        short = "s",
+        long = "source",
+        default_value = "",
+        hide_default_value = true
+    )]
     pub uri: SourceUri,
 
     /// git revision of the template
@@ -21,6 +31,19 @@ pub struct SourceLoc {
     /// path of the folder under the source uri to use for template
     #[structopt(long = "source-subfolder", parse(from_os_str))]
     pub subfolder: Option<PathBuf>,
+
+    /// clone the full history of the git repository (by default, only the commit of the rev is cloned when possible)
+    #[structopt(long = "full-history")]
+    pub full_history: bool,
+}
+
+/// a template retrieved on the local filesystem
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalTemplate {
+    /// path of the template (subfolder included)
+    pub path: PathBuf,
+    /// the resolved rev, `None` for template not hosted in a git repository (local folder, archive, raw file)
+    pub rev_info: Option<git::RevInfo>,
 }
 
 impl SourceLoc {
@@ -33,29 +56,64 @@ impl SourceLoc {
     }
 
     pub fn as_local_path(&self) -> Result<PathBuf> {
-        let mut path = match self.uri.host {
-            None => self.uri.path.canonicalize()
-                .map_err(|source| Error::CanonicalizePath{path: self.uri.path.clone(), source})?,
-            Some(_) => self.remote_as_local()?,
-        };
+        let mut path = source_provider::provider_for(&self.uri)?.local_path(self)?;
+        if let Some(f) = &self.uri.subfolder {
+            path = path.join(f);
+        }
         if let Some(f) = &self.subfolder {
             path = path.join(f.clone());
         }
         Ok(path)
     }
 
-    // the remote_as_local ignore subfolder
-    fn remote_as_local(&self) -> Result<PathBuf> {
-        let cache_uri = Self::find_remote_cache_folder()?
-            .join(&self.uri.host.as_deref().unwrap_or("no_host"))
-            .join(&self.uri.path)
-            .join(&self.rev);
-        Ok(cache_uri)
+    /// download (if remote and not offline) the template into the cache, and resolve its local path and rev
+    pub fn retrieve(&self, logger: &Logger, offline: bool) -> Result<LocalTemplate> {
+        let path = self.download(logger, offline)?;
+        let provider = source_provider::provider_for(&self.uri)?;
+        let rev_info = provider.find_rev_info(self, &provider.local_path(self)?)?;
+        Ok(LocalTemplate { path, rev_info })
     }
+
+    /// download (if remote and not offline) the template into the cache, and return its local path
     pub fn download(&self, logger: &Logger, offline: bool) -> Result<PathBuf> {
-        if !offline && self.uri.host.is_some() {
-            let remote_path = self.remote_as_local()?;
-            if let Err(v) = git::retrieve(logger, &remote_path, &self.uri.raw, &self.rev) {
+        self.download_with(logger, offline, false)
+    }
+
+    /// like [`SourceLoc::download`], but if `ask_credentials`, the user is asked for credentials
+    /// when the authentication to a git repository fails (then the credentials are saved for the
+    /// next retrievals, if confirmed)
+    pub fn download_with(
+        &self,
+        logger: &Logger,
+        offline: bool,
+        ask_credentials: bool,
+    ) -> Result<PathBuf> {
+        let provider = source_provider::provider_for(&self.uri)?;
+        if !offline && provider.is_remote() {
+            let host = self.uri.host.as_deref().unwrap_or_default();
+            let remote_path = provider.local_path(self)?;
+            let mut auth_cfg = AuthCfg::load()?;
+            let auth = auth_cfg.find(host);
+            let mut retrieved = provider.retrieve(logger, self, &remote_path, auth);
+            let mut attempts = 0;
+            while ask_credentials
+                && attempts < MAX_CREDENTIALS_ATTEMPTS
+                && retrieved.as_ref().err().map(git::is_auth_error) == Some(true)
+            {
+                attempts += 1;
+                warn!(logger, "failed to authenticate"; "src" => ?&self, "error" => ?&retrieved);
+                let auth = crate::ui::ask_host_auth(host)?;
+                retrieved = provider.retrieve(logger, self, &remote_path, Some(&auth));
+                if retrieved.is_ok() {
+                    let path = AuthCfg::find_file()?;
+                    if crate::ui::confirm_save_host_auth(host, &path)? {
+                        info!(logger, "save credentials"; "host" => host, "path" => ?&path);
+                        auth_cfg.upsert(auth);
+                        auth_cfg.save(path)?;
+                    }
+                }
+            }
+            if let Err(v) = retrieved {
                 warn!(logger, "failed to download"; "src" => ?&self, "path" => ?&remote_path, "error" => ?&v);
                 if remote_path.exists() {
                     fs::remove_dir_all(&remote_path).map_err(|source| Error::RemoveFolder {
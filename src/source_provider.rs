@@ -0,0 +1,240 @@
+//! providers of the templates, selected by the uri of the source: local folder, git repository,
+//! archive (see [`archive`](crate::archive)), raw file via http(s) (see [`raw_file`](crate::raw_file)).
+//!
+//! Other protocols (eg an internal registry) can be plugged in by the library's users with
+//! [`register`]: the providers registered are tried before the default ones.
+//! The uri `<scheme>://<host>/<path>` of an unknown scheme is parsed, so it can be accepted by a
+//! registered provider.
+
+use crate::archive;
+use crate::auth::HostAuth;
+use crate::error::*;
+use crate::git::{self, RevInfo};
+use crate::raw_file;
+use crate::source_loc::SourceLoc;
+use crate::source_uri::SourceUri;
+use lazy_static::lazy_static;
+use slog::Logger;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+pub trait SourceProvider: Send + Sync {
+    /// the provider retrieves the templates of the `uri`
+    fn accepts(&self, uri: &SourceUri) -> bool;
+
+    /// the template is retrieved into the cache (false for a template already on the local filesystem)
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    /// the template is versioned by the rev of the source (a folder per rev into the cache, the
+    /// commit of the rev is recorded into the lockfile)
+    fn is_versioned(&self) -> bool {
+        false
+    }
+
+    /// the local folder of the template (without the subfolders), by default
+    /// `<cache>/<host>/<path>[/<rev>]`
+    fn local_path(&self, src: &SourceLoc) -> Result<PathBuf> {
+        let path = SourceLoc::find_remote_cache_folder()?
+            .join(src.uri.host.as_deref().unwrap_or("no_host"))
+            .join(&src.uri.path);
+        if self.is_versioned() {
+            Ok(path.join(&src.rev))
+        } else {
+            Ok(path)
+        }
+    }
+
+    /// download the template `src` into the folder `dst` (the `local_path`), with the credentials
+    /// of its host if defined
+    fn retrieve(
+        &self,
+        logger: &Logger,
+        src: &SourceLoc,
+        dst: &Path,
+        auth: Option<&HostAuth>,
+    ) -> Result<()>;
+
+    /// the resolved rev of the template retrieved into `dst` (none if not versioned)
+    fn find_rev_info(&self, _src: &SourceLoc, _dst: &Path) -> Result<Option<RevInfo>> {
+        Ok(None)
+    }
+}
+
+/// the templates on the local filesystem (uri without host)
+#[derive(Debug, Clone, Default)]
+pub struct LocalProvider;
+
+impl SourceProvider for LocalProvider {
+    fn accepts(&self, uri: &SourceUri) -> bool {
+        uri.host.is_none()
+    }
+
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    fn local_path(&self, src: &SourceLoc) -> Result<PathBuf> {
+        src.uri
+            .path
+            .canonicalize()
+            .map_err(|source| Error::CanonicalizePath {
+                path: src.uri.path.clone(),
+                source,
+            })
+    }
+
+    fn retrieve(&self, _: &Logger, _: &SourceLoc, _: &Path, _: Option<&HostAuth>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// the templates hosted into git repositories (`https://`, `http://`, `ssh://`, `git@`)
+#[derive(Debug, Clone, Default)]
+pub struct GitProvider;
+
+impl SourceProvider for GitProvider {
+    fn accepts(&self, uri: &SourceUri) -> bool {
+        uri.host.is_some()
+            && ["https://", "http://", "ssh://", "git@"]
+                .iter()
+                .any(|prefix| uri.raw.starts_with(prefix))
+    }
+
+    fn is_versioned(&self) -> bool {
+        true
+    }
+
+    fn retrieve(
+        &self,
+        logger: &Logger,
+        src: &SourceLoc,
+        dst: &Path,
+        auth: Option<&HostAuth>,
+    ) -> Result<()> {
+        git::retrieve_with_auth(logger, dst, &src.uri.raw, &src.rev, auth, src.full_history)
+    }
+
+    fn find_rev_info(&self, src: &SourceLoc, dst: &Path) -> Result<Option<RevInfo>> {
+        Ok(Some(git::find_rev_info(dst, &src.rev)?))
+    }
+}
+
+/// the templates published as archive via http(s) or s3
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveProvider;
+
+impl SourceProvider for ArchiveProvider {
+    fn accepts(&self, uri: &SourceUri) -> bool {
+        uri.is_archive()
+    }
+
+    fn retrieve(
+        &self,
+        logger: &Logger,
+        src: &SourceLoc,
+        dst: &Path,
+        auth: Option<&HostAuth>,
+    ) -> Result<()> {
+        archive::retrieve(logger, dst, src.uri.url(), src.uri.checksum(), auth)
+    }
+}
+
+/// the templates published as a single raw file via http(s) (`raw+https://`)
+#[derive(Debug, Clone, Default)]
+pub struct RawFileProvider;
+
+impl SourceProvider for RawFileProvider {
+    fn accepts(&self, uri: &SourceUri) -> bool {
+        uri.is_raw_file()
+    }
+
+    fn retrieve(
+        &self,
+        logger: &Logger,
+        src: &SourceLoc,
+        dst: &Path,
+        auth: Option<&HostAuth>,
+    ) -> Result<()> {
+        raw_file::retrieve(logger, dst, src.uri.url(), auth)
+    }
+}
+
+lazy_static! {
+    static ref PROVIDERS: RwLock<Vec<Arc<dyn SourceProvider>>> = RwLock::new(vec![
+        Arc::new(LocalProvider),
+        Arc::new(ArchiveProvider),
+        Arc::new(RawFileProvider),
+        Arc::new(GitProvider),
+    ]);
+}
+
+/// register a provider, tried before the providers already registered (so it can override them)
+pub fn register(provider: Arc<dyn SourceProvider>) {
+    PROVIDERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(0, provider);
+}
+
+/// the provider of the templates of the `uri`
+pub fn provider_for(uri: &SourceUri) -> Result<Arc<dyn SourceProvider>> {
+    PROVIDERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|p| p.accepts(uri))
+        .cloned()
+        .ok_or_else(|| Error::NoSourceProvider {
+            uri: uri.raw.clone(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use std::str::FromStr;
+
+    struct RegistryProvider;
+
+    impl SourceProvider for RegistryProvider {
+        fn accepts(&self, uri: &SourceUri) -> bool {
+            uri.raw.starts_with("registry://")
+        }
+
+        fn retrieve(
+            &self,
+            _: &Logger,
+            _: &SourceLoc,
+            _: &Path,
+            _: Option<&HostAuth>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_provider_for() -> Result<(), Box<dyn std::error::Error>> {
+        let versioned = |s: &str| -> Result<bool, Box<dyn std::error::Error>> {
+            Ok(provider_for(&SourceUri::from_str(s)?)?.is_versioned())
+        };
+        assert_that!(versioned("https://github.com/ffizer/ffizer.git")?).is_true();
+        assert_that!(versioned("git@github.com:ffizer/ffizer")?).is_true();
+        assert_that!(versioned("https://example.com/tmpl.zip")?).is_false();
+        assert_that!(versioned("raw+https://example.com/LICENSE")?).is_false();
+        let local = provider_for(&SourceUri::from_str("/foo/bar")?)?;
+        assert_that!(local.is_remote()).is_false();
+
+        let uri = SourceUri::from_str("registry://templates.example.com/rust-cli")?;
+        assert_that!(&uri.host).is_equal_to(Some("templates.example.com".to_owned()));
+        assert!(matches!(
+            provider_for(&uri),
+            Err(Error::NoSourceProvider { .. })
+        ));
+        register(Arc::new(RegistryProvider));
+        assert_that!(provider_for(&uri)?.is_remote()).is_true();
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
+use crate::archive::ArchiveKind;
 use crate::error::*;
 use regex::Regex;
 use serde_plain::derive_deserialize_from_str;
+use std::iter::Iterator;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::iter::Iterator;
 
 // create my own URI because didn't found acceptable solution
 // - http = "0.1.13" failed to parse "git@github.com:ffizer/ffizer.git"
@@ -14,20 +15,37 @@ pub struct SourceUri {
     pub raw: String,
     pub path: PathBuf,
     pub host: Option<String>,
+    /// folder inside the remote source to use as template (`<uri>//<subfolder>`)
+    pub subfolder: Option<PathBuf>,
 }
 
 derive_deserialize_from_str!(SourceUri, "source uri");
 
+/// prefix of the uri of a template published as a single raw file via http(s)
+const RAW_FILE_PREFIX: &str = "raw+";
+
 impl FromStr for SourceUri {
     type Err = crate::Error;
 
-    fn from_str(s: &str) -> Result<Self> {
+    fn from_str(s_full: &str) -> Result<Self> {
+        let (s, subfolder) = split_subfolder(s_full);
+        let s = s.as_str();
+        let archive_re = Regex::new(
+            r"^(https?|s3)://([[:alnum:]:\._-]+@)?(?P<host>[[:alnum:]\._-]+)(:\d+)?/(?P<path>[[:alnum:]\._\-/]+\.(zip|tar\.gz|tgz))(#sha256=[[:xdigit:]]+)?$",
+        ).map_err(|source| Error::ParseGitUri{value: s.to_owned(), source})?;
+        let raw_file_re = Regex::new(
+            r"^raw\+https?://([[:alnum:]:\._-]+@)?(?P<host>[[:alnum:]\._-]+)(:\d+)?/(?P<path>[[:alnum:]\._\-/]+)$",
+        ).map_err(|source| Error::ParseGitUri{value: s.to_owned(), source})?;
         let url_re = Regex::new(
             r"^(https?|ssh)://([[:alnum:]:\._-]+@)?(?P<host>[[:alnum:]\._-]+)(:\d+)?/(?P<path>[[:alnum:]\._\-/]+).git$",
         ).map_err(|source| Error::ParseGitUri{value: s.to_owned(), source})?;
         let url_re2 = Regex::new(
             r"^(https?|ssh)://([[:alnum:]:\._-]+@)?(?P<host>[[:alnum:]\._-]+)(:\d+)?/(?P<path>[[:alnum:]\._\-/]+)$",
         ).map_err(|source| Error::ParseGitUri{value: s.to_owned(), source})?;
+        // any other scheme, for the providers registered by the library's users
+        let other_re = Regex::new(
+            r"^[[:alpha:]][[:alnum:]\+\.-]*://([[:alnum:]:\._-]+@)?(?P<host>[[:alnum:]\._-]+)(:\d+)?/(?P<path>[[:alnum:]\._\-/]+)$",
+        ).map_err(|source| Error::ParseGitUri{value: s.to_owned(), source})?;
         let git_re =
             Regex::new(r"^git@(?P<host>[[:alnum:]\._-]+):(?P<path>[[:alnum:]\._\-/]+).git$")
                 .map_err(|source| Error::ParseGitUri {
@@ -39,27 +57,80 @@ impl FromStr for SourceUri {
                 value: s.to_owned(),
                 source,
             })?;
-        git_re
+        archive_re
             .captures(s)
+            .or_else(|| raw_file_re.captures(s))
+            .or_else(|| git_re.captures(s))
             .or_else(|| git_re2.captures(s))
             .or_else(|| url_re.captures(s))
             .or_else(|| url_re2.captures(s))
+            .or_else(|| other_re.captures(s))
             .map(|caps| SourceUri {
                 raw: s.to_owned(),
                 path: PathBuf::from(caps["path"].to_owned()),
                 host: Some(caps["host"].to_owned()),
+                subfolder: subfolder.clone(),
             })
             .or_else(|| {
+                // `//` is a valid separator in local path
                 Some(SourceUri {
-                    raw: s.to_owned(),
-                    path: PathBuf::from(change_local_path_sep(s)),
+                    raw: s_full.to_owned(),
+                    path: PathBuf::from(change_local_path_sep(s_full)),
                     host: None,
+                    subfolder: None,
                 })
             })
             .ok_or(Error::Unknown("failed to parse source uri".to_owned()))
     }
 }
 
+impl SourceUri {
+    /// the uri is an archive (`.zip`, `.tar.gz`, `.tgz`) to download via http(s) or s3
+    pub fn is_archive(&self) -> bool {
+        self.host.is_some() && ArchiveKind::from_path(&self.path).is_some()
+    }
+
+    /// the uri is a single raw file to download via http(s) (`raw+https://<host>/<path>`)
+    pub fn is_raw_file(&self) -> bool {
+        self.host.is_some() && self.raw.starts_with(RAW_FILE_PREFIX)
+    }
+
+    /// the uri without the fragment (eg: `#sha256=...`) and without the prefix `raw+`
+    pub fn url(&self) -> &str {
+        let url = self.raw.split('#').next().unwrap_or(&self.raw);
+        url.strip_prefix(RAW_FILE_PREFIX).unwrap_or(url)
+    }
+
+    /// the expected sha256 of the archive, defined as fragment of the uri (`...tar.gz#sha256=<hex>`)
+    pub fn checksum(&self) -> Option<&str> {
+        const MARKER: &str = "#sha256=";
+        self.raw.find(MARKER).map(|i| &self.raw[i + MARKER.len()..])
+    }
+}
+
+/// split `<uri>//<subfolder>` (the first `//` after the scheme), the fragment (`#...`) is kept with the uri
+fn split_subfolder(s: &str) -> (String, Option<PathBuf>) {
+    let start = s.find("://").map(|i| i + 3).unwrap_or(0);
+    match s[start..].find("//") {
+        Some(i) => {
+            let (uri, rest) = s.split_at(start + i);
+            let rest = &rest[2..];
+            let (subfolder, fragment) = match rest.find('#') {
+                Some(j) => rest.split_at(j),
+                None => (rest, ""),
+            };
+            let subfolder = subfolder.trim_matches('/');
+            let subfolder = if subfolder.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(subfolder))
+            };
+            (format!("{}{}", uri, fragment), subfolder)
+        }
+        None => (s.to_owned(), None),
+    }
+}
+
 //HACK to support Path -> string -> Path
 fn change_local_path_sep(s: &str) -> String {
     if cfg!(windows) {
@@ -78,6 +149,7 @@ impl Default for SourceUri {
             raw: ".".to_owned(),
             path: PathBuf::from("."),
             host: None,
+            subfolder: None,
         }
     }
 }
@@ -92,6 +164,7 @@ mod tests {
             raw: s.to_owned(),
             path: PathBuf::from(path.to_owned()),
             host: host.map(|s| s.into()),
+            subfolder: None,
         });
     }
 
@@ -141,6 +214,82 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_source_uri_from_str_http_archive() {
+        assert_source_uri_from_str(
+            "https://example.com/releases/v1.0/tmpl.tar.gz",
+            "releases/v1.0/tmpl.tar.gz",
+            Some("example.com"),
+        );
+        let sha256 = "185f8db32271fe25f561a6fc938b2e264306ec304eda518007d1764826381969";
+        let raw = format!("https://example.com/tmpl.zip#sha256={}", sha256);
+        let uri = SourceUri::from_str(&raw).unwrap();
+        assert_that!(&uri.path).is_equal_to(&PathBuf::from("tmpl.zip"));
+        assert_that!(uri.is_archive()).is_true();
+        assert_that!(uri.url()).is_equal_to("https://example.com/tmpl.zip");
+        assert_that!(uri.checksum()).is_equal_to(Some(sha256));
+        let uri = SourceUri::from_str("https://github.com/ffizer/ffizer.git").unwrap();
+        assert_that!(uri.is_archive()).is_false();
+        assert_that!(uri.checksum()).is_equal_to(None);
+    }
+
+    #[test]
+    fn test_source_uri_from_str_http_raw_file() {
+        assert_source_uri_from_str(
+            "raw+https://artifacts.example.com/templates/README.md.ffizer.hbs",
+            "templates/README.md.ffizer.hbs",
+            Some("artifacts.example.com"),
+        );
+        let uri = SourceUri::from_str("raw+http://example.com:8080/LICENSE").unwrap();
+        assert_that!(uri.is_raw_file()).is_true();
+        assert_that!(uri.is_archive()).is_false();
+        assert_that!(uri.url()).is_equal_to("http://example.com:8080/LICENSE");
+        let uri = SourceUri::from_str("https://github.com/ffizer/ffizer").unwrap();
+        assert_that!(uri.is_raw_file()).is_false();
+    }
+
+    #[test]
+    fn test_source_uri_from_str_s3_archive() {
+        assert_source_uri_from_str(
+            "s3://my-templates/rust-cli/v1.0.tar.gz",
+            "rust-cli/v1.0.tar.gz",
+            Some("my-templates"),
+        );
+        let uri = SourceUri::from_str("s3://my-templates/rust-cli/v1.0.tar.gz").unwrap();
+        assert_that!(uri.is_archive()).is_true();
+    }
+
+    #[test]
+    fn test_source_uri_from_str_other_scheme() {
+        assert_source_uri_from_str(
+            "registry://templates.example.com/rust-cli",
+            "rust-cli",
+            Some("templates.example.com"),
+        );
+        assert_source_uri_from_str("file:///foo/bar", "file:///foo/bar", None);
+    }
+
+    #[test]
+    fn test_source_uri_from_str_with_subfolder() {
+        let uri =
+            SourceUri::from_str("https://github.com/org/monorepo.git//templates/rust-cli").unwrap();
+        assert_that!(&uri).is_equal_to(&SourceUri {
+            raw: "https://github.com/org/monorepo.git".to_owned(),
+            path: PathBuf::from("org/monorepo"),
+            host: Some("github.com".to_owned()),
+            subfolder: Some(PathBuf::from("templates/rust-cli")),
+        });
+        let uri = SourceUri::from_str("git@github.com:org/monorepo.git//templates/").unwrap();
+        assert_that!(&uri.raw).is_equal_to("git@github.com:org/monorepo.git".to_owned());
+        assert_that!(&uri.subfolder).is_equal_to(Some(PathBuf::from("templates")));
+        let uri =
+            SourceUri::from_str("https://example.com/tmpl.zip//tmpl-1.0#sha256=abcd").unwrap();
+        assert_that!(&uri.raw).is_equal_to("https://example.com/tmpl.zip#sha256=abcd".to_owned());
+        assert_that!(&uri.subfolder).is_equal_to(Some(PathBuf::from("tmpl-1.0")));
+        // `//` in local path is not a subfolder
+        assert_source_uri_from_str("/foo//bar", "/foo//bar", None);
+    }
+
     #[test]
     fn test_source_uri_from_str_http_with_git_extension_and_username() {
         assert_source_uri_from_str(
@@ -0,0 +1,238 @@
+//! helpers to test a template from rust (eg integration tests of a repository of templates), enabled
+//! with the feature `testing`: the template is applied into a temporary folder (without question),
+//! then the generated tree and the content of the files are checked.
+//!
+//! ```no_run
+//! use ffizer::testing::TemplateTest;
+//!
+//! let applied = TemplateTest::new("./my-template")?
+//!     .variable("project_name", "demo")
+//!     .apply()?;
+//! applied.assert_exists("Cargo.toml");
+//! applied.assert_contains("Cargo.toml", r#"name = "demo""#);
+//! applied.assert_same_tree("./tests/expected/demo");
+//! # Ok::<(), ffizer::error::Error>(())
+//! ```
+
+use crate::error::*;
+use crate::tools::dir_diff_list;
+use crate::{ApplyOpts, AskConfirmation, Ctx, SourceLoc, SourceUri, UpdateMode};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tempfile::{tempdir, TempDir};
+
+/// a template to apply with its answers (the variables not provided use their default value)
+#[derive(Debug, Clone)]
+pub struct TemplateTest {
+    src: SourceLoc,
+    variables: Vec<(String, String)>,
+    existing: Option<PathBuf>,
+    offline: bool,
+}
+
+impl TemplateTest {
+    /// the template at `uri` (a local folder, a git repository,...)
+    pub fn new(uri: &str) -> Result<TemplateTest> {
+        Ok(TemplateTest {
+            src: SourceLoc {
+                uri: SourceUri::from_str(uri)?,
+                rev: "master".to_owned(),
+                ..Default::default()
+            },
+            variables: vec![],
+            existing: None,
+            offline: false,
+        })
+    }
+
+    /// the answer of the variable `name` (parsed as yaml, like `--variables name=value`)
+    pub fn variable(mut self, name: &str, value: &str) -> TemplateTest {
+        self.variables.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// the rev (git) of the template
+    pub fn rev(mut self, rev: &str) -> TemplateTest {
+        self.src.rev = rev.to_owned();
+        self
+    }
+
+    /// the folder of the template under its uri
+    pub fn subfolder<P: AsRef<Path>>(mut self, subfolder: P) -> TemplateTest {
+        self.src.subfolder = Some(subfolder.as_ref().to_path_buf());
+        self
+    }
+
+    /// a folder copied into the destination before the apply (to test the update of a project)
+    pub fn existing<P: AsRef<Path>>(mut self, folder: P) -> TemplateTest {
+        self.existing = Some(folder.as_ref().to_path_buf());
+        self
+    }
+
+    /// use only the templates already cached (for the remote templates)
+    pub fn offline(mut self, offline: bool) -> TemplateTest {
+        self.offline = offline;
+        self
+    }
+
+    /// apply the template into a new temporary folder (removed when the result is dropped),
+    /// without question: the existing files are overridden, the scripts are run
+    pub fn apply(&self) -> Result<AppliedTemplate> {
+        let dir = tempdir().map_err(|source| Error::CreateTmpFolder { source })?;
+        if let Some(existing) = &self.existing {
+            crate::tools::copy(existing, dir.path())?;
+        }
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                src: self.src.clone(),
+                dst_folder: dir.path().to_path_buf(),
+                key_value: self.variables.clone(),
+                confirm: AskConfirmation::Never,
+                update_mode: UpdateMode::Override,
+                no_interaction: true,
+                offline: self.offline,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        crate::process(&ctx)?;
+        Ok(AppliedTemplate { dir })
+    }
+}
+
+/// the folder generated by [`TemplateTest::apply`], the `assert_*` panic with the differences
+#[derive(Debug)]
+pub struct AppliedTemplate {
+    dir: TempDir,
+}
+
+impl AppliedTemplate {
+    /// the path of the generated folder
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// the paths (relative, sorted) of the files and folders generated
+    pub fn list(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for entry in walkdir::WalkDir::new(self.path()).min_depth(1) {
+            let entry = entry?;
+            paths.push(entry.path().strip_prefix(self.path())?.to_path_buf());
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// the content of the generated file
+    pub fn read<P: AsRef<Path>>(&self, relative: P) -> Result<String> {
+        let path = self.path().join(relative);
+        fs::read_to_string(&path).map_err(|source| Error::ReadFile { path, source })
+    }
+
+    pub fn assert_exists<P: AsRef<Path>>(&self, relative: P) {
+        let relative = relative.as_ref();
+        assert!(
+            self.path().join(relative).exists(),
+            "{:?} is not generated, generated: {:#?}",
+            relative,
+            self.list().unwrap_or_default()
+        );
+    }
+
+    pub fn assert_not_exists<P: AsRef<Path>>(&self, relative: P) {
+        let relative = relative.as_ref();
+        assert!(
+            !self.path().join(relative).exists(),
+            "{:?} is generated",
+            relative
+        );
+    }
+
+    /// the generated file has exactly the `expected` content
+    pub fn assert_content<P: AsRef<Path>>(&self, relative: P, expected: &str) {
+        let relative = relative.as_ref();
+        let actual = self
+            .read(relative)
+            .unwrap_or_else(|err| panic!("{:?} can't be read: {}", relative, err));
+        assert_eq!(actual, expected, "unexpected content of {:?}", relative);
+    }
+
+    /// the generated file contains `expected`
+    pub fn assert_contains<P: AsRef<Path>>(&self, relative: P, expected: &str) {
+        let relative = relative.as_ref();
+        let actual = self
+            .read(relative)
+            .unwrap_or_else(|err| panic!("{:?} can't be read: {}", relative, err));
+        assert!(
+            actual.contains(expected),
+            "{:?} doesn't contain {:?}, content:\n{}",
+            relative,
+            expected,
+            actual
+        );
+    }
+
+    /// the generated folder has the same files and folders, with the same contents, than `expected`
+    pub fn assert_same_tree<P: AsRef<Path>>(&self, expected: P) {
+        let diffs = dir_diff_list::search_diff(self.path(), expected.as_ref())
+            .unwrap_or_else(|err| panic!("fail to compare with {:?}: {}", expected.as_ref(), err));
+        assert!(diffs.is_empty(), "differences: {:#?}", diffs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    fn make_template() -> Result<TempDir, Box<dyn std::error::Error>> {
+        let template = tempdir()?;
+        fs::write(
+            template.path().join(".ffizer.yaml"),
+            "variables:\n  - name: project\n    default_value: demo\n",
+        )?;
+        fs::create_dir_all(template.path().join("src"))?;
+        fs::write(
+            template.path().join("README.md.ffizer.hbs"),
+            "# {{ project }}\n",
+        )?;
+        fs::write(template.path().join("src/main.rs"), "fn main() {}\n")?;
+        Ok(template)
+    }
+
+    #[test]
+    fn test_apply_and_assert() -> Result<(), Box<dyn std::error::Error>> {
+        let template = make_template()?;
+        let uri = template.path().to_str().expect("template path as str");
+        let applied = TemplateTest::new(uri)?.apply()?;
+        applied.assert_content("README.md", "# demo\n");
+        applied.assert_exists("src/main.rs");
+        applied.assert_not_exists(".ffizer.yaml");
+        assert_that!(applied.list()?).is_equal_to(vec![
+            PathBuf::from("README.md"),
+            PathBuf::from("src"),
+            PathBuf::from("src/main.rs"),
+        ]);
+
+        let applied = TemplateTest::new(uri)?.variable("project", "foo").apply()?;
+        applied.assert_contains("README.md", "foo");
+        let expected = tempdir()?;
+        fs::create_dir_all(expected.path().join("src"))?;
+        fs::write(expected.path().join("README.md"), "# foo\n")?;
+        fs::write(expected.path().join("src/main.rs"), "fn main() {}\n")?;
+        applied.assert_same_tree(expected.path());
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected content")]
+    fn test_assert_content_fails() {
+        let template = make_template().expect("create template");
+        let uri = template.path().to_str().expect("template path as str");
+        let applied = TemplateTest::new(uri)
+            .and_then(|t| t.apply())
+            .expect("apply template");
+        applied.assert_content("README.md", "# other\n");
+    }
+}
@@ -102,14 +102,14 @@ pub fn search_diff<A: AsRef<Path>, B: AsRef<Path>>(
             // asserting(&format!("test content of {:?} vs {:?}", a, b))
             //     .that(&read_to_vec(a.path())?)
             //     .is_equal_to(&read_to_vec(b.path())?);
-            let actual_content = fs::read_to_string(actual_entry.path())?.replace("\r\n", "\n");
-            let expect_content = fs::read_to_string(expect_entry.path())?.replace("\r\n", "\n");
-            if actual_content != expect_content {
+            let actual_content = fs::read(actual_entry.path())?;
+            let expect_content = fs::read(expect_entry.path())?;
+            if !is_same_content(&actual_content, &expect_content) {
                 add_diff(
                     &expect_rpath,
                     Difference::StringContent {
-                        actual: actual_content,
-                        expect: expect_content,
+                        actual: String::from_utf8_lossy(&actual_content).into_owned(),
+                        expect: String::from_utf8_lossy(&expect_content).into_owned(),
                     },
                 );
                 actual_index += 1;
@@ -156,6 +156,15 @@ pub fn search_diff<A: AsRef<Path>, B: AsRef<Path>>(
     Ok(differences)
 }
 
+/// the texts are compared with the end of lines normalized, the other contents (binary or not
+/// encoded in UTF-8) are compared as is
+fn is_same_content(actual: &[u8], expect: &[u8]) -> bool {
+    match (std::str::from_utf8(actual), std::str::from_utf8(expect)) {
+        (Ok(actual), Ok(expect)) => actual.replace("\r\n", "\n") == expect.replace("\r\n", "\n"),
+        _ => actual == expect,
+    }
+}
+
 fn walk_dir<P: AsRef<Path>>(path: P) -> Result<Vec<DirEntry>, walkdir::Error> {
     WalkDir::new(path).sort_by(compare).into_iter().collect()
 }
@@ -149,6 +149,7 @@ impl SampleRun {
         let ctx = crate::Ctx {
             logger,
             cmd_opt: sample.args.clone(),
+            ..Default::default()
         };
         crate::process(&ctx)?;
         let diffs = dir_diff_list::search_diff(destination, &sample.expected)?;
@@ -0,0 +1,131 @@
+use crate::error::*;
+use crate::git;
+use difference::{Changeset, Difference};
+use std::path::Path;
+
+/// number of unchanged lines displayed around the changes
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DiffLine {
+    /// header of a hunk (eg `@@ -1,3 +1,4 @@`)
+    Hunk(String),
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+impl DiffLine {
+    fn is_change(&self) -> bool {
+        matches!(self, DiffLine::Removed(_) | DiffLine::Added(_))
+    }
+}
+
+/// compute the unified diff (lines of `local` replaced by the lines of `remote`)
+pub(crate) fn unified_diff(local: &str, remote: &str) -> Vec<DiffLine> {
+    // the last newline doesn't start an other line
+    let trim = |s: &str| s.strip_suffix('\n').unwrap_or(s).to_owned();
+    let changeset = Changeset::new(&trim(local), &trim(remote), "\n");
+    let mut lines = vec![];
+    for diff in changeset.diffs {
+        let (text, f): (String, fn(String) -> DiffLine) = match diff {
+            Difference::Same(s) => (s, DiffLine::Same),
+            Difference::Rem(s) => (s, DiffLine::Removed),
+            Difference::Add(s) => (s, DiffLine::Added),
+        };
+        lines.extend(text.split('\n').map(|l| f(l.to_owned())));
+    }
+    // ranges of lines to display: the changes with their context
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for (i, _) in lines.iter().enumerate().filter(|(_, l)| l.is_change()) {
+        let start = i.saturating_sub(CONTEXT_LINES);
+        let end = (i + CONTEXT_LINES + 1).min(lines.len());
+        match ranges.last_mut() {
+            Some(last) if last.1 >= start => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    let mut out = vec![];
+    for (start, end) in ranges {
+        let count = |range: &[DiffLine], removed: bool| {
+            range
+                .iter()
+                .filter(|l| match l {
+                    DiffLine::Same(_) => true,
+                    DiffLine::Removed(_) => removed,
+                    DiffLine::Added(_) => !removed,
+                    DiffLine::Hunk(_) => false,
+                })
+                .count()
+        };
+        let (before, hunk) = (&lines[..start], &lines[start..end]);
+        out.push(DiffLine::Hunk(format!(
+            "@@ -{},{} +{},{} @@",
+            count(before, true) + 1,
+            count(hunk, true),
+            count(before, false) + 1,
+            count(hunk, false)
+        )));
+        out.extend_from_slice(hunk);
+    }
+    out
+}
+
+/// launch the diff tool (defined in the git's configuration) to compare the `local` file
+/// with the `remote` content
+pub(crate) fn launch_diff_tool(local: &Path, remote: &[u8]) -> Result<()> {
+    let diff_cmd = git::find_cmd_tool("diff").map_err(|source| Error::GitFindConfig {
+        key: "diff".into(),
+        source,
+    })?;
+    let tmp_dir = tempfile::tempdir().map_err(|source| Error::CreateFolder {
+        path: std::env::temp_dir(),
+        source,
+    })?;
+    let remote_path = tmp_dir.path().join(
+        local
+            .file_name()
+            .map(|n| format!("{}.REMOTE", n.to_string_lossy()))
+            .unwrap_or_else(|| "REMOTE".to_owned()),
+    );
+    std::fs::write(&remote_path, remote).map_err(|source| Error::WriteFile {
+        path: remote_path.clone(),
+        source,
+    })?;
+    let cmd_all = diff_cmd
+        .replace("$LOCAL", &local.to_string_lossy())
+        .replace("$REMOTE", &remote_path.to_string_lossy());
+    let cmd = cmd_all.split(' ').collect::<Vec<_>>();
+    std::process::Command::new(cmd[0])
+        .args(&cmd[1..])
+        .status()
+        .map_err(|source| Error::RunCommand {
+            cmd: cmd_all,
+            source,
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let local = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let remote = "1\n2\n3\n4\n5\n6\nseven\n8\n9\n10\n";
+        assert_that!(unified_diff(local, remote)).is_equal_to(vec![
+            DiffLine::Hunk("@@ -4,7 +4,7 @@".to_owned()),
+            DiffLine::Same("4".to_owned()),
+            DiffLine::Same("5".to_owned()),
+            DiffLine::Same("6".to_owned()),
+            DiffLine::Removed("7".to_owned()),
+            DiffLine::Added("seven".to_owned()),
+            DiffLine::Same("8".to_owned()),
+            DiffLine::Same("9".to_owned()),
+            DiffLine::Same("10".to_owned()),
+        ]);
+        assert_that!(unified_diff(local, local)).is_empty();
+    }
+}
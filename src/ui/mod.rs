@@ -1,20 +1,32 @@
+mod diff;
 mod tree;
 
+use crate::auth::HostAuth;
+use crate::cfg::{FeatureDef, FEATURES_VARIABLE};
+use crate::changelog::TemplateChangelog;
+use crate::check::Drift;
 use crate::cli_opt::*;
 use crate::error::*;
-use crate::variable_def::VariableDef;
+use crate::fingerprint::Fingerprint;
+use crate::hbs_helpers::new_hbs;
+use crate::manifest::OwnershipChange;
+use crate::upgrade::UpgradeOutcome;
+use crate::variable_def::{SectionDef, VariableDef, VariableType};
 use crate::FileOperation;
-use crate::{Action, Ctx, Variables};
+use crate::Ownership;
+use crate::{Action, Ctx, ExecuteSummary, PlanDiff, SkippedSource, Variables};
 use console::Style;
 use console::Term;
 use dialoguer::Confirm;
 use dialoguer::Input;
+use dialoguer::MultiSelect;
+use dialoguer::Password;
 use dialoguer::Select;
-use handlebars_misc_helpers::new_hbs;
 use lazy_static::lazy_static;
 use serde_yaml::Value;
 use slog::debug;
 use std::borrow::Cow;
+use std::path::PathBuf;
 
 lazy_static! {
     static ref TERM: Term = Term::stdout();
@@ -26,6 +38,27 @@ fn write_title(s: &str) -> Result<()> {
     Ok(())
 }
 
+/// write the title of the section, then its intro (rendered with the `variables`)
+fn write_section(
+    handlebars: &handlebars::Handlebars,
+    variables: &Variables,
+    section: &SectionDef,
+) -> Result<()> {
+    TERM.write_line(&format!("\n{}", TITLE_STYLE.apply_to(&section.title)))?;
+    if let Some(intro) = &section.intro {
+        let intro = handlebars
+            .render_template(intro, variables)
+            .map_err(|source| Error::Handlebars {
+                when: format!("define intro of section '{}'", &section.name),
+                template: intro.clone(),
+                source,
+            })?;
+        TERM.write_line(intro.trim_end())?;
+    }
+    TERM.write_line("")?;
+    Ok(())
+}
+
 pub struct VariableResponse {
     value: String,
     idx: Option<usize>,
@@ -35,6 +68,8 @@ pub struct VariableRequest {
     prompt: String,
     default_value: Option<VariableResponse>,
     values: Vec<String>,
+    /// ask without echo
+    secret: bool,
 }
 
 pub fn ask_variables(
@@ -46,17 +81,53 @@ pub fn ask_variables(
     variables.append(&mut init);
     let handlebars = new_hbs();
     write_title("Configure variables")?;
+    // the section of the last question asked
+    let mut current_section: Option<String> = None;
     // TODO optimize to reduce clones
     for variable in list_variables.iter().cloned() {
         let name = variable.name;
-        if variables.contains_key(&name) {
+        if variable.secret {
+            variables.mark_secret(name.clone());
+        }
+        if let Some(value) = variables.get(&name).cloned() {
+            if variable.value_type == VariableType::Bool {
+                let value = to_bool(&name, &value_to_string(&value))?;
+                variables.insert(name, value)?;
+            } else if variable.value_type == VariableType::List {
+                variables.insert(name, to_list(value))?;
+            } else if !variable.select_in_values.is_empty() {
+                let values = values_for_selection(&variable.select_in_values)?;
+                let idx = find_idx_in_values(&name, &values, &value_to_string(&value))?;
+                variables.insert(format!("{}__idx", name), idx)?;
+            }
             continue;
         }
+        // the prompt and the default value of a namespaced variable are rendered with the variables
+        // of its namespace (without prefix)
+        let scoped = variable.namespace.as_ref().map(|namespace| {
+            let mut scoped = variables.clone();
+            scoped.append(&mut variables.find_namespaced(namespace));
+            scoped
+        });
+        let render_variables = scoped.as_ref().unwrap_or(&variables);
+        if !variable.hidden && !ctx.cmd_opt.no_interaction {
+            if let Some(section) = &variable.section {
+                if current_section.as_ref() != Some(&section.name) {
+                    write_section(&handlebars, render_variables, section)?;
+                }
+            }
+            current_section = variable.section.as_ref().map(|s| s.name.clone());
+        }
+        let default_list = if variable.value_type == VariableType::List {
+            default_list(&handlebars, render_variables, &variable.default_value)?
+        } else {
+            vec![]
+        };
         let request = {
             let prompt = if variable.ask.is_some() {
                 let ask = variable.ask.expect("variable ask should defined");
                 handlebars
-                    .render_template(&ask, &variables)
+                    .render_template(&ask, render_variables)
                     .map_err(|source| Error::Handlebars {
                         when: format!("define prompt for '{}'", &name),
                         template: ask.clone(),
@@ -65,11 +136,7 @@ pub fn ask_variables(
             } else {
                 name.clone()
             };
-            let values: Vec<String> = variable
-                .select_in_values
-                .iter()
-                .map(|v| serde_yaml::from_value(v.clone()).map_err(Error::from))
-                .collect::<Result<Vec<String>>>()?;
+            let values = values_for_selection(&variable.select_in_values)?;
             //TODO ValuesForSelection::Empty => vec![],
             // ValuesForSelection::Sequence(v) => v.clone(),
             // ValuesForSelection::String(s) => {
@@ -96,7 +163,7 @@ pub fn ask_variables(
                 })
                 .and_then(|tmpl| {
                     handlebars
-                        .render_template(&tmpl, &variables)
+                        .render_template(&tmpl, render_variables)
                         //TODO better manage error
                         // .context(crate::Handlebars {
                         //     when: format!("define default_value for '{}'", &name),
@@ -105,24 +172,46 @@ pub fn ask_variables(
                         .ok()
                 })
                 .map(|value| {
+                    // string default value are quoted (to be parsed as string)
                     let idx = values
                         .iter()
-                        .enumerate()
-                        .filter_map(|(i, v)| if v == &value { Some(i) } else { None })
-                        .next();
+                        .position(|v| v == &value || format!("\"{}\"", v) == value);
                     VariableResponse { value, idx }
                 });
             VariableRequest {
                 prompt,
                 values,
                 default_value,
+                secret: variable.secret,
             }
         };
+        if variable.value_type == VariableType::Bool {
+            let default_value = request
+                .default_value
+                .map(|v| to_bool(&name, v.value.trim_matches('"')))
+                .transpose()?;
+            let value = if variable.hidden || ctx.cmd_opt.no_interaction {
+                default_value.unwrap_or(false)
+            } else {
+                ask_bool_value(&request.prompt, default_value)?
+            };
+            variables.insert(name, value)?;
+            continue;
+        }
+        if variable.value_type == VariableType::List {
+            let value = if variable.hidden || ctx.cmd_opt.no_interaction {
+                default_list
+            } else {
+                ask_list_value(&request.prompt, default_list)?
+            };
+            variables.insert(name, value)?;
+            continue;
+        }
         let resp = if variable.hidden || ctx.cmd_opt.no_interaction {
-            request.default_value.unwrap_or(VariableResponse {
-                value: "".to_owned(),
-                idx: None,
-            })
+            if !variable.hidden && request.default_value.is_none() && request.values.is_empty() {
+                return Err(Error::MissingVariable { name });
+            }
+            select_without_interaction(&name, request)?
         } else {
             ask_variable_value(request)?
         };
@@ -134,8 +223,196 @@ pub fn ask_variables(
     Ok(variables)
 }
 
+/// ask the features to select (multi-select), the selection is stored into the variable `features`
+/// (a map of `<name>: <selected>`), a selection already defined (eg from cli `features=docker,ci`) is not asked
+pub(crate) fn ask_features(
+    ctx: &Ctx,
+    features: &[FeatureDef],
+    variables: &mut Variables,
+) -> Result<Vec<String>> {
+    if features.is_empty() {
+        return Ok(vec![]);
+    }
+    let names = features.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+    let selected = match variables.get(FEATURES_VARIABLE).cloned() {
+        Some(value) => {
+            let selected = to_feature_names(value);
+            if let Some(unknown) = selected.iter().find(|n| !names.contains(n)) {
+                return Err(Error::StringValueNotIn {
+                    value_name: FEATURES_VARIABLE.to_owned(),
+                    value: unknown.clone(),
+                    accepted: names,
+                });
+            }
+            selected
+        }
+        None if ctx.cmd_opt.no_interaction => features
+            .iter()
+            .filter(|f| f.default)
+            .map(|f| f.name.clone())
+            .collect(),
+        None => {
+            write_title("Select features")?;
+            let items = features
+                .iter()
+                .map(|f| (f.ask.clone().unwrap_or_else(|| f.name.clone()), f.default))
+                .collect::<Vec<_>>();
+            MultiSelect::new()
+                .with_prompt("features (space to toggle, enter to confirm)")
+                .items_checked(&items)
+                .interact()?
+                .into_iter()
+                .map(|idx| names[idx].clone())
+                .collect()
+        }
+    };
+    let mut selection = serde_yaml::Mapping::new();
+    for name in &names {
+        selection.insert(
+            Value::String(name.clone()),
+            Value::Bool(selected.contains(name)),
+        );
+    }
+    variables.insert(FEATURES_VARIABLE, Value::Mapping(selection))?;
+    Ok(selected)
+}
+
+/// the names of the selected features from a list, a string (comma separated) or a map of `<name>: <selected>`
+fn to_feature_names(value: Value) -> Vec<String> {
+    match value {
+        Value::Mapping(m) => m
+            .into_iter()
+            .filter(|(_, v)| v == &Value::Bool(true))
+            .map(|(k, _)| value_to_string(&k))
+            .collect(),
+        Value::String(s) => s
+            .split(',')
+            .map(|n| n.trim())
+            .filter(|n| !n.is_empty())
+            .map(|n| n.to_owned())
+            .collect(),
+        v => to_list(v).iter().map(value_to_string).collect(),
+    }
+}
+
+fn values_for_selection(select_in_values: &[Value]) -> Result<Vec<String>> {
+    select_in_values
+        .iter()
+        .map(|v| serde_yaml::from_value(v.clone()).map_err(Error::from))
+        .collect::<Result<Vec<String>>>()
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(ref v) => v.clone(),
+        Value::Bool(ref v) => format!("{}", v),
+        Value::Number(ref v) => format!("{}", v),
+        v => serde_yaml::to_string(v)
+            .map(|s| s.trim_start_matches("---").trim().to_owned())
+            .unwrap_or_default(),
+    }
+}
+
+fn to_bool(name: &str, value: &str) -> Result<bool> {
+    match Variables::value_from_str(value)? {
+        Value::Bool(b) => Ok(b),
+        _ => Err(Error::StringValueNotIn {
+            value_name: name.to_owned(),
+            value: value.to_owned(),
+            accepted: vec!["true".to_owned(), "false".to_owned()],
+        }),
+    }
+}
+
+/// a sequence is kept as is, an empty string is an empty list, other value is a list of one value
+fn to_list(value: Value) -> Vec<Value> {
+    match value {
+        Value::Sequence(v) => v,
+        Value::Null => vec![],
+        Value::String(ref v) if v.is_empty() => vec![],
+        v => vec![v],
+    }
+}
+
+/// the default value of a list could be a yaml list (of templates) or a template rendered as a yaml list
+fn default_list(
+    handlebars: &handlebars::Handlebars,
+    variables: &Variables,
+    default_value: &Option<Value>,
+) -> Result<Vec<Value>> {
+    let render = |tmpl: &str| {
+        handlebars
+            .render_template(tmpl, variables)
+            .map_err(|source| Error::Handlebars {
+                when: "define default_value of list".to_owned(),
+                template: tmpl.to_owned(),
+                source,
+            })
+    };
+    match default_value {
+        None => Ok(vec![]),
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .map(|v| match v {
+                Value::String(tmpl) => Ok(Value::String(render(tmpl)?)),
+                v => Ok(v.clone()),
+            })
+            .collect(),
+        Some(Value::String(tmpl)) => Ok(to_list(Variables::value_from_str(&render(tmpl)?)?)),
+        Some(v) => Ok(to_list(v.clone())),
+    }
+}
+
+fn find_idx_in_values(name: &str, values: &[String], value: &str) -> Result<usize> {
+    values
+        .iter()
+        .position(|v| v == value)
+        .ok_or_else(|| Error::StringValueNotIn {
+            value_name: name.to_owned(),
+            value: value.to_owned(),
+            accepted: values.to_vec(),
+        })
+}
+
+/// use the default value (or the first value of the selection) when no interaction is possible,
+/// a default value outside the list of values is rejected
+fn select_without_interaction(name: &str, req: VariableRequest) -> Result<VariableResponse> {
+    match req.default_value {
+        None if req.values.is_empty() => Ok(VariableResponse {
+            value: "".to_owned(),
+            idx: None,
+        }),
+        None => Ok(VariableResponse {
+            value: req.values[0].clone(),
+            idx: Some(0),
+        }),
+        Some(resp) if req.values.is_empty() => Ok(resp),
+        Some(resp) => {
+            let idx = match resp.idx {
+                Some(idx) => idx,
+                None => find_idx_in_values(name, &req.values, resp.value.trim_matches('"'))?,
+            };
+            Ok(VariableResponse {
+                value: req.values[idx].clone(),
+                idx: Some(idx),
+            })
+        }
+    }
+}
+
 pub fn ask_variable_value(req: VariableRequest) -> Result<VariableResponse> {
-    if req.values.is_empty() {
+    if req.values.is_empty() && req.secret {
+        let value = Password::new()
+            .with_prompt(&req.prompt)
+            .allow_empty_password(req.default_value.is_some())
+            .interact()?;
+        // the answer is kept as a string (quoted like the default value)
+        let value = match (value.is_empty(), req.default_value) {
+            (true, Some(default_value)) => default_value.value,
+            _ => serde_json::to_string(&value)?,
+        };
+        Ok(VariableResponse { value, idx: None })
+    } else if req.values.is_empty() {
         let mut input = Input::new();
         if let Some(default_value) = req.default_value {
             input.default(default_value.value);
@@ -159,6 +436,52 @@ pub fn ask_variable_value(req: VariableRequest) -> Result<VariableResponse> {
     }
 }
 
+pub fn ask_bool_value(prompt: &str, default_value: Option<bool>) -> Result<bool> {
+    let mut input = Confirm::new();
+    input.with_prompt(prompt);
+    if let Some(default_value) = default_value {
+        input.default(default_value);
+    }
+    input.interact().map_err(Error::from)
+}
+
+/// ask values one by one, until an empty answer
+pub fn ask_list_value(prompt: &str, default_value: Vec<Value>) -> Result<Vec<Value>> {
+    if !default_value.is_empty() {
+        let defaults = default_value
+            .iter()
+            .map(value_to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let keep = Confirm::new()
+            .with_prompt(format!(
+                "{} [{}], keep the default values ?",
+                prompt, defaults
+            ))
+            .default(true)
+            .interact()?;
+        if keep {
+            return Ok(default_value);
+        }
+    }
+    let mut values = vec![];
+    loop {
+        let value: String = Input::new()
+            .with_prompt(format!(
+                "{} (value #{}, empty to finish)",
+                prompt,
+                values.len() + 1
+            ))
+            .allow_empty(true)
+            .interact()?;
+        if value.trim().is_empty() {
+            break;
+        }
+        values.push(Variables::value_from_str(&value)?);
+    }
+    Ok(values)
+}
+
 fn format_operation(op: &FileOperation) -> Cow<'static, str> {
     let s = match op {
         FileOperation::Nothing => "do nothing",
@@ -166,20 +489,30 @@ fn format_operation(op: &FileOperation) -> Cow<'static, str> {
         FileOperation::MkDir => "make dir",
         FileOperation::AddFile => "add file",
         FileOperation::UpdateFile => "update file",
+        FileOperation::Append => "append to file",
+        FileOperation::Patch => "patch file",
+        FileOperation::Merge => "merge into file",
     };
     console::pad_str(s, 15, console::Alignment::Left, Some("..."))
 }
 
 //TODO add flag to filter display: all, changes, none
-pub fn confirm_plan(ctx: &Ctx, actions: &[Action]) -> Result<bool> {
+pub fn confirm_plan(
+    ctx: &Ctx,
+    actions: &mut [Action],
+    skipped: &[SkippedSource],
+    diffs: &[PlanDiff],
+    ignored_by_git: &[PathBuf],
+) -> Result<bool> {
     write_title("Plan to execute")?;
     debug!(ctx.logger, "plan"; "actions" => ?actions);
     let prefixes = tree::provide_prefix(actions, |parent, item| {
         Some(parent.dst_path.relative.as_path()) == item.dst_path.relative.parent()
     });
+    let reason_style = Style::new().dim();
     for (a, prefix) in actions.iter().zip(prefixes.iter()) {
         let p = a.dst_path.base.join(&a.dst_path.relative);
-        let s = format!(
+        let mut s = format!(
             "   - {} \x1B[38;2;{};{};{}m{}\x1B[0m{}",
             format_operation(&a.operation),
             80,
@@ -188,19 +521,309 @@ pub fn confirm_plan(ctx: &Ctx, actions: &[Action]) -> Result<bool> {
             prefix,
             p.file_name().and_then(|v| v.to_str()).unwrap_or("???"),
         );
+        // the reason of the operations on the existing files is always shown
+        if ctx.cmd_opt.explain
+            || a.operation == FileOperation::Nothing
+            || a.operation == FileOperation::Ignore
+            || a.operation == FileOperation::UpdateFile
+        {
+            s.push_str(&format!(
+                "  {}",
+                reason_style.apply_to(format!("({})", a.reason))
+            ));
+        }
         TERM.write_line(&s)?;
+        if ctx.cmd_opt.explain {
+            for overridden in &a.overridden {
+                TERM.write_line(&format!(
+                    "                     {}",
+                    reason_style.apply_to(format!(
+                        "overrides {}",
+                        PathBuf::from(overridden).to_string_lossy()
+                    ))
+                ))?;
+            }
+        }
     }
-    let r = if ctx.cmd_opt.confirm == AskConfirmation::Always {
-        Confirm::new()
+    if ctx.cmd_opt.explain && !skipped.is_empty() {
+        TERM.write_line("")?;
+        TERM.write_line("   Skipped from the templates")?;
+        for s in skipped {
+            TERM.write_line(&format!(
+                "   - {}  {}",
+                PathBuf::from(&s.src).to_string_lossy(),
+                reason_style.apply_to(format!("({})", s.reason))
+            ))?;
+        }
+    }
+    if ctx.cmd_opt.show_diff || ctx.cmd_opt.diff_tool {
+        for d in diffs {
+            show_plan_diff(ctx, d)?;
+        }
+    }
+    if !ignored_by_git.is_empty() {
+        let style = Style::new().yellow();
+        TERM.write_line("")?;
+        for path in ignored_by_git {
+            TERM.write_line(&format!(
+                "   {} {} is ignored by the .gitignore of the destination",
+                style.apply_to("warning:"),
+                path.to_string_lossy()
+            ))?;
+        }
+    }
+    let r = match ctx.cmd_opt.confirm {
+        AskConfirmation::Always => Confirm::new()
             .with_prompt("Do you want to apply plan ?")
-            .interact()?
-    } else {
+            .interact()?,
+        AskConfirmation::PerFile if !ctx.cmd_opt.no_interaction => {
+            ask_update_modes(actions, diffs)?
+        }
         //TODO implement a algo for auto, like if no change then no ask.
-        true
+        _ => true,
     };
     Ok(r)
 }
 
+#[derive(Debug, Clone, PartialEq)]
+enum FileDecision {
+    Apply(UpdateMode),
+    ShowDiff,
+    ApplyToRemaining(UpdateMode),
+    Quit,
+}
+
+/// ask what to do for each existing file to update (like `git add -p`),
+/// return false if the user quit (to not apply the plan)
+fn ask_update_modes(actions: &mut [Action], diffs: &[PlanDiff]) -> Result<bool> {
+    let values = [
+        ("keep - keep existing local file (ignore template)", FileDecision::Apply(UpdateMode::Keep)),
+        ("override - override local file with file from template", FileDecision::Apply(UpdateMode::Override)),
+        ("diff - show diff then ask again", FileDecision::ShowDiff),
+        ("merge - try to merge existing local with remote template via merge tool (defined in the git's configuration)", FileDecision::Apply(UpdateMode::Merge)),
+        ("keep all - keep this file and the remaining files", FileDecision::ApplyToRemaining(UpdateMode::Keep)),
+        ("override all - override this file and the remaining files", FileDecision::ApplyToRemaining(UpdateMode::Override)),
+        ("quit - do not apply the plan", FileDecision::Quit),
+    ];
+    let mut remaining_mode = None;
+    // managed files are always overridden
+    for a in actions.iter_mut().filter(|a| {
+        a.operation == FileOperation::UpdateFile && a.ownership != Some(Ownership::Managed)
+    }) {
+        if let Some(mode) = &remaining_mode {
+            a.update_mode = Some(UpdateMode::clone(mode));
+            continue;
+        }
+        loop {
+            let idx = Select::new()
+                .with_prompt(&format!(
+                    "Modification of {:?} (use arrow + return to select option)",
+                    a.dst_path.relative
+                ))
+                .items(&values.iter().map(|v| v.0).collect::<Vec<_>>())
+                .default(0)
+                .paged(false)
+                .interact()?;
+            match &values[idx].1 {
+                FileDecision::ShowDiff => {
+                    if let Some(d) = diffs.iter().find(|d| d.dst_path == a.dst_path) {
+                        show_unified_diff(d)?;
+                    }
+                }
+                FileDecision::Apply(mode) => {
+                    a.update_mode = Some(mode.clone());
+                    break;
+                }
+                FileDecision::ApplyToRemaining(mode) => {
+                    a.update_mode = Some(mode.clone());
+                    remaining_mode = Some(mode.clone());
+                    break;
+                }
+                FileDecision::Quit => return Ok(false),
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// show the changes of a file to update (as unified diff and/or via the diff tool)
+fn show_plan_diff(ctx: &Ctx, d: &PlanDiff) -> Result<()> {
+    let path = std::path::PathBuf::from(&d.dst_path);
+    TERM.write_line(&format!(
+        "\n{}",
+        TITLE_STYLE.apply_to(d.dst_path.relative.to_string_lossy())
+    ))?;
+    if ctx.cmd_opt.show_diff {
+        show_unified_diff(d)?;
+    }
+    if ctx.cmd_opt.diff_tool {
+        diff::launch_diff_tool(&path, &d.remote)?;
+    }
+    Ok(())
+}
+
+fn show_unified_diff(d: &PlanDiff) -> Result<()> {
+    match (
+        std::str::from_utf8(&d.local),
+        std::str::from_utf8(&d.remote),
+    ) {
+        (Ok(local), Ok(remote)) => {
+            for line in diff::unified_diff(local, remote) {
+                let s = match line {
+                    diff::DiffLine::Hunk(s) => Style::new().cyan().apply_to(s),
+                    diff::DiffLine::Same(s) => Style::new().apply_to(format!(" {}", s)),
+                    diff::DiffLine::Removed(s) => Style::new().red().apply_to(format!("-{}", s)),
+                    diff::DiffLine::Added(s) => Style::new().green().apply_to(format!("+{}", s)),
+                };
+                TERM.write_line(&s.to_string())?;
+            }
+        }
+        _ => TERM.write_line("   binary files differ")?,
+    }
+    Ok(())
+}
+
+pub(crate) fn show_summary(ctx: &Ctx, summary: &ExecuteSummary) -> Result<()> {
+    debug!(ctx.logger, "summary"; "summary" => ?summary);
+    let filtered = if summary.filtered > 0 {
+        format!(", {} filtered", summary.filtered)
+    } else {
+        "".to_owned()
+    };
+    TERM.write_line(&format!(
+        "   {} created, {} updated, {} skipped{} in {}",
+        summary.created,
+        summary.updated,
+        summary.skipped,
+        filtered,
+        indicatif::HumanDuration(summary.elapsed),
+    ))?;
+    Ok(())
+}
+
+pub(crate) fn show_fingerprint(
+    ctx: &Ctx,
+    fingerprint: &Fingerprint,
+    previous: Option<&Fingerprint>,
+) -> Result<()> {
+    debug!(ctx.logger, "fingerprint"; "hash" => &fingerprint.hash, "previous" => previous.map(|p| &p.hash));
+    let comparison = match previous {
+        None => "",
+        Some(p) if p.hash == fingerprint.hash => " (same as the previous apply)",
+        Some(_) => " (changed since the previous apply)",
+    };
+    TERM.write_line(&format!(
+        "   fingerprint {}{}",
+        &fingerprint.hash[..fingerprint.hash.len().min(12)],
+        comparison
+    ))?;
+    Ok(())
+}
+
+/// show the changes of the templates since the commits applied (before confirming the plan)
+pub(crate) fn show_changelogs(ctx: &Ctx, changelogs: &[TemplateChangelog]) -> Result<()> {
+    if changelogs.is_empty() {
+        return Ok(());
+    }
+    debug!(ctx.logger, "changelogs"; "changelogs" => ?changelogs);
+    write_title("Changes of the templates")?;
+    let dim = Style::new().dim();
+    for changelog in changelogs {
+        TERM.write_line(&format!(
+            "   {} {}",
+            changelog.uri,
+            dim.apply_to(format!(
+                "({}..{})",
+                &changelog.from[..changelog.from.len().min(7)],
+                &changelog.to[..changelog.to.len().min(7)]
+            ))
+        ))?;
+        for commit in &changelog.commits {
+            TERM.write_line(&format!("   - {}", commit))?;
+        }
+        if let Some(excerpt) = &changelog.excerpt {
+            for line in excerpt.lines() {
+                TERM.write_line(&format!("     {}", line))?;
+            }
+        }
+        if changelog.commits.is_empty() && changelog.excerpt.is_none() {
+            TERM.write_line(&format!(
+                "     {}",
+                dim.apply_to("no log or changelog available")
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// ask to confirm the upgrade (only with `--confirm always`)
+pub(crate) fn confirm_upgrade(ctx: &Ctx) -> Result<bool> {
+    let r = match ctx.cmd_opt.confirm {
+        AskConfirmation::Always if !ctx.cmd_opt.no_interaction => Confirm::new()
+            .with_prompt("Do you want to upgrade ?")
+            .interact()?,
+        _ => true,
+    };
+    Ok(r)
+}
+
+pub(crate) fn show_upgrade_outcomes(
+    ctx: &Ctx,
+    outcomes: &[(std::path::PathBuf, UpgradeOutcome)],
+) -> Result<()> {
+    debug!(ctx.logger, "upgrade outcomes"; "outcomes" => ?outcomes);
+    write_title("Upgrade")?;
+    for (path, outcome) in outcomes {
+        let style = if *outcome == UpgradeOutcome::Conflicted {
+            Style::new().red()
+        } else {
+            Style::new().green()
+        };
+        let outcome_str = style.apply_to(format!("{:>10}", outcome));
+        TERM.write_line(&format!("   {} {}", outcome_str, path.to_string_lossy()))?;
+    }
+    let conflicts = outcomes
+        .iter()
+        .filter(|(_, o)| *o == UpgradeOutcome::Conflicted)
+        .count();
+    if conflicts > 0 {
+        TERM.write_line(&format!(
+            "   {} file(s) with conflicts to resolve (see the markers `<<<<<<<`)",
+            conflicts
+        ))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn show_drifts(ctx: &Ctx, drifts: &[(std::path::PathBuf, Drift)]) -> Result<()> {
+    debug!(ctx.logger, "drifts"; "drifts" => ?drifts);
+    write_title("Check")?;
+    for (path, drift) in drifts {
+        let drift_str = Style::new().red().apply_to(format!("{:>10}", drift));
+        TERM.write_line(&format!("   {} {}", drift_str, path.to_string_lossy()))?;
+    }
+    if drifts.is_empty() {
+        TERM.write_line("   no drift, the project is up to date with its template")?;
+    }
+    Ok(())
+}
+
+pub(crate) fn show_ownership_changes(ctx: &Ctx, changes: &[OwnershipChange]) -> Result<()> {
+    debug!(ctx.logger, "ownership changes"; "changes" => ?changes);
+    for change in changes {
+        TERM.write_line(&format!(
+            "   ownership of {} changed: {} -> {}",
+            change.path.to_string_lossy(),
+            change.previous,
+            change
+                .next
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| "none".to_owned()),
+        ))?;
+    }
+    Ok(())
+}
+
 pub fn show_difference<P>(local: P, remote: P) -> Result<()>
 where
     P: AsRef<std::path::Path>,
@@ -247,6 +870,91 @@ where
     Ok(values[idx].1.clone())
 }
 
+/// ask the user to select a template (`labels`: its name and its description), searched by a query
+/// (fuzzy), return the index of the label selected
+pub fn ask_template(labels: &[String]) -> Result<usize> {
+    write_title("Select a template")?;
+    loop {
+        let query: String = Input::new()
+            .with_prompt("Search (empty to list all)")
+            .allow_empty(true)
+            .interact()?;
+        let matches = crate::registry::fuzzy_filter(&query, labels);
+        if matches.is_empty() {
+            TERM.write_line(&format!("no template matches {:?}", query))?;
+            continue;
+        }
+        let mut items = matches
+            .iter()
+            .map(|i| labels[*i].as_str())
+            .collect::<Vec<_>>();
+        items.push("(search again)");
+        let idx = Select::new()
+            .with_prompt("Template (use arrow + return to select option)")
+            .items(&items)
+            .default(0)
+            .paged(true)
+            .interact()?;
+        if let Some(i) = matches.get(idx) {
+            return Ok(*i);
+        }
+    }
+}
+
+/// ask the credentials to access the `host` (after a failure of authentication)
+pub fn ask_host_auth(host: &str) -> Result<HostAuth> {
+    write_title(&format!("Authentication to {}", host))?;
+    let methods = [
+        "token (personal access token, used as password)",
+        "username & password",
+        "ssh key",
+    ];
+    let idx = Select::new()
+        .with_prompt("Authentication method (use arrow + return to select option)")
+        .items(&methods)
+        .default(0)
+        .paged(false)
+        .interact()?;
+    let mut auth = HostAuth {
+        host: host.to_owned(),
+        ..Default::default()
+    };
+    match idx {
+        0 => {
+            auth.token = Some(Password::new().with_prompt("token").interact()?);
+        }
+        1 => {
+            auth.username = Some(Input::new().with_prompt("username").interact()?);
+            auth.password = Some(Password::new().with_prompt("password").interact()?);
+        }
+        _ => {
+            let ssh_key: String = Input::new()
+                .with_prompt("path of the private key")
+                .interact()?;
+            auth.ssh_key = Some(std::path::PathBuf::from(ssh_key));
+            let passphrase = Password::new()
+                .with_prompt("passphrase (empty if none)")
+                .allow_empty_password(true)
+                .interact()?;
+            auth.password = Some(passphrase).filter(|p| !p.is_empty());
+        }
+    }
+    Ok(auth)
+}
+
+/// ask to save the credentials (in plain text) into the file `path`, for the next retrievals
+pub fn confirm_save_host_auth(host: &str, path: &std::path::Path) -> Result<bool> {
+    Confirm::new()
+        .with_prompt(format!(
+            "Save the credentials of {} into {} (in plain text) ?",
+            host,
+            path.display()
+        ))
+        .default(false)
+        .interact()
+        .map_err(Error::from)
+}
+
 pub fn show_message(
     _ctx: &Ctx,
     template_name: impl std::fmt::Display,
@@ -285,3 +993,85 @@ pub fn confirm_run_script(
             .map_err(Error::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    fn new_request(default_value: Option<&str>, values: &[&str]) -> VariableRequest {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        VariableRequest {
+            prompt: "license".to_owned(),
+            default_value: default_value.map(|value| VariableResponse {
+                value: value.to_owned(),
+                idx: values.iter().position(|v| v == value),
+            }),
+            values,
+            secret: false,
+        }
+    }
+
+    #[test]
+    fn test_select_without_interaction_use_default_value() {
+        let req = new_request(Some("Apache-2.0"), &["MIT", "Apache-2.0", "GPL"]);
+        let resp = select_without_interaction("license", req).unwrap();
+        assert_that!(&resp.value).is_equal_to("Apache-2.0".to_owned());
+        assert_that!(&resp.idx).is_equal_to(Some(1));
+    }
+
+    #[test]
+    fn test_select_without_interaction_use_first_value_without_default() {
+        let req = new_request(None, &["MIT", "Apache-2.0", "GPL"]);
+        let resp = select_without_interaction("license", req).unwrap();
+        assert_that!(&resp.value).is_equal_to("MIT".to_owned());
+        assert_that!(&resp.idx).is_equal_to(Some(0));
+    }
+
+    #[test]
+    fn test_select_without_interaction_reject_default_value_not_in_values() {
+        let req = new_request(Some("BSD"), &["MIT", "Apache-2.0", "GPL"]);
+        assert!(select_without_interaction("license", req).is_err());
+    }
+
+    #[test]
+    fn test_to_bool() {
+        assert_that!(to_bool("flag", "true").unwrap()).is_true();
+        assert_that!(to_bool("flag", "false").unwrap()).is_false();
+        assert!(to_bool("flag", "maybe").is_err());
+    }
+
+    #[test]
+    fn test_to_list() {
+        let a = Value::String("a".to_owned());
+        assert_that!(to_list(Value::String("".to_owned()))).is_equal_to(vec![]);
+        assert_that!(to_list(a.clone())).is_equal_to(vec![a.clone()]);
+        assert_that!(to_list(Variables::value_from_str("[a, b]").unwrap()))
+            .is_equal_to(vec![a, Value::String("b".to_owned())]);
+    }
+
+    #[test]
+    fn test_default_list() {
+        let handlebars = new_hbs();
+        let mut variables = Variables::default();
+        variables.insert("prj", "foo").unwrap();
+        let from_seq = Variables::value_from_str("[ 'core', '{{ prj }}_cli' ]").unwrap();
+        let expected = vec![
+            Value::String("core".to_owned()),
+            Value::String("foo_cli".to_owned()),
+        ];
+        assert_that!(default_list(&handlebars, &variables, &Some(from_seq)).unwrap())
+            .is_equal_to(&expected);
+        let from_str = Value::String("[ core, '{{ prj }}_cli' ]".to_owned());
+        assert_that!(default_list(&handlebars, &variables, &Some(from_str)).unwrap())
+            .is_equal_to(&expected);
+        assert_that!(default_list(&handlebars, &variables, &None).unwrap()).is_equal_to(vec![]);
+    }
+
+    #[test]
+    fn test_find_idx_in_values() {
+        let values = vec!["MIT".to_owned(), "GPL".to_owned()];
+        assert_that!(find_idx_in_values("license", &values, "GPL").unwrap()).is_equal_to(1);
+        assert_that!(find_idx_in_values("license", &values, "BSD")).is_err();
+    }
+}
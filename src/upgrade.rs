@@ -0,0 +1,330 @@
+//! upgrade a project to an other rev of its template: the rev applied (the commits recorded into the
+//! lockfile) and the new rev are rendered, then merged (3-way, with `git merge-file`) with the current
+//! files of the project, so the local changes survive the changes of the template.
+
+use crate::changelog;
+use crate::error::*;
+use crate::fingerprint::Fingerprint;
+use crate::lockfile::Lockfile;
+use crate::source_loc::SourceLoc;
+use crate::ui;
+use crate::{
+    extract_variables, prepare_plan_with, render_plan, update_fingerprint, update_lockfile, Ctx,
+    RenderedContent,
+};
+use slog::debug;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UpgradeOutcome {
+    /// new file or folder of the template
+    Added,
+    /// file not changed locally, replaced by the new version
+    Updated,
+    /// local changes and changes of the template merged
+    Merged,
+    /// local changes and changes of the template merged, with conflicts to resolve
+    Conflicted,
+    /// file removed from the template (and not changed locally)
+    Removed,
+    /// local version kept: file removed from the template but changed locally, or file deleted locally
+    Kept,
+}
+
+impl fmt::Display for UpgradeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            UpgradeOutcome::Added => "added",
+            UpgradeOutcome::Updated => "updated",
+            UpgradeOutcome::Merged => "merged",
+            UpgradeOutcome::Conflicted => "conflicted",
+            UpgradeOutcome::Removed => "removed",
+            UpgradeOutcome::Kept => "kept",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// upgrade the project `ctx.cmd_opt.dst_folder` to the rev `to` of its template,
+/// return the files changed (path relative to the project)
+pub(crate) fn upgrade(ctx: &Ctx, to: &str) -> Result<Vec<(PathBuf, UpgradeOutcome)>> {
+    let dst_folder = &ctx.cmd_opt.dst_folder;
+    let source = Lockfile::load(dst_folder)?
+        .and_then(|l| l.source)
+        .ok_or_else(|| Error::NoLockedSource {
+            lockfile: Lockfile::path_in(dst_folder),
+        })?;
+    let old_src = SourceLoc {
+        uri: source.uri.parse()?,
+        rev: source.commit_id.clone(),
+        subfolder: source.subfolder.clone(),
+        full_history: ctx.cmd_opt.src.full_history,
+    };
+    let mut new_ctx = ctx.clone();
+    new_ctx.cmd_opt.src = SourceLoc {
+        rev: to.to_owned(),
+        ..old_src.clone()
+    };
+    new_ctx.cmd_opt.locked = false;
+
+    // the answers recorded, overridden by the `ffizer.vars.yaml` and the cli
+    let mut variables = Fingerprint::load(dst_folder)?
+        .map(|f| f.answers)
+        .unwrap_or_default();
+    variables.append(&mut extract_variables(&new_ctx)?);
+    debug!(ctx.logger, "rendering new rev"; "src" => ?&new_ctx.cmd_opt.src);
+    let new_plan = prepare_plan_with(&new_ctx, variables)?;
+    let changelogs = changelog::find_changelogs(&new_ctx, &new_plan.template_composite)?;
+    ui::show_changelogs(ctx, &changelogs)?;
+    if !ui::confirm_upgrade(ctx)? {
+        return Err(Error::ApplyAborted {});
+    }
+    let new_rendered = render_plan(&new_ctx, &new_plan)?;
+
+    // the rev applied, with its imports at the commits applied, and the same answers
+    let mut old_ctx = ctx.clone();
+    old_ctx.cmd_opt.src = old_src;
+    old_ctx.cmd_opt.locked = true;
+    old_ctx.cmd_opt.no_interaction = true;
+    old_ctx.allow_commands = false;
+    debug!(ctx.logger, "rendering applied rev"; "src" => ?&old_ctx.cmd_opt.src);
+    let old_plan = prepare_plan_with(&old_ctx, new_plan.variables.clone())?;
+    let old_rendered = render_plan(&old_ctx, &old_plan)?;
+
+    let mut outcomes = vec![];
+    for (relative, new) in &new_rendered {
+        ctx.cancel.check("upgrading")?;
+        let path = dst_folder.join(relative);
+        let outcome = match new {
+            RenderedContent::Dir if path.is_dir() => None,
+            RenderedContent::Dir => {
+                fs::create_dir_all(&path).map_err(|source| Error::CreateFolder {
+                    path: path.clone(),
+                    source,
+                })?;
+                Some(UpgradeOutcome::Added)
+            }
+            RenderedContent::File(new) => {
+                let base = match old_rendered.get(relative) {
+                    Some(RenderedContent::File(base)) => Some(base.as_slice()),
+                    _ => None,
+                };
+                upgrade_file(&path, base, new)?
+            }
+        };
+        if let Some(outcome) = outcome {
+            outcomes.push((relative.clone(), outcome));
+        }
+    }
+    for (relative, old) in &old_rendered {
+        if new_rendered.contains_key(relative) {
+            continue;
+        }
+        let path = dst_folder.join(relative);
+        if let RenderedContent::File(base) = old {
+            if !path.is_file() {
+                continue;
+            }
+            let current = read(&path)?;
+            if &current == base {
+                fs::remove_file(&path).map_err(|source| Error::RemoveFile {
+                    path: path.clone(),
+                    source,
+                })?;
+                outcomes.push((relative.clone(), UpgradeOutcome::Removed));
+            } else {
+                outcomes.push((relative.clone(), UpgradeOutcome::Kept));
+            }
+        }
+    }
+    update_lockfile(&new_ctx, &new_plan.template_composite)?;
+    update_fingerprint(&new_ctx, &new_plan.template_composite, &new_plan.variables)?;
+    Ok(outcomes)
+}
+
+/// upgrade the file at `path` from the `base` version (`None` if not part of the rev applied)
+/// to the `new` version
+fn upgrade_file(path: &Path, base: Option<&[u8]>, new: &[u8]) -> Result<Option<UpgradeOutcome>> {
+    if !path.exists() {
+        if base.is_some() {
+            // deleted locally
+            return Ok(Some(UpgradeOutcome::Kept));
+        }
+        write(path, new)?;
+        return Ok(Some(UpgradeOutcome::Added));
+    }
+    let current = read(path)?;
+    if current == new || base == Some(new) {
+        return Ok(None);
+    }
+    if base == Some(current.as_slice()) {
+        write(path, new)?;
+        return Ok(Some(UpgradeOutcome::Updated));
+    }
+    let (merged, conflicted) = merge_file(path, &current, base.unwrap_or_default(), new)?;
+    write(path, &merged)?;
+    if conflicted {
+        Ok(Some(UpgradeOutcome::Conflicted))
+    } else {
+        Ok(Some(UpgradeOutcome::Merged))
+    }
+}
+
+/// 3-way merge with `git merge-file`, return the merged content (with conflict markers),
+/// and true if there are conflicts
+fn merge_file(path: &Path, current: &[u8], base: &[u8], new: &[u8]) -> Result<(Vec<u8>, bool)> {
+    let tmp_dir = tempfile::tempdir().map_err(|source| Error::CreateFolder {
+        path: std::env::temp_dir(),
+        source,
+    })?;
+    let files = [("LOCAL", current), ("BASE", base), ("REMOTE", new)];
+    let mut paths = vec![];
+    for (name, content) in &files {
+        let p = tmp_dir.path().join(name);
+        write(&p, content)?;
+        paths.push(p);
+    }
+    let mut cmd = std::process::Command::new("git");
+    cmd.args(&[
+        "merge-file",
+        "-p",
+        "-L",
+        "local",
+        "-L",
+        "base",
+        "-L",
+        "template",
+    ])
+    .args(&paths);
+    let output = cmd.output().map_err(|source| Error::RunCommand {
+        cmd: format!("{:?}", cmd),
+        source,
+    })?;
+    // the exit code is the number of conflicts, negative on error
+    match output.status.code() {
+        Some(code) if (0..128).contains(&code) => Ok((output.stdout, code > 0)),
+        _ => Err(Error::ThreeWayMerge {
+            path: path.to_path_buf(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        }),
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|source| Error::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|source| Error::CreateFolder {
+            path: parent.to_path_buf(),
+            source,
+        })?;
+    }
+    fs::write(path, content).map_err(|source| Error::WriteFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockfile::LockedTemplate;
+    use crate::variables::Variables;
+    use crate::ApplyOpts;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_upgrade_file() -> Result<(), Box<dyn std::error::Error>> {
+        if std::process::Command::new("git")
+            .arg("version")
+            .output()
+            .is_err()
+        {
+            eprintln!("skip the test because `git` is not installed");
+            return Ok(());
+        }
+        let tmp_dir = tempdir()?;
+        let path = tmp_dir.path().join("file.txt");
+
+        // not changed locally
+        fs::write(&path, "a\nb\nc\n")?;
+        assert_that!(upgrade_file(&path, Some(b"a\nb\nc\n"), b"a\nB\nc\n")?)
+            .is_equal_to(Some(UpgradeOutcome::Updated));
+        assert_that!(fs::read_to_string(&path)?).is_equal_to("a\nB\nc\n".to_owned());
+
+        // changed locally and by the template
+        fs::write(&path, "A\nb\nc\n")?;
+        assert_that!(upgrade_file(&path, Some(b"a\nb\nc\n"), b"a\nb\nC\n")?)
+            .is_equal_to(Some(UpgradeOutcome::Merged));
+        assert_that!(fs::read_to_string(&path)?).is_equal_to("A\nb\nC\n".to_owned());
+
+        // same line changed locally and by the template
+        fs::write(&path, "local\n")?;
+        assert_that!(upgrade_file(&path, Some(b"base\n"), b"template\n")?)
+            .is_equal_to(Some(UpgradeOutcome::Conflicted));
+        assert_that!(fs::read_to_string(&path)?).contains("<<<<<<< local");
+
+        // not changed by the template
+        fs::write(&path, "local\n")?;
+        assert_that!(upgrade_file(&path, Some(b"base\n"), b"base\n")?).is_none();
+        assert_that!(fs::read_to_string(&path)?).is_equal_to("local\n".to_owned());
+
+        // deleted locally
+        fs::remove_file(&path)?;
+        assert_that!(upgrade_file(&path, Some(b"base\n"), b"template\n")?)
+            .is_equal_to(Some(UpgradeOutcome::Kept));
+        assert_that!(path.exists()).is_false();
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_keep_answers() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let template = tmp_dir.path().join("template");
+        fs::create_dir_all(&template)?;
+        fs::write(
+            template.join(".ffizer.yaml"),
+            "variables:\n  - name: name\n    default_value: foo\n",
+        )?;
+        fs::create_dir_all(template.join("{{ name }}"))?;
+        let project = tmp_dir.path().join("project");
+        fs::create_dir_all(&project)?;
+        let lockfile = Lockfile {
+            source: Some(LockedTemplate {
+                uri: template.to_string_lossy().to_string(),
+                rev: "master".to_owned(),
+                subfolder: None,
+                commit_id: "0000000".to_owned(),
+            }),
+            ..Default::default()
+        };
+        lockfile.save(&project)?;
+        let mut answers = Variables::default();
+        answers.insert("name", "bar")?;
+        Fingerprint::new("0.0.0", vec![], &answers)?.save(&project)?;
+
+        let ctx = Ctx {
+            cmd_opt: ApplyOpts {
+                dst_folder: project.clone(),
+                no_interaction: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let outcomes = upgrade(&ctx, "master")?;
+        assert_that!(outcomes).is_equal_to(vec![(PathBuf::from("bar"), UpgradeOutcome::Added)]);
+        assert_that!(project.join("bar").is_dir()).is_true();
+        let answers = Fingerprint::load(&project)?.map(|f| f.answers);
+        assert_that!(answers.and_then(|a| a.get("name").cloned()))
+            .is_equal_to(Some(serde_yaml::Value::from("bar")));
+        Ok(())
+    }
+}
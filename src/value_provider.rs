@@ -0,0 +1,141 @@
+//! the providers of the default value of a variable, resolved before the questions (eg to reuse the
+//! defaults of the tooling of an organization): the output of a command, or a key of a dotenv file.
+
+use crate::error::*;
+use run_script::ScriptOptions;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueProvider {
+    /// the output (trimmed) of a command, run into the destination folder (if it exists)
+    Cmd(String),
+    /// the value of the `key` into a dotenv file (`KEY=VALUE` lines), the `path` is relative to the
+    /// destination folder (or to the home of the user if it starts with `~/`)
+    Dotenv { path: PathBuf, key: String },
+}
+
+impl ValueProvider {
+    /// the value provided (`None` if the command failed or printed nothing, if the dotenv file or
+    /// the key doesn't exist), the command is only run if `run_cmd`
+    pub(crate) fn provide(&self, dst_folder: &Path, run_cmd: bool) -> Result<Option<String>> {
+        match self {
+            ValueProvider::Cmd(cmd) => {
+                if !run_cmd {
+                    return Ok(None);
+                }
+                let mut options = ScriptOptions::new();
+                if dst_folder.is_dir() {
+                    options.working_directory = Some(dst_folder.to_path_buf());
+                }
+                let (code, stdout, _) =
+                    run_script::run(cmd, &vec![], &options).map_err(|source| {
+                        Error::ScriptError {
+                            script: cmd.clone(),
+                            source,
+                        }
+                    })?;
+                let value = stdout.trim();
+                Ok(Some(value.to_owned()).filter(|v| code == 0 && !v.is_empty()))
+            }
+            ValueProvider::Dotenv { path, key } => {
+                let path = resolve_path(dst_folder, path);
+                match fs::read_to_string(&path) {
+                    Ok(content) => Ok(find_in_dotenv(&content, key)),
+                    Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+                    Err(source) => Err(Error::ReadFile { path, source }),
+                }
+            }
+        }
+    }
+}
+
+fn resolve_path(dst_folder: &Path, path: &Path) -> PathBuf {
+    match (
+        path.strip_prefix("~"),
+        directories::BaseDirs::new().map(|d| d.home_dir().to_path_buf()),
+    ) {
+        (Ok(relative), Some(home)) => home.join(relative),
+        _ => dst_folder.join(path),
+    }
+}
+
+/// the value of `key` into the content of a dotenv file: `KEY=VALUE` lines (optionally prefixed by
+/// `export `, the value optionally quoted), `#` starts a comment line
+fn find_in_dotenv(content: &str, key: &str) -> Option<String> {
+    content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.starts_with('#'))
+        .map(|l| l.strip_prefix("export ").unwrap_or(l))
+        .filter_map(|l| {
+            let mut kv = l.splitn(2, '=');
+            Some((kv.next()?.trim(), kv.next()?.trim()))
+        })
+        .filter(|(k, _)| *k == key)
+        .map(|(_, v)| {
+            let quoted = v.len() >= 2
+                && ((v.starts_with('"') && v.ends_with('"'))
+                    || (v.starts_with('\'') && v.ends_with('\'')));
+            if quoted {
+                v[1..v.len() - 1].to_owned()
+            } else {
+                v.to_owned()
+            }
+        })
+        .next_back()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_in_dotenv() {
+        let content = r#"
+            # defaults of acme
+            REGISTRY=registry.acme.com
+            export OWNER="platform team"
+            LICENSE='MIT'
+            EMPTY=
+        "#;
+        assert_that!(find_in_dotenv(content, "REGISTRY"))
+            .is_equal_to(Some("registry.acme.com".to_owned()));
+        assert_that!(find_in_dotenv(content, "OWNER"))
+            .is_equal_to(Some("platform team".to_owned()));
+        assert_that!(find_in_dotenv(content, "LICENSE")).is_equal_to(Some("MIT".to_owned()));
+        assert_that!(find_in_dotenv(content, "EMPTY")).is_equal_to(Some("".to_owned()));
+        assert_that!(find_in_dotenv(content, "defaults")).is_none();
+        assert_that!(find_in_dotenv(content, "UNKNOWN")).is_none();
+    }
+
+    #[test]
+    fn test_provide_from_dotenv() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let provider = ValueProvider::Dotenv {
+            path: PathBuf::from(".env"),
+            key: "REGISTRY".to_owned(),
+        };
+        assert_that!(provider.provide(tmp_dir.path(), true)?).is_none();
+        fs::write(tmp_dir.path().join(".env"), "REGISTRY=registry.acme.com\n")?;
+        assert_that!(provider.provide(tmp_dir.path(), true)?)
+            .is_equal_to(Some("registry.acme.com".to_owned()));
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_provide_from_cmd() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir()?;
+        let cmd = |v: &str| ValueProvider::Cmd(v.to_owned());
+        assert_that!(cmd("echo ' bob@acme.com '").provide(tmp_dir.path(), true)?)
+            .is_equal_to(Some("bob@acme.com".to_owned()));
+        assert_that!(cmd("echo 'bob@acme.com'").provide(tmp_dir.path(), false)?).is_none();
+        assert_that!(cmd("echo ''").provide(tmp_dir.path(), true)?).is_none();
+        assert_that!(cmd("echo 'partial'; exit 1").provide(tmp_dir.path(), true)?).is_none();
+        Ok(())
+    }
+}
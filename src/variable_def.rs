@@ -1,13 +1,55 @@
+use crate::value_provider::ValueProvider;
+use schemars::JsonSchema;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+/// type of the value of a variable
+pub enum VariableType {
+    /// a value parsed as yaml from the answer (string, number, bool,...)
+    String,
+    /// a boolean asked as a yes/no confirmation
+    Bool,
+    /// a list of values, asked one by one until an empty answer
+    List,
+}
+
+impl Default for VariableType {
+    fn default() -> Self {
+        VariableType::String
+    }
+}
+
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct VariableDef {
     /// name of variable used in the template
     pub name: String,
     /// optionnal default value
     pub default_value: Option<serde_yaml::Value>,
+    /// the providers of the default value (tried in order before the questions, the first value
+    /// provided replaces `default_value`)
+    pub default_providers: Vec<ValueProvider>,
     /// sentence to ask the value (default to the name on variable)
     pub ask: Option<String>,
     /// is the variable hidden to the user (could be usefull to cache shared variable/data)
     pub hidden: bool,
+    /// is the value a secret (asked without echo, masked into the logs, not persisted)
+    pub secret: bool,
     /// if non-empty then the value should selected into the list of value
     pub select_in_values: Vec<serde_yaml::Value>,
+    /// type of the value
+    pub value_type: VariableType,
+    /// namespace of the imported template that defines the variable (the name is prefixed by `<namespace>.`)
+    pub namespace: Option<String>,
+    /// section of the variable, displayed before the first question of a group of its variables
+    pub section: Option<SectionDef>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SectionDef {
+    /// name of the section (prefixed by `<namespace>.` for an imported template)
+    pub name: String,
+    /// title displayed before the questions of the section
+    pub title: String,
+    /// text displayed after the title (handlebars template)
+    pub intro: Option<String>,
 }
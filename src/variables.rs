@@ -1,22 +1,124 @@
 use crate::error::*;
 use serde::Serialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct Variables(BTreeMap<String, serde_yaml::Value>);
+/// name of the files of answers, into the destination folder or its parents
+pub const VARS_FILENAME: &str = "ffizer.vars.yaml";
+/// name of the variable with the predefined values (a map), eg `ffizer.dst_folder_name`
+pub const FFIZER_VARIABLE: &str = "ffizer";
+/// the value of a secret into the logs and the records
+pub const MASKED_VALUE: &str = "********";
+/// the words into the name of a variable that make its value a secret
+const SECRET_WORDS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "private_key",
+    "credential",
+];
+
+#[derive(Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Variables {
+    values: BTreeMap<String, serde_yaml::Value>,
+    /// the names of the secret variables: masked into the logs, not persisted (see [`Variables::without_secrets`])
+    #[serde(skip)]
+    secrets: BTreeSet<String>,
+}
 
 impl Variables {
     pub fn append(&mut self, v: &mut Variables) {
-        self.0.append(&mut v.0);
+        self.values.append(&mut v.values);
+        self.secrets.append(&mut v.secrets);
     }
 
     pub fn insert<K: Into<String>, V: Serialize>(&mut self, key: K, value: V) -> Result<()> {
-        self.0.insert(key.into(), serde_yaml::to_value(value)?);
+        self.values.insert(key.into(), serde_yaml::to_value(value)?);
         Ok(())
     }
 
+    pub fn get<K: Into<String>>(&self, key: K) -> Option<&serde_yaml::Value> {
+        self.values.get(&key.into())
+    }
+
+    pub fn remove<K: Into<String>>(&mut self, key: K) -> Option<serde_yaml::Value> {
+        let key = key.into();
+        self.secrets.remove(&key);
+        self.values.remove(&key)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
     pub fn contains_key<K: Into<String>>(&mut self, key: K) -> bool {
-        self.0.contains_key(&key.into())
+        self.values.contains_key(&key.into())
+    }
+
+    /// flag the variable as a secret (its value is still available for the rendering)
+    pub fn mark_secret<K: Into<String>>(&mut self, key: K) {
+        self.secrets.insert(key.into());
+    }
+
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.secrets.contains(key)
+    }
+
+    /// the variables without the secrets, flagged or named like a secret (to persist them)
+    pub fn without_secrets(&self) -> Variables {
+        Variables {
+            values: self
+                .values
+                .iter()
+                .filter(|(k, _)| !self.secrets.contains(*k) && !is_secret_name(k))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            secrets: BTreeSet::new(),
+        }
+    }
+
+    /// set the predefined value `ffizer.<name>`
+    pub fn insert_ffizer<V: Serialize>(&mut self, name: &str, value: V) -> Result<()> {
+        let mut predefined = match self.values.remove(FFIZER_VARIABLE) {
+            Some(serde_yaml::Value::Mapping(m)) => m,
+            _ => serde_yaml::Mapping::new(),
+        };
+        predefined.insert(name.into(), serde_yaml::to_value(value)?);
+        self.values.insert(
+            FFIZER_VARIABLE.to_owned(),
+            serde_yaml::Value::Mapping(predefined),
+        );
+        Ok(())
+    }
+
+    /// the variables of the `namespace` (named `<namespace>.<name>`), without the prefix
+    pub fn find_namespaced(&self, namespace: &str) -> Variables {
+        let prefix = format!("{}.", namespace);
+        Variables {
+            values: self
+                .values
+                .iter()
+                .filter_map(|(k, v)| {
+                    k.strip_prefix(&prefix)
+                        .map(|name| (name.to_owned(), v.clone()))
+                })
+                .collect(),
+            secrets: self
+                .secrets
+                .iter()
+                .filter_map(|k| k.strip_prefix(&prefix).map(|name| name.to_owned()))
+                .collect(),
+        }
     }
 
     pub fn value_from_str(s: &str) -> Result<serde_yaml::Value> {
@@ -25,6 +127,81 @@ impl Variables {
     }
 }
 
+/// the values of the secrets are masked (eg into the logs)
+impl std::fmt::Debug for Variables {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let masked = serde_yaml::Value::String(MASKED_VALUE.to_owned());
+        f.debug_map()
+            .entries(self.values.iter().map(|(k, v)| {
+                if self.secrets.contains(k) {
+                    (k, &masked)
+                } else {
+                    (k, v)
+                }
+            }))
+            .finish()
+    }
+}
+
+/// the value of `--variables` to read the answers from stdin
+pub const STDIN_VARIABLES: &str = "-";
+
+/// parse the answers (a map as yaml or json, empty content for no answer)
+pub fn parse_vars(content: &str) -> Result<Variables> {
+    if content.trim().is_empty() {
+        return Ok(Variables::default());
+    }
+    serde_yaml::from_str(content).map_err(Error::from)
+}
+
+/// read the answers from stdin (see [`parse_vars`]), eg piped by a wrapper or a web ui
+pub fn read_vars_from_stdin() -> Result<Variables> {
+    let mut content = String::new();
+    std::io::stdin()
+        .read_to_string(&mut content)
+        .map_err(|source| Error::ReadStdin { source })?;
+    parse_vars(&content)
+}
+
+/// list the `ffizer.vars.yaml` that apply to the `dst_folder`: from the root of the project
+/// (the nearest parent with a `.git` folder, else the root of the file system) down to the `dst_folder`
+pub fn find_vars_files(dst_folder: &Path) -> Vec<PathBuf> {
+    let dst = crate::files::absolute_path(dst_folder);
+    let mut found = vec![];
+    for dir in dst.ancestors() {
+        let path = dir.join(VARS_FILENAME);
+        if path.is_file() {
+            found.push(path);
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    found.reverse();
+    found
+}
+
+/// load the answers of the `ffizer.vars.yaml` that apply to the `dst_folder`,
+/// the answers of the deeper files override the answers of their parents
+pub fn load_vars_files(dst_folder: &Path) -> Result<Variables> {
+    let mut variables = Variables::default();
+    for path in find_vars_files(dst_folder) {
+        let content = fs::read_to_string(&path).map_err(|source| Error::ReadFile {
+            path: path.clone(),
+            source,
+        })?;
+        let mut answers = parse_vars(&content)?;
+        variables.append(&mut answers);
+    }
+    Ok(variables)
+}
+
+/// true if the name of the variable (eg `db.password`, `GITHUB_TOKEN`) looks like a secret
+pub(crate) fn is_secret_name(name: &str) -> bool {
+    let name = name.to_lowercase().replace('-', "_");
+    SECRET_WORDS.iter().any(|w| name.contains(w))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,4 +220,85 @@ mod tests {
         assert_that!(&Variables::value_from_str("42").unwrap())
             .is_equal_to(&serde_yaml::to_value(42).unwrap());
     }
+
+    #[test]
+    fn test_find_namespaced() -> Result<(), Box<dyn std::error::Error>> {
+        let mut variables = Variables::default();
+        variables.insert("project_name", "app")?;
+        variables.insert("lib.project_name", "core")?;
+        variables.insert("lib.log.level", "info")?;
+        variables.insert("library", "none")?;
+        let lib = variables.find_namespaced("lib");
+        assert_that!(lib.get("project_name"))
+            .is_equal_to(Some(&Variables::value_from_str("core")?));
+        assert_that!(lib.get("log.level")).is_equal_to(Some(&Variables::value_from_str("info")?));
+        assert_that!(lib.get("library")).is_none();
+        assert_that!(variables.find_namespaced("cli").is_empty()).is_true();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_vars_as_yaml_or_json() -> Result<(), Box<dyn std::error::Error>> {
+        let from_yaml = parse_vars("project_name: app\nport: 8080\nfeatures: [docker]\n")?;
+        let from_json =
+            parse_vars(r#"{"project_name": "app", "port": 8080, "features": ["docker"]}"#)?;
+        assert_that!(&from_json).is_equal_to(&from_yaml);
+        assert_that!(from_yaml.get("port")).is_equal_to(Some(&Variables::value_from_str("8080")?));
+        assert_that!(parse_vars(" \n")?.is_empty()).is_true();
+        assert_that!(parse_vars("- not\n- a map\n")).is_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_vars_files() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempfile::tempdir()?;
+        let project = tmp_dir.path().join("project");
+        let service = project.join("services/foo");
+        fs::create_dir_all(project.join(".git"))?;
+        fs::create_dir_all(&service)?;
+        fs::write(tmp_dir.path().join(VARS_FILENAME), "outside: true\n")?;
+        fs::write(project.join(VARS_FILENAME), "org: acme\nlang: rust\n")?;
+        fs::write(
+            project.join("services").join(VARS_FILENAME),
+            "lang: go\nport: 8080\n",
+        )?;
+
+        let variables = load_vars_files(&service)?;
+        assert_that!(variables.get("org")).is_equal_to(Some(&Variables::value_from_str("acme")?));
+        assert_that!(variables.get("lang")).is_equal_to(Some(&Variables::value_from_str("go")?));
+        assert_that!(variables.get("port")).is_equal_to(Some(&Variables::value_from_str("8080")?));
+        // stop at the root of the project
+        assert_that!(variables.get("outside")).is_none();
+        Ok(())
+    }
+
+    #[test]
+    fn test_secrets() -> Result<(), Box<dyn std::error::Error>> {
+        let mut variables = Variables::default();
+        variables.insert("user", "bob")?;
+        variables.insert("db.password", "p4ss")?;
+        variables.mark_secret("db.password");
+        assert_that!(variables.get("db.password"))
+            .is_equal_to(Some(&serde_yaml::Value::String("p4ss".to_owned())));
+        let debug = format!("{:?}", variables);
+        assert_that!(debug.contains("p4ss")).is_false();
+        assert_that!(debug.contains(MASKED_VALUE)).is_true();
+        assert_that!(variables.find_namespaced("db").is_secret("password")).is_true();
+        let persisted = serde_yaml::to_string(&variables.without_secrets())?;
+        assert_that!(persisted.contains("password")).is_false();
+        assert_that!(persisted.contains("bob")).is_true();
+        Ok(())
+    }
+
+    #[test]
+    fn test_without_secrets_by_name() -> Result<(), Box<dyn std::error::Error>> {
+        let mut variables = Variables::default();
+        variables.insert("user", "bob")?;
+        variables.insert("GITHUB_TOKEN", "t0ken")?;
+        assert_that!(variables.is_secret("GITHUB_TOKEN")).is_false();
+        let persisted = variables.without_secrets();
+        assert_that!(persisted.get("GITHUB_TOKEN")).is_none();
+        assert_that!(persisted.get("user")).is_some();
+        Ok(())
+    }
 }
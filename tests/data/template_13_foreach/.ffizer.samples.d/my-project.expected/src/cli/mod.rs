@@ -0,0 +1 @@
+// module cli (#1)
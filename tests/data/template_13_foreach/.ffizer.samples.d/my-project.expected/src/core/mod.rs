@@ -0,0 +1 @@
+// module core (#0)
@@ -156,3 +156,124 @@ fn log_should_report_error() -> Result<(), Box<dyn Error>> {
         .failure();
     Ok(())
 }
+
+#[test]
+fn test_layers() -> Result<(), Box<dyn Error>> {
+    let tmp_dir = tempdir()?;
+    let base_path = tmp_dir.path().join("base");
+    let lang_path = tmp_dir.path().join("lang");
+    let ci_path = tmp_dir.path().join("ci");
+    let actual_path = tmp_dir.path().join("actual");
+    let expected_path = tmp_dir.path().join("expected");
+    for (template, files) in &[
+        (&base_path, vec!["a.txt", "b.txt", "c.txt"]),
+        (&lang_path, vec!["b.txt", "c.txt"]),
+        (&ci_path, vec!["c.txt"]),
+    ] {
+        fs::create_dir_all(template)?;
+        for file in files {
+            fs::write(
+                template.join(file),
+                template.file_name().unwrap().to_str().unwrap(),
+            )?;
+        }
+    }
+    fs::create_dir_all(&expected_path)?;
+    fs::write(expected_path.join("a.txt"), "base")?;
+    fs::write(expected_path.join("b.txt"), "lang")?;
+    fs::write(expected_path.join("c.txt"), "ci")?;
+    let stack_path = tmp_dir.path().join("stack.yaml");
+    fs::write(
+        &stack_path,
+        format!("- uri: {}\n", lang_path.to_str().unwrap()),
+    )?;
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("apply")
+        .arg("--no-interaction")
+        .arg("--confirm")
+        .arg("never")
+        .arg("--destination")
+        .arg(actual_path.to_str().unwrap())
+        .arg("--source")
+        .arg(base_path.to_str().unwrap())
+        .arg("--stack")
+        .arg(stack_path.to_str().unwrap())
+        .arg("--layer")
+        .arg(ci_path.to_str().unwrap())
+        .ok()?;
+    assert_is_same(&actual_path, &expected_path, &output)
+}
+
+#[test]
+fn test_keepdir() -> Result<(), Box<dyn Error>> {
+    let tmp_dir = tempdir()?;
+    let template_path = tmp_dir.path().join("template");
+    let actual_path = tmp_dir.path().join("actual").join("my-project");
+    let expected_path = tmp_dir.path().join("expected");
+    fs::create_dir_all(template_path.join("logs"))?;
+    fs::write(template_path.join("logs").join(".ffizer.keepdir"), "")?;
+    fs::create_dir_all(template_path.join("src"))?;
+    fs::write(template_path.join("src").join("main.rs"), "fn main() {}")?;
+    fs::create_dir_all(expected_path.join("logs"))?;
+    fs::create_dir_all(expected_path.join("src"))?;
+    fs::write(expected_path.join("src").join("main.rs"), "fn main() {}")?;
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .arg("apply")
+        .arg("--no-interaction")
+        .arg("--confirm")
+        .arg("never")
+        .arg("--destination")
+        .arg(actual_path.to_str().unwrap())
+        .arg("--source")
+        .arg(template_path.to_str().unwrap())
+        .ok()?;
+    assert_is_same(&actual_path, &expected_path, &output)
+}
+
+#[test]
+fn test_source_from_registry() -> Result<(), Box<dyn Error>> {
+    let tmp_dir = tempdir()?;
+    let template_path = tmp_dir.path().join("template");
+    let actual_path = tmp_dir.path().join("actual");
+    let registry_path = tmp_dir.path().join("registry.yaml");
+    fs::create_dir_all(&template_path)?;
+    fs::write(template_path.join("README.md"), "hello")?;
+    fs::write(
+        &registry_path,
+        format!(
+            "templates:\n  - name: hello\n    source: {}\n",
+            template_path.to_str().unwrap()
+        ),
+    )?;
+
+    // the only template registered is applied without question
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .env("FFIZER_REGISTRY_FILE", &registry_path)
+        .arg("apply")
+        .arg("--no-interaction")
+        .arg("--destination")
+        .arg(actual_path.to_str().unwrap())
+        .ok()?;
+    assert_eq!(fs::read_to_string(actual_path.join("README.md"))?, "hello");
+
+    // without interaction, a template can't be selected between several
+    fs::write(
+        &registry_path,
+        format!(
+            "templates:\n  - name: hello\n    source: {0}\n  - name: other\n    source: {0}\n",
+            template_path.to_str().unwrap()
+        ),
+    )?;
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))?
+        .env("FFIZER_REGISTRY_FILE", &registry_path)
+        .arg("apply")
+        .arg("--no-interaction")
+        .arg("--destination")
+        .arg(actual_path.to_str().unwrap())
+        .assert()
+        .stderr(predicate::str::contains("MissingSource"))
+        .code(2);
+    Ok(())
+}